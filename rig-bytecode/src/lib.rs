@@ -1,3 +1,21 @@
+/// How a function binds `this`, and whether `new` may call it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FunctionKind {
+    /// A plain function, which `new` may also call as a constructor
+    Normal,
+    /// An arrow function, which sees the `this` of the scope it was created
+    /// in
+    Arrow,
+    /// A generator function, sync or async
+    Generator,
+    /// An async function
+    Async,
+    /// A method, getter or setter of a class
+    Method,
+    /// The constructor of a class, which only `new` may call
+    ClassConstructor,
+}
+
 /// Represents a set of instructions for a virtual machine.
 #[derive(Debug, Clone)]
 pub enum Instruction {
@@ -97,6 +115,15 @@ pub enum Instruction {
     /// - `b`: The second operand register index (8 bits).
     Eq { dst: u8, a: u8, b: u8 },
 
+    /// Compares two registers with the `==` operator, converting operands
+    /// of different types before comparing them.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `a`: The first operand register index (8 bits).
+    /// - `b`: The second operand register index (8 bits).
+    LooseEq { dst: u8, a: u8, b: u8 },
+
     /// Compares if the value in the first register is less than the second.
     ///
     /// # Parameters
@@ -119,7 +146,8 @@ pub enum Instruction {
     /// - `offset`: The jump offset (32 bits).
     Jmp { offset: i32 },
 
-    /// Performs a conditional jump based on a register's value.
+    /// Performs a conditional jump based on a register's value. The jump
+    /// is taken if the value is truthy.
     ///
     /// # Parameters
     /// - `cond`: The condition register index (8 bits).
@@ -133,6 +161,22 @@ pub enum Instruction {
     /// - `arg_count`: The number of arguments (8 bits).
     Call { func_reg: u8, arg_count: u8 },
 
+    /// Calls a function as a method. The `this` value is in the register
+    /// after the function, followed by the arguments.
+    ///
+    /// # Parameters
+    /// - `func_reg`: The function register index (8 bits).
+    /// - `arg_count`: The number of arguments (8 bits).
+    CallMethod { func_reg: u8, arg_count: u8 },
+
+    /// Calls a function with the elements of an array as its arguments. The
+    /// `this` value and the array follow the function register, and the
+    /// result replaces the function.
+    ///
+    /// # Parameters
+    /// - `func_reg`: The function register index (8 bits).
+    CallSpread { func_reg: u8 },
+
     /// Returns from a function with specified values.
     ///
     /// # Parameters
@@ -183,6 +227,20 @@ pub enum Instruction {
     /// - `src`: The source register index (8 bits).
     SetScope { var_idx: u32, src: u8 },
 
+    /// Enters a block by pushing an empty scope for the variables it
+    /// declares.
+    PushScope,
+
+    /// Leaves a block by popping the scope `PushScope` pushed.
+    PopScope,
+
+    /// Replaces the innermost scope with a copy of it, so that closures
+    /// made in one iteration of a loop keep that iteration's variables.
+    CopyScope,
+
+    /// Throws the `TypeError` of assigning to a constant.
+    ThrowConstAssignment,
+
     /// Creates a new array in a register.
     ///
     /// # Parameters
@@ -212,6 +270,14 @@ pub enum Instruction {
     /// - `src`: The source register index (8 bits).
     TypeOf { dst: u8, src: u8 },
 
+    /// Determines the type of a variable, which is `"undefined"` rather
+    /// than an error if no scope declares it.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `var_idx`: The variable index (32 bits).
+    TypeOfScope { dst: u8, var_idx: u32 },
+
     /// Checks if an object is an instance of a constructor.
     ///
     /// # Parameters
@@ -220,16 +286,37 @@ pub enum Instruction {
     /// - `ctor`: The constructor register index (8 bits).
     InstanceOf { dst: u8, obj: u8, ctor: u8 },
 
+    /// Checks if an object has a property, as the `in` operator does. Holes
+    /// in an array are not properties.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `key`: The property key register index (8 bits).
+    /// - `obj`: The object register index (8 bits).
+    In { dst: u8, key: u8, obj: u8 },
+
+    /// Removes a property from an object, leaving a hole if it is an array
+    /// element, and stores `true` in a register.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `obj`: The object register index (8 bits).
+    /// - `key`: The property key register index (8 bits).
+    Delete { dst: u8, obj: u8, key: u8 },
+
     /// Declares a function with a specified number of parameters.
     ///
     /// # Parameters
     /// - `reg`: The register index (8 bits).
     /// - `name_idx`: The function name index (32 bits).
     /// - `param_count`: The number of parameters (8 bits).
+    /// - `kind`: How the function binds `this` and whether it is a
+    ///   constructor.
     DeclareFunc {
         reg: u8,
         name_idx: u32,
         param_count: u8,
+        kind: FunctionKind,
     },
 
     /// Declares a variable by its name index.
@@ -240,4 +327,281 @@ pub enum Instruction {
 
     /// Enables strict mode.
     UseStrict,
+
+    /// Throws the value in a register as an exception.
+    ///
+    /// # Parameters
+    /// - `src`: The register holding the thrown value (8 bits).
+    Throw { src: u8 },
+
+    /// Installs an exception handler. If an exception is thrown before the
+    /// matching `LeaveTry`, execution continues at the handler with the
+    /// exception in `exc_reg`.
+    ///
+    /// # Parameters
+    /// - `offset`: The jump offset to the handler (32 bits).
+    /// - `exc_reg`: The register that receives the exception (8 bits).
+    EnterTry { offset: i32, exc_reg: u8 },
+
+    /// Removes the most recently installed exception handler.
+    LeaveTry,
+
+    /// Gets an iterator over a value through its `Symbol.iterator` method,
+    /// for `IteratorNext` and `IteratorClose` to use.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the iterator (8 bits).
+    /// - `src`: The iterated value register index (8 bits).
+    GetIterator { dst: u8, src: u8 },
+
+    /// Takes the next value from an iterator. Once the iterator is
+    /// finished, stores undefined instead and jumps.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `iter`: The iterator register index (8 bits).
+    /// - `offset`: The jump offset taken when finished (32 bits).
+    IteratorNext { dst: u8, iter: u8, offset: i32 },
+
+    /// Calls the `return` method of an iterator that has not finished, as a
+    /// loop that stops early must.
+    ///
+    /// # Parameters
+    /// - `iter`: The iterator register index (8 bits).
+    IteratorClose { iter: u8 },
+
+    /// Lists the enumerable string keys of a value and the objects it
+    /// inherits from, for `ForInNext` to walk. Undefined and null have no
+    /// keys.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the key iterator (8 bits).
+    /// - `src`: The enumerated value register index (8 bits).
+    ForInPrepare { dst: u8, src: u8 },
+
+    /// Takes the next key from a key iterator, skipping keys that have been
+    /// deleted since `ForInPrepare`. Once the keys run out, stores undefined
+    /// instead and jumps.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `iter`: The key iterator register index (8 bits).
+    /// - `offset`: The jump offset taken when finished (32 bits).
+    ForInNext { dst: u8, iter: u8, offset: i32 },
+
+    /// Suspends the running generator function before its body runs, and
+    /// returns a generator object holding the suspended frame. The first
+    /// call to the generator's `next` resumes after this instruction.
+    CreateGenerator,
+
+    /// Suspends the running generator, handing a value to the caller of
+    /// `next`. When resumed by `next`, stores the value passed in and
+    /// continues; by `throw`, throws the value passed in from here; by
+    /// `return`, stores the value passed in and jumps to code that returns
+    /// it.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the value passed in (8 bits).
+    /// - `src`: The yielded value register index (8 bits).
+    /// - `offset`: The jump offset taken when resumed by `return` (32 bits).
+    Yield { dst: u8, src: u8, offset: i32 },
+
+    /// Passes a resumption of the running generator on to an inner
+    /// iterator, as `yield*` does: calls its `next`, `throw` or `return`
+    /// method and, unless that finishes it, suspends with the result. The
+    /// instruction runs again each time the generator is resumed. Once the
+    /// iterator finishes, stores its final value and continues, or jumps if
+    /// it finished because of `return`.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the final value (8 bits).
+    /// - `iter`: The iterator register index (8 bits).
+    /// - `offset`: The jump offset taken when finished by `return` (32
+    ///   bits).
+    YieldDelegate { dst: u8, iter: u8, offset: i32 },
+
+    /// Suspends the running async function before its body runs, then runs
+    /// the body up to its first `Await` and returns a promise for the
+    /// function's result.
+    CreateAsync,
+
+    /// Suspends the running async generator function before its body runs,
+    /// and returns an async generator object holding the suspended frame.
+    CreateAsyncGenerator,
+
+    /// Suspends the running async function until the promise for a value
+    /// settles. Resumes by storing the fulfilled value, or by throwing the
+    /// rejection reason from here.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the fulfilled value (8 bits).
+    /// - `src`: The awaited value register index (8 bits).
+    Await { dst: u8, src: u8 },
+
+    /// Gets an async iterator over a value through its
+    /// `Symbol.asyncIterator` method, or else a sync iterator whose values
+    /// are awaited, for `AsyncIteratorNext` and `AsyncIteratorClose` to
+    /// use.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the iterator (8 bits).
+    /// - `src`: The iterated value register index (8 bits).
+    GetAsyncIterator { dst: u8, src: u8 },
+
+    /// Calls the `next` method of an async iterator, storing the result for
+    /// `Await`. Until `IteratorValue` has checked the awaited result, the
+    /// iterator counts as finished, so that a failed step is not closed.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the result (8 bits).
+    /// - `iter`: The iterator register index (8 bits).
+    AsyncIteratorNext { dst: u8, iter: u8 },
+
+    /// Takes apart the awaited result of `AsyncIteratorNext`: stores its
+    /// value, and jumps if it says the iterator is done.
+    ///
+    /// # Parameters
+    /// - `dst`: The register holding the result, which receives the value
+    ///   (8 bits).
+    /// - `iter`: The iterator register index (8 bits).
+    /// - `offset`: The jump offset taken when done (32 bits).
+    IteratorValue { dst: u8, iter: u8, offset: i32 },
+
+    /// Calls the `return` method of an async iterator that has not
+    /// finished, storing the result for `Await`, or undefined if there is
+    /// nothing to call.
+    ///
+    /// # Parameters
+    /// - `dst`: The register that receives the result (8 bits).
+    /// - `iter`: The iterator register index (8 bits).
+    AsyncIteratorClose { dst: u8, iter: u8 },
+
+    /// Loads the `this` value of the running function, or of the function
+    /// an arrow function was created in. Throws in a derived class
+    /// constructor that has not called `super()` yet.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    LoadThis { dst: u8 },
+
+    /// Calls a function as a constructor, as `new` does. The arguments
+    /// follow the function register, and the new object replaces the
+    /// function.
+    ///
+    /// # Parameters
+    /// - `func_reg`: The constructor register index (8 bits).
+    /// - `arg_count`: The number of arguments (8 bits).
+    Construct { func_reg: u8, arg_count: u8 },
+
+    /// Calls a function as a constructor with the elements of an array as
+    /// its arguments. The array follows the function register, and the new
+    /// object replaces the function.
+    ///
+    /// # Parameters
+    /// - `func_reg`: The constructor register index (8 bits).
+    ConstructSpread { func_reg: u8 },
+
+    /// Calls the constructor of the class a derived class extends, as
+    /// `super(...)` does, with the elements of an array as its arguments.
+    /// The new object becomes the `this` value of the running constructor,
+    /// and the derived class's fields are initialized on it.
+    ///
+    /// # Parameters
+    /// - `func_reg`: The register holding the derived class, followed by
+    ///   the array of arguments; receives the new object (8 bits).
+    SuperCall { func_reg: u8 },
+
+    /// Reads a property from the object a method's home object inherits
+    /// from, as `super[key]` does, with the running `this` value as the
+    /// receiver of any getter.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `home`: The home object register index (8 bits).
+    /// - `key`: The key register index (8 bits).
+    GetSuper { dst: u8, home: u8, key: u8 },
+
+    /// Writes a property as `super[key] = value` does: through a setter
+    /// the home object inherits, or else onto the running `this` value.
+    ///
+    /// # Parameters
+    /// - `home`: The home object register index (8 bits).
+    /// - `key`: The key register index (8 bits).
+    /// - `value`: The value register index (8 bits).
+    SetSuper { home: u8, key: u8, value: u8 },
+
+    /// Makes a class from the constructor function in a register, with a
+    /// new prototype object for its instances. The class extended, if any,
+    /// is in the register after the constructor, and the function that
+    /// initializes the fields of each instance, or undefined, in the one
+    /// after that. The class replaces the constructor, and its prototype
+    /// replaces the class extended.
+    ///
+    /// # Parameters
+    /// - `reg`: The constructor register index (8 bits).
+    /// - `extends`: Whether the class extends another (1 bit).
+    /// - `implicit`: Whether the class has no constructor of its own, so
+    ///   that constructing it only initializes fields, after passing the
+    ///   arguments to the class extended (1 bit).
+    CreateClass {
+        reg: u8,
+        extends: bool,
+        implicit: bool,
+    },
+
+    /// Defines an own property of an object, replacing any accessor and
+    /// calling no setter, as class fields and methods are defined. A
+    /// private name as the key adds a private member, which must not exist
+    /// yet.
+    ///
+    /// # Parameters
+    /// - `obj`: The object register index (8 bits).
+    /// - `key`: The key register index (8 bits).
+    /// - `value`: The value register index (8 bits).
+    DefineOwn { obj: u8, key: u8, value: u8 },
+
+    /// Defines the getter of an accessor property, keeping its setter if
+    /// it already has one.
+    ///
+    /// # Parameters
+    /// - `obj`: The object register index (8 bits).
+    /// - `key`: The key register index (8 bits).
+    /// - `value`: The getter function register index (8 bits).
+    DefineGetter { obj: u8, key: u8, value: u8 },
+
+    /// Defines the setter of an accessor property, keeping its getter if
+    /// it already has one.
+    ///
+    /// # Parameters
+    /// - `obj`: The object register index (8 bits).
+    /// - `key`: The key register index (8 bits).
+    /// - `value`: The setter function register index (8 bits).
+    DefineSetter { obj: u8, key: u8, value: u8 },
+
+    /// Creates a private name such as `#count`, distinct on every
+    /// evaluation of the class declaring it. Used as a property key, it
+    /// reads and writes private members, which only objects the class
+    /// initialized have.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `name_idx`: The name index, including the `#` (32 bits).
+    CreatePrivateName { dst: u8, name_idx: u32 },
+
+    /// Copies the own enumerable properties of an object into a new
+    /// object, leaving out the keys an object pattern has already taken,
+    /// for a rest element such as `...rest` in `{ a, ...rest }`.
+    ///
+    /// # Parameters
+    /// - `dst`: The destination register index (8 bits).
+    /// - `src`: The source object register index (8 bits).
+    /// - `excluded`: The first of the registers holding the keys to leave
+    ///   out (8 bits).
+    /// - `count`: The number of keys to leave out (8 bits).
+    CopyRest {
+        dst: u8,
+        src: u8,
+        excluded: u8,
+        count: u8,
+    },
 }
@@ -0,0 +1,351 @@
+//! Syntax tree produced by the parser.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub body: Vec<Stmt>,
+}
+
+/// An ES module, whose body may also hold `import` and `export`
+/// declarations.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub body: Vec<Stmt>,
+    /// Whether `await` is used outside any function, so that evaluating the
+    /// module may have to wait
+    pub has_top_level_await: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    Var,
+    Let,
+    Const,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct VarDecl {
+    pub target: Pattern,
+    pub init: Option<Expr>,
+}
+
+/// Where a declaration or assignment stores its value, taking the value
+/// apart if it is an array or object pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pattern {
+    Ident(String),
+    /// A property or element, which only assignments may target
+    Expr(Box<Expr>),
+    /// `[a, , b = 1, ...rest]`, with `None` for each elided element
+    Array {
+        elements: Vec<Option<PatternElement>>,
+        rest: Option<Box<Pattern>>,
+    },
+    /// `{ a, b: c, [key]: d = 1, ...rest }`
+    Object {
+        properties: Vec<(PropertyKey, PatternElement)>,
+        rest: Option<Box<Pattern>>,
+    },
+}
+
+/// An element of an array or object pattern, with the default used when
+/// the value is undefined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternElement {
+    pub target: Pattern,
+    pub default: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: Option<String>,
+    pub params: Vec<String>,
+    pub body: Vec<Stmt>,
+    pub is_arrow: bool,
+    /// Declared with `function*`
+    pub is_generator: bool,
+    /// Declared with `async`
+    pub is_async: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stmt {
+    Var {
+        kind: VarKind,
+        decls: Vec<VarDecl>,
+    },
+    Function(Function),
+    Return(Option<Expr>),
+    If {
+        test: Expr,
+        consequent: Box<Stmt>,
+        alternate: Option<Box<Stmt>>,
+    },
+    While {
+        test: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        test: Expr,
+    },
+    For {
+        init: Option<Box<Stmt>>,
+        test: Option<Expr>,
+        update: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    /// `for (target of iterable)`, with `kind` set when the target is
+    /// declared in the loop head, or `for await` when `is_await` is set
+    ForOf {
+        kind: Option<VarKind>,
+        target: Pattern,
+        iterable: Expr,
+        body: Box<Stmt>,
+        is_await: bool,
+    },
+    /// `for (target in object)`, with `kind` set as for `ForOf`
+    ForIn {
+        kind: Option<VarKind>,
+        target: Pattern,
+        object: Expr,
+        body: Box<Stmt>,
+    },
+    Break,
+    Continue,
+    Throw(Expr),
+    Try {
+        block: Vec<Stmt>,
+        handler: Option<CatchClause>,
+        finalizer: Option<Vec<Stmt>>,
+    },
+    Block(Vec<Stmt>),
+    Expr(Expr),
+    Empty,
+    Class(Class),
+    /// Only at the top level of a module
+    Import(ImportDecl),
+    /// Only at the top level of a module
+    Export(ExportDecl),
+}
+
+/// A class declaration or expression. Only declarations must be named.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Class {
+    pub name: Option<String>,
+    /// The class extended, as in `class A extends B`
+    pub extends: Option<Box<Expr>>,
+    /// Absent when the class has no constructor of its own
+    pub constructor: Option<Function>,
+    pub members: Vec<ClassMember>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassMember {
+    pub key: ClassKey,
+    pub is_static: bool,
+    pub kind: ClassMemberKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassKey {
+    Public(PropertyKey),
+    /// A private name such as `#count`, including the `#`
+    Private(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClassMemberKind {
+    Method(Function),
+    Getter(Function),
+    Setter(Function),
+    /// A field, with the initializer run for each instance, or once for a
+    /// static field
+    Field(Option<Expr>),
+}
+
+/// `import ... from "source"`, or `import "source"` with no specifiers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportDecl {
+    pub specifiers: Vec<ImportSpecifier>,
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportSpecifier {
+    /// `import local from ...`
+    Default(String),
+    /// `import * as local from ...`
+    Namespace(String),
+    /// `import { imported as local } from ...`
+    Named { imported: String, local: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportDecl {
+    /// `export var ...`, `export function f() {}` and the like
+    Declaration(Box<Stmt>),
+    /// `export default function () {}`, where an anonymous function is
+    /// named `default`
+    DefaultFunction(Function),
+    /// `export default expr`
+    DefaultExpr(Expr),
+    /// `export { local as exported }`, re-exporting from `source` if given
+    Named {
+        specifiers: Vec<ExportSpecifier>,
+        source: Option<String>,
+    },
+    /// `export * from "source"`, or `export * as exported from "source"`
+    All {
+        exported: Option<String>,
+        source: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSpecifier {
+    pub local: String,
+    pub exported: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatchClause {
+    /// Name bound to the exception, absent for `catch { ... }`
+    pub param: Option<String>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    TypeOf,
+    Void,
+    Delete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+    Eq,
+    NotEq,
+    StrictEq,
+    StrictNotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    InstanceOf,
+    In,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogicalOp {
+    And,
+    Or,
+    Nullish,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOp {
+    Increment,
+    Decrement,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PropertyKey {
+    Named(String),
+    Computed(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    /// String literal as UTF-16 code units, since escapes may produce lone
+    /// surrogates
+    String(Vec<u16>),
+    Bool(bool),
+    Null,
+    Ident(String),
+    /// Array literal, with `None` for each elided element as in `[1, , 3]`
+    Array(Vec<Option<Expr>>),
+    Object(Vec<(PropertyKey, Expr)>),
+    Function(Box<Function>),
+    Unary {
+        op: UnaryOp,
+        arg: Box<Expr>,
+    },
+    Binary {
+        op: BinaryOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    Logical {
+        op: LogicalOp,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// Assignment, with `op` set for compound forms such as `+=`
+    Assign {
+        op: Option<BinaryOp>,
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    /// Destructuring assignment such as `[a, b] = [b, a]`
+    AssignPattern {
+        target: Box<Pattern>,
+        value: Box<Expr>,
+    },
+    Update {
+        op: UpdateOp,
+        prefix: bool,
+        target: Box<Expr>,
+    },
+    Conditional {
+        test: Box<Expr>,
+        consequent: Box<Expr>,
+        alternate: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    Member {
+        object: Box<Expr>,
+        property: String,
+    },
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `object.#name`, with the `#` included in `name`
+    PrivateMember {
+        object: Box<Expr>,
+        name: String,
+    },
+    /// A private name on its own, only as the left operand of `in`
+    PrivateName(String),
+    This,
+    New {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+    },
+    /// `super(...args)` in a derived class constructor
+    SuperCall(Vec<Expr>),
+    /// `super.name` or `super[key]` in a class member
+    SuperProperty(Box<PropertyKey>),
+    Class(Box<Class>),
+    Sequence(Vec<Expr>),
+    /// `...items` in an array literal or argument list
+    Spread(Box<Expr>),
+    /// `yield argument`, or `yield* argument` when `delegate` is set
+    Yield {
+        argument: Option<Box<Expr>>,
+        delegate: bool,
+    },
+    Await(Box<Expr>),
+}
@@ -0,0 +1,331 @@
+//! Splits source text into tokens.
+
+use crate::ParseError;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenKind {
+    Ident(String),
+    Number(f64),
+    String(Vec<u16>),
+    Punct(&'static str),
+    Eof,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub line: usize,
+    pub column: usize,
+    /// True if a line terminator separates this token from the previous one,
+    /// which drives automatic semicolon insertion
+    pub newline_before: bool,
+}
+
+/// Punctuators, longest first so the lexer can match greedily.
+const PUNCTUATORS: &[&str] = &[
+    ">>>=", "...", "===", "!==", "**=", "<<=", ">>=", ">>>", "&&=", "||=", "??=", "=>", "==", "!=",
+    "<=", ">=", "&&", "||", "??", "?.", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+    "**", "<<", ">>", "{", "}", "(", ")", "[", "]", ";", ",", "<", ">", "+", "-", "*", "/", "%",
+    "&", "|", "^", "!", "~", "?", ":", "=", ".", "#", "@",
+];
+
+pub fn tokenize(source: &str) -> Result<Vec<Token>, ParseError> {
+    Lexer {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+    }
+    .run()
+}
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    fn run(mut self) -> Result<Vec<Token>, ParseError> {
+        let mut tokens = Vec::new();
+        loop {
+            let newline_before = self.skip_trivia()?;
+            let (line, column) = (self.line, self.column);
+            let kind = match self.peek() {
+                None => TokenKind::Eof,
+                Some(c) if is_ident_start(c) => self.ident(),
+                Some(c) if c.is_ascii_digit() => self.number()?,
+                Some('.') if self.peek_at(1).is_some_and(|c| c.is_ascii_digit()) => {
+                    self.number()?
+                }
+                Some(q @ ('"' | '\'')) => self.string(q)?,
+                Some(_) => self.punct()?,
+            };
+            let eof = kind == TokenKind::Eof;
+            tokens.push(Token {
+                kind,
+                line,
+                column,
+                newline_before,
+            });
+            if eof {
+                return Ok(tokens);
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    /// Skips whitespace and comments, reporting whether a line terminator
+    /// was crossed.
+    fn skip_trivia(&mut self) -> Result<bool, ParseError> {
+        let mut newline = false;
+        loop {
+            match self.peek() {
+                Some(c) if is_line_terminator(c) => {
+                    newline = true;
+                    self.bump();
+                }
+                Some(c) if c.is_whitespace() || c == '\u{FEFF}' => {
+                    self.bump();
+                }
+                Some('/') if self.peek_at(1) == Some('/') => {
+                    while self.peek().is_some_and(|c| !is_line_terminator(c)) {
+                        self.bump();
+                    }
+                }
+                Some('/') if self.peek_at(1) == Some('*') => {
+                    self.bump();
+                    self.bump();
+                    loop {
+                        match self.bump() {
+                            Some('*') if self.peek() == Some('/') => {
+                                self.bump();
+                                break;
+                            }
+                            Some(c) if is_line_terminator(c) => newline = true,
+                            Some(_) => {}
+                            None => return Err(self.error("Unterminated comment")),
+                        }
+                    }
+                }
+                _ => return Ok(newline),
+            }
+        }
+    }
+
+    fn ident(&mut self) -> TokenKind {
+        let mut name = String::new();
+        while let Some(c) = self.peek().filter(|&c| is_ident_part(c)) {
+            name.push(c);
+            self.bump();
+        }
+        TokenKind::Ident(name)
+    }
+
+    fn number(&mut self) -> Result<TokenKind, ParseError> {
+        if self.peek() == Some('0') {
+            let radix = match self.peek_at(1) {
+                Some('x' | 'X') => Some(16),
+                Some('o' | 'O') => Some(8),
+                Some('b' | 'B') => Some(2),
+                _ => None,
+            };
+            if let Some(radix) = radix {
+                self.bump();
+                self.bump();
+                let mut value = 0.0f64;
+                let mut digits = 0;
+                while let Some(d) = self.peek().and_then(|c| c.to_digit(radix)) {
+                    value = value * radix as f64 + d as f64;
+                    digits += 1;
+                    self.bump();
+                }
+                if digits == 0 {
+                    return Err(self.error("Invalid number literal"));
+                }
+                return self.end_number(value);
+            }
+        }
+
+        let mut text = String::new();
+        while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+            text.push(c);
+            self.bump();
+        }
+        if self.peek() == Some('.') {
+            text.push('.');
+            self.bump();
+            while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+                text.push(c);
+                self.bump();
+            }
+        }
+        if let Some(e @ ('e' | 'E')) = self.peek() {
+            let sign = self.peek_at(1).filter(|&c| c == '+' || c == '-');
+            let digit_at = if sign.is_some() { 2 } else { 1 };
+            if self.peek_at(digit_at).is_some_and(|c| c.is_ascii_digit()) {
+                text.push(e);
+                self.bump();
+                if let Some(sign) = sign {
+                    text.push(sign);
+                    self.bump();
+                }
+                while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+                    text.push(c);
+                    self.bump();
+                }
+            }
+        }
+        let value = text
+            .parse::<f64>()
+            .map_err(|_| self.error("Invalid number literal"))?;
+        self.end_number(value)
+    }
+
+    fn end_number(&self, value: f64) -> Result<TokenKind, ParseError> {
+        if self.peek().is_some_and(is_ident_start) {
+            return Err(self.error("Identifier directly after number"));
+        }
+        Ok(TokenKind::Number(value))
+    }
+
+    fn string(&mut self, quote: char) -> Result<TokenKind, ParseError> {
+        self.bump();
+        let mut units = Vec::new();
+        loop {
+            match self.bump() {
+                None => return Err(self.error("Unterminated string literal")),
+                Some(c) if c == quote => return Ok(TokenKind::String(units)),
+                Some(c) if is_line_terminator(c) => {
+                    return Err(self.error("Unterminated string literal"))
+                }
+                Some('\\') => self.escape(&mut units)?,
+                Some(c) => {
+                    let mut buf = [0u16; 2];
+                    units.extend_from_slice(c.encode_utf16(&mut buf));
+                }
+            }
+        }
+    }
+
+    fn escape(&mut self, units: &mut Vec<u16>) -> Result<(), ParseError> {
+        let c = self
+            .bump()
+            .ok_or_else(|| self.error("Unterminated string literal"))?;
+        let unit = match c {
+            'n' => '\n' as u16,
+            't' => '\t' as u16,
+            'r' => '\r' as u16,
+            'b' => 0x08,
+            'f' => 0x0C,
+            'v' => 0x0B,
+            '0' if !self.peek().is_some_and(|c| c.is_ascii_digit()) => 0,
+            'x' => self.hex_digits(2)? as u16,
+            'u' => {
+                if self.peek() == Some('{') {
+                    self.bump();
+                    let mut code = 0u32;
+                    while let Some(d) = self.peek().and_then(|c| c.to_digit(16)) {
+                        code = code * 16 + d;
+                        if code > 0x10FFFF {
+                            return Err(self.error("Invalid Unicode escape"));
+                        }
+                        self.bump();
+                    }
+                    if self.bump() != Some('}') {
+                        return Err(self.error("Invalid Unicode escape"));
+                    }
+                    if code > 0xFFFF {
+                        let code = code - 0x10000;
+                        units.push(0xD800 + (code >> 10) as u16);
+                        units.push(0xDC00 + (code & 0x3FF) as u16);
+                        return Ok(());
+                    }
+                    code as u16
+                } else {
+                    self.hex_digits(4)? as u16
+                }
+            }
+            // Line continuation
+            c if is_line_terminator(c) => return Ok(()),
+            c => {
+                let mut buf = [0u16; 2];
+                units.extend_from_slice(c.encode_utf16(&mut buf));
+                return Ok(());
+            }
+        };
+        units.push(unit);
+        Ok(())
+    }
+
+    fn hex_digits(&mut self, count: usize) -> Result<u32, ParseError> {
+        let mut value = 0;
+        for _ in 0..count {
+            let d = self
+                .bump()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| self.error("Invalid hexadecimal escape"))?;
+            value = value * 16 + d;
+        }
+        Ok(value)
+    }
+
+    fn punct(&mut self) -> Result<TokenKind, ParseError> {
+        for p in PUNCTUATORS {
+            if p.chars()
+                .enumerate()
+                .all(|(i, c)| self.peek_at(i) == Some(c))
+            {
+                // `?.` followed by a digit is a conditional, as in `a?.5:b`
+                if *p == "?." && self.peek_at(2).is_some_and(|c| c.is_ascii_digit()) {
+                    continue;
+                }
+                for _ in 0..p.len() {
+                    self.bump();
+                }
+                return Ok(TokenKind::Punct(p));
+            }
+        }
+        Err(self.error(&format!("Unexpected character '{}'", self.peek().unwrap())))
+    }
+}
+
+fn is_line_terminator(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_ident_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$' || c == '\u{200C}' || c == '\u{200D}'
+}
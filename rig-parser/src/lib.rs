@@ -0,0 +1,25 @@
+//! Parser for the JavaScript subset accepted by rig.
+
+use std::fmt;
+
+pub mod ast;
+pub mod lexer;
+pub mod parser;
+
+pub use parser::{parse, parse_function_body, parse_module};
+
+/// A syntax error with the position where it was detected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}:{})", self.message, self.line, self.column)
+    }
+}
+
+impl std::error::Error for ParseError {}
@@ -1,3 +1,22 @@
+use std::io::Read;
+
+/// Parses the file named on the command line, or stdin, and prints the
+/// syntax tree.
 fn main() {
-    println!("Hello, world!");
+    let mut source = String::new();
+    let result = match std::env::args().nth(1) {
+        Some(path) => std::fs::read_to_string(path).map(|s| source = s),
+        None => std::io::stdin().read_to_string(&mut source).map(|_| ()),
+    };
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+    match rig_parser::parse(&source) {
+        Ok(program) => println!("{:#?}", program),
+        Err(err) => {
+            eprintln!("SyntaxError: {}", err);
+            std::process::exit(1);
+        }
+    }
 }
@@ -0,0 +1,2232 @@
+//! Recursive descent parser producing the syntax tree in [`crate::ast`].
+
+use crate::ast::*;
+use crate::lexer::{tokenize, Token, TokenKind};
+use crate::ParseError;
+
+const RESERVED_WORDS: &[&str] = &[
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "default",
+    "delete",
+    "do",
+    "else",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+];
+
+/// How deeply statements and expressions may nest. The parser and the
+/// compiler both recurse on the nesting, so deep input would otherwise
+/// overflow the native stack.
+const MAX_DEPTH: usize = 256;
+
+pub fn parse(source: &str) -> Result<Program, ParseError> {
+    let mut parser = Parser::new(source)?;
+    let mut body = Vec::new();
+    while !parser.at_eof() {
+        body.push(parser.statement()?);
+    }
+    Ok(Program { body })
+}
+
+/// Parses the body of a function, where `return` may appear at the top
+/// level.
+pub fn parse_function_body(source: &str) -> Result<Vec<Stmt>, ParseError> {
+    let mut parser = Parser::new(source)?;
+    parser.function_depth = 1;
+    let mut body = Vec::new();
+    while !parser.at_eof() {
+        body.push(parser.statement()?);
+    }
+    Ok(body)
+}
+
+/// Parses an ES module, where `import` and `export` may appear at the top
+/// level and `await` may be used outside functions.
+pub fn parse_module(source: &str) -> Result<Module, ParseError> {
+    let mut parser = Parser::new(source)?;
+    parser.in_async = true;
+    let mut body = Vec::new();
+    while !parser.at_eof() {
+        let stmt = if parser.is_keyword("import") {
+            parser.import_declaration()?
+        } else if parser.is_keyword("export") {
+            parser.export_declaration()?
+        } else {
+            parser.statement()?
+        };
+        body.push(stmt);
+    }
+    Ok(Module {
+        body,
+        has_top_level_await: parser.top_level_await,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Number of enclosing functions, used to reject `return` at top level
+    function_depth: usize,
+    /// Set while parsing the head of a `for` statement, where `in` starts a
+    /// `for-in` instead of being an operator
+    no_in: bool,
+    /// Set inside the body of a generator, where `yield` is an operator
+    in_generator: bool,
+    /// Set inside the body of an async function, where `await` is an
+    /// operator
+    in_async: bool,
+    /// Set once a module uses `await` outside any function
+    top_level_await: bool,
+    /// The private names of each class body being parsed, innermost last
+    classes: Vec<PrivateNames>,
+    /// Number of statements and expressions being parsed, one inside
+    /// another
+    depth: usize,
+}
+
+/// The private names a class body declares, and those it uses, which an
+/// enclosing class may declare instead.
+#[derive(Default)]
+struct PrivateNames {
+    declared: Vec<String>,
+    used: Vec<(Token, String)>,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, ParseError> {
+        Ok(Parser {
+            tokens: tokenize(source)?,
+            pos: 0,
+            function_depth: 0,
+            no_in: false,
+            in_generator: false,
+            in_async: false,
+            top_level_await: false,
+            classes: Vec::new(),
+            depth: 0,
+        })
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos]
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        let idx = (self.pos + offset).min(self.tokens.len() - 1);
+        &self.tokens[idx]
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn at_eof(&self) -> bool {
+        self.peek().kind == TokenKind::Eof
+    }
+
+    fn is_punct(&self, p: &str) -> bool {
+        matches!(self.peek().kind, TokenKind::Punct(q) if q == p)
+    }
+
+    fn is_keyword(&self, k: &str) -> bool {
+        matches!(&self.peek().kind, TokenKind::Ident(name) if name == k)
+    }
+
+    fn eat_punct(&mut self, p: &str) -> bool {
+        if self.is_punct(p) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat_keyword(&mut self, k: &str) -> bool {
+        if self.is_keyword(k) {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_punct(&mut self, p: &str) -> Result<(), ParseError> {
+        if self.eat_punct(p) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_keyword(&mut self, k: &str) -> Result<(), ParseError> {
+        if self.eat_keyword(k) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn error_at(&self, token: &Token, message: String) -> ParseError {
+        ParseError {
+            message,
+            line: token.line,
+            column: token.column,
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        let token = self.peek();
+        let message = match &token.kind {
+            TokenKind::Eof => "Unexpected end of input".to_string(),
+            TokenKind::Ident(name) => format!("Unexpected token '{}'", name),
+            TokenKind::Punct(p) => format!("Unexpected token '{}'", p),
+            TokenKind::Number(_) => "Unexpected number".to_string(),
+            TokenKind::String(_) => "Unexpected string".to_string(),
+        };
+        self.error_at(token, message)
+    }
+
+    /// Parses a binding name, rejecting reserved words.
+    fn identifier(&mut self) -> Result<String, ParseError> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) if !RESERVED_WORDS.contains(&name.as_str()) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Parses a property name after `.`, where reserved words are allowed.
+    fn property_name(&mut self) -> Result<String, ParseError> {
+        match &self.peek().kind {
+            TokenKind::Ident(name) => {
+                let name = name.clone();
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Consumes a statement terminator, applying automatic semicolon
+    /// insertion before `}`, at end of input, or after a line break.
+    /// Runs `parse` one level of nesting deeper, failing once the source
+    /// nests more than `MAX_DEPTH` levels.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error_at(self.peek(), "Too much nesting".to_string()));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn semicolon(&mut self) -> Result<(), ParseError> {
+        if self.eat_punct(";") || self.is_punct("}") || self.at_eof() || self.peek().newline_before
+        {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nested(Self::nested_statement)
+    }
+
+    fn nested_statement(&mut self) -> Result<Stmt, ParseError> {
+        if self.is_punct("{") {
+            return Ok(Stmt::Block(self.block()?));
+        }
+        if self.eat_punct(";") {
+            return Ok(Stmt::Empty);
+        }
+        let TokenKind::Ident(word) = &self.peek().kind else {
+            return self.expression_statement();
+        };
+        match word.as_str() {
+            "var" | "let" | "const" => {
+                let stmt = self.var_declaration()?;
+                self.semicolon()?;
+                Ok(stmt)
+            }
+            "function" => {
+                self.advance();
+                let is_generator = self.eat_punct("*");
+                let name = self.identifier()?;
+                Ok(Stmt::Function(self.function_rest(
+                    Some(name),
+                    is_generator,
+                    false,
+                )?))
+            }
+            "async" if self.is_async_function() => {
+                self.advance();
+                self.advance();
+                let is_generator = self.eat_punct("*");
+                let name = self.identifier()?;
+                Ok(Stmt::Function(self.function_rest(
+                    Some(name),
+                    is_generator,
+                    true,
+                )?))
+            }
+            "return" => {
+                let token = self.advance();
+                if self.function_depth == 0 {
+                    return Err(self.error_at(&token, "Illegal return statement".to_string()));
+                }
+                let arg = if self.is_punct(";")
+                    || self.is_punct("}")
+                    || self.at_eof()
+                    || self.peek().newline_before
+                {
+                    None
+                } else {
+                    Some(self.expression()?)
+                };
+                self.semicolon()?;
+                Ok(Stmt::Return(arg))
+            }
+            "if" => {
+                self.advance();
+                self.expect_punct("(")?;
+                let test = self.expression()?;
+                self.expect_punct(")")?;
+                let consequent = Box::new(self.statement()?);
+                let alternate = if self.eat_keyword("else") {
+                    Some(Box::new(self.statement()?))
+                } else {
+                    None
+                };
+                Ok(Stmt::If {
+                    test,
+                    consequent,
+                    alternate,
+                })
+            }
+            "while" => {
+                self.advance();
+                self.expect_punct("(")?;
+                let test = self.expression()?;
+                self.expect_punct(")")?;
+                let body = Box::new(self.statement()?);
+                Ok(Stmt::While { test, body })
+            }
+            "do" => {
+                self.advance();
+                let body = Box::new(self.statement()?);
+                if !self.eat_keyword("while") {
+                    return Err(self.unexpected());
+                }
+                self.expect_punct("(")?;
+                let test = self.expression()?;
+                self.expect_punct(")")?;
+                self.eat_punct(";");
+                Ok(Stmt::DoWhile { body, test })
+            }
+            "for" => self.for_statement(),
+            "break" => {
+                self.advance();
+                self.semicolon()?;
+                Ok(Stmt::Break)
+            }
+            "continue" => {
+                self.advance();
+                self.semicolon()?;
+                Ok(Stmt::Continue)
+            }
+            "throw" => {
+                let token = self.advance();
+                if self.peek().newline_before {
+                    return Err(self.error_at(&token, "Illegal newline after throw".to_string()));
+                }
+                let arg = self.expression()?;
+                self.semicolon()?;
+                Ok(Stmt::Throw(arg))
+            }
+            "try" => self.try_statement(),
+            "class" => Ok(Stmt::Class(self.class(true)?)),
+            _ => self.expression_statement(),
+        }
+    }
+
+    /// Parses an `import` declaration from the `import` keyword.
+    fn import_declaration(&mut self) -> Result<Stmt, ParseError> {
+        self.advance();
+        let mut specifiers = Vec::new();
+        if !matches!(self.peek().kind, TokenKind::String(_)) {
+            if matches!(self.peek().kind, TokenKind::Ident(_)) {
+                specifiers.push(ImportSpecifier::Default(self.identifier()?));
+            }
+            // A default import may be followed by a namespace or named ones
+            if specifiers.is_empty() || self.eat_punct(",") {
+                self.import_specifiers(&mut specifiers)?;
+            }
+            self.expect_keyword("from")?;
+        }
+        let source = self.module_specifier()?;
+        self.semicolon()?;
+        Ok(Stmt::Import(ImportDecl { specifiers, source }))
+    }
+
+    /// Parses `* as local` or `{ imported as local, ... }` in an import.
+    fn import_specifiers(
+        &mut self,
+        specifiers: &mut Vec<ImportSpecifier>,
+    ) -> Result<(), ParseError> {
+        if self.eat_punct("*") {
+            self.expect_keyword("as")?;
+            specifiers.push(ImportSpecifier::Namespace(self.identifier()?));
+            return Ok(());
+        }
+        self.expect_punct("{")?;
+        while !self.eat_punct("}") {
+            let token = self.peek().clone();
+            let imported = self.property_name()?;
+            let local = if self.eat_keyword("as") {
+                self.identifier()?
+            } else if RESERVED_WORDS.contains(&imported.as_str()) {
+                return Err(self.error_at(&token, format!("Unexpected token '{}'", imported)));
+            } else {
+                imported.clone()
+            };
+            specifiers.push(ImportSpecifier::Named { imported, local });
+            if !self.is_punct("}") {
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses an `export` declaration from the `export` keyword.
+    fn export_declaration(&mut self) -> Result<Stmt, ParseError> {
+        self.advance();
+        let export = if self.eat_keyword("default") {
+            if self.is_keyword("function") || self.is_async_function() {
+                let is_async = self.eat_keyword("async");
+                self.advance();
+                let is_generator = self.eat_punct("*");
+                let name = if self.is_punct("(") {
+                    "default".to_string()
+                } else {
+                    self.identifier()?
+                };
+                ExportDecl::DefaultFunction(self.function_rest(
+                    Some(name),
+                    is_generator,
+                    is_async,
+                )?)
+            } else {
+                let expr = self.assignment()?;
+                self.semicolon()?;
+                ExportDecl::DefaultExpr(expr)
+            }
+        } else if self.eat_punct("*") {
+            let exported = if self.eat_keyword("as") {
+                Some(self.property_name()?)
+            } else {
+                None
+            };
+            self.expect_keyword("from")?;
+            let source = self.module_specifier()?;
+            self.semicolon()?;
+            ExportDecl::All { exported, source }
+        } else if self.eat_punct("{") {
+            let mut specifiers = Vec::new();
+            let mut reserved = None;
+            while !self.eat_punct("}") {
+                let token = self.peek().clone();
+                let local = self.property_name()?;
+                if RESERVED_WORDS.contains(&local.as_str()) {
+                    reserved.get_or_insert((token, local.clone()));
+                }
+                let exported = if self.eat_keyword("as") {
+                    self.property_name()?
+                } else {
+                    local.clone()
+                };
+                specifiers.push(ExportSpecifier { local, exported });
+                if !self.is_punct("}") {
+                    self.expect_punct(",")?;
+                }
+            }
+            let source = if self.eat_keyword("from") {
+                Some(self.module_specifier()?)
+            } else {
+                None
+            };
+            // Reserved words can only name exports of another module
+            if let (None, Some((token, name))) = (&source, reserved) {
+                return Err(self.error_at(&token, format!("Unexpected token '{}'", name)));
+            }
+            self.semicolon()?;
+            ExportDecl::Named { specifiers, source }
+        } else if ["var", "let", "const", "function", "class"]
+            .iter()
+            .any(|k| self.is_keyword(k))
+            || self.is_async_function()
+        {
+            ExportDecl::Declaration(Box::new(self.statement()?))
+        } else {
+            return Err(self.unexpected());
+        };
+        Ok(Stmt::Export(export))
+    }
+
+    /// Parses the string naming the module an import or export is from.
+    fn module_specifier(&mut self) -> Result<String, ParseError> {
+        match &self.peek().kind {
+            TokenKind::String(units) => {
+                let specifier = String::from_utf16_lossy(units);
+                self.advance();
+                Ok(specifier)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.advance();
+        let block = self.block()?;
+        let handler = if self.eat_keyword("catch") {
+            let param = if self.eat_punct("(") {
+                let name = self.identifier()?;
+                self.expect_punct(")")?;
+                Some(name)
+            } else {
+                None
+            };
+            let body = self.block()?;
+            Some(CatchClause { param, body })
+        } else {
+            None
+        };
+        let finalizer = if self.eat_keyword("finally") {
+            Some(self.block()?)
+        } else {
+            None
+        };
+        if handler.is_none() && finalizer.is_none() {
+            return Err(self.error_at(
+                self.peek(),
+                "Missing catch or finally after try".to_string(),
+            ));
+        }
+        Ok(Stmt::Try {
+            block,
+            handler,
+            finalizer,
+        })
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+        self.semicolon()?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        self.expect_punct("{")?;
+        let mut body = Vec::new();
+        while !self.eat_punct("}") {
+            if self.at_eof() {
+                return Err(self.unexpected());
+            }
+            body.push(self.statement()?);
+        }
+        Ok(body)
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        let kind = match &self.advance().kind {
+            TokenKind::Ident(k) if k == "let" => VarKind::Let,
+            TokenKind::Ident(k) if k == "const" => VarKind::Const,
+            _ => VarKind::Var,
+        };
+        let mut decls = Vec::new();
+        loop {
+            let target = self.binding_pattern()?;
+            let init = if self.eat_punct("=") {
+                Some(self.assignment()?)
+            } else {
+                None
+            };
+            if init.is_none() && !matches!(target, Pattern::Ident(_)) {
+                return Err(self.error_at(
+                    self.peek(),
+                    "Missing initializer in destructuring declaration".to_string(),
+                ));
+            }
+            if init.is_none() && kind == VarKind::Const {
+                return Err(self.error_at(
+                    self.peek(),
+                    "Missing initializer in const declaration".to_string(),
+                ));
+            }
+            decls.push(VarDecl { target, init });
+            if !self.eat_punct(",") {
+                return Ok(Stmt::Var { kind, decls });
+            }
+        }
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        self.advance();
+        let is_await = self.in_async && self.eat_keyword("await");
+        if is_await && self.function_depth == 0 {
+            self.top_level_await = true;
+        }
+        self.expect_punct("(")?;
+        let init = if self.is_punct(";") {
+            None
+        } else if self.is_keyword("var") || self.is_keyword("let") || self.is_keyword("const") {
+            // Look past the first binding for `of` or `in` before parsing the
+            // head as a declaration
+            let start = self.pos;
+            let kind = match &self.advance().kind {
+                TokenKind::Ident(k) if k == "let" => VarKind::Let,
+                TokenKind::Ident(k) if k == "const" => VarKind::Const,
+                _ => VarKind::Var,
+            };
+            let target = self.binding_pattern()?;
+            if self.eat_keyword("of") {
+                return self.for_of_rest(Some(kind), target, is_await);
+            }
+            if is_await {
+                return Err(self.unexpected());
+            }
+            if self.eat_keyword("in") {
+                return self.for_in_rest(Some(kind), target);
+            }
+            self.pos = start;
+            Some(Box::new(self.var_declaration()?))
+        } else {
+            let start = self.peek().clone();
+            self.no_in = true;
+            let init = self.expression();
+            self.no_in = false;
+            let init = init?;
+            if self.eat_keyword("of") {
+                let target = self.to_pattern(init, &start)?;
+                return self.for_of_rest(None, target, is_await);
+            }
+            if is_await {
+                return Err(self.unexpected());
+            }
+            if self.eat_keyword("in") {
+                let target = self.to_pattern(init, &start)?;
+                return self.for_in_rest(None, target);
+            }
+            Some(Box::new(Stmt::Expr(init)))
+        };
+        if is_await {
+            return Err(self.unexpected());
+        }
+        self.expect_punct(";")?;
+        let test = if self.is_punct(";") {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect_punct(";")?;
+        let update = if self.is_punct(")") {
+            None
+        } else {
+            Some(self.expression()?)
+        };
+        self.expect_punct(")")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::For {
+            init,
+            test,
+            update,
+            body,
+        })
+    }
+
+    /// Parses the rest of a `for-of` or `for await` statement after `of`.
+    fn for_of_rest(
+        &mut self,
+        kind: Option<VarKind>,
+        target: Pattern,
+        is_await: bool,
+    ) -> Result<Stmt, ParseError> {
+        let iterable = self.assignment()?;
+        self.expect_punct(")")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForOf {
+            kind,
+            target,
+            iterable,
+            body,
+            is_await,
+        })
+    }
+
+    /// Parses the rest of a `for-in` statement after `in`.
+    fn for_in_rest(&mut self, kind: Option<VarKind>, target: Pattern) -> Result<Stmt, ParseError> {
+        let object = self.expression()?;
+        self.expect_punct(")")?;
+        let body = Box::new(self.statement()?);
+        Ok(Stmt::ForIn {
+            kind,
+            target,
+            object,
+            body,
+        })
+    }
+
+    /// Parses the target of a declaration: a name, or an array or object
+    /// pattern.
+    fn binding_pattern(&mut self) -> Result<Pattern, ParseError> {
+        if self.eat_punct("[") {
+            let mut elements = Vec::new();
+            let mut rest = None;
+            while !self.eat_punct("]") {
+                if self.eat_punct(",") {
+                    elements.push(None);
+                    continue;
+                }
+                if self.eat_punct("...") {
+                    rest = Some(Box::new(self.nested(Self::binding_pattern)?));
+                    self.expect_punct("]")?;
+                    break;
+                }
+                elements.push(Some(self.binding_element()?));
+                if !self.is_punct("]") {
+                    self.expect_punct(",")?;
+                }
+            }
+            return Ok(Pattern::Array { elements, rest });
+        }
+        if self.eat_punct("{") {
+            let mut properties = Vec::new();
+            let mut rest = None;
+            while !self.eat_punct("}") {
+                if self.eat_punct("...") {
+                    rest = Some(Box::new(Pattern::Ident(self.identifier()?)));
+                    self.expect_punct("}")?;
+                    break;
+                }
+                let key = self.property_key()?;
+                let element = if self.eat_punct(":") {
+                    self.binding_element()?
+                } else {
+                    // Shorthand `{ name }` or `{ name = default }`
+                    let target = match &key {
+                        PropertyKey::Named(name) if !RESERVED_WORDS.contains(&name.as_str()) => {
+                            Pattern::Ident(name.clone())
+                        }
+                        _ => return Err(self.unexpected()),
+                    };
+                    let default = if self.eat_punct("=") {
+                        Some(self.assignment()?)
+                    } else {
+                        None
+                    };
+                    PatternElement { target, default }
+                };
+                properties.push((key, element));
+                if !self.is_punct("}") {
+                    self.expect_punct(",")?;
+                }
+            }
+            return Ok(Pattern::Object { properties, rest });
+        }
+        Ok(Pattern::Ident(self.identifier()?))
+    }
+
+    fn binding_element(&mut self) -> Result<PatternElement, ParseError> {
+        let target = self.nested(Self::binding_pattern)?;
+        let default = if self.eat_punct("=") {
+            Some(self.assignment()?)
+        } else {
+            None
+        };
+        Ok(PatternElement { target, default })
+    }
+
+    /// Reinterprets an array or object literal, already parsed as an
+    /// expression, as the pattern on the left of an assignment.
+    fn to_pattern(&self, expr: Expr, start: &Token) -> Result<Pattern, ParseError> {
+        let invalid =
+            || self.error_at(start, "Invalid destructuring assignment target".to_string());
+        match expr {
+            Expr::Ident(name) => Ok(Pattern::Ident(name)),
+            Expr::Member { .. } | Expr::Index { .. } => Ok(Pattern::Expr(Box::new(expr))),
+            Expr::Array(items) => {
+                let count = items.len();
+                let mut elements = Vec::new();
+                let mut rest = None;
+                for (i, item) in items.into_iter().enumerate() {
+                    match item {
+                        Some(Expr::Spread(arg)) if i + 1 == count => {
+                            rest = Some(Box::new(self.to_pattern(*arg, start)?));
+                        }
+                        Some(Expr::Spread(_)) => {
+                            return Err(self
+                                .error_at(start, "Rest element must be last element".to_string()))
+                        }
+                        Some(item) => elements.push(Some(self.to_pattern_element(item, start)?)),
+                        None => elements.push(None),
+                    }
+                }
+                Ok(Pattern::Array { elements, rest })
+            }
+            Expr::Object(properties) => properties
+                .into_iter()
+                .map(|(key, value)| Ok((key, self.to_pattern_element(value, start)?)))
+                .collect::<Result<_, _>>()
+                .map(|properties| Pattern::Object {
+                    properties,
+                    rest: None,
+                }),
+            _ => Err(invalid()),
+        }
+    }
+
+    fn to_pattern_element(&self, expr: Expr, start: &Token) -> Result<PatternElement, ParseError> {
+        Ok(match expr {
+            Expr::Assign {
+                op: None,
+                target,
+                value,
+            } => PatternElement {
+                target: self.to_pattern(*target, start)?,
+                default: Some(*value),
+            },
+            Expr::AssignPattern { target, value } => PatternElement {
+                target: *target,
+                default: Some(*value),
+            },
+            expr => PatternElement {
+                target: self.to_pattern(expr, start)?,
+                default: None,
+            },
+        })
+    }
+
+    /// Parses the parameter list and body of a function.
+    fn function_rest(
+        &mut self,
+        name: Option<String>,
+        is_generator: bool,
+        is_async: bool,
+    ) -> Result<Function, ParseError> {
+        self.expect_punct("(")?;
+        let mut params = Vec::new();
+        while !self.eat_punct(")") {
+            params.push(self.identifier()?);
+            if !self.is_punct(")") {
+                self.expect_punct(",")?;
+            }
+        }
+        self.function_depth += 1;
+        let in_generator = std::mem::replace(&mut self.in_generator, is_generator);
+        let in_async = std::mem::replace(&mut self.in_async, is_async);
+        let body = self.block();
+        self.in_generator = in_generator;
+        self.in_async = in_async;
+        self.function_depth -= 1;
+        Ok(Function {
+            name,
+            params,
+            body: body?,
+            is_arrow: false,
+            is_generator,
+            is_async,
+        })
+    }
+
+    fn arrow_function(&mut self, params: Vec<String>, is_async: bool) -> Result<Expr, ParseError> {
+        self.expect_punct("=>")?;
+        self.function_depth += 1;
+        let in_generator = std::mem::replace(&mut self.in_generator, false);
+        let in_async = std::mem::replace(&mut self.in_async, is_async);
+        let body = if self.is_punct("{") {
+            self.block()
+        } else {
+            self.assignment().map(|expr| vec![Stmt::Return(Some(expr))])
+        };
+        self.in_generator = in_generator;
+        self.in_async = in_async;
+        self.function_depth -= 1;
+        Ok(Expr::Function(Box::new(Function {
+            name: None,
+            params,
+            body: body?,
+            is_arrow: true,
+            is_generator: false,
+            is_async,
+        })))
+    }
+
+    /// Whether the next tokens are `async function`, with no line break in
+    /// between.
+    fn is_async_function(&self) -> bool {
+        self.is_keyword("async")
+            && matches!(&self.peek_at(1).kind, TokenKind::Ident(k) if k == "function")
+            && !self.peek_at(1).newline_before
+    }
+
+    /// Looks past a parenthesized list starting `start` tokens ahead to see
+    /// whether it is followed by `=>`.
+    fn is_arrow_params(&self, start: usize) -> bool {
+        let mut depth = 0;
+        let mut offset = start;
+        loop {
+            match &self.peek_at(offset).kind {
+                TokenKind::Punct("(") => depth += 1,
+                TokenKind::Punct(")") => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return matches!(self.peek_at(offset + 1).kind, TokenKind::Punct("=>"));
+                    }
+                }
+                TokenKind::Eof => return false,
+                _ => {}
+            }
+            offset += 1;
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, ParseError> {
+        let first = self.assignment()?;
+        if !self.is_punct(",") {
+            return Ok(first);
+        }
+        let mut exprs = vec![first];
+        while self.eat_punct(",") {
+            exprs.push(self.assignment()?);
+        }
+        Ok(Expr::Sequence(exprs))
+    }
+
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        self.nested(Self::nested_assignment)
+    }
+
+    fn nested_assignment(&mut self) -> Result<Expr, ParseError> {
+        if self.in_generator && self.is_keyword("yield") {
+            return self.yield_expression();
+        }
+        // `async x => ...` and `async (x) => ...`
+        let is_async = self.is_keyword("async") && !self.peek_at(1).newline_before && {
+            match &self.peek_at(1).kind {
+                TokenKind::Ident(name) => {
+                    matches!(self.peek_at(2).kind, TokenKind::Punct("=>"))
+                        && !RESERVED_WORDS.contains(&name.as_str())
+                }
+                TokenKind::Punct("(") => self.is_arrow_params(1),
+                _ => false,
+            }
+        };
+        if is_async {
+            self.advance();
+        }
+        if let TokenKind::Ident(name) = &self.peek().kind {
+            if matches!(self.peek_at(1).kind, TokenKind::Punct("=>"))
+                && !RESERVED_WORDS.contains(&name.as_str())
+            {
+                let name = name.clone();
+                self.advance();
+                return self.arrow_function(vec![name], is_async);
+            }
+        }
+        if self.is_punct("(") && self.is_arrow_params(0) {
+            self.advance();
+            let mut params = Vec::new();
+            while !self.eat_punct(")") {
+                params.push(self.identifier()?);
+                if !self.is_punct(")") {
+                    self.expect_punct(",")?;
+                }
+            }
+            return self.arrow_function(params, is_async);
+        }
+
+        let start = self.peek().clone();
+        let target = self.conditional()?;
+        if matches!(target, Expr::Array(_) | Expr::Object(_)) && self.is_punct("=") {
+            self.advance();
+            let target = self.to_pattern(target, &start)?;
+            let value = self.assignment()?;
+            return Ok(Expr::AssignPattern {
+                target: Box::new(target),
+                value: Box::new(value),
+            });
+        }
+        let op = match self.peek().kind {
+            TokenKind::Punct("=") => None,
+            TokenKind::Punct("+=") => Some(BinaryOp::Add),
+            TokenKind::Punct("-=") => Some(BinaryOp::Sub),
+            TokenKind::Punct("*=") => Some(BinaryOp::Mul),
+            TokenKind::Punct("/=") => Some(BinaryOp::Div),
+            TokenKind::Punct("%=") => Some(BinaryOp::Mod),
+            TokenKind::Punct("**=") => Some(BinaryOp::Pow),
+            _ => return Ok(target),
+        };
+        if !is_assignment_target(&target) {
+            return Err(self.error_at(&start, "Invalid assignment target".to_string()));
+        }
+        self.advance();
+        let value = self.assignment()?;
+        Ok(Expr::Assign {
+            op,
+            target: Box::new(target),
+            value: Box::new(value),
+        })
+    }
+
+    /// Parses `yield`, whose argument is optional, or `yield*`.
+    fn yield_expression(&mut self) -> Result<Expr, ParseError> {
+        self.advance();
+        if self.peek().newline_before {
+            return Ok(Expr::Yield {
+                argument: None,
+                delegate: false,
+            });
+        }
+        let delegate = self.eat_punct("*");
+        let ends = [")", "]", "}", ",", ";", ":"];
+        let argument = if !delegate && (self.at_eof() || ends.iter().any(|p| self.is_punct(p))) {
+            None
+        } else {
+            Some(Box::new(self.assignment()?))
+        };
+        Ok(Expr::Yield { argument, delegate })
+    }
+
+    fn conditional(&mut self) -> Result<Expr, ParseError> {
+        let test = self.binary(0)?;
+        if !self.eat_punct("?") {
+            return Ok(test);
+        }
+        let consequent = self.assignment()?;
+        self.expect_punct(":")?;
+        let alternate = self.assignment()?;
+        Ok(Expr::Conditional {
+            test: Box::new(test),
+            consequent: Box::new(consequent),
+            alternate: Box::new(alternate),
+        })
+    }
+
+    /// Precedence climbing over the binary and logical operators.
+    fn binary(&mut self, min_prec: u8) -> Result<Expr, ParseError> {
+        let mut left = self.unary()?;
+        loop {
+            let Some((prec, op)) = binary_operator(&self.peek().kind) else {
+                return Ok(left);
+            };
+            if prec < min_prec || (self.no_in && matches!(op, Operator::Binary(BinaryOp::In))) {
+                return Ok(left);
+            }
+            self.advance();
+            // `**` is the only right-associative binary operator
+            let right_assoc = matches!(op, Operator::Binary(BinaryOp::Pow));
+            let next_prec = if right_assoc { prec } else { prec + 1 };
+            let right = self.nested(|parser| parser.binary(next_prec))?;
+            left = match op {
+                Operator::Binary(op) => Expr::Binary {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+                Operator::Logical(op) => Expr::Logical {
+                    op,
+                    left: Box::new(left),
+                    right: Box::new(right),
+                },
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let op = match &self.peek().kind {
+            TokenKind::Punct("-") => Some(UnaryOp::Neg),
+            TokenKind::Punct("!") => Some(UnaryOp::Not),
+            TokenKind::Ident(k) if k == "typeof" => Some(UnaryOp::TypeOf),
+            TokenKind::Ident(k) if k == "void" => Some(UnaryOp::Void),
+            TokenKind::Ident(k) if k == "delete" => Some(UnaryOp::Delete),
+            _ => None,
+        };
+        if let Some(op) = op {
+            let token = self.advance();
+            let arg = self.nested(Self::unary)?;
+            if op == UnaryOp::Delete && matches!(arg, Expr::PrivateMember { .. }) {
+                return Err(self.error_at(&token, "Private fields can not be deleted".to_string()));
+            }
+            return Ok(Expr::Unary {
+                op,
+                arg: Box::new(arg),
+            });
+        }
+        if self.in_async && self.eat_keyword("await") {
+            if self.function_depth == 0 {
+                self.top_level_await = true;
+            }
+            let arg = self.nested(Self::unary)?;
+            return Ok(Expr::Await(Box::new(arg)));
+        }
+        let update = match self.peek().kind {
+            TokenKind::Punct("++") => Some(UpdateOp::Increment),
+            TokenKind::Punct("--") => Some(UpdateOp::Decrement),
+            _ => None,
+        };
+        if let Some(op) = update {
+            let token = self.advance();
+            let target = self.nested(Self::unary)?;
+            if !is_assignment_target(&target) {
+                return Err(self.error_at(&token, "Invalid update target".to_string()));
+            }
+            return Ok(Expr::Update {
+                op,
+                prefix: true,
+                target: Box::new(target),
+            });
+        }
+        self.postfix()
+    }
+
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.peek().clone();
+        let expr = self.call_member()?;
+        if self.peek().newline_before {
+            return Ok(expr);
+        }
+        let op = match self.peek().kind {
+            TokenKind::Punct("++") => UpdateOp::Increment,
+            TokenKind::Punct("--") => UpdateOp::Decrement,
+            _ => return Ok(expr),
+        };
+        if !is_assignment_target(&expr) {
+            return Err(self.error_at(&start, "Invalid update target".to_string()));
+        }
+        self.advance();
+        Ok(Expr::Update {
+            op,
+            prefix: false,
+            target: Box::new(expr),
+        })
+    }
+
+    fn call_member(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = if self.is_keyword("new") {
+            self.new_expression()?
+        } else {
+            self.primary()?
+        };
+        loop {
+            if self.eat_punct(".") {
+                expr = self.member_rest(expr)?;
+            } else if self.eat_punct("[") {
+                let index = self.expression()?;
+                self.expect_punct("]")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                };
+            } else if self.eat_punct("(") {
+                let args = self.arguments()?;
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    args,
+                };
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Parses `new callee(args)` from the `new` keyword, where the callee
+    /// may be a member but not a call, and the arguments may be left out.
+    fn new_expression(&mut self) -> Result<Expr, ParseError> {
+        self.advance();
+        let mut callee = if self.is_keyword("new") {
+            self.nested(Self::new_expression)?
+        } else {
+            self.primary()?
+        };
+        loop {
+            if self.eat_punct(".") {
+                callee = self.member_rest(callee)?;
+            } else if self.eat_punct("[") {
+                let index = self.expression()?;
+                self.expect_punct("]")?;
+                callee = Expr::Index {
+                    object: Box::new(callee),
+                    index: Box::new(index),
+                };
+            } else {
+                break;
+            }
+        }
+        let args = if self.eat_punct("(") {
+            self.arguments()?
+        } else {
+            Vec::new()
+        };
+        Ok(Expr::New {
+            callee: Box::new(callee),
+            args,
+        })
+    }
+
+    /// Parses the property after `.`, which may be a private name.
+    fn member_rest(&mut self, object: Expr) -> Result<Expr, ParseError> {
+        if self.is_punct("#") {
+            let name = self.private_name()?;
+            return Ok(Expr::PrivateMember {
+                object: Box::new(object),
+                name,
+            });
+        }
+        let property = self.property_name()?;
+        Ok(Expr::Member {
+            object: Box::new(object),
+            property,
+        })
+    }
+
+    /// Parses a private name such as `#count` used outside its declaration,
+    /// which some class around it must declare.
+    fn private_name(&mut self) -> Result<String, ParseError> {
+        let token = self.peek().clone();
+        let name = self.private_name_declaration()?;
+        match self.classes.last_mut() {
+            Some(class) => class.used.push((token, name.clone())),
+            None => return Err(undeclared_private_name(&token, &name)),
+        }
+        Ok(name)
+    }
+
+    /// Parses `#` and the name right after it.
+    fn private_name_declaration(&mut self) -> Result<String, ParseError> {
+        let hash = self.advance();
+        let token = self.peek();
+        match &token.kind {
+            TokenKind::Ident(name)
+                if token.line == hash.line && token.column == hash.column + 1 =>
+            {
+                let name = format!("#{}", name);
+                self.advance();
+                Ok(name)
+            }
+            _ => Err(self.error_at(&hash, "Invalid or unexpected token".to_string())),
+        }
+    }
+
+    /// Parses call arguments after the opening parenthesis.
+    fn arguments(&mut self) -> Result<Vec<Expr>, ParseError> {
+        let mut args = Vec::new();
+        while !self.eat_punct(")") {
+            args.push(self.element()?);
+            if !self.is_punct(")") {
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(args)
+    }
+
+    /// Parses an array element or call argument, which may be spread.
+    fn element(&mut self) -> Result<Expr, ParseError> {
+        if self.eat_punct("...") {
+            Ok(Expr::Spread(Box::new(self.assignment()?)))
+        } else {
+            self.assignment()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let token = self.peek().clone();
+        match token.kind {
+            TokenKind::Number(n) => {
+                self.advance();
+                Ok(Expr::Number(n))
+            }
+            TokenKind::String(s) => {
+                self.advance();
+                Ok(Expr::String(s))
+            }
+            TokenKind::Punct("(") => {
+                self.advance();
+                // `in` is an operator again inside parentheses
+                let no_in = std::mem::replace(&mut self.no_in, false);
+                let expr = self.expression();
+                self.no_in = no_in;
+                let expr = expr?;
+                self.expect_punct(")")?;
+                Ok(expr)
+            }
+            TokenKind::Punct("[") => {
+                self.advance();
+                let mut elements = Vec::new();
+                while !self.eat_punct("]") {
+                    if self.eat_punct(",") {
+                        elements.push(None);
+                        continue;
+                    }
+                    elements.push(Some(self.element()?));
+                    if !self.is_punct("]") {
+                        self.expect_punct(",")?;
+                    }
+                }
+                Ok(Expr::Array(elements))
+            }
+            TokenKind::Punct("{") => self.object_literal(),
+            TokenKind::Ident(name) => match name.as_str() {
+                "true" => {
+                    self.advance();
+                    Ok(Expr::Bool(true))
+                }
+                "false" => {
+                    self.advance();
+                    Ok(Expr::Bool(false))
+                }
+                "null" => {
+                    self.advance();
+                    Ok(Expr::Null)
+                }
+                "function" => self.function_expression(false),
+                "async" if self.is_async_function() => {
+                    self.advance();
+                    self.function_expression(true)
+                }
+                "this" => {
+                    self.advance();
+                    Ok(Expr::This)
+                }
+                "super" => self.super_expression(),
+                "class" => Ok(Expr::Class(Box::new(self.class(false)?))),
+                _ => Ok(Expr::Ident(self.identifier()?)),
+            },
+            // `#name in object` tests for a private member
+            TokenKind::Punct("#") => {
+                let name = self.private_name()?;
+                if !self.is_keyword("in") {
+                    return Err(self.unexpected());
+                }
+                Ok(Expr::PrivateName(name))
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Parses `super(args)`, `super.name` or `super[key]`. Whether `super`
+    /// may be used there is left to the compiler.
+    fn super_expression(&mut self) -> Result<Expr, ParseError> {
+        let token = self.advance();
+        if self.eat_punct("(") {
+            Ok(Expr::SuperCall(self.arguments()?))
+        } else if self.eat_punct(".") {
+            let name = self.property_name()?;
+            Ok(Expr::SuperProperty(Box::new(PropertyKey::Named(name))))
+        } else if self.eat_punct("[") {
+            let key = self.expression()?;
+            self.expect_punct("]")?;
+            Ok(Expr::SuperProperty(Box::new(PropertyKey::Computed(key))))
+        } else {
+            Err(self.error_at(&token, "'super' keyword unexpected here".to_string()))
+        }
+    }
+
+    /// Parses a class from the `class` keyword. Declarations must be named.
+    fn class(&mut self, is_declaration: bool) -> Result<Class, ParseError> {
+        self.advance();
+        let name = if is_declaration || !(self.is_keyword("extends") || self.is_punct("{")) {
+            Some(self.identifier()?)
+        } else {
+            None
+        };
+        let extends = if self.eat_keyword("extends") {
+            Some(Box::new(self.call_member()?))
+        } else {
+            None
+        };
+        self.expect_punct("{")?;
+        self.classes.push(PrivateNames::default());
+        let body = self.class_body(&name);
+        let names = self.classes.pop().unwrap_or_default();
+        let (constructor, members) = body?;
+        // Names this class does not declare must be declared around it
+        for (token, name) in names.used {
+            if names.declared.contains(&name) {
+                continue;
+            }
+            match self.classes.last_mut() {
+                Some(outer) => outer.used.push((token, name)),
+                None => return Err(undeclared_private_name(&token, &name)),
+            }
+        }
+        Ok(Class {
+            name,
+            extends,
+            constructor,
+            members,
+        })
+    }
+
+    /// Parses the members of a class after the opening brace, returning
+    /// its constructor apart from the others.
+    fn class_body(
+        &mut self,
+        class_name: &Option<String>,
+    ) -> Result<(Option<Function>, Vec<ClassMember>), ParseError> {
+        let mut constructor = None;
+        let mut members: Vec<ClassMember> = Vec::new();
+        while !self.eat_punct("}") {
+            if self.eat_punct(";") {
+                continue;
+            }
+            let is_static = self.is_modifier("static");
+            if is_static {
+                self.advance();
+            }
+            let is_async = self.is_modifier("async") && !self.peek_at(1).newline_before;
+            if is_async {
+                self.advance();
+            }
+            let is_generator = self.eat_punct("*");
+            let accessor = if is_async || is_generator {
+                None
+            } else if self.is_modifier("get") || self.is_modifier("set") {
+                Some(self.advance())
+            } else {
+                None
+            };
+            let token = self.peek().clone();
+            let key = if self.is_punct("#") {
+                ClassKey::Private(self.private_name_declaration()?)
+            } else {
+                ClassKey::Public(self.property_key()?)
+            };
+            let is_named = |name: &str| matches!(&key, ClassKey::Public(PropertyKey::Named(key)) if key == name);
+            let error = |message: &str| {
+                Err(ParseError {
+                    message: message.to_string(),
+                    line: token.line,
+                    column: token.column,
+                })
+            };
+            if is_static && is_named("prototype") {
+                return error("Classes may not have a static property named 'prototype'");
+            }
+            let kind = if is_async || is_generator || accessor.is_some() || self.is_punct("(") {
+                if !is_static && is_named("constructor") {
+                    if accessor.is_some() {
+                        return error("Class constructor may not be an accessor");
+                    } else if is_generator {
+                        return error("Class constructor may not be a generator");
+                    } else if is_async {
+                        return error("Class constructor may not be an async method");
+                    } else if constructor.is_some() {
+                        return error("A class may only have one constructor");
+                    }
+                    constructor = Some(self.function_rest(class_name.clone(), false, false)?);
+                    continue;
+                }
+                let name = match &key {
+                    ClassKey::Public(PropertyKey::Named(name)) | ClassKey::Private(name) => {
+                        Some(name.clone())
+                    }
+                    ClassKey::Public(PropertyKey::Computed(_)) => None,
+                };
+                let function = self.function_rest(name, is_generator, is_async)?;
+                match accessor.map(|token| token.kind) {
+                    Some(TokenKind::Ident(word)) if word == "get" => {
+                        ClassMemberKind::Getter(function)
+                    }
+                    Some(_) => ClassMemberKind::Setter(function),
+                    None => ClassMemberKind::Method(function),
+                }
+            } else {
+                if is_named("constructor") {
+                    return error("Classes may not have a field named 'constructor'");
+                }
+                let init = if self.eat_punct("=") {
+                    Some(self.field_initializer()?)
+                } else {
+                    None
+                };
+                self.semicolon()?;
+                ClassMemberKind::Field(init)
+            };
+            if let ClassKey::Private(name) = &key {
+                if name == "#constructor" {
+                    return error("Classes may not have a private field named '#constructor'");
+                }
+                // Only a getter and a setter may share a private name
+                let clash = members
+                    .iter()
+                    .filter(|member| member.key == key)
+                    .any(|member| {
+                        member.is_static != is_static
+                            || !matches!(
+                                (&member.kind, &kind),
+                                (ClassMemberKind::Getter(_), ClassMemberKind::Setter(_))
+                                    | (ClassMemberKind::Setter(_), ClassMemberKind::Getter(_))
+                            )
+                    });
+                if clash {
+                    return error(&format!("Identifier '{}' has already been declared", name));
+                }
+                if let Some(class) = self.classes.last_mut() {
+                    class.declared.push(name.clone());
+                }
+            }
+            members.push(ClassMember {
+                key,
+                is_static,
+                kind,
+            });
+        }
+        Ok((constructor, members))
+    }
+
+    /// Whether the next word modifies the class member after it, rather than
+    /// being its name.
+    fn is_modifier(&self, word: &str) -> bool {
+        self.is_keyword(word)
+            && !matches!(
+                self.peek_at(1).kind,
+                TokenKind::Punct("(" | "=" | ";" | "}")
+            )
+    }
+
+    /// Parses the initializer of a class field, which runs like the body of
+    /// a method.
+    fn field_initializer(&mut self) -> Result<Expr, ParseError> {
+        self.function_depth += 1;
+        let in_generator = std::mem::replace(&mut self.in_generator, false);
+        let in_async = std::mem::replace(&mut self.in_async, false);
+        let init = self.assignment();
+        self.in_generator = in_generator;
+        self.in_async = in_async;
+        self.function_depth -= 1;
+        init
+    }
+
+    /// Parses a function expression from the `function` keyword.
+    fn function_expression(&mut self, is_async: bool) -> Result<Expr, ParseError> {
+        self.advance();
+        let is_generator = self.eat_punct("*");
+        let name = if self.is_punct("(") {
+            None
+        } else {
+            Some(self.identifier()?)
+        };
+        Ok(Expr::Function(Box::new(self.function_rest(
+            name,
+            is_generator,
+            is_async,
+        )?)))
+    }
+
+    fn object_literal(&mut self) -> Result<Expr, ParseError> {
+        self.expect_punct("{")?;
+        let mut properties = Vec::new();
+        while !self.eat_punct("}") {
+            // `async name() {}` is an async method, unless `async` is the
+            // name itself
+            let is_async = self.is_keyword("async")
+                && !self.peek_at(1).newline_before
+                && !matches!(
+                    self.peek_at(1).kind,
+                    TokenKind::Punct("(" | ":" | "," | "}" | "=")
+                );
+            if is_async {
+                self.advance();
+            }
+            // `*name() {}` is a generator method
+            let is_generator = self.eat_punct("*");
+            let key = self.property_key()?;
+            let value = if is_async || is_generator || self.is_punct("(") {
+                let name = match &key {
+                    PropertyKey::Named(name) => Some(name.clone()),
+                    PropertyKey::Computed(_) => None,
+                };
+                Expr::Function(Box::new(self.function_rest(
+                    name,
+                    is_generator,
+                    is_async,
+                )?))
+            } else if self.eat_punct(":") {
+                self.assignment()?
+            } else {
+                // Shorthand `{ name }`
+                match &key {
+                    PropertyKey::Named(name) if !RESERVED_WORDS.contains(&name.as_str()) => {
+                        Expr::Ident(name.clone())
+                    }
+                    _ => return Err(self.unexpected()),
+                }
+            };
+            properties.push((key, value));
+            if !self.is_punct("}") {
+                self.expect_punct(",")?;
+            }
+        }
+        Ok(Expr::Object(properties))
+    }
+
+    /// Parses the key of a property in an object literal or pattern.
+    fn property_key(&mut self) -> Result<PropertyKey, ParseError> {
+        let key = match &self.peek().kind {
+            TokenKind::Ident(name) => PropertyKey::Named(name.clone()),
+            TokenKind::String(units) => PropertyKey::Named(String::from_utf16_lossy(units)),
+            TokenKind::Number(n) => PropertyKey::Named(format!("{}", n)),
+            TokenKind::Punct("[") => {
+                self.advance();
+                let key = self.assignment()?;
+                self.expect_punct("]")?;
+                return Ok(PropertyKey::Computed(key));
+            }
+            _ => return Err(self.unexpected()),
+        };
+        self.advance();
+        Ok(key)
+    }
+}
+
+enum Operator {
+    Binary(BinaryOp),
+    Logical(LogicalOp),
+}
+
+fn binary_operator(kind: &TokenKind) -> Option<(u8, Operator)> {
+    use Operator::*;
+    Some(match kind {
+        TokenKind::Punct("??") => (1, Logical(LogicalOp::Nullish)),
+        TokenKind::Punct("||") => (2, Logical(LogicalOp::Or)),
+        TokenKind::Punct("&&") => (3, Logical(LogicalOp::And)),
+        TokenKind::Punct("==") => (7, Binary(BinaryOp::Eq)),
+        TokenKind::Punct("!=") => (7, Binary(BinaryOp::NotEq)),
+        TokenKind::Punct("===") => (7, Binary(BinaryOp::StrictEq)),
+        TokenKind::Punct("!==") => (7, Binary(BinaryOp::StrictNotEq)),
+        TokenKind::Punct("<") => (8, Binary(BinaryOp::Lt)),
+        TokenKind::Punct("<=") => (8, Binary(BinaryOp::Le)),
+        TokenKind::Punct(">") => (8, Binary(BinaryOp::Gt)),
+        TokenKind::Punct(">=") => (8, Binary(BinaryOp::Ge)),
+        TokenKind::Ident(k) if k == "instanceof" => (8, Binary(BinaryOp::InstanceOf)),
+        TokenKind::Ident(k) if k == "in" => (8, Binary(BinaryOp::In)),
+        TokenKind::Punct("+") => (10, Binary(BinaryOp::Add)),
+        TokenKind::Punct("-") => (10, Binary(BinaryOp::Sub)),
+        TokenKind::Punct("*") => (11, Binary(BinaryOp::Mul)),
+        TokenKind::Punct("/") => (11, Binary(BinaryOp::Div)),
+        TokenKind::Punct("%") => (11, Binary(BinaryOp::Mod)),
+        TokenKind::Punct("**") => (12, Binary(BinaryOp::Pow)),
+        _ => return None,
+    })
+}
+
+fn is_assignment_target(expr: &Expr) -> bool {
+    matches!(
+        expr,
+        Expr::Ident(_)
+            | Expr::Member { .. }
+            | Expr::Index { .. }
+            | Expr::PrivateMember { .. }
+            | Expr::SuperProperty(_)
+    )
+}
+
+fn undeclared_private_name(token: &Token, name: &str) -> ParseError {
+    ParseError {
+        message: format!(
+            "Private field '{}' must be declared in an enclosing class",
+            name
+        ),
+        line: token.line,
+        column: token.column,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ident(name: &str) -> Box<Expr> {
+        Box::new(Expr::Ident(name.to_string()))
+    }
+
+    #[test]
+    fn test_precedence() {
+        let program = parse("a + b * c ** d ** e;").unwrap();
+
+        let pow = Expr::Binary {
+            op: BinaryOp::Pow,
+            left: ident("c"),
+            right: Box::new(Expr::Binary {
+                op: BinaryOp::Pow,
+                left: ident("d"),
+                right: ident("e"),
+            }),
+        };
+        let expected = Expr::Binary {
+            op: BinaryOp::Add,
+            left: ident("a"),
+            right: Box::new(Expr::Binary {
+                op: BinaryOp::Mul,
+                left: ident("b"),
+                right: Box::new(pow),
+            }),
+        };
+        assert_eq!(program.body, vec![Stmt::Expr(expected)]);
+    }
+
+    #[test]
+    fn test_automatic_semicolon_insertion() {
+        let program = parse("let a = 1\nlet b = a\nfunction f() { return\n a }").unwrap();
+
+        assert_eq!(program.body.len(), 3);
+        let Stmt::Function(f) = &program.body[2] else {
+            panic!("expected function declaration");
+        };
+        assert_eq!(f.body, vec![Stmt::Return(None), Stmt::Expr(*ident("a"))]);
+    }
+
+    #[test]
+    fn test_arrow_functions() {
+        let program = parse("f = (a, b) => a; g = x => { return x };").unwrap();
+
+        let Stmt::Expr(Expr::Assign { value, .. }) = &program.body[0] else {
+            panic!("expected assignment");
+        };
+        let Expr::Function(f) = &**value else {
+            panic!("expected function");
+        };
+        assert!(f.is_arrow);
+        assert_eq!(f.params, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(f.body, vec![Stmt::Return(Some(*ident("a")))]);
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let program = parse(r#"'a\n\u{1F600}\uD800';"#).unwrap();
+
+        assert_eq!(
+            program.body,
+            vec![Stmt::Expr(Expr::String(vec![
+                'a' as u16,
+                '\n' as u16,
+                0xD83D,
+                0xDE00,
+                0xD800
+            ]))]
+        );
+    }
+
+    #[test]
+    fn test_error_position() {
+        let err = parse("let x = 1;\nlet = 2;").unwrap_err();
+
+        assert_eq!((err.line, err.column), (2, 5));
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        assert!(parse(&format!("{}1", "- ".repeat(200))).is_ok());
+        let err = parse(&format!("{}1", "- ".repeat(1000))).unwrap_err();
+        assert_eq!(err.message, "Too much nesting");
+        for source in [
+            format!("{}1", "a = ".repeat(1000)),
+            format!("{}1", "typeof ".repeat(1000)),
+            format!("{}1", "2 ** ".repeat(1000)),
+        ] {
+            assert!(parse(&source).is_err());
+        }
+    }
+
+    #[test]
+    fn test_try_statement() {
+        let program = parse("try { throw x } catch { } finally { y }").unwrap();
+
+        assert_eq!(
+            program.body,
+            vec![Stmt::Try {
+                block: vec![Stmt::Throw(*ident("x"))],
+                handler: Some(CatchClause {
+                    param: None,
+                    body: vec![]
+                }),
+                finalizer: Some(vec![Stmt::Expr(*ident("y"))]),
+            }]
+        );
+        assert!(parse("try { }").is_err());
+        assert!(parse("throw\nx").is_err());
+    }
+
+    #[test]
+    fn test_array_elision() {
+        let program = parse("[, 1, , ];").unwrap();
+
+        assert_eq!(
+            program.body,
+            vec![Stmt::Expr(Expr::Array(vec![
+                None,
+                Some(Expr::Number(1.0)),
+                None
+            ]))]
+        );
+        assert_eq!(
+            parse("[1, ];").unwrap().body,
+            vec![Stmt::Expr(Expr::Array(vec![Some(Expr::Number(1.0))]))]
+        );
+    }
+
+    #[test]
+    fn test_in_and_delete() {
+        let program = parse("delete a[0] in b;").unwrap();
+
+        let delete = Expr::Unary {
+            op: UnaryOp::Delete,
+            arg: Box::new(Expr::Index {
+                object: ident("a"),
+                index: Box::new(Expr::Number(0.0)),
+            }),
+        };
+        assert_eq!(
+            program.body,
+            vec![Stmt::Expr(Expr::Binary {
+                op: BinaryOp::In,
+                left: Box::new(delete),
+                right: ident("b"),
+            })]
+        );
+    }
+
+    #[test]
+    fn test_for_of_and_patterns() {
+        let program = parse("for (const [a, , b = 1, ...c] of d) ;").unwrap();
+
+        let target = Pattern::Array {
+            elements: vec![
+                Some(PatternElement {
+                    target: Pattern::Ident("a".to_string()),
+                    default: None,
+                }),
+                None,
+                Some(PatternElement {
+                    target: Pattern::Ident("b".to_string()),
+                    default: Some(Expr::Number(1.0)),
+                }),
+            ],
+            rest: Some(Box::new(Pattern::Ident("c".to_string()))),
+        };
+        assert_eq!(
+            program.body,
+            vec![Stmt::ForOf {
+                kind: Some(VarKind::Const),
+                target,
+                iterable: *ident("d"),
+                body: Box::new(Stmt::Empty),
+                is_await: false,
+            }]
+        );
+
+        let program = parse("({ a, b: [x.y] } = o);").unwrap();
+        let target = Pattern::Object {
+            properties: vec![
+                (
+                    PropertyKey::Named("a".to_string()),
+                    PatternElement {
+                        target: Pattern::Ident("a".to_string()),
+                        default: None,
+                    },
+                ),
+                (
+                    PropertyKey::Named("b".to_string()),
+                    PatternElement {
+                        target: Pattern::Array {
+                            elements: vec![Some(PatternElement {
+                                target: Pattern::Expr(Box::new(Expr::Member {
+                                    object: ident("x"),
+                                    property: "y".to_string(),
+                                })),
+                                default: None,
+                            })],
+                            rest: None,
+                        },
+                        default: None,
+                    },
+                ),
+            ],
+            rest: None,
+        };
+        assert_eq!(
+            program.body,
+            vec![Stmt::Expr(Expr::AssignPattern {
+                target: Box::new(target),
+                value: ident("o"),
+            })]
+        );
+        let program = parse("var { a, ...rest } = o;").unwrap();
+        let target = Pattern::Object {
+            properties: vec![(
+                PropertyKey::Named("a".to_string()),
+                PatternElement {
+                    target: Pattern::Ident("a".to_string()),
+                    default: None,
+                },
+            )],
+            rest: Some(Box::new(Pattern::Ident("rest".to_string()))),
+        };
+        assert_eq!(
+            program.body,
+            vec![Stmt::Var {
+                kind: VarKind::Var,
+                decls: vec![VarDecl {
+                    target,
+                    init: Some(*ident("o")),
+                }],
+            }]
+        );
+        assert!(parse("let [a];").is_err());
+        assert!(parse("const a;").is_err());
+        assert!(parse("[...a, b] = c;").is_err());
+        assert!(parse("var { ...a, b } = c;").is_err());
+        assert!(parse("var { ...[a] } = c;").is_err());
+        assert!(parse("[a + 1] = c;").is_err());
+    }
+
+    #[test]
+    fn test_spread() {
+        let program = parse("f(...a, [...b]);").unwrap();
+
+        assert_eq!(
+            program.body,
+            vec![Stmt::Expr(Expr::Call {
+                callee: ident("f"),
+                args: vec![
+                    Expr::Spread(ident("a")),
+                    Expr::Array(vec![Some(Expr::Spread(ident("b")))]),
+                ],
+            })]
+        );
+    }
+
+    #[test]
+    fn test_for_in() {
+        let program = parse("for (var k in o) ; for (x.y in ('a' in o)) ;").unwrap();
+
+        assert_eq!(
+            program.body,
+            vec![
+                Stmt::ForIn {
+                    kind: Some(VarKind::Var),
+                    target: Pattern::Ident("k".to_string()),
+                    object: *ident("o"),
+                    body: Box::new(Stmt::Empty),
+                },
+                Stmt::ForIn {
+                    kind: None,
+                    target: Pattern::Expr(Box::new(Expr::Member {
+                        object: ident("x"),
+                        property: "y".to_string(),
+                    })),
+                    object: Expr::Binary {
+                        op: BinaryOp::In,
+                        left: Box::new(Expr::String("a".encode_utf16().collect())),
+                        right: ident("o"),
+                    },
+                    body: Box::new(Stmt::Empty),
+                },
+            ]
+        );
+        assert!(parse("for (var i = ('a' in o) ? 0 : 1; i < 1; i++) ;").is_ok());
+    }
+
+    #[test]
+    fn test_generators() {
+        let program =
+            parse("function* g() { yield; yield* a; x = yield 1; } ({ *m() {} });").unwrap();
+
+        let Stmt::Function(f) = &program.body[0] else {
+            panic!("expected function");
+        };
+        assert!(f.is_generator);
+        assert_eq!(
+            f.body,
+            vec![
+                Stmt::Expr(Expr::Yield {
+                    argument: None,
+                    delegate: false,
+                }),
+                Stmt::Expr(Expr::Yield {
+                    argument: Some(ident("a")),
+                    delegate: true,
+                }),
+                Stmt::Expr(Expr::Assign {
+                    op: None,
+                    target: ident("x"),
+                    value: Box::new(Expr::Yield {
+                        argument: Some(Box::new(Expr::Number(1.0))),
+                        delegate: false,
+                    }),
+                }),
+            ]
+        );
+        let Stmt::Expr(Expr::Object(properties)) = &program.body[1] else {
+            panic!("expected object");
+        };
+        assert!(matches!(&properties[0].1, Expr::Function(f) if f.is_generator));
+        // Outside generators `yield` is an identifier
+        assert_eq!(
+            parse("yield;").unwrap().body,
+            vec![Stmt::Expr(*ident("yield"))]
+        );
+    }
+
+    #[test]
+    fn test_async() {
+        let program = parse(
+            "async function f() { await a; for await (x of y) ; } \
+             (async x => x, async () => await b, async function* () {}, { async m() {}, async: 1 });",
+        )
+        .unwrap();
+        let Stmt::Function(f) = &program.body[0] else {
+            panic!("expected function");
+        };
+        assert!(f.is_async && !f.is_generator);
+        assert_eq!(f.body[0], Stmt::Expr(Expr::Await(ident("a"))));
+        assert!(matches!(f.body[1], Stmt::ForOf { is_await: true, .. }));
+        let Stmt::Expr(Expr::Sequence(exprs)) = &program.body[1] else {
+            panic!("expected sequence");
+        };
+        assert!(matches!(&exprs[0], Expr::Function(f) if f.is_async && f.is_arrow));
+        let Expr::Function(arrow) = &exprs[1] else {
+            panic!("expected function");
+        };
+        assert_eq!(
+            arrow.body,
+            vec![Stmt::Return(Some(Expr::Await(ident("b"))))]
+        );
+        assert!(matches!(&exprs[2], Expr::Function(f) if f.is_async && f.is_generator));
+        let Expr::Object(properties) = &exprs[3] else {
+            panic!("expected object");
+        };
+        assert!(matches!(&properties[0].1, Expr::Function(f) if f.is_async));
+        assert_eq!(properties[1].1, Expr::Number(1.0));
+
+        // Outside async functions `async` and `await` are identifiers
+        assert_eq!(
+            parse("async(await);").unwrap().body,
+            vec![Stmt::Expr(Expr::Call {
+                callee: ident("async"),
+                args: vec![*ident("await")],
+            })]
+        );
+        assert!(parse("function f() { for await (x of y) ; }").is_err());
+        assert!(parse("async function f() { for await (x in y) ; }").is_err());
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        assert!(parse("return 1;").is_err());
+        assert_eq!(
+            parse_function_body("return 1;"),
+            Ok(vec![Stmt::Return(Some(Expr::Number(1.0)))])
+        );
+        assert!(parse_function_body("}); x; (function () {").is_err());
+    }
+
+    #[test]
+    fn test_modules() {
+        let module = parse_module(
+            "import 'setup'; import a, { b, default as c, d as e } from './x.js'; \
+             import * as ns from './y.js'; \
+             export const f = 1; export function g() {} export default function () {} \
+             export { f as h, g }; export { default, i as j } from './z.js'; \
+             export * from './w.js'; export * as k from './v.js';",
+        )
+        .unwrap();
+        assert!(!module.has_top_level_await);
+        let body = module.body;
+        assert_eq!(
+            body[0],
+            Stmt::Import(ImportDecl {
+                specifiers: vec![],
+                source: "setup".to_string(),
+            })
+        );
+        assert_eq!(
+            body[1],
+            Stmt::Import(ImportDecl {
+                specifiers: vec![
+                    ImportSpecifier::Default("a".to_string()),
+                    ImportSpecifier::Named {
+                        imported: "b".to_string(),
+                        local: "b".to_string(),
+                    },
+                    ImportSpecifier::Named {
+                        imported: "default".to_string(),
+                        local: "c".to_string(),
+                    },
+                    ImportSpecifier::Named {
+                        imported: "d".to_string(),
+                        local: "e".to_string(),
+                    },
+                ],
+                source: "./x.js".to_string(),
+            })
+        );
+        assert_eq!(
+            body[2],
+            Stmt::Import(ImportDecl {
+                specifiers: vec![ImportSpecifier::Namespace("ns".to_string())],
+                source: "./y.js".to_string(),
+            })
+        );
+        assert!(matches!(
+            &body[3],
+            Stmt::Export(ExportDecl::Declaration(decl)) if matches!(**decl, Stmt::Var { .. })
+        ));
+        assert!(matches!(
+            &body[5],
+            Stmt::Export(ExportDecl::DefaultFunction(f)) if f.name.as_deref() == Some("default")
+        ));
+        assert!(matches!(
+            &body[7],
+            Stmt::Export(ExportDecl::Named { specifiers, source: Some(_) })
+                if specifiers[1] == ExportSpecifier {
+                    local: "i".to_string(),
+                    exported: "j".to_string(),
+                }
+        ));
+        assert_eq!(
+            body[9],
+            Stmt::Export(ExportDecl::All {
+                exported: Some("k".to_string()),
+                source: "./v.js".to_string(),
+            })
+        );
+
+        let module = parse_module("export default await f(); for await (x of y) ;").unwrap();
+        assert!(module.has_top_level_await);
+        assert!(parse_module("async function f() { await x; }")
+            .is_ok_and(|module| !module.has_top_level_await));
+        assert!(parse_module("if (x) { import a from 'a'; }").is_err());
+        assert!(parse_module("import { default } from 'a';").is_err());
+        assert!(parse_module("export { default };").is_err());
+        assert!(parse("import a from 'a';").is_err());
+        assert!(parse("export const a = 1;").is_err());
+    }
+
+    #[test]
+    fn test_classes() {
+        let program = parse(
+            "class B extends A { #x = 1; static y; constructor() { super(); } \
+             get z() { return this.#x; } static #m() {} has(o) { return #x in o; } } \
+             new a.b(1); (class { m() { return super.m(); } });",
+        )
+        .unwrap();
+        let Stmt::Class(class) = &program.body[0] else {
+            panic!("expected class");
+        };
+        assert_eq!(class.name.as_deref(), Some("B"));
+        assert_eq!(class.extends, Some(ident("A")));
+        assert_eq!(
+            class.constructor.as_ref().unwrap().body,
+            vec![Stmt::Expr(Expr::SuperCall(vec![]))]
+        );
+        assert_eq!(
+            class.members[0],
+            ClassMember {
+                key: ClassKey::Private("#x".to_string()),
+                is_static: false,
+                kind: ClassMemberKind::Field(Some(Expr::Number(1.0))),
+            }
+        );
+        assert!(matches!(
+            &class.members[1],
+            ClassMember { key: ClassKey::Public(PropertyKey::Named(name)), is_static: true, kind: ClassMemberKind::Field(None) }
+                if name == "y"
+        ));
+        let ClassMemberKind::Getter(getter) = &class.members[2].kind else {
+            panic!("expected getter");
+        };
+        assert_eq!(
+            getter.body,
+            vec![Stmt::Return(Some(Expr::PrivateMember {
+                object: Box::new(Expr::This),
+                name: "#x".to_string(),
+            }))]
+        );
+        assert!(matches!(
+            &class.members[3],
+            ClassMember { key: ClassKey::Private(name), is_static: true, kind: ClassMemberKind::Method(_) }
+                if name == "#m"
+        ));
+        assert_eq!(
+            program.body[1],
+            Stmt::Expr(Expr::New {
+                callee: Box::new(Expr::Member {
+                    object: ident("a"),
+                    property: "b".to_string(),
+                }),
+                args: vec![Expr::Number(1.0)],
+            })
+        );
+        assert!(matches!(&program.body[2], Stmt::Expr(Expr::Class(class)) if class.name.is_none()));
+
+        for source in [
+            "class A { m() { this.#x; } }",
+            "class A { #x; #x; }",
+            "class A { constructor() {} constructor() {} }",
+            "class A { get constructor() {} }",
+            "class A { static prototype() {} }",
+            "class A { #constructor; }",
+            "class A { #x; m() { delete this.#x; } }",
+            "this.#x;",
+        ] {
+            assert!(parse(source).is_err(), "{source}");
+        }
+        assert_eq!(
+            parse("class A { m() { return this.#y; } }")
+                .unwrap_err()
+                .message,
+            "Private field '#y' must be declared in an enclosing class"
+        );
+        assert!(parse("class A { get #x() {} set #x(v) {} }").is_ok());
+    }
+}
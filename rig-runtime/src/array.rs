@@ -0,0 +1,691 @@
+//! Element storage for arrays.
+//!
+//! Arrays start out packed, with a value at every index below the length.
+//! Writing past the end or deleting an element leaves holes, which turns the
+//! storage holey. Writing far past the end switches to sparse storage that
+//! only holds the elements present, so `a[1e9] = 1` stays cheap.
+//!
+//! Property keys that are not array indices, such as `"-1"` or `"foo"`, are
+//! kept apart as named properties. `length` is neither: it is computed from
+//! the elements.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::heap::{ARRAY_SIZE, ELEMENT_SIZE, ENTRY_SIZE};
+use crate::{Atom, JsString, Properties, Value};
+
+/// Largest gap a write may open up past the end of dense storage before the
+/// array switches to sparse storage.
+const MAX_DENSE_GAP: u32 = 1024;
+
+/// Bytes taken by one element of sparse storage, including tree overhead.
+const SPARSE_ENTRY_SIZE: usize = size_of::<(u32, Value)>() + 16;
+
+/// Bytes taken by one slot of holey storage.
+const HOLEY_SLOT_SIZE: usize = size_of::<Option<Value>>();
+
+/// Largest valid array index; the length must stay below 2^32.
+pub const MAX_INDEX: u32 = u32::MAX - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElementKind {
+    Packed,
+    Holey,
+    Sparse,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Elements {
+    /// Every index below the length holds a value
+    Packed(Vec<Value>),
+    /// Dense storage where `None` marks a hole
+    Holey(Vec<Option<Value>>),
+    /// Only the elements present, keyed by index
+    Sparse {
+        elements: BTreeMap<u32, Value>,
+        len: u32,
+    },
+}
+
+/// The elements of an array, its length and its named properties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsArray {
+    elements: Elements,
+    properties: Properties,
+    /// `None` inherits from `Array.prototype`; otherwise another object or
+    /// null
+    prototype: Option<Value>,
+}
+
+impl Default for JsArray {
+    fn default() -> Self {
+        JsArray::new()
+    }
+}
+
+impl From<Vec<Value>> for JsArray {
+    fn from(values: Vec<Value>) -> Self {
+        JsArray {
+            elements: Elements::Packed(values),
+            properties: Properties::new(),
+            prototype: None,
+        }
+    }
+}
+
+impl JsArray {
+    pub fn new() -> Self {
+        JsArray::from(Vec::new())
+    }
+
+    fn from_slots(slots: Vec<Option<Value>>) -> Self {
+        let elements = if slots.iter().all(Option::is_some) {
+            Elements::Packed(slots.into_iter().flatten().collect())
+        } else {
+            Elements::Holey(slots)
+        };
+        JsArray {
+            elements,
+            properties: Properties::new(),
+            prototype: None,
+        }
+    }
+
+    fn from_sparse(elements: BTreeMap<u32, Value>, len: u32) -> Self {
+        JsArray {
+            elements: Elements::Sparse { elements, len },
+            properties: Properties::new(),
+            prototype: None,
+        }
+    }
+
+    pub fn kind(&self) -> ElementKind {
+        match self.elements {
+            Elements::Packed(_) => ElementKind::Packed,
+            Elements::Holey(_) => ElementKind::Holey,
+            Elements::Sparse { .. } => ElementKind::Sparse,
+        }
+    }
+
+    pub fn len(&self) -> u32 {
+        match &self.elements {
+            Elements::Packed(values) => values.len() as u32,
+            Elements::Holey(slots) => slots.len() as u32,
+            Elements::Sparse { len, .. } => *len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The element at `index`, or `None` for a hole or an index past the
+    /// end.
+    pub fn get(&self, index: u32) -> Option<&Value> {
+        match &self.elements {
+            Elements::Packed(values) => values.get(index as usize),
+            Elements::Holey(slots) => slots.get(index as usize).and_then(Option::as_ref),
+            Elements::Sparse { elements, .. } => elements.get(&index),
+        }
+    }
+
+    /// Whether `index` holds an element, as opposed to a hole.
+    pub fn has(&self, index: u32) -> bool {
+        self.get(index).is_some()
+    }
+
+    /// The elements present, in index order.
+    pub fn iter(&self) -> Box<dyn Iterator<Item = (u32, &Value)> + '_> {
+        match &self.elements {
+            Elements::Packed(values) => {
+                Box::new(values.iter().enumerate().map(|(i, v)| (i as u32, v)))
+            }
+            Elements::Holey(slots) => Box::new(
+                slots
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(i, v)| v.as_ref().map(|v| (i as u32, v))),
+            ),
+            Elements::Sparse { elements, .. } => Box::new(elements.iter().map(|(&i, v)| (i, v))),
+        }
+    }
+
+    /// Number of elements present, not counting holes.
+    pub fn count(&self) -> usize {
+        match &self.elements {
+            Elements::Packed(values) => values.len(),
+            Elements::Holey(slots) => slots.iter().filter(|v| v.is_some()).count(),
+            Elements::Sparse { elements, .. } => elements.len(),
+        }
+    }
+
+    pub fn push(&mut self, value: Value) {
+        let len = self.len();
+        self.set(len, value);
+    }
+
+    /// Stores `value` at `index`, growing the array if needed. Indices past
+    /// `MAX_INDEX` are ignored, as they are not array indices.
+    pub fn set(&mut self, index: u32, value: Value) {
+        if index > MAX_INDEX {
+            return;
+        }
+        let appends = self.kind() == ElementKind::Packed && index == self.len();
+        if index >= self.len() && !appends {
+            self.set_len(index + 1);
+        }
+        match &mut self.elements {
+            Elements::Packed(values) if appends => values.push(value),
+            Elements::Packed(values) => values[index as usize] = value,
+            Elements::Holey(slots) => slots[index as usize] = Some(value),
+            Elements::Sparse { elements, .. } => {
+                elements.insert(index, value);
+            }
+        }
+    }
+
+    /// Removes the element at `index`, leaving a hole.
+    pub fn delete(&mut self, index: u32) {
+        if !self.has(index) {
+            return;
+        }
+        self.make_holey();
+        match &mut self.elements {
+            Elements::Packed(_) => unreachable!("holey storage was just made"),
+            Elements::Holey(slots) => slots[index as usize] = None,
+            Elements::Sparse { elements, .. } => {
+                elements.remove(&index);
+            }
+        }
+    }
+
+    /// Changes the length, dropping elements at or past the new length or
+    /// adding holes up to it.
+    pub fn set_len(&mut self, len: u32) {
+        if len > self.len() {
+            if self.goes_sparse(len - 1) {
+                self.make_sparse();
+            } else {
+                self.make_holey();
+            }
+        }
+        match &mut self.elements {
+            Elements::Packed(values) => values.truncate(len as usize),
+            Elements::Holey(slots) => slots.resize(len as usize, None),
+            Elements::Sparse { elements, len: old } => {
+                elements.split_off(&len);
+                *old = len;
+            }
+        }
+    }
+
+    /// The first index at or after `from` that holds an element.
+    pub fn next_index(&self, from: u32) -> Option<u32> {
+        match &self.elements {
+            Elements::Packed(values) => ((from as usize) < values.len()).then_some(from),
+            Elements::Holey(slots) => slots
+                .get(from as usize..)?
+                .iter()
+                .position(Option::is_some)
+                .map(|offset| from + offset as u32),
+            Elements::Sparse { elements, .. } => elements.range(from..).next().map(|(&i, _)| i),
+        }
+    }
+
+    /// The last index at or before `from` that holds an element.
+    pub fn prev_index(&self, from: u32) -> Option<u32> {
+        match &self.elements {
+            Elements::Packed(values) => match values.len() {
+                0 => None,
+                len => Some(from.min(len as u32 - 1)),
+            },
+            Elements::Holey(slots) => {
+                let end = (from as usize).saturating_add(1).min(slots.len());
+                slots[..end]
+                    .iter()
+                    .rposition(Option::is_some)
+                    .map(|i| i as u32)
+            }
+            Elements::Sparse { elements, .. } => {
+                elements.range(..=from).next_back().map(|(&i, _)| i)
+            }
+        }
+    }
+
+    /// Copies the elements from `start` up to `end`, keeping holes.
+    pub fn slice(&self, start: u32, end: u32) -> JsArray {
+        let end = end.min(self.len());
+        let start = start.min(end);
+        let range = start as usize..end as usize;
+        match &self.elements {
+            Elements::Packed(values) => JsArray::from(values[range].to_vec()),
+            Elements::Holey(slots) => JsArray::from_slots(slots[range].to_vec()),
+            Elements::Sparse { elements, .. } => JsArray::from_sparse(
+                elements
+                    .range(start..end)
+                    .map(|(&i, v)| (i - start, v.clone()))
+                    .collect(),
+                end - start,
+            ),
+        }
+    }
+
+    /// Removes `delete` elements from `start` and inserts `items` in their
+    /// place, moving later elements and their holes along. Returns the
+    /// removed elements. The caller checks the new length fits.
+    pub fn splice(&mut self, start: u32, delete: u32, items: Vec<Value>) -> JsArray {
+        let len = self.len();
+        let start = start.min(len);
+        let delete = delete.min(len - start);
+        let inserted = items.len() as u32;
+        let range = start as usize..(start + delete) as usize;
+        match &mut self.elements {
+            Elements::Packed(values) => {
+                JsArray::from(values.splice(range, items).collect::<Vec<_>>())
+            }
+            Elements::Holey(slots) => {
+                let removed = slots.splice(range, items.into_iter().map(Some)).collect();
+                JsArray::from_slots(removed)
+            }
+            Elements::Sparse { elements, len } => {
+                let mut removed = elements.split_off(&start);
+                let rest = removed.split_off(&(start + delete));
+                let removed = removed.into_iter().map(|(i, v)| (i - start, v)).collect();
+                for (i, item) in items.into_iter().enumerate() {
+                    elements.insert(start + i as u32, item);
+                }
+                for (i, v) in rest {
+                    elements.insert(i - delete + inserted, v);
+                }
+                *len = *len - delete + inserted;
+                JsArray::from_sparse(removed, delete)
+            }
+        }
+    }
+
+    /// Reverses the elements in place, holes included.
+    pub fn reverse(&mut self) {
+        match &mut self.elements {
+            Elements::Packed(values) => values.reverse(),
+            Elements::Holey(slots) => slots.reverse(),
+            Elements::Sparse { elements, len } => {
+                let Some(last) = len.checked_sub(1) else {
+                    return;
+                };
+                *elements = std::mem::take(elements)
+                    .into_iter()
+                    .map(|(i, v)| (last - i, v))
+                    .collect();
+            }
+        }
+    }
+
+    /// Replaces the elements with `values` followed by holes up to `len`,
+    /// keeping the named properties.
+    pub fn set_elements(&mut self, values: Vec<Value>, len: u32) {
+        self.elements = Elements::Packed(values);
+        self.set_len(len);
+    }
+
+    /// The named property `name`, which is not an array index.
+    pub fn property(&self, name: &Atom) -> Option<&Value> {
+        self.properties.get(name)
+    }
+
+    /// Named properties, which are not array indices.
+    pub fn properties(&self) -> impl Iterator<Item = (&Atom, &Value)> {
+        self.properties.iter()
+    }
+
+    /// The prototype set on this array, or `None` if it inherits from
+    /// `Array.prototype`.
+    pub fn prototype(&self) -> Option<&Value> {
+        self.prototype.as_ref()
+    }
+
+    pub(crate) fn set_prototype(&mut self, prototype: Value) {
+        self.prototype = Some(prototype);
+    }
+
+    pub(crate) fn set_property(&mut self, name: Atom, value: Value) {
+        self.properties.insert(name, value);
+    }
+
+    pub(crate) fn delete_property(&mut self, name: &Atom) {
+        self.properties.shift_remove(name);
+    }
+
+    /// Bytes a `set` at `index` would add to the heap.
+    pub(crate) fn set_cost(&self, index: u32) -> usize {
+        let len = self.len();
+        if index > MAX_INDEX || self.has(index) {
+            return 0;
+        }
+        let entry = match self.kind() {
+            ElementKind::Sparse => SPARSE_ENTRY_SIZE,
+            _ if index >= len && self.goes_sparse(index) => SPARSE_ENTRY_SIZE,
+            ElementKind::Packed if index == len => return ELEMENT_SIZE,
+            // Filling a hole in dense storage reuses its slot
+            _ => 0,
+        };
+        entry + self.set_len_cost(index + 1)
+    }
+
+    /// Bytes a `set_len` to `len` would add to the heap.
+    pub(crate) fn set_len_cost(&self, len: u32) -> usize {
+        let old = self.len();
+        if len <= old {
+            return 0;
+        }
+        let added = (len - old) as usize * HOLEY_SLOT_SIZE;
+        match self.kind() {
+            ElementKind::Sparse => 0,
+            // Converting dense storage costs an entry per element
+            _ if self.goes_sparse(len - 1) => self.count() * SPARSE_ENTRY_SIZE,
+            ElementKind::Packed => added + old as usize * (HOLEY_SLOT_SIZE - ELEMENT_SIZE),
+            ElementKind::Holey => added,
+        }
+    }
+
+    pub(crate) fn heap_size(&self) -> usize {
+        ARRAY_SIZE
+            + self.properties.len() * ENTRY_SIZE
+            + match &self.elements {
+                Elements::Packed(values) => values.capacity() * ELEMENT_SIZE,
+                Elements::Holey(slots) => slots.capacity() * HOLEY_SLOT_SIZE,
+                Elements::Sparse { elements, .. } => elements.len() * SPARSE_ENTRY_SIZE,
+            }
+    }
+
+    fn goes_sparse(&self, index: u32) -> bool {
+        let len = self.len();
+        self.kind() != ElementKind::Sparse && index > len && index - len > MAX_DENSE_GAP
+    }
+
+    fn make_holey(&mut self) {
+        if let Elements::Packed(values) = &mut self.elements {
+            let slots = std::mem::take(values).into_iter().map(Some).collect();
+            self.elements = Elements::Holey(slots);
+        }
+    }
+
+    fn make_sparse(&mut self) {
+        let len = self.len();
+        let elements = match &mut self.elements {
+            Elements::Packed(values) => std::mem::take(values)
+                .into_iter()
+                .enumerate()
+                .map(|(i, v)| (i as u32, v))
+                .collect(),
+            Elements::Holey(slots) => std::mem::take(slots)
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, v)| v.map(|v| (i as u32, v)))
+                .collect(),
+            Elements::Sparse { .. } => return,
+        };
+        self.elements = Elements::Sparse { elements, len };
+    }
+}
+
+/// A property key, with array indices told apart from other names.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Key {
+    Index(u32),
+    Name(Atom),
+}
+
+impl Key {
+    /// Converts a value used as a property key, as in `obj[key]`.
+    pub fn new(key: &Value) -> Key {
+        match array_index(key) {
+            Some(index) => Key::Index(index),
+            None => match key {
+                Value::String(s) => Key::Name(Atom::from_js_string(s)),
+                Value::Symbol(symbol) => Key::Name(Atom::from_symbol(symbol)),
+                other => Key::Name(Atom::from_js_string(&other.to_js_string())),
+            },
+        }
+    }
+
+    /// The key as a property name, for objects that do not store indices
+    /// separately.
+    pub fn into_atom(self) -> Atom {
+        match self {
+            Key::Index(index) => Atom::intern(&index.to_string()),
+            Key::Name(name) => name,
+        }
+    }
+}
+
+thread_local! {
+    /// Arrays being joined on this thread, so that an array containing
+    /// itself joins as an empty string instead of recursing forever
+    static JOINING: RefCell<Vec<*const RefCell<JsArray>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Joins the elements' strings with `separator`. Holes, undefined and null
+/// join as empty strings.
+pub(crate) fn join(array: &Rc<RefCell<JsArray>>, separator: &JsString) -> JsString {
+    let Ok(joined) = join_with(array, separator, |_| Ok::<_, Infallible>(()));
+    joined
+}
+
+/// Joins like [`join`], calling `step` with the length written so far
+/// before each element and separator, so that it can stop a join that
+/// grows too long.
+pub(crate) fn join_with<E>(
+    array: &Rc<RefCell<JsArray>>,
+    separator: &JsString,
+    mut step: impl FnMut(usize) -> Result<(), E>,
+) -> Result<JsString, E> {
+    let ptr = Rc::as_ptr(array);
+    if JOINING.with(|joining| joining.borrow().contains(&ptr)) {
+        return Ok(JsString::from(""));
+    }
+    JOINING.with(|joining| joining.borrow_mut().push(ptr));
+    let units = join_units(&array.borrow(), separator, &mut step);
+    JOINING.with(|joining| joining.borrow_mut().pop());
+    Ok(JsString::from_utf16(&units?))
+}
+
+fn join_units<E>(
+    array: &JsArray,
+    separator: &JsString,
+    step: &mut impl FnMut(usize) -> Result<(), E>,
+) -> Result<Vec<u16>, E> {
+    let mut units = Vec::new();
+    // Separators written so far; element `i` follows the `i`th
+    let mut separators = 0;
+    for (i, value) in array.iter() {
+        write_separators(&mut units, &mut separators, i, separator, step)?;
+        if !matches!(value, Value::Undefined | Value::Null) {
+            step(units.len())?;
+            units.extend(value.to_js_string().code_units());
+        }
+    }
+    let count = array.len().saturating_sub(1);
+    write_separators(&mut units, &mut separators, count, separator, step)?;
+    Ok(units)
+}
+
+/// Writes separators until `count` have been written. Empty separators are
+/// skipped over at once, so that joining a sparse array with `""` takes
+/// time in its elements rather than its length.
+fn write_separators<E>(
+    units: &mut Vec<u16>,
+    separators: &mut u32,
+    count: u32,
+    separator: &JsString,
+    step: &mut impl FnMut(usize) -> Result<(), E>,
+) -> Result<(), E> {
+    if separator.is_empty() {
+        *separators = (*separators).max(count);
+    }
+    while *separators < count {
+        step(units.len())?;
+        units.extend(separator.code_units());
+        *separators += 1;
+    }
+    Ok(())
+}
+
+/// Whether `name` is `length`, which arrays compute rather than store.
+pub(crate) fn is_length(name: &Atom) -> bool {
+    *name.as_js_string() == *"length"
+}
+
+/// The array index a property key denotes: an integral number or canonical
+/// numeric string from 0 to `MAX_INDEX`.
+pub(crate) fn array_index(key: &Value) -> Option<u32> {
+    match key {
+        Value::Number(n) if n.fract() == 0.0 && *n >= 0.0 && *n <= MAX_INDEX as f64 => {
+            Some(*n as u32)
+        }
+        Value::String(s) => {
+            let len = s.len();
+            if len == 0 || len > 10 || (len > 1 && s.code_unit_at(0) == Some(b'0' as u16)) {
+                return None;
+            }
+            let mut index: u64 = 0;
+            for unit in s.code_units() {
+                let digit = (unit as u32).checked_sub('0' as u32).filter(|d| *d < 10)?;
+                index = index * 10 + digit as u64;
+            }
+            u32::try_from(index).ok().filter(|&i| i <= MAX_INDEX)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(array: &JsArray) -> Vec<(u32, f64)> {
+        array
+            .iter()
+            .map(|(i, v)| (i, v.as_number().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_packed_until_a_hole_appears() {
+        let mut array = JsArray::new();
+        array.push(Value::Number(1.0));
+        array.set(1, Value::Number(2.0));
+        array.set(0, Value::Number(3.0));
+        assert_eq!(array.kind(), ElementKind::Packed);
+
+        array.set(3, Value::Number(4.0));
+        assert_eq!(array.kind(), ElementKind::Holey);
+        assert_eq!(array.len(), 4);
+        assert!(!array.has(2));
+        assert_eq!(numbers(&array), vec![(0, 3.0), (1, 2.0), (3, 4.0)]);
+    }
+
+    #[test]
+    fn test_undefined_is_not_a_hole() {
+        let mut array = JsArray::new();
+        array.set(1, Value::Undefined);
+
+        assert!(array.has(1));
+        assert!(!array.has(0));
+        assert_eq!(array.count(), 1);
+    }
+
+    #[test]
+    fn test_large_index_goes_sparse() {
+        let mut array = JsArray::from(vec![Value::Number(1.0)]);
+        assert!(array.set_cost(1_000_000_000) < 1024);
+        array.set(1_000_000_000, Value::Number(2.0));
+
+        assert_eq!(array.kind(), ElementKind::Sparse);
+        assert_eq!(array.len(), 1_000_000_001);
+        assert_eq!(numbers(&array), vec![(0, 1.0), (1_000_000_000, 2.0)]);
+        assert!(array.heap_size() < 1024);
+    }
+
+    #[test]
+    fn test_delete_leaves_holes() {
+        let mut array = JsArray::from(vec![Value::Null, Value::Null, Value::Null]);
+        array.delete(1);
+        assert_eq!(array.kind(), ElementKind::Holey);
+        array.delete(2);
+
+        assert_eq!(array.len(), 3);
+        assert_eq!(array.count(), 1);
+        assert!(array.has(0) && !array.has(1) && !array.has(2));
+    }
+
+    #[test]
+    fn test_set_len() {
+        let mut array = JsArray::from(vec![Value::Null, Value::Null, Value::Null]);
+        array.set_len(1);
+        assert_eq!(array.kind(), ElementKind::Packed);
+        assert_eq!(array.count(), 1);
+
+        array.set_len(3);
+        assert_eq!(array.kind(), ElementKind::Holey);
+        assert!(array.has(0) && !array.has(2));
+
+        assert!(array.set_len_cost(1 << 30) < 1024);
+        array.set_len(1 << 30);
+        assert_eq!(array.kind(), ElementKind::Sparse);
+        array.set(5, Value::Null);
+        array.set_len(2);
+        assert_eq!(array.len(), 2);
+        assert_eq!(array.count(), 1);
+    }
+
+    #[test]
+    fn test_splice_keeps_holes() {
+        let mut array = JsArray::from(vec![Value::Number(1.0)]);
+        array.set(3, Value::Number(4.0));
+        let removed = array.splice(0, 2, vec![Value::Number(9.0)]);
+
+        assert_eq!(removed.len(), 2);
+        assert_eq!(numbers(&removed), vec![(0, 1.0)]);
+        assert_eq!(array.len(), 3);
+        assert_eq!(numbers(&array), vec![(0, 9.0), (2, 4.0)]);
+
+        let mut sparse = JsArray::new();
+        sparse.set(1 << 20, Value::Number(1.0));
+        sparse.splice(0, 0, vec![Value::Number(0.0)]);
+        assert_eq!(numbers(&sparse), vec![(0, 0.0), ((1 << 20) + 1, 1.0)]);
+        assert_eq!(sparse.next_index(1), Some((1 << 20) + 1));
+        assert_eq!(sparse.prev_index(1 << 20), Some(0));
+        sparse.reverse();
+        assert_eq!(numbers(&sparse), vec![(0, 1.0), ((1 << 20) + 1, 0.0)]);
+    }
+
+    #[test]
+    fn test_keys() {
+        assert_eq!(Key::new(&Value::from("7")), Key::Index(7));
+        assert_eq!(
+            Key::new(&Value::Number(-1.0)),
+            Key::Name(Atom::intern("-1"))
+        );
+        assert_eq!(
+            Key::new(&Value::Number(0.5)).into_atom(),
+            Atom::intern("0.5")
+        );
+        assert_eq!(Key::Index(7).into_atom(), Atom::intern("7"));
+    }
+
+    #[test]
+    fn test_array_index() {
+        assert_eq!(array_index(&Value::Number(3.0)), Some(3));
+        assert_eq!(array_index(&Value::from("42")), Some(42));
+        assert_eq!(array_index(&Value::from("4294967294")), Some(MAX_INDEX));
+        assert_eq!(array_index(&Value::from("4294967295")), None);
+        assert_eq!(array_index(&Value::from("01")), None);
+        assert_eq!(array_index(&Value::from("-1")), None);
+        assert_eq!(array_index(&Value::Number(1.5)), None);
+        assert_eq!(array_index(&Value::Number(-0.0)), Some(0));
+    }
+}
@@ -0,0 +1,315 @@
+//! Async functions and async generators.
+//!
+//! An async function runs as a generator that nobody else can see: its
+//! frame is suspended at `Await` and resumed by the reactions to the
+//! promise for the awaited value, and each time it stops the promise the
+//! call returned is settled or left pending.
+//!
+//! An async generator queues the calls to its `next`, `return` and `throw`
+//! methods, each with the promise handed back for it, and serves them one
+//! at a time: the front request is settled when the frame yields or
+//! returns, with any awaits in between.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use rig_bytecode::Instruction;
+
+use crate::generator::{generator_state, GeneratorState, Resume, Resumed};
+use crate::heap::{ENTRY_SIZE, FRAME_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::{JsObject, NativeFunction, RuntimeError, Value, VM};
+
+/// The internal state of an async generator object.
+#[derive(Debug, Clone)]
+pub(crate) struct AsyncGenerator {
+    state: GeneratorState,
+    /// Calls waiting to be served, oldest first
+    queue: VecDeque<Request>,
+    /// Set while the front request is being served, so that requests
+    /// made meanwhile wait their turn
+    busy: bool,
+}
+
+/// A call to `next`, `return` or `throw`, with the promise it returned.
+#[derive(Debug, Clone)]
+struct Request {
+    resume: Resume,
+    promise: Value,
+}
+
+impl AsyncGenerator {
+    pub fn state(&self) -> &GeneratorState {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut GeneratorState {
+        &mut self.state
+    }
+
+    /// The values held by queued requests, for measuring the heap.
+    pub fn values(&self) -> Vec<Value> {
+        let mut values = Vec::new();
+        for request in &self.queue {
+            let (Resume::Next(value) | Resume::Throw(value) | Resume::Return(value)) =
+                &request.resume;
+            values.push(value.clone());
+            values.push(request.promise.clone());
+        }
+        values
+    }
+}
+
+impl VM {
+    /// Executes `CreateAsync`: runs the body of the async function until it
+    /// first awaits or finishes, then returns the promise for its result.
+    pub(crate) fn create_async(&mut self) -> Result<Option<Value>, RuntimeError> {
+        self.charge(OBJECT_SIZE + FRAME_SIZE)?;
+        let promise = self.create_promise()?;
+        let frame = self.suspend();
+        let task = Rc::new(RefCell::new(JsObject::with_kind(
+            Value::Null,
+            ObjectKind::Generator(GeneratorState::Suspended(Box::new(frame))),
+        )));
+        self.async_step(&task, &promise, Resume::Next(Value::Undefined))?;
+        Ok(self.pop_frame(promise))
+    }
+
+    /// Resumes an async function's frame, and either waits for the value it
+    /// awaits next or settles its promise with the outcome.
+    fn async_step(
+        &mut self,
+        task: &Rc<RefCell<JsObject>>,
+        promise: &Value,
+        resume: Resume,
+    ) -> Result<(), RuntimeError> {
+        match self.resume_frame(task, resume) {
+            Ok(Resumed::Awaited(value)) => {
+                let (task, promise) = (task.clone(), promise.clone());
+                self.await_value(value, move |vm, resume| {
+                    vm.async_step(&task, &promise, resume)
+                })
+            }
+            Ok(Resumed::Returned(value)) => self.resolve_promise(promise, value),
+            Ok(Resumed::Yielded(_)) => Err(RuntimeError::Internal(
+                "Async function suspended at an unexpected instruction".to_string(),
+            )),
+            Err(err) => {
+                let reason = self.error_value(err)?;
+                self.reject_promise(promise, reason)
+            }
+        }
+    }
+
+    /// Calls `then` with how the promise for `value` settled, from the job
+    /// that reacts to it.
+    pub(crate) fn await_value(
+        &mut self,
+        value: Value,
+        then: impl Fn(&mut VM, Resume) -> Result<(), RuntimeError> + 'static,
+    ) -> Result<(), RuntimeError> {
+        let promise = self.promise_resolve(value)?;
+        let then = Rc::new(then);
+        let on_fulfilled = {
+            let then = then.clone();
+            NativeFunction::new("", move |vm, _this, args| {
+                let value = args.first().cloned().unwrap_or(Value::Undefined);
+                then(vm, Resume::Next(value))?;
+                Ok(Value::Undefined)
+            })
+        };
+        let on_rejected = NativeFunction::new("", move |vm, _this, args| {
+            let reason = args.first().cloned().unwrap_or(Value::Undefined);
+            then(vm, Resume::Throw(reason))?;
+            Ok(Value::Undefined)
+        });
+        self.promise_then(&promise, on_fulfilled.into(), on_rejected.into(), None)
+    }
+
+    /// Executes `CreateAsyncGenerator`, returning the new async generator
+    /// from the running function.
+    pub(crate) fn create_async_generator(&mut self) -> Result<Option<Value>, RuntimeError> {
+        self.charge(OBJECT_SIZE + FRAME_SIZE)?;
+        let frame = self.suspend();
+        let generator = JsObject::with_kind(
+            Value::Object(self.intrinsics.async_generator_prototype.clone()),
+            ObjectKind::AsyncGenerator(AsyncGenerator {
+                state: GeneratorState::Suspended(Box::new(frame)),
+                queue: VecDeque::new(),
+                busy: false,
+            }),
+        );
+        Ok(self.pop_frame(Value::Object(Rc::new(RefCell::new(generator)))))
+    }
+
+    /// Queues a call to an async generator's `next`, `return` or `throw`
+    /// method, named by `method`, and returns the promise for its result.
+    pub(crate) fn async_generator_enqueue(
+        &mut self,
+        generator: &Value,
+        resume: Resume,
+        method: &str,
+    ) -> Result<Value, RuntimeError> {
+        let promise = self.create_promise()?;
+        let obj = match generator {
+            Value::Object(obj) if matches!(obj.borrow().kind(), ObjectKind::AsyncGenerator(_)) => {
+                obj.clone()
+            }
+            _ => {
+                let reason = self.error_value(RuntimeError::TypeError(format!(
+                    "{method} method called on incompatible receiver {}",
+                    generator.to_js_string()
+                )))?;
+                self.reject_promise(&promise, reason)?;
+                return Ok(promise);
+            }
+        };
+        self.charge(ENTRY_SIZE)?;
+        let busy = with_async_generator(&obj, |generator| {
+            generator.queue.push_back(Request {
+                resume,
+                promise: promise.clone(),
+            });
+            generator.busy
+        });
+        if !busy {
+            self.async_generator_drain(&obj)?;
+        }
+        Ok(promise)
+    }
+
+    /// Serves queued requests until the queue is empty or one has to wait.
+    fn async_generator_drain(&mut self, obj: &Rc<RefCell<JsObject>>) -> Result<(), RuntimeError> {
+        loop {
+            let next = with_async_generator(obj, |generator| {
+                if generator.busy {
+                    return None;
+                }
+                let request = generator.queue.front()?.clone();
+                generator.busy = true;
+                Some(request)
+            });
+            let Some(Request { resume, .. }) = next else {
+                return Ok(());
+            };
+            let done = with_async_generator(obj, |generator| {
+                matches!(generator.state, GeneratorState::Done)
+            });
+            match (done, resume) {
+                // A finished generator still waits for the value passed to
+                // `return`
+                (true, Resume::Return(value)) => {
+                    let obj = obj.clone();
+                    return self.await_value(value, move |vm, resume| {
+                        let outcome = match resume {
+                            Resume::Throw(reason) => Err(RuntimeError::Thrown(reason)),
+                            Resume::Next(value) | Resume::Return(value) => {
+                                Ok(Resumed::Returned(value))
+                            }
+                        };
+                        vm.async_generator_settle(&obj, outcome)?;
+                        vm.async_generator_drain(&obj)
+                    });
+                }
+                (true, resume) => {
+                    self.async_generator_settle(obj, finished(resume))?;
+                }
+                (false, resume) => {
+                    // Let a generator that has not started finish first, so
+                    // that `return` waits for its value as above
+                    if self.async_generator_at_start(obj) && !matches!(resume, Resume::Next(_)) {
+                        with_async_generator(obj, |generator| {
+                            generator.state = GeneratorState::Done;
+                            generator.busy = false;
+                        });
+                        continue;
+                    }
+                    return self.async_generator_step(obj, resume);
+                }
+            }
+        }
+    }
+
+    /// Resumes an async generator's frame for the front request, settling
+    /// the request once the frame yields or returns.
+    fn async_generator_step(
+        &mut self,
+        obj: &Rc<RefCell<JsObject>>,
+        resume: Resume,
+    ) -> Result<(), RuntimeError> {
+        match self.resume_frame(obj, resume) {
+            Ok(Resumed::Awaited(value)) => {
+                let obj = obj.clone();
+                self.await_value(value, move |vm, resume| {
+                    vm.async_generator_step(&obj, resume)
+                })
+            }
+            outcome => {
+                self.async_generator_settle(obj, outcome)?;
+                self.async_generator_drain(obj)
+            }
+        }
+    }
+
+    /// Settles the front request with how the frame stopped: a yield hands
+    /// out its result, a return a final result, and an error rejects.
+    fn async_generator_settle(
+        &mut self,
+        obj: &Rc<RefCell<JsObject>>,
+        outcome: Result<Resumed, RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        let request = with_async_generator(obj, |generator| {
+            generator.busy = false;
+            generator.queue.pop_front()
+        });
+        let Some(request) = request else {
+            return Ok(());
+        };
+        match outcome {
+            Ok(Resumed::Yielded(result)) => self.resolve_promise(&request.promise, result),
+            Ok(Resumed::Returned(value)) => {
+                let result = self.iterator_result(value, true)?;
+                self.resolve_promise(&request.promise, result)
+            }
+            Ok(Resumed::Awaited(_)) => Err(RuntimeError::Internal(
+                "Async generator settled while awaiting".to_string(),
+            )),
+            Err(err) => {
+                let reason = self.error_value(err)?;
+                self.reject_promise(&request.promise, reason)
+            }
+        }
+    }
+
+    /// Whether an async generator is suspended before its body has run.
+    fn async_generator_at_start(&self, obj: &Rc<RefCell<JsObject>>) -> bool {
+        match generator_state(obj.borrow_mut().kind_mut()) {
+            Some(GeneratorState::Suspended(frame)) => matches!(
+                self.program.get(frame.pc()),
+                Some(Instruction::CreateAsyncGenerator)
+            ),
+            _ => false,
+        }
+    }
+}
+
+/// The outcome of a `next` or `throw` request to a finished async
+/// generator.
+fn finished(resume: Resume) -> Result<Resumed, RuntimeError> {
+    match resume {
+        Resume::Throw(reason) => Err(RuntimeError::Thrown(reason)),
+        Resume::Next(_) | Resume::Return(_) => Ok(Resumed::Returned(Value::Undefined)),
+    }
+}
+
+/// Runs `f` on the internal state of an async generator object.
+fn with_async_generator<R>(
+    obj: &Rc<RefCell<JsObject>>,
+    f: impl FnOnce(&mut AsyncGenerator) -> R,
+) -> R {
+    match obj.borrow_mut().kind_mut() {
+        ObjectKind::AsyncGenerator(generator) => f(generator),
+        _ => unreachable!("async generator changed kind"),
+    }
+}
@@ -0,0 +1,240 @@
+//! Interned strings for identifiers and property keys.
+//!
+//! Every distinct string is stored once per thread, so atoms compare and hash
+//! by pointer instead of by contents. The table only holds weak references:
+//! a string stays interned while an atom or other clone of it is alive, so
+//! keys made at runtime, such as `o['k' + i]`, are freed along with the
+//! values that use them.
+//!
+//! A symbol used as a property key is an atom too. It is unique already, so
+//! it is not interned.
+
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+
+use crate::string::{hash_code_units, JsString, WeakJsString};
+use crate::JsSymbol;
+
+/// Fewest entries the table sweeps out dropped strings at.
+const MIN_SWEEP: usize = 1024;
+
+thread_local! {
+    static ATOMS: RefCell<Table> = RefCell::new(Table::default());
+}
+
+#[derive(Default)]
+struct Table {
+    /// Interned strings bucketed by content hash, so `&str` names can be
+    /// looked up without first being converted to code units
+    buckets: HashMap<u64, Vec<WeakJsString>>,
+    /// Entries in `buckets`, including dropped strings not yet swept out
+    len: usize,
+    /// `len` at which the next sweep happens, twice the live entries found
+    /// by the last one, so sweeping takes amortized constant time
+    sweep_at: usize,
+}
+
+impl Table {
+    /// Returns the interned string in the bucket for `hash` that `matches`,
+    /// or interns the one `make` returns. Dropped strings found in the
+    /// bucket on the way are removed.
+    fn get_or_insert(
+        &mut self,
+        hash: u64,
+        matches: impl Fn(&JsString) -> bool,
+        make: impl FnOnce() -> JsString,
+    ) -> JsString {
+        let bucket = self.buckets.entry(hash).or_default();
+        let before = bucket.len();
+        let mut found = None;
+        bucket.retain(|weak| match weak.upgrade() {
+            Some(s) => {
+                if found.is_none() && matches(&s) {
+                    found = Some(s);
+                }
+                true
+            }
+            None => false,
+        });
+        self.len -= before - bucket.len();
+        if let Some(s) = found {
+            return s;
+        }
+        let s = make();
+        bucket.push(s.downgrade());
+        self.len += 1;
+        if self.len >= self.sweep_at.max(MIN_SWEEP) {
+            self.sweep();
+        }
+        s
+    }
+
+    /// Removes every dropped string.
+    fn sweep(&mut self) {
+        self.buckets.retain(|_, bucket| {
+            bucket.retain(|weak| !weak.is_dropped());
+            !bucket.is_empty()
+        });
+        self.len = self.buckets.values().map(Vec::len).sum();
+        self.sweep_at = 2 * self.len;
+    }
+}
+
+fn content_hash(units: impl Iterator<Item = u16>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hash_code_units(units, &mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone)]
+pub struct Atom(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    String(JsString),
+    Symbol(JsSymbol),
+}
+
+impl Atom {
+    /// Returns the atom for `s`, adding it to the table if needed.
+    pub fn intern(s: &str) -> Atom {
+        let hash = content_hash(s.encode_utf16());
+        let s = ATOMS.with(|atoms| {
+            atoms
+                .borrow_mut()
+                .get_or_insert(hash, |a| *a == *s, || JsString::new(s))
+        });
+        Atom(Repr::String(s))
+    }
+
+    /// Returns the atom for a runtime string. Neither looking up nor
+    /// inserting copies the string.
+    pub fn from_js_string(s: &JsString) -> Atom {
+        let hash = content_hash(s.code_units());
+        // Hashing flattened `s` already, so a rope is stored flat
+        let s = ATOMS.with(|atoms| {
+            atoms
+                .borrow_mut()
+                .get_or_insert(hash, |a| a == s, || s.clone())
+        });
+        Atom(Repr::String(s))
+    }
+
+    /// The key for a symbol.
+    pub fn from_symbol(symbol: &JsSymbol) -> Atom {
+        Atom(Repr::Symbol(symbol.clone()))
+    }
+
+    /// The string, or for a symbol its descriptive string such as
+    /// `Symbol(foo)`.
+    pub fn as_js_string(&self) -> &JsString {
+        match &self.0 {
+            Repr::String(s) => s,
+            Repr::Symbol(symbol) => symbol.descriptive_string(),
+        }
+    }
+
+    pub fn as_symbol(&self) -> Option<&JsSymbol> {
+        match &self.0 {
+            Repr::Symbol(symbol) => Some(symbol),
+            Repr::String(_) => None,
+        }
+    }
+
+    pub fn is_symbol(&self) -> bool {
+        self.as_symbol().is_some()
+    }
+
+    fn as_ptr(&self) -> *const () {
+        match &self.0 {
+            Repr::String(s) => s.as_ptr(),
+            Repr::Symbol(symbol) => symbol.as_ptr(),
+        }
+    }
+}
+
+impl From<Atom> for JsString {
+    fn from(atom: Atom) -> Self {
+        match atom.0 {
+            Repr::String(s) => s,
+            Repr::Symbol(symbol) => symbol.descriptive_string().clone(),
+        }
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Self) -> bool {
+        // Strings and symbols are separate allocations, so pointers alone
+        // tell them apart
+        self.as_ptr() == other.as_ptr()
+    }
+}
+
+impl Eq for Atom {}
+
+impl Hash for Atom {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_ptr().hash(state);
+    }
+}
+
+impl fmt::Display for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_js_string(), f)
+    }
+}
+
+impl fmt::Debug for Atom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            Repr::String(s) => fmt::Debug::fmt(s, f),
+            Repr::Symbol(symbol) => fmt::Debug::fmt(symbol, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intern_shares_storage() {
+        let a = Atom::intern("length");
+        let b = Atom::from_js_string(&JsString::from("length".to_string()));
+
+        assert_eq!(a, b);
+        assert!(JsString::ptr_eq(a.as_js_string(), b.as_js_string()));
+        assert_ne!(a, Atom::intern("size"));
+    }
+
+    #[test]
+    fn test_symbol_keys_are_distinct() {
+        let symbol = JsSymbol::new(Some(JsString::from("length")));
+        let key = Atom::from_symbol(&symbol);
+
+        assert_eq!(key, Atom::from_symbol(&symbol));
+        assert_ne!(key, Atom::intern("Symbol(length)"));
+        assert_ne!(
+            key,
+            Atom::from_symbol(&JsSymbol::new(Some(JsString::from("length"))))
+        );
+        assert_eq!(key.to_string(), "Symbol(length)");
+    }
+
+    #[test]
+    fn test_dropped_strings_are_released() {
+        let weak = Atom::intern("released").as_js_string().downgrade();
+        assert!(weak.is_dropped());
+
+        let kept = Atom::intern("kept");
+        for i in 0..100_000 {
+            Atom::from_js_string(&JsString::from(format!("key{i}")));
+        }
+        let len = ATOMS.with(|atoms| atoms.borrow().len);
+        assert!(len < 10_000, "{len} entries");
+        assert_eq!(Atom::intern("kept"), kept);
+    }
+}
@@ -0,0 +1,893 @@
+//! The `Array` constructor and `Array.prototype`.
+//!
+//! Methods require `this` to be an array. Callbacks may change the array
+//! they are iterating over, so it is borrowed afresh for every element and
+//! never across a call.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use super::{
+    arg, callable, define_iterator, define_methods, define_statics, iterable_values,
+    relative_index, to_integer, MAX_STRING_LENGTH,
+};
+use crate::array::join_with;
+use crate::heap::ELEMENT_SIZE;
+use crate::iterator::{Iteration, IterationKind};
+use crate::{JsArray, JsString, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.array_prototype.clone();
+    define_methods(
+        &prototype,
+        &[
+            ("at", at),
+            ("concat", concat),
+            ("entries", entries),
+            ("every", every),
+            ("fill", fill),
+            ("filter", filter),
+            ("find", find),
+            ("findIndex", find_index),
+            ("flat", flat),
+            ("flatMap", flat_map),
+            ("forEach", for_each),
+            ("includes", includes),
+            ("indexOf", index_of),
+            ("join", join_method),
+            ("keys", keys),
+            ("map", map),
+            ("pop", pop),
+            ("push", push),
+            ("reduce", reduce),
+            ("reduceRight", reduce_right),
+            ("reverse", reverse),
+            ("shift", shift),
+            ("slice", slice),
+            ("some", some),
+            ("sort", sort),
+            ("splice", splice),
+            ("toString", to_string),
+            ("unshift", unshift),
+            ("values", values),
+        ],
+    );
+    let values = prototype.borrow().get("values").cloned();
+    if let Some(values) = values {
+        define_iterator(&prototype, values.clone());
+        vm.intrinsics.array_values = Some(values);
+    }
+    let ctor = NativeFunction::new("Array", construct);
+    define_statics(&ctor, &[("from", from), ("isArray", is_array), ("of", of)]);
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Array", ctor);
+}
+
+fn this_array(this: &Value, method: &str) -> Result<Rc<RefCell<JsArray>>, RuntimeError> {
+    match this {
+        Value::Array(arr) => Ok(arr.clone()),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Array.prototype.{method} called on a non-array"
+        ))),
+    }
+}
+
+/// Starts an iteration over the array behind `this`, for `keys`, `values`
+/// and `entries`.
+fn iterate(
+    vm: &mut VM,
+    this: &Value,
+    method: &str,
+    kind: IterationKind,
+) -> Result<Value, RuntimeError> {
+    let arr = this_array(this, method)?;
+    let prototype = Value::Object(vm.intrinsics.array_iterator_prototype.clone());
+    vm.new_iterator(prototype, Iteration::array(arr, kind))
+}
+
+/// Fails if an array would grow past the maximum length.
+fn check_length(len: u64) -> Result<u32, RuntimeError> {
+    u32::try_from(len).map_err(|_| RuntimeError::RangeError("Invalid array length".to_string()))
+}
+
+/// Stores `value` at `index`, charging for any growth.
+fn set_element(
+    vm: &mut VM,
+    arr: &RefCell<JsArray>,
+    index: u32,
+    value: Value,
+) -> Result<(), RuntimeError> {
+    let cost = arr.borrow().set_cost(index);
+    vm.charge(cost)?;
+    arr.borrow_mut().set(index, value);
+    Ok(())
+}
+
+/// The first element at or after `from` and below `len`.
+fn next_element(arr: &RefCell<JsArray>, from: u32, len: u32) -> Option<(u32, Value)> {
+    let arr = arr.borrow();
+    let index = arr.next_index(from).filter(|&i| i < len)?;
+    Some((index, arr.get(index)?.clone()))
+}
+
+/// The last element at or before `from`.
+fn prev_element(arr: &RefCell<JsArray>, from: u32) -> Option<(u32, Value)> {
+    let arr = arr.borrow();
+    let index = arr.prev_index(from)?;
+    Some((index, arr.get(index)?.clone()))
+}
+
+/// Calls `callback` with an element, its index and the array, as the
+/// iteration methods do.
+fn call_back(
+    vm: &mut VM,
+    callback: &Value,
+    this_arg: &Value,
+    element: Value,
+    index: u32,
+    array: &Value,
+) -> Result<Value, RuntimeError> {
+    vm.call_function(
+        callback,
+        this_arg.clone(),
+        &[element, Value::Number(index as f64), array.clone()],
+    )
+}
+
+/// SameValueZero, the equality `includes` uses: like `===`, except that
+/// NaN equals itself.
+fn same_value_zero(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y || (x.is_nan() && y.is_nan()),
+        _ => a == b,
+    }
+}
+
+fn construct(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    if let [Value::Number(n)] = args {
+        // A single number is the length rather than an element
+        let len = *n as u32;
+        if len as f64 != *n {
+            return Err(RuntimeError::RangeError("Invalid array length".to_string()));
+        }
+        let mut array = JsArray::new();
+        vm.charge(array.set_len_cost(len))?;
+        array.set_len(len);
+        return vm.new_array(array);
+    }
+    vm.new_array(JsArray::from(args.to_vec()))
+}
+
+fn from(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let items = arg(args, 0);
+    let map_fn = arg(args, 1);
+    if !map_fn.is_undefined() {
+        callable(&map_fn)?;
+    }
+    let values = match iterable_values(vm, &items)? {
+        Some(values) => values,
+        None if items.is_undefined() || items.is_null() => {
+            return Err(RuntimeError::TypeError(format!(
+                "{} is not iterable",
+                items.to_js_string()
+            )))
+        }
+        // Anything else is read as an array-like object
+        None => {
+            let len = vm.get_property(&items, &Value::from("length"))?;
+            let len = to_integer(&len).clamp(0.0, u32::MAX as f64) as u32;
+            vm.charge(len as usize * ELEMENT_SIZE)?;
+            let mut values = Vec::with_capacity(len as usize);
+            for i in 0..len {
+                values.push(vm.get_property(&items, &Value::Number(i as f64))?);
+            }
+            values
+        }
+    };
+    let values = if map_fn.is_undefined() {
+        values
+    } else {
+        let this_arg = arg(args, 2);
+        let mut mapped = Vec::with_capacity(values.len());
+        for (i, value) in values.into_iter().enumerate() {
+            mapped.push(vm.call_function(
+                &map_fn,
+                this_arg.clone(),
+                &[value, Value::Number(i as f64)],
+            )?);
+        }
+        mapped
+    };
+    vm.new_array(JsArray::from(values))
+}
+
+fn is_array(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(matches!(arg(args, 0), Value::Array(_))))
+}
+
+fn of(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.new_array(JsArray::from(args.to_vec()))
+}
+
+fn at(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "at")?;
+    let arr = arr.borrow();
+    let n = to_integer(&arg(args, 0));
+    let index = if n < 0.0 { arr.len() as f64 + n } else { n };
+    if index < 0.0 || index >= arr.len() as f64 {
+        return Ok(Value::Undefined);
+    }
+    Ok(arr.get(index as u32).cloned().unwrap_or(Value::Undefined))
+}
+
+fn concat(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "concat")?;
+    let result = JsArray::new();
+    let result = vm.new_array(result)?;
+    let Value::Array(target) = &result else {
+        unreachable!()
+    };
+    for item in std::iter::once(&this).chain(args) {
+        let offset = target.borrow().len();
+        match item {
+            // Arrays are spread, keeping their holes
+            Value::Array(source) => {
+                let source = source.borrow().slice(0, u32::MAX);
+                let len = check_length(offset as u64 + source.len() as u64)?;
+                vm.charge(source.heap_size())?;
+                let mut target = target.borrow_mut();
+                for (i, value) in source.iter() {
+                    target.set(offset + i, value.clone());
+                }
+                if len > target.len() {
+                    target.set_len(len);
+                }
+            }
+            value => {
+                check_length(offset as u64 + 1)?;
+                set_element(vm, target, offset, value.clone())?;
+            }
+        }
+    }
+    drop(arr);
+    Ok(result)
+}
+
+fn entries(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    iterate(vm, &this, "entries", IterationKind::Entries)
+}
+
+fn every(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "every")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    let mut k = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        if !call_back(vm, &callback, &this_arg, value, i, &this)?.to_boolean() {
+            return Ok(Value::Boolean(false));
+        }
+    }
+    Ok(Value::Boolean(true))
+}
+
+fn fill(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "fill")?;
+    let len = arr.borrow().len();
+    let value = arg(args, 0);
+    let start = relative_index(&arg(args, 1), len, 0);
+    let end = relative_index(&arg(args, 2), len, len);
+    for i in start..end {
+        set_element(vm, &arr, i, value.clone())?;
+    }
+    Ok(this)
+}
+
+fn filter(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "filter")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let result = vm.new_array(JsArray::new())?;
+    let Value::Array(target) = &result else {
+        unreachable!()
+    };
+    let len = arr.borrow().len();
+    let mut k = 0;
+    let mut count = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        if call_back(vm, &callback, &this_arg, value.clone(), i, &this)?.to_boolean() {
+            set_element(vm, target, count, value)?;
+            count += 1;
+        }
+    }
+    Ok(result)
+}
+
+/// Shared by `find` and `findIndex`, which visit holes as undefined.
+fn find_element(
+    vm: &mut VM,
+    this: &Value,
+    args: &[Value],
+    method: &str,
+) -> Result<Option<(u32, Value)>, RuntimeError> {
+    let arr = this_array(this, method)?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    for i in 0..len {
+        let value = arr.borrow().get(i).cloned().unwrap_or(Value::Undefined);
+        if call_back(vm, &callback, &this_arg, value.clone(), i, this)?.to_boolean() {
+            return Ok(Some((i, value)));
+        }
+    }
+    Ok(None)
+}
+
+fn find(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let found = find_element(vm, &this, args, "find")?;
+    Ok(found.map_or(Value::Undefined, |(_, value)| value))
+}
+
+fn find_index(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let found = find_element(vm, &this, args, "findIndex")?;
+    Ok(Value::Number(found.map_or(-1.0, |(i, _)| i as f64)))
+}
+
+/// Appends the elements of `source` to `target`, flattening nested arrays
+/// `depth` levels deep. `active` holds the arrays being flattened, to stop
+/// an array that contains itself.
+fn flatten_into(
+    vm: &mut VM,
+    target: &RefCell<JsArray>,
+    source: &Rc<RefCell<JsArray>>,
+    depth: f64,
+    active: &mut Vec<*const RefCell<JsArray>>,
+) -> Result<(), RuntimeError> {
+    if active.contains(&Rc::as_ptr(source)) {
+        return Err(RuntimeError::RangeError(
+            "Maximum call stack size exceeded".to_string(),
+        ));
+    }
+    active.push(Rc::as_ptr(source));
+    let len = source.borrow().len();
+    let mut k = 0;
+    while let Some((i, value)) = next_element(source, k, len) {
+        k = i + 1;
+        match value {
+            Value::Array(inner) if depth >= 1.0 => {
+                flatten_into(vm, target, &inner, depth - 1.0, active)?
+            }
+            value => {
+                let end = check_length(target.borrow().len() as u64 + 1)?;
+                set_element(vm, target, end - 1, value)?;
+            }
+        }
+    }
+    active.pop();
+    Ok(())
+}
+
+fn flat(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "flat")?;
+    let depth = match arg(args, 0) {
+        Value::Undefined => 1.0,
+        depth => to_integer(&depth),
+    };
+    let result = vm.new_array(JsArray::new())?;
+    let Value::Array(target) = &result else {
+        unreachable!()
+    };
+    flatten_into(vm, target, &arr, depth, &mut Vec::new())?;
+    Ok(result)
+}
+
+fn flat_map(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "flatMap")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let result = vm.new_array(JsArray::new())?;
+    let Value::Array(target) = &result else {
+        unreachable!()
+    };
+    let len = arr.borrow().len();
+    let mut k = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        match call_back(vm, &callback, &this_arg, value, i, &this)? {
+            Value::Array(inner) => flatten_into(vm, target, &inner, 0.0, &mut Vec::new())?,
+            value => {
+                let end = check_length(target.borrow().len() as u64 + 1)?;
+                set_element(vm, target, end - 1, value)?;
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn for_each(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "forEach")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    let mut k = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        call_back(vm, &callback, &this_arg, value, i, &this)?;
+    }
+    Ok(Value::Undefined)
+}
+
+fn includes(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "includes")?;
+    let arr = arr.borrow();
+    let search = arg(args, 0);
+    let start = relative_index(&arg(args, 1), arr.len(), 0);
+    let mut present = 0;
+    for (_, value) in arr.iter().skip_while(|(i, _)| *i < start) {
+        if same_value_zero(value, &search) {
+            return Ok(Value::Boolean(true));
+        }
+        present += 1;
+    }
+    // Holes read as undefined
+    let has_hole = present < arr.len() - start;
+    Ok(Value::Boolean(search.is_undefined() && has_hole))
+}
+
+fn index_of(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "indexOf")?;
+    let arr = arr.borrow();
+    let search = arg(args, 0);
+    let start = relative_index(&arg(args, 1), arr.len(), 0);
+    let found = arr
+        .iter()
+        .skip_while(|(i, _)| *i < start)
+        .find(|(_, value)| **value == search);
+    Ok(Value::Number(found.map_or(-1.0, |(i, _)| i as f64)))
+}
+
+fn join_method(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "join")?;
+    let separator = match arg(args, 0) {
+        Value::Undefined => JsString::from(","),
+        separator => separator.to_js_string(),
+    };
+    // The separators alone may be too long to build
+    let separators = arr.borrow().len().saturating_sub(1) as usize;
+    if separators.saturating_mul(separator.len()) > MAX_STRING_LENGTH {
+        return Err(RuntimeError::RangeError(
+            "Invalid string length".to_string(),
+        ));
+    }
+    // Charge the output as it grows, like `JSON.stringify`
+    let mut charged = 0;
+    let joined = join_with(&arr, &separator, |len| {
+        if len > MAX_STRING_LENGTH {
+            return Err(RuntimeError::RangeError(
+                "Invalid string length".to_string(),
+            ));
+        }
+        vm.tick()?;
+        if len > charged {
+            vm.reserve_scratch((len - charged) * size_of::<u16>())?;
+            charged = len;
+        }
+        Ok(())
+    });
+    vm.release_scratch(charged * size_of::<u16>());
+    Ok(Value::String(joined?))
+}
+
+fn keys(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    iterate(vm, &this, "keys", IterationKind::Keys)
+}
+
+/// Shadows `Object.prototype.toString`, so that arrays print their
+/// elements.
+fn to_string(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    join_method(vm, this, &[])
+}
+
+fn map(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "map")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    let result = vm.new_array(JsArray::new())?;
+    let Value::Array(target) = &result else {
+        unreachable!()
+    };
+    // Holes stay holes
+    let cost = target.borrow().set_len_cost(len);
+    vm.charge(cost)?;
+    target.borrow_mut().set_len(len);
+    let mut k = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        let mapped = call_back(vm, &callback, &this_arg, value, i, &this)?;
+        set_element(vm, target, i, mapped)?;
+    }
+    Ok(result)
+}
+
+fn pop(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "pop")?;
+    let mut arr = arr.borrow_mut();
+    let Some(last) = arr.len().checked_sub(1) else {
+        return Ok(Value::Undefined);
+    };
+    let value = arr.get(last).cloned().unwrap_or(Value::Undefined);
+    arr.set_len(last);
+    Ok(value)
+}
+
+fn push(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "push")?;
+    let len = arr.borrow().len();
+    let new_len = check_length(len as u64 + args.len() as u64)?;
+    for (i, value) in args.iter().enumerate() {
+        set_element(vm, &arr, len + i as u32, value.clone())?;
+    }
+    Ok(Value::Number(new_len as f64))
+}
+
+fn reduce(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "reduce")?;
+    let callback = arg(args, 0);
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    let mut k = 0;
+    let mut accumulator = match args.get(1) {
+        Some(initial) => initial.clone(),
+        None => match next_element(&arr, 0, len) {
+            Some((i, value)) => {
+                k = i + 1;
+                value
+            }
+            None => return Err(empty_reduce()),
+        },
+    };
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        accumulator = vm.call_function(
+            &callback,
+            Value::Undefined,
+            &[accumulator, value, Value::Number(i as f64), this.clone()],
+        )?;
+    }
+    Ok(accumulator)
+}
+
+fn reduce_right(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "reduceRight")?;
+    let callback = arg(args, 0);
+    callable(&callback)?;
+    let mut k = arr.borrow().len().checked_sub(1);
+    let mut accumulator = match args.get(1) {
+        Some(initial) => initial.clone(),
+        None => match k.and_then(|k| prev_element(&arr, k)) {
+            Some((i, value)) => {
+                k = i.checked_sub(1);
+                value
+            }
+            None => return Err(empty_reduce()),
+        },
+    };
+    while let Some((i, value)) = k.and_then(|k| prev_element(&arr, k)) {
+        k = i.checked_sub(1);
+        accumulator = vm.call_function(
+            &callback,
+            Value::Undefined,
+            &[accumulator, value, Value::Number(i as f64), this.clone()],
+        )?;
+    }
+    Ok(accumulator)
+}
+
+fn empty_reduce() -> RuntimeError {
+    RuntimeError::TypeError("Reduce of empty array with no initial value".to_string())
+}
+
+fn reverse(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    this_array(&this, "reverse")?.borrow_mut().reverse();
+    Ok(this)
+}
+
+fn shift(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "shift")?;
+    let removed = arr.borrow_mut().splice(0, 1, Vec::new());
+    Ok(removed.get(0).cloned().unwrap_or(Value::Undefined))
+}
+
+fn slice(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "slice")?;
+    let len = arr.borrow().len();
+    let start = relative_index(&arg(args, 0), len, 0);
+    let end = relative_index(&arg(args, 1), len, len);
+    let result = arr.borrow().slice(start, end);
+    vm.new_array(result)
+}
+
+fn some(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "some")?;
+    let (callback, this_arg) = (arg(args, 0), arg(args, 1));
+    callable(&callback)?;
+    let len = arr.borrow().len();
+    let mut k = 0;
+    while let Some((i, value)) = next_element(&arr, k, len) {
+        k = i + 1;
+        if call_back(vm, &callback, &this_arg, value, i, &this)?.to_boolean() {
+            return Ok(Value::Boolean(true));
+        }
+    }
+    Ok(Value::Boolean(false))
+}
+
+/// A stable merge sort whose comparison may fail, unlike the standard
+/// library's sorts.
+fn merge_sort<T>(
+    items: &mut Vec<T>,
+    compare: &mut impl FnMut(&T, &T) -> Result<Ordering, RuntimeError>,
+) -> Result<(), RuntimeError> {
+    if items.len() <= 1 {
+        return Ok(());
+    }
+    let mut right = items.split_off(items.len() / 2);
+    let mut left = std::mem::take(items);
+    merge_sort(&mut left, compare)?;
+    merge_sort(&mut right, compare)?;
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+        // Equal elements keep their order by taking from the left first
+        let next = if compare(r, l)? == Ordering::Less {
+            right.next()
+        } else {
+            left.next()
+        };
+        items.extend(next);
+    }
+    items.extend(left);
+    items.extend(right);
+    Ok(())
+}
+
+fn sort(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "sort")?;
+    let comparator = arg(args, 0);
+    if !comparator.is_undefined() && !comparator.is_function() {
+        return Err(RuntimeError::TypeError(
+            "The comparison function must be either a function or undefined".to_string(),
+        ));
+    }
+    let (mut values, undefined, len) = {
+        let arr = arr.borrow();
+        let (undefined, values): (Vec<_>, Vec<_>) = arr
+            .iter()
+            .map(|(_, value)| value.clone())
+            .partition(Value::is_undefined);
+        (values, undefined, arr.len())
+    };
+    if comparator.is_undefined() {
+        // Without a comparator, elements sort by their strings
+        let mut keyed: Vec<_> = values.into_iter().map(|v| (v.to_js_string(), v)).collect();
+        merge_sort(&mut keyed, &mut |a, b| Ok(a.0.cmp(&b.0)))?;
+        values = keyed.into_iter().map(|(_, v)| v).collect();
+    } else {
+        merge_sort(&mut values, &mut |a, b| {
+            let order = vm
+                .call_function(&comparator, Value::Undefined, &[a.clone(), b.clone()])?
+                .to_number();
+            Ok(order.partial_cmp(&0.0).unwrap_or(Ordering::Equal))
+        })?;
+    }
+    // Undefined sorts after everything else, and holes after that
+    values.extend(undefined);
+    arr.borrow_mut().set_elements(values, len);
+    Ok(this)
+}
+
+fn splice(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "splice")?;
+    let len = arr.borrow().len();
+    let start = relative_index(&arg(args, 0), len, 0);
+    let delete = match args.len() {
+        0 => 0,
+        1 => len - start,
+        _ => to_integer(&args[1]).clamp(0.0, (len - start) as f64) as u32,
+    };
+    let items = args.get(2..).unwrap_or_default().to_vec();
+    check_length(len as u64 - delete as u64 + items.len() as u64)?;
+    vm.charge(items.len() * ELEMENT_SIZE)?;
+    let removed = arr.borrow_mut().splice(start, delete, items);
+    vm.new_array(removed)
+}
+
+fn unshift(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let arr = this_array(&this, "unshift")?;
+    let len = arr.borrow().len();
+    let new_len = check_length(len as u64 + args.len() as u64)?;
+    vm.charge(args.len() * ELEMENT_SIZE)?;
+    arr.borrow_mut().splice(0, 0, args.to_vec());
+    Ok(Value::Number(new_len as f64))
+}
+
+fn values(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    iterate(vm, &this, "values", IterationKind::Values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_join_limits() {
+        let mut vm = VM::default();
+        vm.eval("var a = []; a[1e8] = 1;").unwrap();
+        vm.set_fuel(Some(100_000));
+        assert_eq!(vm.eval("a.join('-')"), Err(RuntimeError::OutOfFuel));
+        vm.set_fuel(None);
+        vm.set_heap_limit(Some(1 << 20));
+        assert_eq!(vm.eval("a.join('-')"), Err(RuntimeError::OutOfMemory));
+        // Empty separators are skipped rather than written one by one
+        assert_eq!(vm.eval("a[1e9] = 2; a.join('')"), Ok(Value::from("12")));
+        assert_eq!(vm.eval("[1, 2].join('-')"), Ok(Value::from("1-2")));
+        assert_eq!(
+            eval(
+                "try { var b = []; b.length = 1000; b.join('x'.repeat(1e6)) } catch (e) { e.name }"
+            ),
+            "RangeError"
+        );
+    }
+
+    #[test]
+    fn test_mutators() {
+        assert_eq!(
+            eval("var a = [1, 2]; [a.push(3, 4), a.pop(), a.shift(), a.unshift(0), a].join(' ')"),
+            "4 4 1 3 0,2,3"
+        );
+        assert_eq!(
+            eval("var a = [1, 2, 3, 4, 5]; var r = a.splice(1, 2, 'x'); r + '|' + a"),
+            "2,3|1,x,4,5"
+        );
+        assert_eq!(eval("[1, 2, 3].reverse().fill(0, -1)"), "3,2,0");
+        assert_eq!(
+            eval("[].pop() === undefined && [].shift() === undefined"),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_accessors() {
+        assert_eq!(eval("[1, 2, 3, 4].slice(1, -1)"), "2,3");
+        assert_eq!(eval("[1].concat(2, [3, [4]]).length"), "4");
+        assert_eq!(
+            eval("[1, 2, 1].indexOf(1, 1) + [0 / 0].indexOf(0 / 0)"),
+            "1"
+        );
+        assert_eq!(
+            eval("[0 / 0].includes(0 / 0) && [, 1].includes(undefined)"),
+            "true"
+        );
+        assert_eq!(
+            eval("[1, 2, 3].at(-1) + ':' + [1, 2, 3].at(5)"),
+            "3:undefined"
+        );
+        assert_eq!(eval("[1, [2, [3, [4]]]].flat(1 / 0).join('-')"), "1-2-3-4");
+        assert_eq!(eval("[null, undefined, 1].join()"), ",,1");
+    }
+
+    #[test]
+    fn test_callbacks() {
+        let source = "
+            var a = [1, 2, 3, 4];
+            var odd = function (x) { return x % 2 == 1; };
+            var log = [];
+            a.forEach(function (x, i) { log.push(i + ':' + x); });
+            [
+                a.map(function (x) { return x * 2; }).join(),
+                a.filter(odd).join(),
+                a.find(odd),
+                a.findIndex(function (x) { return x > 2; }),
+                a.some(odd),
+                a.every(odd),
+                a.reduce(function (acc, x) { return acc + x; }),
+                a.reduceRight(function (acc, x) { return acc + x; }, ''),
+                a.flatMap(function (x) { return [x, x]; }).length,
+                log.join()
+            ].join(' ');
+        ";
+        assert_eq!(
+            eval(source),
+            "2,4,6,8 1,3 1 2 true false 10 4321 8 0:1,1:2,2:3,3:4"
+        );
+    }
+
+    #[test]
+    fn test_callbacks_skip_holes() {
+        let source = "
+            var visited = 0;
+            var a = [1, , 3];
+            a.forEach(function () { visited++; });
+            var mapped = a.map(function (x) { return x * 2; });
+            [visited, 1 in mapped, mapped.length].join();
+        ";
+        assert_eq!(eval(source), "2,false,3");
+    }
+
+    #[test]
+    fn test_sort() {
+        assert_eq!(eval("[10, 9, 1, undefined, , 2].sort()"), "1,10,2,9,,");
+        let source = "
+            var people = [['b', 2], ['a', 1], ['c', 2], ['d', 1]];
+            people.sort(function (x, y) { return x[1] - y[1]; });
+            people.map(function (p) { return p[0]; }).join('');
+        ";
+        assert_eq!(eval(source), "adbc");
+        assert_eq!(
+            eval(
+                "var r; try { [2, 1].sort(function () { throw 'stop'; }); } catch (e) { r = e; } r"
+            ),
+            "stop"
+        );
+    }
+
+    #[test]
+    fn test_statics() {
+        assert_eq!(eval("Array.isArray([]) && !Array.isArray('')"), "true");
+        assert_eq!(eval("Array.of(7).length + Array(7).length"), "8");
+        assert_eq!(
+            eval("Array.from('a\u{1F600}b').length + Array.from({ length: 2, 0: 'x' }).join('|')"),
+            "3x|"
+        );
+        assert_eq!(
+            eval("Array.from([1, 2], function (x, i) { return x + i; })"),
+            "1,3"
+        );
+        assert_eq!(eval("[].constructor === Array && 'push' in []"), "true");
+    }
+
+    #[test]
+    fn test_iterators() {
+        assert_eq!(
+            eval("var it = ['a', 'b'].entries(); var r = it.next(); it.next(); [r.value, r.done, JSON.stringify(it.next())].join(' ')"),
+            "0,a false {\"done\":true}"
+        );
+        assert_eq!(
+            eval("var it = [][Symbol.iterator](); [it[Symbol.iterator]() === it, String(it[Symbol.toStringTag])].join()"),
+            "true,Array Iterator"
+        );
+        assert_eq!(
+            eval("Array.prototype[Symbol.iterator] === Array.prototype.values && Array.from([1, 2].keys()).join() === '0,1'"),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        assert!(matches!(
+            vm.eval("[].map(1)"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            vm.eval("[].reduce(function () {})"),
+            Err(RuntimeError::TypeError(_))
+        ));
+        assert!(matches!(
+            vm.eval("Array(-1)"),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert!(matches!(
+            vm.eval("var a = []; a[0] = a; a.flat(1 / 0)"),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert_eq!(vm.eval("a + ''"), Ok(Value::from("")));
+    }
+}
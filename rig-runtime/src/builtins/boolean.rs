@@ -0,0 +1,62 @@
+//! The `Boolean` function and `Boolean.prototype`.
+
+use super::{arg, define_methods};
+use crate::{NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.boolean_prototype.clone();
+    define_methods(
+        &prototype,
+        &[("toString", to_string), ("valueOf", value_of)],
+    );
+    let ctor = NativeFunction::new("Boolean", construct);
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Boolean", ctor);
+}
+
+fn this_boolean(this: &Value, method: &str) -> Result<bool, RuntimeError> {
+    match this {
+        Value::Boolean(b) => Ok(*b),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Boolean.prototype.{method} requires that 'this' be a Boolean"
+        ))),
+    }
+}
+
+fn construct(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(arg(args, 0).to_boolean()))
+}
+
+fn to_string(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let b = this_boolean(&this, "toString")?;
+    Ok(Value::from(if b { "true" } else { "false" }))
+}
+
+fn value_of(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    this_boolean(&this, "valueOf").map(Value::Boolean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_boolean() {
+        assert_eq!(
+            eval("[true.toString(), false.toString(), true.valueOf(), Boolean(''), Boolean('x')].join()"),
+            "true,false,true,false,true"
+        );
+        assert_eq!(
+            eval("[Object.getPrototypeOf(false) === Boolean.prototype, [0, 1, '', 'a'].filter(Boolean).length].join()"),
+            "true,2"
+        );
+        assert_eq!(
+            VM::default().eval("Boolean.prototype.toString.call(1)"),
+            Err(RuntimeError::TypeError(
+                "Boolean.prototype.toString requires that 'this' be a Boolean".to_string()
+            ))
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! The keyed collections: `Map`, `Set`, `WeakMap` and `WeakSet`.
+//!
+//! Iterators and `forEach` visit entries added during the walk and skip
+//! ones deleted before they are reached.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{
+    arg, callable, define_iterator, define_methods, define_to_string_tag, iterable_values, Builtin,
+};
+use crate::collection::{OrderedTable, WeakTable};
+use crate::heap::{ENTRY_SIZE, OBJECT_SIZE};
+use crate::iterator::{Iteration, IterationKind};
+use crate::object::ObjectKind;
+use crate::{JsObject, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    install_collection(
+        vm,
+        "Map",
+        vm.intrinsics.map_prototype.clone(),
+        construct_map,
+        &[
+            ("clear", map_clear),
+            ("delete", map_delete),
+            ("entries", map_entries),
+            ("forEach", map_for_each),
+            ("get", map_get),
+            ("has", map_has),
+            ("keys", map_keys),
+            ("set", map_set),
+            ("values", map_values),
+        ],
+    );
+    let map_prototype = vm.intrinsics.map_prototype.clone();
+    let entries = map_prototype.borrow().get("entries").cloned();
+    if let Some(entries) = entries {
+        define_iterator(&map_prototype, entries);
+    }
+    install_collection(
+        vm,
+        "Set",
+        vm.intrinsics.set_prototype.clone(),
+        construct_set,
+        &[
+            ("add", set_add),
+            ("clear", set_clear),
+            ("delete", set_delete),
+            ("entries", set_entries),
+            ("forEach", set_for_each),
+            ("has", set_has),
+            ("keys", set_values),
+            ("values", set_values),
+        ],
+    );
+    let set_prototype = vm.intrinsics.set_prototype.clone();
+    let values = set_prototype.borrow().get("values").cloned();
+    if let Some(values) = values {
+        define_iterator(&set_prototype, values);
+    }
+    install_collection(
+        vm,
+        "WeakMap",
+        vm.intrinsics.weak_map_prototype.clone(),
+        construct_weak_map,
+        &[
+            ("delete", weak_map_delete),
+            ("get", weak_map_get),
+            ("has", weak_map_has),
+            ("set", weak_map_set),
+        ],
+    );
+    install_collection(
+        vm,
+        "WeakSet",
+        vm.intrinsics.weak_set_prototype.clone(),
+        construct_weak_set,
+        &[
+            ("add", weak_set_add),
+            ("delete", weak_set_delete),
+            ("has", weak_set_has),
+        ],
+    );
+}
+
+fn install_collection(
+    vm: &mut VM,
+    name: &str,
+    prototype: Rc<RefCell<JsObject>>,
+    construct: Builtin,
+    methods: &[(&str, Builtin)],
+) {
+    define_methods(&prototype, methods);
+    define_to_string_tag(&prototype, name);
+    let ctor = NativeFunction::constructor(name, construct);
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global(name, ctor);
+}
+
+/// The number of entries in a map or set, for the `size` accessor.
+pub(super) fn size(receiver: &Value) -> Option<Value> {
+    let Value::Object(obj) = receiver else {
+        return None;
+    };
+    match obj.borrow().kind() {
+        ObjectKind::Map(table) | ObjectKind::Set(table) => Some(Value::Number(table.len() as f64)),
+        _ => None,
+    }
+}
+
+fn incompatible(method: &str, this: &Value) -> RuntimeError {
+    RuntimeError::TypeError(format!(
+        "Method {method} called on incompatible receiver {}",
+        this.to_js_string()
+    ))
+}
+
+/// Runs `f` on the table behind `this`, which `select` picks out of the
+/// object's internal state.
+fn with_table<T, R>(
+    this: &Value,
+    method: &str,
+    select: fn(&mut ObjectKind) -> Option<&mut T>,
+    f: impl FnOnce(&mut T) -> R,
+) -> Result<R, RuntimeError> {
+    let Value::Object(obj) = this else {
+        return Err(incompatible(method, this));
+    };
+    let mut obj = obj.borrow_mut();
+    match select(obj.kind_mut()) {
+        Some(table) => Ok(f(table)),
+        None => Err(incompatible(method, this)),
+    }
+}
+
+/// Starts an iteration over the table behind `this`, for `keys`, `values`
+/// and `entries`.
+fn iterate(
+    vm: &mut VM,
+    this: &Value,
+    method: &str,
+    select: fn(&mut ObjectKind) -> Option<&mut OrderedTable>,
+    kind: IterationKind,
+    prototype: Rc<RefCell<JsObject>>,
+) -> Result<Value, RuntimeError> {
+    let cursor = with_table(this, method, select, OrderedTable::cursor)?;
+    let Value::Object(collection) = this else {
+        unreachable!("with_table accepted a non-object");
+    };
+    let iteration = Iteration::table(collection.clone(), cursor, kind);
+    vm.new_iterator(Value::Object(prototype), iteration)
+}
+
+fn map_table(kind: &mut ObjectKind) -> Option<&mut OrderedTable> {
+    match kind {
+        ObjectKind::Map(table) => Some(table),
+        _ => None,
+    }
+}
+
+fn set_table(kind: &mut ObjectKind) -> Option<&mut OrderedTable> {
+    match kind {
+        ObjectKind::Set(table) => Some(table),
+        _ => None,
+    }
+}
+
+fn weak_map_table(kind: &mut ObjectKind) -> Option<&mut WeakTable> {
+    match kind {
+        ObjectKind::WeakMap(table) => Some(table),
+        _ => None,
+    }
+}
+
+fn weak_set_table(kind: &mut ObjectKind) -> Option<&mut WeakTable> {
+    match kind {
+        ObjectKind::WeakSet(table) => Some(table),
+        _ => None,
+    }
+}
+
+/// Makes a collection and fills it from the `iterable` argument by calling
+/// its `adder` method, as the constructors do. Maps and weak maps take
+/// `[key, value]` entries rather than values.
+fn construct(
+    vm: &mut VM,
+    prototype: Rc<RefCell<JsObject>>,
+    kind: ObjectKind,
+    adder: Builtin,
+    iterable: &Value,
+) -> Result<Value, RuntimeError> {
+    let keyed = matches!(kind, ObjectKind::Map(_) | ObjectKind::WeakMap(_));
+    vm.charge(OBJECT_SIZE)?;
+    let obj = Value::Object(Rc::new(RefCell::new(JsObject::with_kind(
+        Value::Object(prototype),
+        kind,
+    ))));
+    if iterable.is_undefined() || iterable.is_null() {
+        return Ok(obj);
+    }
+    let Some(values) = iterable_values(vm, iterable)? else {
+        return Err(RuntimeError::TypeError(format!(
+            "{} is not iterable",
+            iterable.to_js_string()
+        )));
+    };
+    for value in values {
+        if !keyed {
+            adder(vm, obj.clone(), &[value])?;
+            continue;
+        }
+        if !value.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Iterator value {} is not an entry object",
+                value.to_js_string()
+            )));
+        }
+        let key = vm.get_property(&value, &Value::Number(0.0))?;
+        let value = vm.get_property(&value, &Value::Number(1.0))?;
+        adder(vm, obj.clone(), &[key, value])?;
+    }
+    Ok(obj)
+}
+
+fn construct_map(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.map_prototype.clone();
+    let kind = ObjectKind::Map(OrderedTable::default());
+    construct(vm, prototype, kind, map_set, &arg(args, 0))
+}
+
+fn construct_set(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.set_prototype.clone();
+    let kind = ObjectKind::Set(OrderedTable::default());
+    construct(vm, prototype, kind, set_add, &arg(args, 0))
+}
+
+fn construct_weak_map(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.weak_map_prototype.clone();
+    let kind = ObjectKind::WeakMap(WeakTable::default());
+    construct(vm, prototype, kind, weak_map_set, &arg(args, 0))
+}
+
+fn construct_weak_set(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.weak_set_prototype.clone();
+    let kind = ObjectKind::WeakSet(WeakTable::default());
+    construct(vm, prototype, kind, weak_set_add, &arg(args, 0))
+}
+
+/// Calls `callback` on each entry of the table behind `this`, reading the
+/// table afresh each step since the callback may change it. A set passes
+/// each value as its own key.
+fn for_each(
+    vm: &mut VM,
+    this: &Value,
+    method: &str,
+    select: fn(&mut ObjectKind) -> Option<&mut OrderedTable>,
+    is_set: bool,
+    args: &[Value],
+) -> Result<(), RuntimeError> {
+    let cursor = with_table(this, method, select, OrderedTable::cursor)?;
+    let callback = arg(args, 0);
+    callable(&callback)?;
+    let this_arg = arg(args, 1);
+    while let Some((key, value)) = with_table(this, method, select, |table| table.next(&cursor))? {
+        let value = if is_set { key.clone() } else { value };
+        vm.call_function(&callback, this_arg.clone(), &[value, key, this.clone()])?;
+    }
+    Ok(())
+}
+
+fn map_clear(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    with_table(&this, "Map.prototype.clear", map_table, OrderedTable::clear)?;
+    Ok(Value::Undefined)
+}
+
+fn map_delete(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let deleted = with_table(&this, "Map.prototype.delete", map_table, |table| {
+        table.remove(&key)
+    })?;
+    Ok(Value::Boolean(deleted))
+}
+
+fn map_entries(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.map_iterator_prototype.clone();
+    let kind = IterationKind::Entries;
+    iterate(
+        vm,
+        &this,
+        "Map.prototype.entries",
+        map_table,
+        kind,
+        prototype,
+    )
+}
+
+fn map_for_each(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    for_each(vm, &this, "Map.prototype.forEach", map_table, false, args)?;
+    Ok(Value::Undefined)
+}
+
+fn map_get(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    with_table(&this, "Map.prototype.get", map_table, |table| {
+        table.get(&key).cloned().unwrap_or(Value::Undefined)
+    })
+}
+
+fn map_has(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let has = with_table(&this, "Map.prototype.has", map_table, |table| {
+        table.has(&key)
+    })?;
+    Ok(Value::Boolean(has))
+}
+
+fn map_keys(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.map_iterator_prototype.clone();
+    let kind = IterationKind::Keys;
+    iterate(vm, &this, "Map.prototype.keys", map_table, kind, prototype)
+}
+
+fn map_set(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let value = arg(args, 1);
+    let has = with_table(&this, "Map.prototype.set", map_table, |table| {
+        table.has(&key)
+    })?;
+    if !has {
+        vm.charge(ENTRY_SIZE)?;
+    }
+    with_table(&this, "Map.prototype.set", map_table, |table| {
+        table.insert(&key, value)
+    })?;
+    Ok(this)
+}
+
+fn map_values(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.map_iterator_prototype.clone();
+    let kind = IterationKind::Values;
+    iterate(
+        vm,
+        &this,
+        "Map.prototype.values",
+        map_table,
+        kind,
+        prototype,
+    )
+}
+
+fn set_add(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let has = with_table(&this, "Set.prototype.add", set_table, |table| {
+        table.has(&value)
+    })?;
+    if !has {
+        vm.charge(ENTRY_SIZE)?;
+        with_table(&this, "Set.prototype.add", set_table, |table| {
+            table.insert(&value, Value::Undefined)
+        })?;
+    }
+    Ok(this)
+}
+
+fn set_clear(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    with_table(&this, "Set.prototype.clear", set_table, OrderedTable::clear)?;
+    Ok(Value::Undefined)
+}
+
+fn set_delete(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let deleted = with_table(&this, "Set.prototype.delete", set_table, |table| {
+        table.remove(&value)
+    })?;
+    Ok(Value::Boolean(deleted))
+}
+
+fn set_entries(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.set_iterator_prototype.clone();
+    let kind = IterationKind::Entries;
+    iterate(
+        vm,
+        &this,
+        "Set.prototype.entries",
+        set_table,
+        kind,
+        prototype,
+    )
+}
+
+fn set_for_each(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    for_each(vm, &this, "Set.prototype.forEach", set_table, true, args)?;
+    Ok(Value::Undefined)
+}
+
+fn set_has(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let has = with_table(&this, "Set.prototype.has", set_table, |table| {
+        table.has(&value)
+    })?;
+    Ok(Value::Boolean(has))
+}
+
+fn set_values(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let prototype = vm.intrinsics.set_iterator_prototype.clone();
+    let kind = IterationKind::Values;
+    iterate(
+        vm,
+        &this,
+        "Set.prototype.values",
+        set_table,
+        kind,
+        prototype,
+    )
+}
+
+fn weak_map_delete(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let deleted = with_table(&this, "WeakMap.prototype.delete", weak_map_table, |table| {
+        table.remove(&key)
+    })?;
+    Ok(Value::Boolean(deleted))
+}
+
+fn weak_map_get(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    with_table(&this, "WeakMap.prototype.get", weak_map_table, |table| {
+        table.get(&key).cloned().unwrap_or(Value::Undefined)
+    })
+}
+
+fn weak_map_has(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let has = with_table(&this, "WeakMap.prototype.has", weak_map_table, |table| {
+        table.has(&key)
+    })?;
+    Ok(Value::Boolean(has))
+}
+
+fn weak_map_set(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = arg(args, 0);
+    let value = arg(args, 1);
+    let has = with_table(&this, "WeakMap.prototype.set", weak_map_table, |table| {
+        table.has(&key)
+    })?;
+    if !key.is_object() {
+        return Err(RuntimeError::TypeError(format!(
+            "Invalid value used as weak map key: {}",
+            key.to_js_string()
+        )));
+    }
+    if !has {
+        vm.charge(ENTRY_SIZE)?;
+    }
+    with_table(&this, "WeakMap.prototype.set", weak_map_table, |table| {
+        table.insert(&key, value)
+    })?;
+    Ok(this)
+}
+
+fn weak_set_add(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let has = with_table(&this, "WeakSet.prototype.add", weak_set_table, |table| {
+        table.has(&value)
+    })?;
+    if !value.is_object() {
+        return Err(RuntimeError::TypeError(format!(
+            "Invalid value used in weak set: {}",
+            value.to_js_string()
+        )));
+    }
+    if !has {
+        vm.charge(ENTRY_SIZE)?;
+        with_table(&this, "WeakSet.prototype.add", weak_set_table, |table| {
+            table.insert(&value, Value::Undefined)
+        })?;
+    }
+    Ok(this)
+}
+
+fn weak_set_delete(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let deleted = with_table(&this, "WeakSet.prototype.delete", weak_set_table, |table| {
+        table.remove(&value)
+    })?;
+    Ok(Value::Boolean(deleted))
+}
+
+fn weak_set_has(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    let has = with_table(&this, "WeakSet.prototype.has", weak_set_table, |table| {
+        table.has(&value)
+    })?;
+    Ok(Value::Boolean(has))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_map() {
+        let source = "
+            var m = new Map([[1, 'a'], ['1', 'b']]);
+            m.set(0 / 0, 'nan').set(-0, 'zero').set(1, 'A');
+            [m.size, m.get(1), m.get('1'), m.get(0 / 0), m.get(0), m.has(2), Array.from(m.keys()).join()].join();
+        ";
+        assert_eq!(eval(source), "4,A,b,nan,zero,false,1,1,NaN,0");
+        assert_eq!(
+            eval("var m = new Map(); m.set('a', 1); m.set('b', 2); [m.delete('a'), m.delete('a'), m.size, JSON.stringify(Array.from(m.entries()))].join()"),
+            "true,false,1,[[\"b\",2]]"
+        );
+        assert_eq!(
+            eval("var k = {}; var m = new Map([[k, 1]]); [m.get(k), m.get({}), m.hasOwnProperty('size'), Map.prototype.size].join()"),
+            "1,,false,"
+        );
+    }
+
+    #[test]
+    fn test_set() {
+        assert_eq!(
+            eval("var s = new Set('hello'); s.add('o').add(1); [s.size, s.has('l'), Array.from(s.values()).join(''), Array.from(s).length].join()"),
+            "5,true,helo1,5"
+        );
+        assert_eq!(
+            eval("var s = new Set([1, 2]); JSON.stringify([Array.from(s.entries()), Array.from(new Set(s).keys())])"),
+            "[[[1,1],[2,2]],[1,2]]"
+        );
+    }
+
+    #[test]
+    fn test_for_each_sees_changes() {
+        let source = "
+            var m = new Map([['a', 1], ['b', 2], ['c', 3]]);
+            var seen = [];
+            m.forEach(function (value, key, map) {
+                seen.push(key + value);
+                if (key === 'a') { map.delete('b'); map.set('d', 4); }
+            });
+            seen.join();
+        ";
+        assert_eq!(eval(source), "a1,c3,d4");
+        let source = "
+            var s = new Set([1]);
+            var seen = [];
+            s.forEach(function (value, key) {
+                seen.push(value + key);
+                if (value < 3) { s.clear(); s.add(value + 1); }
+            });
+            seen.join();
+        ";
+        assert_eq!(eval(source), "2,4,6");
+    }
+
+    #[test]
+    fn test_weak_collections() {
+        assert_eq!(
+            eval("var k = []; var w = new WeakMap([[k, 1]]); var s = new WeakSet([k]); [w.get(k), w.has([]), s.has(k), s.delete(k), s.has(k), w.get(1)].join()"),
+            "1,false,true,true,false,"
+        );
+        let mut vm = VM::default();
+        vm.eval("var w = new WeakMap(); var k = {}; w.set(k, 'value'); k = null")
+            .unwrap();
+        let Value::Object(weak_map) = vm.get_global("w").unwrap() else {
+            panic!("not an object");
+        };
+        let live = match weak_map.borrow().kind() {
+            ObjectKind::WeakMap(table) => table.values().count(),
+            _ => panic!("not a weak map"),
+        };
+        assert_eq!(live, 0);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        for (source, message) in [
+            (
+                "var get = new Map().get; get(1)",
+                "Method Map.prototype.get called on incompatible receiver undefined",
+            ),
+            ("new Map([1])", "Iterator value 1 is not an entry object"),
+            ("new Set(1)", "1 is not iterable"),
+            (
+                "new WeakMap().set(1, 2)",
+                "Invalid value used as weak map key: 1",
+            ),
+            (
+                "new WeakSet().add('x')",
+                "Invalid value used in weak set: x",
+            ),
+            ("new Map().forEach(1)", "1 is not a function"),
+            ("Map()", "Constructor Map requires 'new'"),
+            ("WeakMap([])", "Constructor WeakMap requires 'new'"),
+        ] {
+            match vm.eval(source) {
+                Err(RuntimeError::TypeError(err)) => assert_eq!(err, message, "{source}"),
+                other => panic!("{source}: {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+//! `Function.prototype`, which script and native functions inherit from.
+//!
+//! There is no `Function` constructor, as the VM does not compile source
+//! at run time on behalf of scripts.
+
+use super::{arg, callable, define_methods, to_integer};
+use crate::heap::ELEMENT_SIZE;
+use crate::{RuntimeError, Value, VM};
+
+/// Most arguments `apply` passes on, the limit JavaScriptCore puts on a
+/// call. A longer list fails the way an overflowing stack does.
+const MAX_ARGUMENTS: usize = 65_535;
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.function_prototype.clone();
+    define_methods(&prototype, &[("apply", apply), ("call", call)]);
+}
+
+fn call(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    callable(&this)?;
+    let rest = args.get(1..).unwrap_or_default();
+    vm.call_function(&this, arg(args, 0), rest)
+}
+
+fn apply(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    callable(&this)?;
+    let list = list_from_array_like(vm, &arg(args, 1))?;
+    vm.call_function(&this, arg(args, 0), &list)
+}
+
+/// The arguments `apply` reads from an array or array-like object, with
+/// undefined or null meaning none.
+fn list_from_array_like(vm: &mut VM, value: &Value) -> Result<Vec<Value>, RuntimeError> {
+    let len = match value {
+        Value::Undefined | Value::Null => return Ok(Vec::new()),
+        Value::Array(arr) => arr.borrow().len() as f64,
+        _ if value.is_object() => to_integer(&vm.get_property(value, &Value::from("length"))?),
+        _ => {
+            return Err(RuntimeError::TypeError(
+                "CreateListFromArrayLike called on non-object".to_string(),
+            ))
+        }
+    };
+    let len = len.max(0.0);
+    if len > MAX_ARGUMENTS as f64 {
+        return Err(RuntimeError::RangeError(
+            "Maximum call stack size exceeded".to_string(),
+        ));
+    }
+    let len = len as usize;
+    vm.charge(len * ELEMENT_SIZE)?;
+    let mut list = Vec::with_capacity(len);
+    for i in 0..len {
+        list.push(vm.get_property(value, &Value::Number(i as f64))?);
+    }
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_call_and_apply() {
+        let source = "
+            function f(a, b) { return [this.name, a, b].join(); }
+            var o = { name: 'o' };
+            [f.call(o, 1, 2), f.apply(o, [3]), f.apply(o, { length: 2, 0: 'x', 1: 'y' }),
+             f.call(o), Math.max.apply(null, [1, 5, 2])].join(' ');
+        ";
+        assert_eq!(eval(source), "o,1,2 o,3, o,x,y o,, 5");
+        assert_eq!(
+            eval("[Object.prototype.toString.call([]), Object.prototype.toString.call(null), Object.prototype.hasOwnProperty.call({ a: 1 }, 'a')].join()"),
+            "[object Array],[object Null],true"
+        );
+        assert_eq!(
+            eval("function g() {} [Object.getPrototypeOf(g) === Object.getPrototypeOf(Math.max), g instanceof Object, 'call' in g].join()"),
+            "true,true,true"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        for (source, error) in [
+            (
+                "var call = Object.prototype.toString.call; call()",
+                RuntimeError::TypeError("undefined is not a function".to_string()),
+            ),
+            (
+                "Math.max.apply(null, 1)",
+                RuntimeError::TypeError("CreateListFromArrayLike called on non-object".to_string()),
+            ),
+            (
+                "Math.max.apply(null, { length: 1e9 })",
+                RuntimeError::RangeError("Maximum call stack size exceeded".to_string()),
+            ),
+        ] {
+            assert_eq!(vm.eval(source), Err(error), "{source}");
+        }
+    }
+}
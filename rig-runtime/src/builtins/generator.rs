@@ -0,0 +1,94 @@
+//! The prototypes of the objects generator and async generator functions
+//! return.
+
+use super::{arg, define_methods, define_to_string_tag};
+use crate::generator::Resume;
+use crate::{RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.generator_prototype.clone();
+    prototype
+        .borrow_mut()
+        .set_prototype(Value::Object(vm.intrinsics.iterator_prototype.clone()));
+    define_methods(
+        &prototype,
+        &[
+            ("next", generator_next),
+            ("return", generator_return),
+            ("throw", generator_throw),
+        ],
+    );
+    define_to_string_tag(&prototype, "Generator");
+
+    let prototype = vm.intrinsics.async_generator_prototype.clone();
+    prototype.borrow_mut().set_prototype(Value::Object(
+        vm.intrinsics.async_iterator_prototype.clone(),
+    ));
+    define_methods(
+        &prototype,
+        &[
+            ("next", async_generator_next),
+            ("return", async_generator_return),
+            ("throw", async_generator_throw),
+        ],
+    );
+    define_to_string_tag(&prototype, "AsyncGenerator");
+}
+
+fn generator_next(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.resume_generator(&this, Resume::Next(arg(args, 0)), "next")
+}
+
+fn generator_return(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.resume_generator(&this, Resume::Return(arg(args, 0)), "return")
+}
+
+fn generator_throw(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.resume_generator(&this, Resume::Throw(arg(args, 0)), "throw")
+}
+
+fn async_generator_next(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.async_generator_enqueue(&this, Resume::Next(arg(args, 0)), "next")
+}
+
+fn async_generator_return(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.async_generator_enqueue(&this, Resume::Return(arg(args, 0)), "return")
+}
+
+fn async_generator_throw(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.async_generator_enqueue(&this, Resume::Throw(arg(args, 0)), "throw")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_prototype() {
+        assert_eq!(
+            eval("function* g() {} var it = g(); [it[Symbol.toStringTag], it[Symbol.iterator]() === it, 'next' in it].join()"),
+            "Generator,true,true"
+        );
+    }
+
+    #[test]
+    fn test_async_prototype() {
+        assert_eq!(
+            eval("async function* g() {} var it = g(); [it[Symbol.toStringTag], it[Symbol.asyncIterator]() === it, it.next() instanceof Promise].join()"),
+            "AsyncGenerator,true,true"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        vm.eval("function* g() {} var next = g().next").unwrap();
+        assert_eq!(
+            vm.eval("next()"),
+            Err(RuntimeError::TypeError(
+                "next method called on incompatible receiver undefined".to_string()
+            ))
+        );
+    }
+}
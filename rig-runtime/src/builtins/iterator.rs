@@ -0,0 +1,60 @@
+//! The prototypes of the iterators that arrays, strings, maps and sets hand
+//! out, and the one async iterators share.
+
+use super::define_to_string_tag;
+use crate::object::ObjectKind;
+use crate::{Atom, JsSymbol, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let iterator_prototype = vm.intrinsics.iterator_prototype.clone();
+    iterator_prototype.borrow_mut().properties_mut().insert(
+        Atom::from_symbol(&JsSymbol::iterator()),
+        Value::from(NativeFunction::new("[Symbol.iterator]", iterator)),
+    );
+    vm.intrinsics
+        .async_iterator_prototype
+        .borrow_mut()
+        .properties_mut()
+        .insert(
+            Atom::from_symbol(&JsSymbol::async_iterator()),
+            Value::from(NativeFunction::new("[Symbol.asyncIterator]", iterator)),
+        );
+    for (prototype, tag) in [
+        (&vm.intrinsics.array_iterator_prototype, "Array Iterator"),
+        (&vm.intrinsics.string_iterator_prototype, "String Iterator"),
+        (&vm.intrinsics.map_iterator_prototype, "Map Iterator"),
+        (&vm.intrinsics.set_iterator_prototype, "Set Iterator"),
+    ] {
+        let mut prototype_ref = prototype.borrow_mut();
+        prototype_ref.set_prototype(Value::Object(iterator_prototype.clone()));
+        prototype_ref.insert(
+            "next",
+            NativeFunction::new("next", move |vm, this, _args| next(vm, this, tag)),
+        );
+        drop(prototype_ref);
+        define_to_string_tag(prototype, tag);
+    }
+}
+
+/// Iterators are iterable, yielding themselves, and async iterators are
+/// async iterable the same way.
+fn iterator(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(this)
+}
+
+fn next(vm: &mut VM, this: Value, tag: &str) -> Result<Value, RuntimeError> {
+    let is_iterator = match &this {
+        Value::Object(obj) => matches!(obj.borrow().kind(), ObjectKind::Iterator(_)),
+        _ => false,
+    };
+    if !is_iterator {
+        return Err(RuntimeError::TypeError(format!(
+            "Method {tag}.prototype.next called on incompatible receiver {}",
+            this.to_js_string()
+        )));
+    }
+    match vm.iterator_next(&this)? {
+        Some(value) => vm.iterator_result(value, false),
+        None => vm.iterator_result(Value::Undefined, true),
+    }
+}
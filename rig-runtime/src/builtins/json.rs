@@ -0,0 +1,766 @@
+//! The `JSON` namespace object.
+//!
+//! Both directions recurse on the nesting of the data, so nesting is limited
+//! to `MAX_DEPTH` levels to keep deep input from overflowing the native
+//! stack.
+
+use std::cell::RefCell;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use super::{arg, define_methods, define_to_string_tag, own_keys, to_integer, MAX_STRING_LENGTH};
+use crate::array::Key;
+use crate::heap::{ENTRY_SIZE, OBJECT_SIZE};
+use crate::number;
+use crate::{Atom, JsArray, JsObject, JsString, Properties, RuntimeError, Value, VM};
+
+/// How deeply arrays and objects may nest.
+const MAX_DEPTH: usize = 1000;
+
+pub(super) fn install(vm: &mut VM) {
+    let json = Rc::new(RefCell::new(JsObject::new()));
+    define_methods(&json, &[("parse", parse), ("stringify", stringify)]);
+    define_to_string_tag(&json, "JSON");
+    vm.set_global("JSON", Value::Object(json));
+}
+
+fn too_deep() -> RuntimeError {
+    RuntimeError::RangeError("Maximum call stack size exceeded".to_string())
+}
+
+fn parse(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let text: Vec<u16> = arg(args, 0).to_js_string().code_units().collect();
+    let value = parse_json(vm, &text)?;
+    let reviver = arg(args, 1);
+    if !reviver.is_function() {
+        return Ok(value);
+    }
+    let mut root = JsObject::new();
+    root.insert("", value);
+    let holder = Value::Object(Rc::new(RefCell::new(root)));
+    internalize(vm, &holder, JsString::from(""), &reviver, 0)
+}
+
+/// Parses `text` as `JSON.parse` does without a reviver, whatever scripts
+/// have done to the global `JSON`.
+pub(crate) fn parse_json(vm: &mut VM, text: &[u16]) -> Result<Value, RuntimeError> {
+    let mut parser = Parser {
+        vm,
+        text,
+        pos: 0,
+        depth: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos < text.len() {
+        return Err(RuntimeError::SyntaxError(format!(
+            "Unexpected non-whitespace character after JSON at position {}",
+            parser.pos
+        )));
+    }
+    Ok(value)
+}
+
+/// Passes every value below `holder[name]` to the reviver, innermost first,
+/// replacing each with what the reviver returns or deleting it if that is
+/// undefined.
+fn internalize(
+    vm: &mut VM,
+    holder: &Value,
+    name: JsString,
+    reviver: &Value,
+    depth: usize,
+) -> Result<Value, RuntimeError> {
+    if depth > MAX_DEPTH {
+        return Err(too_deep());
+    }
+    let value = vm.get_property(holder, &Value::String(name.clone()))?;
+    match &value {
+        Value::Array(arr) => {
+            let len = arr.borrow().len();
+            for i in 0..len {
+                let key = JsString::from(i.to_string());
+                let element = internalize(vm, &value, key, reviver, depth + 1)?;
+                if element.is_undefined() {
+                    arr.borrow_mut().delete(i);
+                } else {
+                    vm.set_property(&value, &Value::Number(i as f64), element)?;
+                }
+            }
+        }
+        Value::Object(obj) => {
+            let keys = own_keys(obj.borrow().properties());
+            for key in keys {
+                let name = key.as_js_string().clone();
+                let property = internalize(vm, &value, name.clone(), reviver, depth + 1)?;
+                if property.is_undefined() {
+                    obj.borrow_mut().properties_mut().shift_remove(&key);
+                } else {
+                    vm.set_property(&value, &Value::String(name), property)?;
+                }
+            }
+        }
+        _ => {}
+    }
+    vm.call_function(reviver, holder.clone(), &[Value::String(name), value])
+}
+
+struct Parser<'a> {
+    vm: &'a mut VM,
+    text: &'a [u16],
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    /// A SyntaxError for the code unit at the current position, or for the
+    /// end of the input if there is none.
+    fn error(&self, message: &str) -> RuntimeError {
+        if self.pos >= self.text.len() {
+            return RuntimeError::SyntaxError("Unexpected end of JSON input".to_string());
+        }
+        RuntimeError::SyntaxError(format!("{message} in JSON at position {}", self.pos))
+    }
+
+    fn unexpected(&self) -> RuntimeError {
+        let token = self
+            .text
+            .get(self.pos)
+            .map(|&unit| String::from_utf16_lossy(&[unit]))
+            .unwrap_or_default();
+        self.error(&format!("Unexpected token '{token}'"))
+    }
+
+    fn peek(&self) -> Option<u16> {
+        self.text.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(0x09 | 0x0a | 0x0d | 0x20)) {
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, RuntimeError> {
+        match self.peek() {
+            Some(0x7b) => self.object(),
+            Some(0x5b) => self.array(),
+            Some(0x22) => {
+                let units = self.string()?;
+                self.vm.new_string(&units)
+            }
+            Some(0x2d | 0x30..=0x39) => self.number(),
+            Some(0x74) => self.literal("true", Value::Boolean(true)),
+            Some(0x66) => self.literal("false", Value::Boolean(false)),
+            Some(0x6e) => self.literal("null", Value::Null),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, RuntimeError> {
+        for expected in word.bytes() {
+            if self.peek() != Some(expected as u16) {
+                return Err(self.unexpected());
+            }
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    fn digits(&mut self) -> usize {
+        let start = self.pos;
+        while matches!(self.peek(), Some(unit) if (b'0' as u16..=b'9' as u16).contains(&unit)) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    fn number(&mut self) -> Result<Value, RuntimeError> {
+        let start = self.pos;
+        if self.peek() == Some(b'-' as u16) {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0' as u16) {
+            self.pos += 1;
+        } else if self.digits() == 0 {
+            return Err(self.error("No number after minus sign"));
+        }
+        if self.peek() == Some(b'.' as u16) {
+            self.pos += 1;
+            if self.digits() == 0 {
+                return Err(self.error("Unterminated fractional number"));
+            }
+        }
+        if matches!(self.peek(), Some(0x45 | 0x65)) {
+            self.pos += 1;
+            if matches!(self.peek(), Some(0x2b | 0x2d)) {
+                self.pos += 1;
+            }
+            if self.digits() == 0 {
+                return Err(self.error("Exponent part is missing a number"));
+            }
+        }
+        let literal = String::from_utf16_lossy(&self.text[start..self.pos]);
+        Ok(Value::Number(literal.parse().unwrap()))
+    }
+
+    /// Reads a string literal, returning its code units.
+    fn string(&mut self) -> Result<Vec<u16>, RuntimeError> {
+        self.pos += 1;
+        let mut units = Vec::new();
+        loop {
+            let Some(unit) = self.peek() else {
+                return Err(RuntimeError::SyntaxError(format!(
+                    "Unterminated string in JSON at position {}",
+                    self.pos
+                )));
+            };
+            match unit {
+                0x22 => {
+                    self.pos += 1;
+                    return Ok(units);
+                }
+                0x5c => {
+                    self.pos += 1;
+                    let escaped = match self.peek() {
+                        Some(0x22) => 0x22,
+                        Some(0x5c) => 0x5c,
+                        Some(0x2f) => 0x2f,
+                        Some(0x62) => 0x08,
+                        Some(0x66) => 0x0c,
+                        Some(0x6e) => 0x0a,
+                        Some(0x72) => 0x0d,
+                        Some(0x74) => 0x09,
+                        Some(0x75) => {
+                            let hex = self.text.get(self.pos + 1..self.pos + 5);
+                            let code = hex
+                                .filter(|hex| hex.iter().all(|&u| u < 0x80))
+                                .map(String::from_utf16_lossy)
+                                .and_then(|hex| u16::from_str_radix(&hex, 16).ok());
+                            match code {
+                                Some(code) => {
+                                    self.pos += 4;
+                                    code
+                                }
+                                None => return Err(self.error("Bad Unicode escape")),
+                            }
+                        }
+                        _ => return Err(self.error("Bad escaped character")),
+                    };
+                    units.push(escaped);
+                }
+                0x00..=0x1f => return Err(self.error("Bad control character in string literal")),
+                _ => units.push(unit),
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn enter(&mut self) -> Result<(), RuntimeError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(too_deep());
+        }
+        self.pos += 1;
+        self.skip_whitespace();
+        Ok(())
+    }
+
+    fn array(&mut self) -> Result<Value, RuntimeError> {
+        self.enter()?;
+        let mut elements = Vec::new();
+        if self.peek() != Some(b']' as u16) {
+            loop {
+                elements.push(self.value()?);
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(0x2c) => {
+                        self.pos += 1;
+                        self.skip_whitespace();
+                    }
+                    Some(0x5d) => break,
+                    _ => return Err(self.error("Expected ',' or ']' after array element")),
+                }
+            }
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        self.vm.new_array(JsArray::from(elements))
+    }
+
+    fn object(&mut self) -> Result<Value, RuntimeError> {
+        self.enter()?;
+        self.vm.charge(OBJECT_SIZE)?;
+        let mut properties = Properties::new();
+        if self.peek() != Some(b'}' as u16) {
+            loop {
+                if self.peek() != Some(b'"' as u16) {
+                    return Err(self.error("Expected double-quoted property name"));
+                }
+                let key = Atom::from_js_string(&JsString::from_utf16(&self.string()?));
+                self.skip_whitespace();
+                if self.peek() != Some(b':' as u16) {
+                    return Err(self.error("Expected ':' after property name"));
+                }
+                self.pos += 1;
+                self.skip_whitespace();
+                let value = self.value()?;
+                // A repeated key keeps its first position and its last value
+                if properties.insert(key, value).is_none() {
+                    self.vm.charge(ENTRY_SIZE)?;
+                }
+                self.skip_whitespace();
+                match self.peek() {
+                    Some(0x2c) => {
+                        self.pos += 1;
+                        self.skip_whitespace();
+                    }
+                    Some(0x7d) => break,
+                    _ => return Err(self.error("Expected ',' or '}' after property value")),
+                }
+            }
+        }
+        self.pos += 1;
+        self.depth -= 1;
+        Ok(Value::Object(Rc::new(RefCell::new(JsObject::from(
+            properties,
+        )))))
+    }
+}
+
+fn stringify(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut serializer = Serializer {
+        replacer: None,
+        property_list: None,
+        gap: Vec::new(),
+        indent: Vec::new(),
+        stack: Vec::new(),
+        out: Vec::new(),
+        charged: 0,
+    };
+    match arg(args, 1) {
+        replacer if replacer.is_function() => serializer.replacer = Some(replacer),
+        Value::Array(list) => {
+            let mut keys: Vec<Atom> = Vec::new();
+            for (_, item) in list.borrow().iter() {
+                let key = match item {
+                    Value::String(s) => Atom::from_js_string(s),
+                    Value::Number(_) => Atom::from_js_string(&item.to_js_string()),
+                    _ => continue,
+                };
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+            serializer.property_list = Some(keys);
+        }
+        _ => {}
+    }
+    serializer.gap = match arg(args, 2) {
+        Value::Number(_) => {
+            let spaces = to_integer(&arg(args, 2)).clamp(0.0, 10.0) as usize;
+            vec![b' ' as u16; spaces]
+        }
+        Value::String(s) => s.code_units().take(10).collect(),
+        _ => Vec::new(),
+    };
+
+    let value = arg(args, 0);
+    let mut wrapper = JsObject::new();
+    wrapper.insert("", value.clone());
+    let holder = Value::Object(Rc::new(RefCell::new(wrapper)));
+    let written = serializer.property(vm, &holder, JsString::from(""), value);
+    vm.release_scratch(serializer.charged * size_of::<u16>());
+    if written? {
+        vm.new_string(&serializer.out)
+    } else {
+        Ok(Value::Undefined)
+    }
+}
+
+struct Serializer {
+    /// Function called on every key and value
+    replacer: Option<Value>,
+    /// The only keys of objects to include
+    property_list: Option<Vec<Atom>>,
+    gap: Vec<u16>,
+    indent: Vec<u16>,
+    /// Objects and arrays being serialized, to detect cycles
+    stack: Vec<*const ()>,
+    out: Vec<u16>,
+    /// Length of `out` reserved as scratch space
+    charged: usize,
+}
+
+impl Serializer {
+    /// Writes `value`, found at `holder[key]`. Returns false, having written
+    /// nothing, for values JSON has no representation for.
+    fn property(
+        &mut self,
+        vm: &mut VM,
+        holder: &Value,
+        key: JsString,
+        mut value: Value,
+    ) -> Result<bool, RuntimeError> {
+        self.account(vm)?;
+        if matches!(
+            value,
+            Value::Object(_) | Value::Array(_) | Value::Function(_) | Value::NativeFunction(_)
+        ) {
+            let to_json = vm.get_property(&value, &Value::String(JsString::from("toJSON")))?;
+            if to_json.is_function() {
+                value = vm.call_function(&to_json, value, &[Value::String(key.clone())])?;
+            }
+        }
+        if let Some(replacer) = &self.replacer {
+            value = vm.call_function(replacer, holder.clone(), &[Value::String(key), value])?;
+        }
+        match &value {
+            Value::Null => self.write("null"),
+            Value::Boolean(b) => self.write(if *b { "true" } else { "false" }),
+            Value::Number(n) if n.is_finite() => self.write(&number::to_string(*n)),
+            Value::Number(_) => self.write("null"),
+            Value::String(s) => self.quote(&s.code_units().collect::<Vec<_>>()),
+            Value::Array(arr) => self.array(vm, &value, arr)?,
+            Value::Object(obj) => self.object(vm, &value, obj)?,
+            Value::Undefined | Value::Symbol(_) | Value::Function(_) | Value::NativeFunction(_) => {
+                return Ok(false)
+            }
+        }
+        Ok(true)
+    }
+
+    /// Reserves the output written since the last call as scratch space,
+    /// and spends fuel, so that serializing a huge sparse array stays
+    /// within the VM's limits.
+    fn account(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        if self.out.len() > MAX_STRING_LENGTH {
+            return Err(RuntimeError::RangeError(
+                "Invalid string length".to_string(),
+            ));
+        }
+        vm.tick()?;
+        let len = self.out.len();
+        if len > self.charged {
+            vm.reserve_scratch((len - self.charged) * size_of::<u16>())?;
+            self.charged = len;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, s: &str) {
+        self.out.extend(s.encode_utf16());
+    }
+
+    /// Writes `units` as a string literal, escaping lone surrogates so the
+    /// output is well-formed UTF-16.
+    fn quote(&mut self, units: &[u16]) {
+        self.out.push(b'"' as u16);
+        let mut i = 0;
+        while i < units.len() {
+            let unit = units[i];
+            match unit {
+                0x22 => self.write("\\\""),
+                0x5c => self.write("\\\\"),
+                0x08 => self.write("\\b"),
+                0x0c => self.write("\\f"),
+                0x0a => self.write("\\n"),
+                0x0d => self.write("\\r"),
+                0x09 => self.write("\\t"),
+                0x00..=0x1f => self.write(&format!("\\u{unit:04x}")),
+                0xd800..=0xdbff if matches!(units.get(i + 1), Some(0xdc00..=0xdfff)) => {
+                    self.out.extend_from_slice(&units[i..i + 2]);
+                    i += 1;
+                }
+                0xd800..=0xdfff => self.write(&format!("\\u{unit:04x}")),
+                _ => self.out.push(unit),
+            }
+            i += 1;
+        }
+        self.out.push(b'"' as u16);
+    }
+
+    /// Starts a nested object or array, failing on a cycle.
+    fn enter(&mut self, ptr: *const (), open: char) -> Result<Vec<u16>, RuntimeError> {
+        if self.stack.contains(&ptr) {
+            return Err(RuntimeError::TypeError(
+                "Converting circular structure to JSON".to_string(),
+            ));
+        }
+        if self.stack.len() >= MAX_DEPTH {
+            return Err(too_deep());
+        }
+        self.stack.push(ptr);
+        self.out.push(open as u16);
+        let outer = self.indent.clone();
+        self.indent.extend_from_slice(&self.gap);
+        Ok(outer)
+    }
+
+    /// Ends a nested object or array, given the indentation `enter`
+    /// returned.
+    fn leave(&mut self, outer: Vec<u16>, close: char, empty: bool) {
+        if !empty && !self.gap.is_empty() {
+            self.out.push(b'\n' as u16);
+            self.out.extend_from_slice(&outer);
+        }
+        self.out.push(close as u16);
+        self.indent = outer;
+        self.stack.pop();
+    }
+
+    /// Writes the separator before the member or element at `index`.
+    fn separator(&mut self, index: usize) {
+        if index > 0 {
+            self.out.push(b',' as u16);
+        }
+        if !self.gap.is_empty() {
+            self.out.push(b'\n' as u16);
+            self.out.extend_from_slice(&self.indent);
+        }
+    }
+
+    fn array(
+        &mut self,
+        vm: &mut VM,
+        value: &Value,
+        arr: &Rc<RefCell<JsArray>>,
+    ) -> Result<(), RuntimeError> {
+        let outer = self.enter(Rc::as_ptr(arr) as *const (), '[')?;
+        let len = arr.borrow().len();
+        for i in 0..len {
+            self.separator(i as usize);
+            let element = arr.borrow().get(i).cloned().unwrap_or(Value::Undefined);
+            let key = JsString::from(i.to_string());
+            if !self.property(vm, value, key, element)? {
+                self.write("null");
+            }
+        }
+        self.leave(outer, ']', len == 0);
+        Ok(())
+    }
+
+    fn object(
+        &mut self,
+        vm: &mut VM,
+        value: &Value,
+        obj: &Rc<RefCell<JsObject>>,
+    ) -> Result<(), RuntimeError> {
+        let outer = self.enter(Rc::as_ptr(obj) as *const (), '{')?;
+        let keys = match &self.property_list {
+            Some(keys) => keys.clone(),
+            None => own_keys(obj.borrow().properties()),
+        };
+        let mut written = 0;
+        for key in keys {
+            let Some(property) = vm.own_property(value, &Key::Name(key.clone())) else {
+                continue;
+            };
+            // Members without a representation are left out entirely
+            let mark = self.out.len();
+            self.separator(written);
+            self.quote(&key.as_js_string().code_units().collect::<Vec<_>>());
+            self.out.push(b':' as u16);
+            if !self.gap.is_empty() {
+                self.out.push(b' ' as u16);
+            }
+            if self.property(vm, value, key.as_js_string().clone(), property)? {
+                written += 1;
+            } else {
+                self.out.truncate(mark);
+            }
+        }
+        self.leave(outer, '}', written == 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    fn syntax_error(text: &str) -> String {
+        let mut vm = VM::default();
+        vm.set_global("text", text);
+        match vm.eval("JSON.parse(text)") {
+            Err(RuntimeError::SyntaxError(msg)) => msg,
+            other => panic!("{text}: expected a SyntaxError, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            eval(
+                r#"var v = JSON.parse(' {"a": [1, -2.5e1, true, null], "b": "x\\u0041\\n"} '); v.a.join() + v.b"#
+            ),
+            "1,-25,true,xA\n"
+        );
+        assert_eq!(eval(r#"JSON.parse('{"a": 1, "a": 2}').a"#), "2");
+        assert_eq!(eval("JSON.parse('\"\\\\ud800\"').length"), "1");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(syntax_error("[1, 2"), "Unexpected end of JSON input");
+        assert_eq!(
+            syntax_error("[1,]"),
+            "Unexpected token ']' in JSON at position 3"
+        );
+        assert_eq!(
+            syntax_error("{\"a\" 1}"),
+            "Expected ':' after property name in JSON at position 5"
+        );
+        assert_eq!(
+            syntax_error("{'a': 1}"),
+            "Expected double-quoted property name in JSON at position 1"
+        );
+        assert_eq!(
+            syntax_error("1 2"),
+            "Unexpected non-whitespace character after JSON at position 2"
+        );
+        assert_eq!(
+            syntax_error("01"),
+            "Unexpected non-whitespace character after JSON at position 1"
+        );
+        assert_eq!(syntax_error("-"), "Unexpected end of JSON input");
+        assert_eq!(
+            syntax_error("-x"),
+            "No number after minus sign in JSON at position 1"
+        );
+        assert_eq!(
+            syntax_error("1.e5"),
+            "Unterminated fractional number in JSON at position 2"
+        );
+        assert_eq!(
+            syntax_error("\"a\tb\""),
+            "Bad control character in string literal in JSON at position 2"
+        );
+        assert_eq!(
+            syntax_error("\"\\x\""),
+            "Bad escaped character in JSON at position 2"
+        );
+        assert_eq!(
+            syntax_error("\"abc"),
+            "Unterminated string in JSON at position 4"
+        );
+        assert_eq!(syntax_error("tru"), "Unexpected end of JSON input");
+        assert_eq!(
+            syntax_error("nul!"),
+            "Unexpected token '!' in JSON at position 3"
+        );
+        assert_eq!(syntax_error(""), "Unexpected end of JSON input");
+    }
+
+    #[test]
+    fn test_reviver() {
+        let source = r#"
+            var seen = [];
+            var v = JSON.parse('{"a": 1, "b": [2, 3], "c": {"d": 4}}', function (key, value) {
+                seen.push(key);
+                if (key == 'a') return undefined;
+                return typeof value == 'number' ? value * 10 : value;
+            });
+            [seen.join(), 'a' in v, v.b.join(), v.c.d].join(' ');
+        "#;
+        assert_eq!(eval(source), "a,0,1,b,d,c, false 20,30 40");
+    }
+
+    #[test]
+    fn test_stringify() {
+        assert_eq!(
+            eval(r#"JSON.stringify({ b: 1, a: [true, null, 'x"\n'], 2: 0, 1: 0 })"#),
+            r#"{"1":0,"2":0,"b":1,"a":[true,null,"x\"\n"]}"#
+        );
+        assert_eq!(
+            eval("JSON.stringify([undefined, function () {}, 0 / 0, 1 / 0, , -0])"),
+            "[null,null,null,null,null,0]"
+        );
+        assert_eq!(
+            eval("JSON.stringify({ a: undefined, b: function () {}, c: 1 })"),
+            r#"{"c":1}"#
+        );
+        assert_eq!(
+            eval("[JSON.stringify(undefined), JSON.stringify(function () {}), JSON.stringify('\\ud800')].join()"),
+            r#",,"\ud800""#
+        );
+    }
+
+    #[test]
+    fn test_stringify_indent() {
+        assert_eq!(
+            eval("JSON.stringify({ a: [1, {}], b: [] }, null, 2)"),
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": []\n}"
+        );
+        assert_eq!(eval("JSON.stringify([1], null, '--')"), "[\n--1\n]");
+        assert_eq!(
+            eval("JSON.stringify({ a: 1 }, null, 20)"),
+            "{\n          \"a\": 1\n}"
+        );
+    }
+
+    #[test]
+    fn test_stringify_replacer() {
+        assert_eq!(
+            eval("JSON.stringify({ a: 1, b: 2, c: { a: 3, d: 4 } }, ['a', 'c', 'a'])"),
+            r#"{"a":1,"c":{"a":3}}"#
+        );
+        let source = "
+            JSON.stringify({ a: 1, b: 'x', c: [2] }, function (key, value) {
+                if (key == 'b') return undefined;
+                return typeof value == 'number' ? value + 1 : value;
+            });
+        ";
+        assert_eq!(eval(source), r#"{"a":2,"c":[3]}"#);
+        assert_eq!(
+            eval("JSON.stringify({ d: { toJSON: function (key) { return 'at ' + key; } } })"),
+            r#"{"d":"at d"}"#
+        );
+    }
+
+    #[test]
+    fn test_stringify_cycles() {
+        let mut vm = VM::default();
+        for source in [
+            "var o = {}; o.self = o; JSON.stringify(o)",
+            "var a = [1]; a.push({ a: a }); JSON.stringify(a)",
+        ] {
+            assert!(
+                matches!(
+                    vm.eval(source),
+                    Err(RuntimeError::TypeError(msg)) if msg == "Converting circular structure to JSON"
+                ),
+                "{source}"
+            );
+        }
+        // Shared but acyclic values are fine
+        assert_eq!(eval("var x = [1]; JSON.stringify([x, x])"), "[[1],[1]]");
+    }
+
+    #[test]
+    fn test_stringify_limits() {
+        let mut vm = VM::default();
+        vm.eval("var a = []; a[1e9] = 1;").unwrap();
+        vm.set_fuel(Some(100_000));
+        assert_eq!(vm.eval("JSON.stringify(a)"), Err(RuntimeError::OutOfFuel));
+        vm.set_fuel(None);
+        vm.set_heap_limit(Some(1 << 20));
+        assert_eq!(vm.eval("JSON.stringify(a)"), Err(RuntimeError::OutOfMemory));
+        // The output is released once the call fails
+        assert_eq!(vm.eval("JSON.stringify([1])"), Ok(Value::from("[1]")));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"
+            var text = '{"s":"\\u00e9\\ud83d\\ude00","n":[0.1,1e+21,-5e-7],"o":{"":null}}';
+            JSON.stringify(JSON.parse(text));
+        "#;
+        assert_eq!(
+            eval(source),
+            "{\"s\":\"\u{e9}\u{1F600}\",\"n\":[0.1,1e+21,-5e-7],\"o\":{\"\":null}}"
+        );
+    }
+}
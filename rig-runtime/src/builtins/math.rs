@@ -0,0 +1,291 @@
+//! The `Math` namespace object.
+
+use std::cell::RefCell;
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::rc::Rc;
+
+use super::{arg, define_methods, define_to_string_tag, to_uint32, Builtin};
+use crate::number;
+use crate::{JsObject, NativeFunction, RuntimeError, Value, VM};
+
+/// The generator behind `Math.random`, an xorshift128+ like the one V8
+/// uses. Not suitable for cryptography.
+pub(crate) struct Random {
+    state: [u64; 2],
+}
+
+impl Random {
+    /// A generator seeded from the process's hash randomness.
+    pub fn new() -> Self {
+        Random::with_seed(RandomState::new().hash_one(0u64))
+    }
+
+    /// A generator that always produces the same sequence for `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        // Spread the seed over both words with splitmix64, since xorshift
+        // must not start from all zeros
+        let mut seed = seed;
+        let mut next = || {
+            seed = seed.wrapping_add(0x9e3779b97f4a7c15);
+            let mut z = seed;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+            z ^ (z >> 31)
+        };
+        Random {
+            state: [next(), next()],
+        }
+    }
+
+    /// A number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        let [mut s1, s0] = self.state;
+        s1 ^= s1 << 23;
+        s1 ^= s1 >> 17;
+        s1 ^= s0;
+        s1 ^= s0 >> 26;
+        self.state = [s0, s1];
+        // The top 53 bits make an evenly spread double
+        (s0.wrapping_add(s1) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// A `Math` function of one number.
+type Unary = fn(f64) -> f64;
+
+pub(super) fn install(vm: &mut VM) {
+    let math = Rc::new(RefCell::new(JsObject::new()));
+    let unary: &[(&str, Unary)] = &[
+        ("abs", f64::abs),
+        ("acos", f64::acos),
+        ("acosh", f64::acosh),
+        ("asin", f64::asin),
+        ("asinh", f64::asinh),
+        ("atan", f64::atan),
+        ("atanh", f64::atanh),
+        ("cbrt", f64::cbrt),
+        ("ceil", f64::ceil),
+        ("cos", f64::cos),
+        ("cosh", f64::cosh),
+        ("exp", f64::exp),
+        ("expm1", f64::exp_m1),
+        ("floor", f64::floor),
+        ("fround", |x| x as f32 as f64),
+        ("log", f64::ln),
+        ("log10", f64::log10),
+        ("log1p", f64::ln_1p),
+        ("log2", f64::log2),
+        ("round", round),
+        ("sign", sign),
+        ("sin", f64::sin),
+        ("sinh", f64::sinh),
+        ("sqrt", f64::sqrt),
+        ("tan", f64::tan),
+        ("tanh", f64::tanh),
+        ("trunc", f64::trunc),
+    ];
+    for &(name, f) in unary {
+        let function = NativeFunction::new(name, move |_, _, args| {
+            Ok(Value::Number(f(arg(args, 0).to_number())))
+        });
+        math.borrow_mut().insert(name, function);
+    }
+    let methods: &[(&str, Builtin)] = &[
+        ("atan2", atan2),
+        ("clz32", clz32),
+        ("hypot", hypot),
+        ("imul", imul),
+        ("max", max),
+        ("min", min),
+        ("pow", pow),
+        ("random", random),
+    ];
+    define_methods(&math, methods);
+    define_to_string_tag(&math, "Math");
+    for (name, value) in [
+        ("E", std::f64::consts::E),
+        ("LN10", std::f64::consts::LN_10),
+        ("LN2", std::f64::consts::LN_2),
+        ("LOG10E", std::f64::consts::LOG10_E),
+        ("LOG2E", std::f64::consts::LOG2_E),
+        ("PI", std::f64::consts::PI),
+        ("SQRT1_2", std::f64::consts::FRAC_1_SQRT_2),
+        ("SQRT2", std::f64::consts::SQRT_2),
+    ] {
+        math.borrow_mut().insert(name, value);
+    }
+    vm.set_global("Math", Value::Object(math));
+}
+
+/// Rounds half-way cases towards positive infinity, keeping the sign of
+/// zero.
+fn round(x: f64) -> f64 {
+    if !x.is_finite() || x.trunc() == x {
+        return x;
+    }
+    let floor = x.floor();
+    let rounded = if x - floor >= 0.5 { floor + 1.0 } else { floor };
+    if rounded == 0.0 && x < 0.0 {
+        -0.0
+    } else {
+        rounded
+    }
+}
+
+fn sign(x: f64) -> f64 {
+    if x == 0.0 || x.is_nan() {
+        x
+    } else {
+        x.signum()
+    }
+}
+
+fn numbers(args: &[Value]) -> impl Iterator<Item = f64> + '_ {
+    args.iter().map(Value::to_number)
+}
+
+fn atan2(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let y = arg(args, 0).to_number();
+    let x = arg(args, 1).to_number();
+    Ok(Value::Number(y.atan2(x)))
+}
+
+fn clz32(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(
+        to_uint32(&arg(args, 0)).leading_zeros() as f64
+    ))
+}
+
+fn hypot(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let values: Vec<f64> = numbers(args).map(f64::abs).collect();
+    // An infinite argument wins even over NaN
+    if values.iter().any(|x| x.is_infinite()) {
+        return Ok(Value::Number(f64::INFINITY));
+    }
+    if values.iter().any(|x| x.is_nan()) {
+        return Ok(Value::Number(f64::NAN));
+    }
+    // Scale by the largest value so that squaring cannot overflow
+    let largest = values.iter().copied().fold(0.0, f64::max);
+    if largest == 0.0 {
+        return Ok(Value::Number(0.0));
+    }
+    let sum: f64 = values.iter().map(|x| (x / largest).powi(2)).sum();
+    Ok(Value::Number(largest * sum.sqrt()))
+}
+
+fn imul(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let a = to_uint32(&arg(args, 0)) as i32;
+    let b = to_uint32(&arg(args, 1)) as i32;
+    Ok(Value::Number(a.wrapping_mul(b) as f64))
+}
+
+fn max(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let result = numbers(args).fold(f64::NEG_INFINITY, |acc, x| {
+        if acc.is_nan() || x.is_nan() {
+            f64::NAN
+        } else if x > acc || (x == acc && acc.is_sign_negative()) {
+            // +0 is larger than -0
+            x
+        } else {
+            acc
+        }
+    });
+    Ok(Value::Number(result))
+}
+
+fn min(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let result = numbers(args).fold(f64::INFINITY, |acc, x| {
+        if acc.is_nan() || x.is_nan() {
+            f64::NAN
+        } else if x < acc || (x == acc && x.is_sign_negative()) {
+            // -0 is smaller than +0
+            x
+        } else {
+            acc
+        }
+    });
+    Ok(Value::Number(result))
+}
+
+fn pow(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = arg(args, 0).to_number();
+    let y = arg(args, 1).to_number();
+    Ok(Value::Number(number::pow(x, y)))
+}
+
+fn random(vm: &mut VM, _this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(vm.random.next_f64()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_rounding() {
+        assert_eq!(
+            eval("[Math.round(2.5), Math.round(-2.5), Math.round(-0.2), Math.floor(-1.5), Math.trunc(-1.5)].join()"),
+            "3,-2,0,-2,-1"
+        );
+        assert_eq!(eval("1 / Math.round(-0.2)"), "-Infinity");
+        assert_eq!(
+            eval("Math.fround(5.5) + ' ' + Math.fround(5.05)"),
+            "5.5 5.050000190734863"
+        );
+    }
+
+    #[test]
+    fn test_integer_functions() {
+        assert_eq!(
+            eval(
+                "[Math.clz32(1), Math.clz32(0), Math.imul(0xffffffff, 5), Math.imul(3, 4)].join()"
+            ),
+            "31,32,-5,12"
+        );
+    }
+
+    #[test]
+    fn test_min_max() {
+        assert_eq!(eval("Math.max(1, 3, 2) + Math.min(1, 3, 2)"), "4");
+        assert_eq!(eval("Math.max() + ' ' + Math.min()"), "-Infinity Infinity");
+        assert_eq!(eval("Math.max(1, 0 / 0, 3)"), "NaN");
+        assert_eq!(
+            eval("1 / Math.max(-0, 0) + ' ' + 1 / Math.min(0, -0)"),
+            "Infinity -Infinity"
+        );
+    }
+
+    #[test]
+    fn test_hypot_and_pow() {
+        assert_eq!(eval("Math.hypot(3, 4) + ' ' + Math.hypot()"), "5 0");
+        assert_eq!(eval("Math.hypot(0 / 0, 1 / 0)"), "Infinity");
+        assert_eq!(
+            eval("Math.hypot(3 * 2 ** 600, 4 * 2 ** 600) === 5 * 2 ** 600"),
+            "true"
+        );
+        assert_eq!(eval("Math.pow(1, 1 / 0) + ' ' + 2 ** 10"), "NaN 1024");
+        assert_eq!(eval("Math.sign(-3) + Math.sign(0) + Math.abs(-2)"), "1");
+        assert_eq!(eval("Math.sqrt(2) === Math.SQRT2"), "true");
+    }
+
+    #[test]
+    fn test_seeded_random() {
+        let sequence = |seed| {
+            let mut vm = VM::default();
+            vm.set_random_seed(seed);
+            vm.eval("[Math.random(), Math.random(), Math.random()].join()")
+                .unwrap()
+                .to_js_string()
+                .to_std_string_lossy()
+        };
+        assert_eq!(sequence(7), sequence(7));
+        assert_ne!(sequence(7), sequence(8));
+        let mut random = Random::with_seed(0);
+        assert!((0..1000)
+            .map(|_| random.next_f64())
+            .all(|x| (0.0..1.0).contains(&x)));
+    }
+}
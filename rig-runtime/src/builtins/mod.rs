@@ -0,0 +1,351 @@
+//! Built-in constructors and prototypes, installed into the global scope of
+//! every VM.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::array::{array_index, Key};
+use crate::heap::{self, Sizer};
+use crate::{
+    Atom, JsArray, JsObject, JsString, JsSymbol, NativeFunction, Properties, RuntimeError, Value,
+    VM,
+};
+
+mod array;
+mod boolean;
+mod collection;
+mod function;
+mod generator;
+mod iterator;
+mod json;
+mod math;
+mod number;
+mod object;
+mod promise;
+mod string;
+mod symbol;
+
+pub(crate) use json::parse_json;
+pub(crate) use math::Random;
+pub(crate) use object::copy_rest;
+
+/// The longest string built-ins that size their result up front will
+/// build, matching V8's limit.
+pub(crate) const MAX_STRING_LENGTH: usize = (1 << 29) - 24;
+
+/// Signature of a built-in function.
+type Builtin = fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError>;
+
+/// Objects the VM consults on its own, such as the prototype that array
+/// methods are looked up on.
+#[derive(Default)]
+pub(crate) struct Intrinsics {
+    /// Where the prototype chain of every object ends
+    pub object_prototype: Rc<RefCell<JsObject>>,
+    pub array_prototype: Rc<RefCell<JsObject>>,
+    /// What script and native functions inherit from
+    pub function_prototype: Rc<RefCell<JsObject>>,
+    /// Where methods of string primitives are looked up
+    pub string_prototype: Rc<RefCell<JsObject>>,
+    pub number_prototype: Rc<RefCell<JsObject>>,
+    pub boolean_prototype: Rc<RefCell<JsObject>>,
+    pub map_prototype: Rc<RefCell<JsObject>>,
+    pub set_prototype: Rc<RefCell<JsObject>>,
+    pub weak_map_prototype: Rc<RefCell<JsObject>>,
+    pub weak_set_prototype: Rc<RefCell<JsObject>>,
+    /// Where methods of symbols are looked up
+    pub symbol_prototype: Rc<RefCell<JsObject>>,
+    /// What the built-in iterators inherit from
+    pub iterator_prototype: Rc<RefCell<JsObject>>,
+    pub array_iterator_prototype: Rc<RefCell<JsObject>>,
+    pub string_iterator_prototype: Rc<RefCell<JsObject>>,
+    pub map_iterator_prototype: Rc<RefCell<JsObject>>,
+    pub set_iterator_prototype: Rc<RefCell<JsObject>>,
+    /// What the objects generator functions return inherit from
+    pub generator_prototype: Rc<RefCell<JsObject>>,
+    pub promise_prototype: Rc<RefCell<JsObject>>,
+    /// What async generators inherit from, by way of their own prototype
+    pub async_iterator_prototype: Rc<RefCell<JsObject>>,
+    pub async_generator_prototype: Rc<RefCell<JsObject>>,
+    /// `Array.prototype.values`, which iterating an array skips calling
+    /// while it is still the array's `Symbol.iterator`
+    pub array_values: Option<Value>,
+    /// `String.prototype[Symbol.iterator]`, likewise for strings
+    pub string_iterator: Option<Value>,
+}
+
+impl Intrinsics {
+    fn prototypes(&self) -> [&Rc<RefCell<JsObject>>; 20] {
+        [
+            &self.object_prototype,
+            &self.array_prototype,
+            &self.function_prototype,
+            &self.string_prototype,
+            &self.number_prototype,
+            &self.boolean_prototype,
+            &self.map_prototype,
+            &self.set_prototype,
+            &self.weak_map_prototype,
+            &self.weak_set_prototype,
+            &self.symbol_prototype,
+            &self.iterator_prototype,
+            &self.array_iterator_prototype,
+            &self.string_iterator_prototype,
+            &self.map_iterator_prototype,
+            &self.set_iterator_prototype,
+            &self.generator_prototype,
+            &self.promise_prototype,
+            &self.async_iterator_prototype,
+            &self.async_generator_prototype,
+        ]
+    }
+
+    pub fn measure(&self, sizer: &mut Sizer) {
+        for prototype in self.prototypes() {
+            sizer.value(&Value::Object(prototype.clone()));
+        }
+    }
+
+    /// Whether `value` is one of the prototypes, whose native functions are
+    /// the built-in methods that enumeration leaves out.
+    pub fn is_prototype(&self, value: &Value) -> bool {
+        let Value::Object(obj) = value else {
+            return false;
+        };
+        self.prototypes()
+            .iter()
+            .any(|prototype| Rc::ptr_eq(prototype, obj))
+    }
+
+    /// Empties the prototypes, breaking the cycles between them and their
+    /// constructors.
+    pub fn clear(&self) {
+        for prototype in self.prototypes() {
+            prototype.borrow_mut().properties_mut().clear();
+        }
+    }
+
+    /// Reads an accessor property that `holder`, one of the prototypes,
+    /// provides for `receiver`.
+    pub fn accessor(&self, holder: &Value, key: &Key, receiver: &Value) -> Option<Value> {
+        let Value::Object(holder) = holder else {
+            return None;
+        };
+        let Key::Name(name) = key else {
+            return None;
+        };
+        let name = name.as_js_string();
+        if *name == *"size"
+            && (Rc::ptr_eq(holder, &self.map_prototype) || Rc::ptr_eq(holder, &self.set_prototype))
+        {
+            collection::size(receiver)
+        } else if *name == *"description" && Rc::ptr_eq(holder, &self.symbol_prototype) {
+            symbol::description(receiver)
+        } else {
+            None
+        }
+    }
+}
+
+pub(crate) fn install(vm: &mut VM) {
+    object::install(vm);
+    array::install(vm);
+    boolean::install(vm);
+    function::install(vm);
+    collection::install(vm);
+    iterator::install(vm);
+    generator::install(vm);
+    json::install(vm);
+    math::install(vm);
+    number::install(vm);
+    promise::install(vm);
+    string::install(vm);
+    symbol::install(vm);
+}
+
+/// Adds native methods to a prototype or namespace object.
+fn define_methods(target: &RefCell<JsObject>, methods: &[(&str, Builtin)]) {
+    let mut target = target.borrow_mut();
+    for &(name, method) in methods {
+        target.insert(name, NativeFunction::new(name, method));
+    }
+}
+
+/// Sets the `Symbol.toStringTag` of a prototype or namespace object, the
+/// name `Object.prototype.toString` reports for it.
+fn define_to_string_tag(target: &RefCell<JsObject>, tag: &str) {
+    target.borrow_mut().properties_mut().insert(
+        Atom::from_symbol(&JsSymbol::to_string_tag()),
+        Value::from(tag),
+    );
+}
+
+/// Makes `method` the `Symbol.iterator` method of a prototype.
+fn define_iterator(target: &RefCell<JsObject>, method: Value) {
+    target
+        .borrow_mut()
+        .properties_mut()
+        .insert(Atom::from_symbol(&JsSymbol::iterator()), method);
+}
+
+/// Adds native functions as properties of a constructor.
+fn define_statics(ctor: &NativeFunction, functions: &[(&str, Builtin)]) {
+    for &(name, function) in functions {
+        ctor.set_property(name, NativeFunction::new(name, function));
+    }
+}
+
+/// The string keys of an object in the order the language specifies: array
+/// indices in ascending order, then other keys in the order they were added.
+/// Symbol keys are left out.
+pub(crate) fn own_keys(properties: &Properties) -> Vec<Atom> {
+    let mut indices = Vec::new();
+    let mut names = Vec::new();
+    for key in properties.keys().filter(|key| !key.is_symbol()) {
+        match array_index(&Value::String(key.as_js_string().clone())) {
+            Some(index) => indices.push((index, key.clone())),
+            None => names.push(key.clone()),
+        }
+    }
+    indices.sort_unstable_by_key(|&(index, _)| index);
+    indices
+        .into_iter()
+        .map(|(_, key)| key)
+        .chain(names)
+        .collect()
+}
+
+/// The names of the own properties of `value`, in property order.
+pub(crate) fn own_property_names(value: &Value, include_length: bool) -> Vec<JsString> {
+    let names = |keys: Vec<Atom>| keys.into_iter().map(|key| key.as_js_string().clone());
+    let index = |i: u32| JsString::from(i.to_string());
+    let length = include_length.then(|| JsString::from("length"));
+    match value {
+        Value::Object(obj) => names(own_keys(obj.borrow().properties())).collect(),
+        Value::Array(arr) => {
+            let arr = arr.borrow();
+            let indices = arr.iter().map(|(i, _)| index(i));
+            let named = arr
+                .properties()
+                .filter(|(key, _)| !key.is_symbol())
+                .map(|(key, _)| key.as_js_string().clone());
+            indices.chain(length).chain(named).collect()
+        }
+        Value::String(s) => (0..s.len() as u32).map(index).chain(length).collect(),
+        Value::NativeFunction(func) => names(own_keys(&func.properties())).collect(),
+        Value::Function(closure) => names(own_keys(&closure.properties.borrow())).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The values `for-of` would visit in `items`, or `None` if it is not
+/// iterable.
+fn iterable_values(vm: &mut VM, items: &Value) -> Result<Option<Vec<Value>>, RuntimeError> {
+    let method = vm.iterator_method(items)?;
+    if method.is_undefined() || method.is_null() {
+        return Ok(None);
+    }
+    let iterator = vm.iterator_from_method(items, method)?;
+    let mut values = Vec::new();
+    while let Some(value) = vm.iterator_next(&iterator)? {
+        vm.charge(heap::ELEMENT_SIZE)?;
+        values.push(value);
+    }
+    Ok(Some(values))
+}
+
+/// The argument at `index`, or undefined if too few were passed.
+fn arg(args: &[Value], index: usize) -> Value {
+    args.get(index).cloned().unwrap_or(Value::Undefined)
+}
+
+/// Converts a value to an integer, truncating towards zero and mapping NaN
+/// to zero.
+fn to_integer(value: &Value) -> f64 {
+    let n = value.to_number();
+    if n.is_nan() {
+        0.0
+    } else {
+        n.trunc()
+    }
+}
+
+/// Converts a value to an unsigned 32-bit integer, wrapping around as the
+/// bitwise operators do.
+fn to_uint32(value: &Value) -> u32 {
+    let n = value.to_number();
+    if n.is_finite() {
+        n.trunc().rem_euclid(4294967296.0) as u32
+    } else {
+        0
+    }
+}
+
+/// Resolves a relative index argument such as the `start` of `slice`:
+/// negative values count back from `len`, and the result is clamped to
+/// `0..=len`. Undefined gives `default`.
+fn relative_index(value: &Value, len: u32, default: u32) -> u32 {
+    if value.is_undefined() {
+        return default;
+    }
+    let n = to_integer(value);
+    let len = len as f64;
+    let index = if n < 0.0 {
+        (len + n).max(0.0)
+    } else {
+        n.min(len)
+    };
+    index as u32
+}
+
+/// Runs `source` in a fresh VM, returning its completion value as a
+/// string.
+#[cfg(test)]
+pub(crate) fn eval(source: &str) -> String {
+    let mut vm = VM::default();
+    match vm.eval(source) {
+        Ok(value) => value.to_js_string().to_std_string_lossy(),
+        Err(err) => panic!("{source}: {err}"),
+    }
+}
+
+/// Runs `source` and the jobs it queues, returning the global `log`.
+#[cfg(test)]
+pub(crate) fn eval_async(source: &str) -> String {
+    let mut vm = VM::default();
+    vm.eval("var log = []").unwrap();
+    if let Err(err) = vm.eval(source).and_then(|_| vm.run_microtasks()) {
+        panic!("{source}: {err}");
+    }
+    vm.eval("log.join()")
+        .unwrap()
+        .to_js_string()
+        .to_std_string_lossy()
+}
+
+/// Fails unless `value` can be called.
+fn callable(value: &Value) -> Result<(), RuntimeError> {
+    if value.is_function() {
+        Ok(())
+    } else {
+        Err(RuntimeError::TypeError(format!(
+            "{} is not a function",
+            value.to_js_string()
+        )))
+    }
+}
+
+impl VM {
+    /// Wraps `array` in a value, charging for its storage.
+    pub(crate) fn new_array(&mut self, array: JsArray) -> Result<Value, RuntimeError> {
+        self.charge(array.heap_size())?;
+        Ok(Value::Array(Rc::new(RefCell::new(array))))
+    }
+
+    /// Makes a string from code units, charging for its storage.
+    pub(crate) fn new_string(&mut self, units: &[u16]) -> Result<Value, RuntimeError> {
+        let latin1 = units.iter().all(|&u| u < 0x100);
+        self.charge(heap::string_size(units.len(), latin1))?;
+        Ok(Value::String(JsString::from_utf16(units)))
+    }
+}
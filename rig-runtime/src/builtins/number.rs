@@ -0,0 +1,349 @@
+//! The `Number` constructor, `Number.prototype`, and the global number
+//! functions and constants.
+
+use super::{arg, define_methods, define_statics, to_integer, to_uint32};
+use crate::number;
+use crate::string::is_whitespace;
+use crate::{JsString, NativeFunction, RuntimeError, Value, VM};
+
+/// The largest integer up to which every integer is a double.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.number_prototype.clone();
+    define_methods(
+        &prototype,
+        &[
+            ("toExponential", to_exponential),
+            ("toFixed", to_fixed),
+            ("toPrecision", to_precision),
+            ("toString", to_string),
+            ("valueOf", value_of),
+        ],
+    );
+    let ctor = NativeFunction::new("Number", construct);
+    define_statics(
+        &ctor,
+        &[
+            ("isFinite", is_finite),
+            ("isInteger", is_integer),
+            ("isNaN", is_nan),
+            ("isSafeInteger", is_safe_integer),
+        ],
+    );
+    // The parsing functions are shared with the global object
+    let parse_float = NativeFunction::new("parseFloat", parse_float);
+    let parse_int = NativeFunction::new("parseInt", parse_int);
+    ctor.set_property("parseFloat", parse_float.clone());
+    ctor.set_property("parseInt", parse_int.clone());
+    for (name, value) in [
+        ("EPSILON", f64::EPSILON),
+        ("MAX_SAFE_INTEGER", MAX_SAFE_INTEGER),
+        ("MAX_VALUE", f64::MAX),
+        ("MIN_SAFE_INTEGER", -MAX_SAFE_INTEGER),
+        ("MIN_VALUE", f64::from_bits(1)),
+        ("NaN", f64::NAN),
+        ("NEGATIVE_INFINITY", f64::NEG_INFINITY),
+        ("POSITIVE_INFINITY", f64::INFINITY),
+    ] {
+        ctor.set_property(name, value);
+    }
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Number", ctor);
+
+    vm.set_global("NaN", f64::NAN);
+    vm.set_global("Infinity", f64::INFINITY);
+    vm.set_global("parseFloat", parse_float);
+    vm.set_global("parseInt", parse_int);
+    vm.set_global(
+        "isFinite",
+        NativeFunction::new("isFinite", |_, _, args| {
+            Ok(Value::Boolean(arg(args, 0).to_number().is_finite()))
+        }),
+    );
+    vm.set_global(
+        "isNaN",
+        NativeFunction::new("isNaN", |_, _, args| {
+            Ok(Value::Boolean(arg(args, 0).to_number().is_nan()))
+        }),
+    );
+}
+
+fn this_number(this: &Value, method: &str) -> Result<f64, RuntimeError> {
+    match this {
+        Value::Number(n) => Ok(*n),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Number.prototype.{method} requires that 'this' be a Number"
+        ))),
+    }
+}
+
+/// Converts a digit-count argument, failing unless it lies in `range`.
+fn digits(
+    value: &Value,
+    range: std::ops::RangeInclusive<f64>,
+    message: &str,
+) -> Result<usize, RuntimeError> {
+    let n = to_integer(value);
+    if range.contains(&n) {
+        Ok(n as usize)
+    } else {
+        Err(RuntimeError::RangeError(message.to_string()))
+    }
+}
+
+fn string(s: String) -> Result<Value, RuntimeError> {
+    Ok(Value::String(JsString::from(s)))
+}
+
+fn construct(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Number(args.first().map_or(0.0, Value::to_number)))
+}
+
+fn is_finite(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(
+        matches!(arg(args, 0), Value::Number(n) if n.is_finite()),
+    ))
+}
+
+fn is_integer(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(
+        matches!(arg(args, 0), Value::Number(n) if n.is_finite() && n.trunc() == n),
+    ))
+}
+
+fn is_nan(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(
+        matches!(arg(args, 0), Value::Number(n) if n.is_nan()),
+    ))
+}
+
+fn is_safe_integer(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(matches!(
+        arg(args, 0),
+        Value::Number(n) if n.trunc() == n && n.abs() <= MAX_SAFE_INTEGER
+    )))
+}
+
+/// The argument as a string with leading whitespace removed.
+fn trimmed_start(value: &Value) -> String {
+    let units: Vec<u16> = value.to_js_string().code_units().collect();
+    let start = units
+        .iter()
+        .position(|&u| !is_whitespace(u))
+        .unwrap_or(units.len());
+    String::from_utf16_lossy(&units[start..])
+}
+
+fn parse_float(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let s = trimmed_start(&arg(args, 0));
+    let bytes = s.as_bytes();
+    let sign = usize::from(matches!(bytes.first(), Some(b'+' | b'-')));
+    if s[sign..].starts_with("Infinity") {
+        let infinity = if bytes[0] == b'-' {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+        return Ok(Value::Number(infinity));
+    }
+    // Find the longest prefix that is a decimal literal
+    let digits_from = |mut i: usize| {
+        while bytes.get(i).is_some_and(u8::is_ascii_digit) {
+            i += 1;
+        }
+        i
+    };
+    let int_end = digits_from(sign);
+    let mut end = int_end;
+    if bytes.get(end) == Some(&b'.') {
+        let frac_end = digits_from(end + 1);
+        if frac_end > end + 1 || int_end > sign {
+            end = frac_end;
+        }
+    }
+    if end == sign || (end == sign + 1 && bytes[sign] == b'.') {
+        return Ok(Value::Number(f64::NAN));
+    }
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let exponent_sign = usize::from(matches!(bytes.get(end + 1), Some(b'+' | b'-')));
+        let exponent_end = digits_from(end + 1 + exponent_sign);
+        if exponent_end > end + 1 + exponent_sign {
+            end = exponent_end;
+        }
+    }
+    Ok(Value::Number(s[..end].parse().unwrap_or(f64::NAN)))
+}
+
+fn parse_int(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let s = trimmed_start(&arg(args, 0));
+    let (negative, s) = match s.as_bytes().first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, &s[..]),
+    };
+    let mut radix = to_uint32(&arg(args, 1)) as i32;
+    if radix != 0 && !(2..=36).contains(&radix) {
+        return Ok(Value::Number(f64::NAN));
+    }
+    let mut s = s;
+    if radix == 0 || radix == 16 {
+        if let Some(rest) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            s = rest;
+            radix = 16;
+        }
+    }
+    if radix == 0 {
+        radix = 10;
+    }
+    let radix = radix as u32;
+    let end = s.find(|c: char| !c.is_digit(radix)).unwrap_or(s.len());
+    let digits = &s[..end];
+    if digits.is_empty() {
+        return Ok(Value::Number(f64::NAN));
+    }
+    let n = if radix == 10 {
+        // Correctly rounded, however many digits there are
+        digits.parse().unwrap()
+    } else {
+        digits.chars().fold(0.0, |n, c| {
+            n * radix as f64 + c.to_digit(radix).unwrap() as f64
+        })
+    };
+    Ok(Value::Number(if negative { -n } else { n }))
+}
+
+fn to_exponential(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = this_number(&this, "toExponential")?;
+    let fraction_digits = arg(args, 0);
+    if !x.is_finite() {
+        return string(number::to_string(x));
+    }
+    let f = match fraction_digits {
+        Value::Undefined => None,
+        ref f => Some(digits(
+            f,
+            0.0..=100.0,
+            "toExponential() argument must be between 0 and 100",
+        )?),
+    };
+    string(number::to_exponential(x, f))
+}
+
+fn to_fixed(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = this_number(&this, "toFixed")?;
+    let f = digits(
+        &arg(args, 0),
+        0.0..=100.0,
+        "toFixed() digits argument must be between 0 and 100",
+    )?;
+    if !x.is_finite() || x.abs() >= 1e21 {
+        return string(number::to_string(x));
+    }
+    string(number::to_fixed(x, f))
+}
+
+fn to_precision(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = this_number(&this, "toPrecision")?;
+    let precision = arg(args, 0);
+    if precision.is_undefined() || !x.is_finite() {
+        return string(number::to_string(x));
+    }
+    let p = digits(
+        &precision,
+        1.0..=100.0,
+        "toPrecision() argument must be between 1 and 100",
+    )?;
+    string(number::to_precision(x, p))
+}
+
+fn to_string(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let x = this_number(&this, "toString")?;
+    let radix = match arg(args, 0) {
+        Value::Undefined => 10,
+        ref radix => digits(
+            radix,
+            2.0..=36.0,
+            "toString() radix must be between 2 and 36",
+        )?,
+    };
+    if radix == 10 {
+        string(number::to_string(x))
+    } else {
+        string(number::to_radix_string(x, radix as u32))
+    }
+}
+
+fn value_of(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    this_number(&this, "valueOf").map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_formatting() {
+        assert_eq!(eval("String(0.1 + 0.2)"), "0.30000000000000004");
+        assert_eq!(eval("'' + 1e21 + ' ' + 1e-7 + ' ' + -0"), "1e+21 1e-7 0");
+        assert_eq!(
+            eval("[(1.005).toFixed(2), (2.5).toFixed(0), (1e21).toFixed(2)].join()"),
+            "1.00,3,1e+21"
+        );
+        assert_eq!(
+            eval(
+                "[(123.456).toPrecision(4), (123456).toExponential(2), (255).toString(16)].join()"
+            ),
+            "123.5,1.23e+5,ff"
+        );
+        assert_eq!(
+            eval("(0 / 0).toFixed(2) + (-1 / 0).toPrecision(3)"),
+            "NaN-Infinity"
+        );
+    }
+
+    #[test]
+    fn test_parsing() {
+        assert_eq!(
+            eval("[parseInt('  42px'), parseInt('-0x1f'), parseInt('z', 36), parseInt('12', 1)].join()"),
+            "42,-31,35,NaN"
+        );
+        assert_eq!(
+            eval("[parseFloat('3.14abc'), parseFloat('.5'), parseFloat('1e3x'), parseFloat('-Infinityx'), parseFloat('e1')].join()"),
+            "3.14,0.5,1000,-Infinity,NaN"
+        );
+        assert_eq!(eval("parseFloat('1.') + parseFloat('5e')"), "6");
+        assert_eq!(eval("Number('  0x10 ') + Number() + Number(true)"), "17");
+        assert_eq!(eval("Number.parseInt === parseInt"), "true");
+    }
+
+    #[test]
+    fn test_predicates() {
+        assert_eq!(
+            eval("[Number.isInteger(5), Number.isInteger('5'), Number.isSafeInteger(2 ** 53), Number.isNaN('x'), isNaN('x')].join()"),
+            "true,false,false,false,true"
+        );
+        assert_eq!(
+            eval("Number.MAX_SAFE_INTEGER + ' ' + Number.EPSILON + ' ' + Number.MIN_VALUE"),
+            "9007199254740991 2.220446049250313e-16 5e-324"
+        );
+        assert_eq!(eval("isFinite('12') && !Number.isFinite('12')"), "true");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        for source in ["(1).toFixed(101)", "(1).toString(1)", "(1).toPrecision(0)"] {
+            assert!(
+                matches!(vm.eval(source), Err(RuntimeError::RangeError(_))),
+                "{source}"
+            );
+        }
+        assert!(matches!(
+            vm.eval("var f = (1).toFixed; f(1)"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+}
@@ -0,0 +1,527 @@
+//! The `Object` constructor and `Object.prototype`.
+//!
+//! Every property is enumerable, so the key-listing functions differ only
+//! in whether they include `length`, which arrays and strings have but do
+//! not enumerate.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{arg, define_methods, define_statics, iterable_values, own_property_names};
+use crate::array::Key;
+use crate::heap::{ENTRY_SIZE, OBJECT_SIZE};
+use crate::{
+    Atom, JsArray, JsObject, JsString, JsSymbol, NativeFunction, Properties, RuntimeError, Value,
+    VM,
+};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.object_prototype.clone();
+    // The end of every prototype chain
+    prototype.borrow_mut().set_prototype(Value::Null);
+    define_methods(
+        &prototype,
+        &[
+            ("hasOwnProperty", has_own_property),
+            ("isPrototypeOf", is_prototype_of),
+            ("propertyIsEnumerable", property_is_enumerable),
+            ("toString", to_string),
+            ("valueOf", value_of),
+        ],
+    );
+    let ctor = NativeFunction::new("Object", construct);
+    define_statics(
+        &ctor,
+        &[
+            ("assign", assign),
+            ("entries", entries),
+            ("fromEntries", from_entries),
+            ("getOwnPropertyNames", get_own_property_names),
+            ("getOwnPropertySymbols", get_own_property_symbols),
+            ("getPrototypeOf", get_prototype_of),
+            ("hasOwn", has_own),
+            ("is", is),
+            ("keys", keys),
+            ("setPrototypeOf", set_prototype_of),
+            ("values", values),
+        ],
+    );
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Object", ctor);
+}
+
+/// Fails for the values that cannot be converted to objects.
+fn to_object(value: &Value) -> Result<(), RuntimeError> {
+    match value {
+        Value::Undefined | Value::Null => Err(RuntimeError::TypeError(
+            "Cannot convert undefined or null to object".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The symbols keying own properties of `value`, in the order they were
+/// added.
+fn own_property_symbols(value: &Value) -> Vec<JsSymbol> {
+    let symbols = |properties: &Properties| {
+        properties
+            .keys()
+            .filter_map(|key| key.as_symbol().cloned())
+            .collect()
+    };
+    match value {
+        Value::Object(obj) => symbols(obj.borrow().properties()),
+        Value::Array(arr) => arr
+            .borrow()
+            .properties()
+            .filter_map(|(key, _)| key.as_symbol().cloned())
+            .collect(),
+        Value::NativeFunction(func) => symbols(&func.properties()),
+        Value::Function(closure) => symbols(&closure.properties.borrow()),
+        _ => Vec::new(),
+    }
+}
+
+/// SameValue: like `===`, except that NaN equals itself and the two zeros
+/// differ.
+fn same_value(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => {
+            (x.is_nan() && y.is_nan()) || (x == y && x.is_sign_negative() == y.is_sign_negative())
+        }
+        _ => a == b,
+    }
+}
+
+fn construct(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    match arg(args, 0) {
+        Value::Undefined | Value::Null => {
+            vm.charge(OBJECT_SIZE)?;
+            Ok(Value::Object(Rc::new(RefCell::new(JsObject::new()))))
+        }
+        // Primitives cannot be boxed, so they are returned as they are
+        value => Ok(value),
+    }
+}
+
+fn assign(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let target = arg(args, 0);
+    to_object(&target)?;
+    for source in args.iter().skip(1) {
+        if source.is_undefined() || source.is_null() {
+            continue;
+        }
+        let names = own_property_names(source, false)
+            .into_iter()
+            .map(Value::String);
+        let symbols = own_property_symbols(source).into_iter().map(Value::Symbol);
+        for key in names.chain(symbols).collect::<Vec<_>>() {
+            let value = vm.get_property(source, &key)?;
+            vm.set_property(&target, &key, value)?;
+        }
+    }
+    Ok(target)
+}
+
+/// Copies the own enumerable properties of `source` into a new object,
+/// leaving out the keys in `excluded`, for `CopyRest`.
+pub(crate) fn copy_rest(
+    vm: &mut VM,
+    source: &Value,
+    excluded: &[Value],
+) -> Result<Value, RuntimeError> {
+    if source.is_undefined() || source.is_null() {
+        let source = source.to_js_string();
+        return Err(RuntimeError::TypeError(format!(
+            "Cannot destructure '{source}' as it is {source}."
+        )));
+    }
+    let excluded: Vec<Atom> = excluded
+        .iter()
+        .map(|key| Key::new(key).into_atom())
+        .collect();
+    let names = own_property_names(source, false)
+        .into_iter()
+        .map(Value::String);
+    let symbols = own_property_symbols(source).into_iter().map(Value::Symbol);
+    vm.charge(OBJECT_SIZE)?;
+    let mut rest = JsObject::new();
+    for key in names.chain(symbols).collect::<Vec<_>>() {
+        let name = Key::new(&key).into_atom();
+        if excluded.contains(&name) {
+            continue;
+        }
+        let value = vm.get_property(source, &key)?;
+        vm.charge(ENTRY_SIZE)?;
+        rest.properties_mut().insert(name, value);
+    }
+    Ok(Value::Object(Rc::new(RefCell::new(rest))))
+}
+
+fn entries(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let mut entries = Vec::new();
+    for name in own_property_names(&obj, false) {
+        let key = Value::String(name);
+        let value = vm.get_property(&obj, &key)?;
+        entries.push(vm.new_array(JsArray::from(vec![key, value]))?);
+    }
+    vm.new_array(JsArray::from(entries))
+}
+
+fn from_entries(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let iterable = arg(args, 0);
+    let entries = match iterable {
+        Value::Undefined | Value::Null => None,
+        _ => iterable_values(vm, &iterable)?,
+    };
+    let Some(entries) = entries else {
+        return Err(RuntimeError::TypeError(format!(
+            "{} is not iterable",
+            iterable.to_js_string()
+        )));
+    };
+    vm.charge(OBJECT_SIZE)?;
+    let obj = Value::Object(Rc::new(RefCell::new(JsObject::new())));
+    for entry in entries {
+        vm.tick()?;
+        if !entry.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Iterator value {} is not an entry object",
+                entry.to_js_string()
+            )));
+        }
+        let key = vm.get_property(&entry, &Value::Number(0.0))?;
+        let value = vm.get_property(&entry, &Value::Number(1.0))?;
+        vm.set_property(&obj, &key, value)?;
+    }
+    Ok(obj)
+}
+
+fn get_own_property_names(
+    vm: &mut VM,
+    _this: Value,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let names = own_property_names(&obj, true);
+    vm.new_array(
+        names
+            .into_iter()
+            .map(Value::String)
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+fn get_own_property_symbols(
+    vm: &mut VM,
+    _this: Value,
+    args: &[Value],
+) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let symbols = own_property_symbols(&obj);
+    vm.new_array(
+        symbols
+            .into_iter()
+            .map(Value::Symbol)
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+fn get_prototype_of(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    Ok(vm.prototype_of(&obj))
+}
+
+fn has_own(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let key = Key::new(&arg(args, 1));
+    Ok(Value::Boolean(vm.own_property(&obj, &key).is_some()))
+}
+
+fn is(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(same_value(&arg(args, 0), &arg(args, 1))))
+}
+
+fn keys(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let names = own_property_names(&obj, false);
+    vm.new_array(
+        names
+            .into_iter()
+            .map(Value::String)
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+fn set_prototype_of(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    let prototype = arg(args, 1);
+    if obj.is_undefined() || obj.is_null() {
+        return Err(RuntimeError::TypeError(
+            "Object.setPrototypeOf called on null or undefined".to_string(),
+        ));
+    }
+    if !prototype.is_object() && !prototype.is_null() {
+        return Err(RuntimeError::TypeError(format!(
+            "Object prototype may only be an Object or null: {}",
+            prototype.to_js_string()
+        )));
+    }
+    match &obj {
+        Value::Object(_) | Value::Array(_) => {}
+        // Primitives are left alone, as their boxes would be thrown away
+        _ if !obj.is_object() => return Ok(obj),
+        // Functions inherit from their intrinsic prototypes only
+        _ if vm.prototype_of(&obj) == prototype => return Ok(obj),
+        _ => {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot change the prototype of {}",
+                obj.type_of()
+            )))
+        }
+    };
+    let mut ancestor = prototype.clone();
+    while !ancestor.is_null() {
+        if ancestor == obj {
+            return Err(RuntimeError::TypeError(
+                "Cyclic __proto__ value".to_string(),
+            ));
+        }
+        ancestor = vm.prototype_of(&ancestor);
+    }
+    match &obj {
+        Value::Object(target) => target.borrow_mut().set_prototype(prototype),
+        Value::Array(target) => target.borrow_mut().set_prototype(prototype),
+        _ => unreachable!(),
+    }
+    Ok(obj)
+}
+
+fn values(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let obj = arg(args, 0);
+    to_object(&obj)?;
+    let mut values = Vec::new();
+    for name in own_property_names(&obj, false) {
+        values.push(vm.get_property(&obj, &Value::String(name))?);
+    }
+    vm.new_array(JsArray::from(values))
+}
+
+fn has_own_property(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    to_object(&this)?;
+    let key = Key::new(&arg(args, 0));
+    Ok(Value::Boolean(vm.own_property(&this, &key).is_some()))
+}
+
+fn is_prototype_of(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let value = arg(args, 0);
+    if !value.is_object() {
+        return Ok(Value::Boolean(false));
+    }
+    to_object(&this)?;
+    let mut ancestor = vm.prototype_of(&value);
+    while !ancestor.is_null() {
+        if ancestor == this {
+            return Ok(Value::Boolean(true));
+        }
+        ancestor = vm.prototype_of(&ancestor);
+    }
+    Ok(Value::Boolean(false))
+}
+
+fn property_is_enumerable(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    to_object(&this)?;
+    let key = Key::new(&arg(args, 0));
+    let length = matches!(&key, Key::Name(name) if crate::array::is_length(name))
+        && matches!(this, Value::Array(_) | Value::String(_));
+    Ok(Value::Boolean(
+        !length && vm.own_property(&this, &key).is_some(),
+    ))
+}
+
+/// The tag `Object.prototype.toString` reports for a value's kind, unless
+/// the value has a `Symbol.toStringTag`.
+fn tag(value: &Value) -> &'static str {
+    match value {
+        Value::Undefined => "Undefined",
+        Value::Null => "Null",
+        Value::Boolean(_) => "Boolean",
+        Value::Number(_) => "Number",
+        Value::String(_) => "String",
+        Value::Array(_) => "Array",
+        Value::Function(_) | Value::NativeFunction(_) => "Function",
+        Value::Symbol(_) | Value::Object(_) => "Object",
+    }
+}
+
+fn to_string(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let tag = match &this {
+        Value::Undefined | Value::Null => JsString::from(tag(&this)),
+        _ => match vm.get_property(&this, &Value::Symbol(JsSymbol::to_string_tag()))? {
+            Value::String(tag) => tag,
+            _ => JsString::from(tag(&this)),
+        },
+    };
+    let tag = JsString::from("[object ")
+        .concat(&tag)
+        .concat(&JsString::from("]"));
+    Ok(Value::String(tag))
+}
+
+fn value_of(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    to_object(&this)?;
+    Ok(this)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_keys_values_entries() {
+        assert_eq!(
+            eval("Object.keys({ b: 1, a: 2, 10: 0, 2: 0 }).join()"),
+            "2,10,b,a"
+        );
+        assert_eq!(eval("Object.values({ a: 1, b: 'x' }).join()"), "1,x");
+        assert_eq!(
+            eval("Object.entries({ a: 1, b: 2 }).map(function (e) { return e.join('='); }).join('&')"),
+            "a=1&b=2"
+        );
+        assert_eq!(
+            eval("var a = [1, , 3]; a.x = 0; Object.keys(a).join()"),
+            "0,2,x"
+        );
+        assert_eq!(eval("Object.keys('ab').join()"), "0,1");
+        assert_eq!(
+            eval("Object.getOwnPropertyNames([5]).join() + ' ' + Object.getOwnPropertyNames('a').join()"),
+            "0,length 0,length"
+        );
+    }
+
+    #[test]
+    fn test_assign_and_from_entries() {
+        assert_eq!(
+            eval("var t = { a: 1 }; var r = Object.assign(t, { b: 2 }, null, { a: 3 }); r === t && t.a + t.b"),
+            "5"
+        );
+        assert_eq!(eval("Object.fromEntries([['a', 1], ['b', 2]]).b"), "2");
+        assert_eq!(
+            eval("JSON.stringify(Object.fromEntries(Object.entries({ x: 1, y: 2 })))"),
+            r#"{"x":1,"y":2}"#
+        );
+        assert_eq!(
+            eval("var s = Symbol('s'); var o = Object.fromEntries(new Map([['a', 1], [s, 2]])); o.a + o[s]"),
+            "3"
+        );
+        assert_eq!(
+            eval("function* g() { yield ['k', 'v']; } Object.fromEntries(g()).k"),
+            "v"
+        );
+        let mut vm = VM::default();
+        assert_eq!(
+            vm.eval("Object.fromEntries(1)"),
+            Err(RuntimeError::TypeError("1 is not iterable".to_string()))
+        );
+        vm.set_fuel(Some(1_000));
+        assert_eq!(
+            vm.eval("var a = []; a.length = 10000; Object.fromEntries(a.fill(['k', 1]))"),
+            Err(RuntimeError::OutOfFuel)
+        );
+    }
+
+    #[test]
+    fn test_prototypes() {
+        let source = "
+            var base = { greet: function () { return 'hi'; } };
+            var o = Object.setPrototypeOf({ own: 1 }, base);
+            [
+                o.greet(),
+                'greet' in o,
+                o.hasOwnProperty('greet'),
+                Object.hasOwn(o, 'own'),
+                Object.getPrototypeOf(o) === base,
+                base.isPrototypeOf(o),
+                Object.prototype.isPrototypeOf(o),
+                Object.getPrototypeOf(base) === Object.prototype,
+                Object.getPrototypeOf([]) === Array.prototype,
+                Object.getPrototypeOf(Object.prototype)
+            ].join();
+        ";
+        assert_eq!(eval(source), "hi,true,false,true,true,true,true,true,true,");
+        assert_eq!(
+            eval("var o = Object.setPrototypeOf({}, null); [o.toString, 'toString' in {}].join()"),
+            ",true"
+        );
+        assert_eq!(
+            eval("var a = Object.setPrototypeOf([1], { x: 2 }); [a.x, a.join, Array.isArray(a), a[0]].join()"),
+            "2,,true,1"
+        );
+    }
+
+    #[test]
+    fn test_prototype_errors() {
+        let mut vm = VM::default();
+        for source in [
+            "var a = {}; var b = Object.setPrototypeOf({}, a); Object.setPrototypeOf(a, b)",
+            "Object.setPrototypeOf({}, 1)",
+            "Object.keys(null)",
+            "Object.setPrototypeOf(function () {}, {})",
+        ] {
+            assert!(
+                matches!(vm.eval(source), Err(RuntimeError::TypeError(_))),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_prototype_methods() {
+        assert_eq!(
+            eval("[({}).toString(), [1, 2].toString(), [].toString === Object.prototype.toString, Object.prototype.toString.call([1])].join()"),
+            "[object Object],1,2,false,[object Array]"
+        );
+        let mut vm = VM::default();
+        let to_string = vm.eval("Object.prototype.toString").unwrap();
+        let values = vm
+            .eval("[[], 1, 'x', true, null, undefined, function () {}, {}]")
+            .unwrap();
+        let Value::Array(values) = values else {
+            panic!("not an array");
+        };
+        let tags: Vec<String> = values
+            .borrow()
+            .iter()
+            .map(|(_, value)| {
+                vm.call_function(&to_string, value.clone(), &[])
+                    .unwrap()
+                    .to_js_string()
+                    .to_std_string_lossy()
+            })
+            .collect();
+        assert_eq!(
+            tags.join(","),
+            "[object Array],[object Number],[object String],[object Boolean],[object Null],[object Undefined],[object Function],[object Object]"
+        );
+        assert_eq!(
+            eval("[[1].propertyIsEnumerable(0), [1].propertyIsEnumerable('length'), ({ a: 1 }).propertyIsEnumerable('b')].join()"),
+            "true,false,false"
+        );
+        assert_eq!(
+            eval("[Object.is(0 / 0, 0 / 0), Object.is(0, -0), Object.is('a', 'a')].join()"),
+            "true,false,true"
+        );
+    }
+}
@@ -0,0 +1,495 @@
+//! `Promise`, its prototype methods and the combinators.
+//!
+//! The combinators resolve each element with `Promise.resolve` and react to
+//! it through its `then` method, so thenables of any kind can be mixed with
+//! promises.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::{arg, callable, define_methods, define_statics, define_to_string_tag};
+use crate::heap::{ELEMENT_SIZE, ENTRY_SIZE, OBJECT_SIZE};
+use crate::promise::as_promise;
+use crate::{JsArray, JsObject, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.promise_prototype.clone();
+    define_methods(
+        &prototype,
+        &[
+            ("catch", promise_catch),
+            ("finally", promise_finally),
+            ("then", promise_then),
+        ],
+    );
+    define_to_string_tag(&prototype, "Promise");
+    let ctor = NativeFunction::constructor("Promise", construct);
+    define_statics(
+        &ctor,
+        &[
+            ("all", all),
+            ("allSettled", all_settled),
+            ("any", any),
+            ("race", race),
+            ("reject", reject),
+            ("resolve", resolve),
+        ],
+    );
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Promise", ctor);
+}
+
+fn construct(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let executor = arg(args, 0);
+    if !executor.is_function() {
+        return Err(RuntimeError::TypeError(format!(
+            "Promise resolver {} is not a function",
+            executor.to_js_string()
+        )));
+    }
+    let promise = vm.create_promise()?;
+    let (resolve, reject) = vm.resolving_functions(&promise);
+    if let Err(err) = vm.call_function(&executor, Value::Undefined, &[resolve, reject.clone()]) {
+        let reason = vm.error_value(err)?;
+        vm.call_function(&reject, Value::Undefined, &[reason])?;
+    }
+    Ok(promise)
+}
+
+fn promise_then(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    if as_promise(&this).is_none() {
+        return Err(RuntimeError::TypeError(format!(
+            "Method Promise.prototype.then called on incompatible receiver {}",
+            this.to_js_string()
+        )));
+    }
+    let derived = vm.create_promise()?;
+    vm.promise_then(&this, arg(args, 0), arg(args, 1), Some(derived.clone()))?;
+    Ok(derived)
+}
+
+fn promise_catch(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    invoke(vm, &this, "then", &[Value::Undefined, arg(args, 0)])
+}
+
+fn promise_finally(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    if !this.is_object() {
+        return Err(RuntimeError::TypeError(format!(
+            "Method Promise.prototype.finally called on incompatible receiver {}",
+            this.to_js_string()
+        )));
+    }
+    let on_finally = arg(args, 0);
+    if !on_finally.is_function() {
+        return invoke(vm, &this, "then", &[on_finally.clone(), on_finally]);
+    }
+    // Both handlers wait for `onFinally` before passing on the outcome
+    let then_finally = {
+        let on_finally = on_finally.clone();
+        NativeFunction::new("", move |vm, _this, args| {
+            let value = arg(args, 0);
+            let result = vm.call_function(&on_finally, Value::Undefined, &[])?;
+            let promise = vm.promise_resolve(result)?;
+            let value_thunk = NativeFunction::new("", move |_, _, _| Ok(value.clone()));
+            invoke(vm, &promise, "then", &[value_thunk.into()])
+        })
+    };
+    let catch_finally = NativeFunction::new("", move |vm, _this, args| {
+        let reason = arg(args, 0);
+        let result = vm.call_function(&on_finally, Value::Undefined, &[])?;
+        let promise = vm.promise_resolve(result)?;
+        let thrower =
+            NativeFunction::new("", move |_, _, _| Err(RuntimeError::Thrown(reason.clone())));
+        invoke(vm, &promise, "then", &[thrower.into()])
+    });
+    invoke(
+        vm,
+        &this,
+        "then",
+        &[then_finally.into(), catch_finally.into()],
+    )
+}
+
+fn resolve(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    vm.promise_resolve(arg(args, 0))
+}
+
+fn reject(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let promise = vm.create_promise()?;
+    vm.reject_promise(&promise, arg(args, 0))?;
+    Ok(promise)
+}
+
+/// Calls the method `name` of `target`.
+fn invoke(vm: &mut VM, target: &Value, name: &str, args: &[Value]) -> Result<Value, RuntimeError> {
+    let method = vm.get_property(target, &Value::from(name))?;
+    callable(&method)?;
+    vm.call_function(&method, target.clone(), args)
+}
+
+/// Which combinator is running, deciding what happens as each element
+/// settles.
+#[derive(Clone, Copy, PartialEq)]
+enum Combinator {
+    All,
+    AllSettled,
+    Any,
+    Race,
+}
+
+fn all(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    combine(vm, &arg(args, 0), Combinator::All)
+}
+
+fn all_settled(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    combine(vm, &arg(args, 0), Combinator::AllSettled)
+}
+
+fn any(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    combine(vm, &arg(args, 0), Combinator::Any)
+}
+
+fn race(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    combine(vm, &arg(args, 0), Combinator::Race)
+}
+
+/// State shared by the element handlers of one `all`, `allSettled` or
+/// `any` call.
+struct Elements {
+    /// The results, or for `any` the rejection reasons, by position
+    values: RefCell<Vec<Value>>,
+    /// Elements yet to settle, plus one until iteration has finished
+    remaining: Cell<usize>,
+    promise: Value,
+    combinator: Combinator,
+}
+
+impl Elements {
+    /// Records that one more element has settled, settling the combined
+    /// promise once all of them have.
+    fn finish_one(&self, vm: &mut VM) -> Result<(), RuntimeError> {
+        let remaining = self.remaining.get() - 1;
+        self.remaining.set(remaining);
+        if remaining > 0 {
+            return Ok(());
+        }
+        let values = vm.new_array(JsArray::from(self.values.take()))?;
+        if self.combinator == Combinator::Any {
+            let error = aggregate_error(vm, values)?;
+            vm.reject_promise(&self.promise, error)
+        } else {
+            vm.resolve_promise(&self.promise, values)
+        }
+    }
+}
+
+/// The error `Promise.any` rejects with when every element was rejected.
+fn aggregate_error(vm: &mut VM, errors: Value) -> Result<Value, RuntimeError> {
+    vm.charge(OBJECT_SIZE + 3 * ENTRY_SIZE)?;
+    let mut error = JsObject::new();
+    error.insert("name", "AggregateError");
+    error.insert("message", "All promises were rejected");
+    error.insert("errors", errors);
+    Ok(Value::Object(Rc::new(RefCell::new(error))))
+}
+
+/// Runs a combinator over the elements of `iterable`. Errors a script can
+/// catch reject the returned promise instead of being thrown.
+fn combine(vm: &mut VM, iterable: &Value, combinator: Combinator) -> Result<Value, RuntimeError> {
+    let promise = vm.create_promise()?;
+    let (resolve, reject) = vm.resolving_functions(&promise);
+    let elements = Rc::new(Elements {
+        values: RefCell::new(Vec::new()),
+        remaining: Cell::new(1),
+        promise: promise.clone(),
+        combinator,
+    });
+    let iterator = match vm.get_iterator(iterable) {
+        Ok(iterator) => iterator,
+        Err(err) => {
+            let reason = vm.error_value(err)?;
+            vm.call_function(&reject, Value::Undefined, &[reason])?;
+            return Ok(promise);
+        }
+    };
+    let mut index = 0;
+    loop {
+        let value = match vm.iterator_next(&iterator) {
+            Ok(Some(value)) => value,
+            Ok(None) => break,
+            // The iterator failed, so it is not closed
+            Err(err) => {
+                let reason = vm.error_value(err)?;
+                vm.call_function(&reject, Value::Undefined, &[reason])?;
+                return Ok(promise);
+            }
+        };
+        if combinator != Combinator::Race {
+            elements.remaining.set(elements.remaining.get() + 1);
+        }
+        let result = (|| {
+            let next = vm.promise_resolve(value)?;
+            let (on_fulfilled, on_rejected) = match combinator {
+                Combinator::All => (
+                    element_handler(vm, &elements, index, "fulfilled")?,
+                    reject.clone(),
+                ),
+                Combinator::AllSettled => (
+                    element_handler(vm, &elements, index, "fulfilled")?,
+                    element_handler(vm, &elements, index, "rejected")?,
+                ),
+                Combinator::Any => (
+                    resolve.clone(),
+                    element_handler(vm, &elements, index, "rejected")?,
+                ),
+                Combinator::Race => (resolve.clone(), reject.clone()),
+            };
+            invoke(vm, &next, "then", &[on_fulfilled, on_rejected])
+        })();
+        if let Err(err) = result {
+            // The original error wins over one from closing
+            if let Err(close) = vm.iterator_close(&iterator) {
+                if !close.is_catchable() {
+                    return Err(close);
+                }
+            }
+            let reason = vm.error_value(err)?;
+            vm.call_function(&reject, Value::Undefined, &[reason])?;
+            return Ok(promise);
+        }
+        index += 1;
+    }
+    if combinator != Combinator::Race {
+        elements.finish_one(vm)?;
+    }
+    Ok(promise)
+}
+
+/// Makes the handler that records how the element at `index` settled.
+/// `outcome` is "fulfilled" or "rejected", which `allSettled` reports as the
+/// status.
+fn element_handler(
+    vm: &mut VM,
+    elements: &Rc<Elements>,
+    index: usize,
+    outcome: &'static str,
+) -> Result<Value, RuntimeError> {
+    vm.charge(ELEMENT_SIZE)?;
+    {
+        let mut values = elements.values.borrow_mut();
+        if values.len() <= index {
+            values.resize(index + 1, Value::Undefined);
+        }
+    }
+    let elements = elements.clone();
+    let already_called = Cell::new(false);
+    let handler = NativeFunction::new("", move |vm, _this, args| {
+        if already_called.replace(true) {
+            return Ok(Value::Undefined);
+        }
+        let value = arg(args, 0);
+        let value = if elements.combinator == Combinator::AllSettled {
+            vm.charge(OBJECT_SIZE + 2 * ENTRY_SIZE)?;
+            let mut result = JsObject::new();
+            result.insert("status", outcome);
+            result.insert(
+                if outcome == "fulfilled" {
+                    "value"
+                } else {
+                    "reason"
+                },
+                value,
+            );
+            Value::Object(Rc::new(RefCell::new(result)))
+        } else {
+            value
+        };
+        elements.values.borrow_mut()[index] = value;
+        elements.finish_one(vm)?;
+        Ok(Value::Undefined)
+    });
+    Ok(handler.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval_async;
+    use crate::RejectionEvent;
+
+    #[test]
+    fn test_then() {
+        assert_eq!(
+            eval_async("new Promise(function (resolve) { log.push('executor'); resolve(1) }).then(function (v) { log.push(v); return v + 1 }).then(function (v) { log.push(v) }); log.push('sync')"),
+            "executor,sync,1,2"
+        );
+        assert_eq!(
+            eval_async("Promise.reject('x').then(function () { log.push('no') }).catch(function (e) { log.push('caught ' + e); return 'y' }).then(function (v) { log.push(v) })"),
+            "caught x,y"
+        );
+        assert_eq!(
+            eval_async("new Promise(function () { throw 'boom' }).then(null, function (e) { log.push(e) })"),
+            "boom"
+        );
+        assert_eq!(
+            eval_async("Promise.resolve(1).then(function () { null.x }).catch(function (e) { log.push(e.name) })"),
+            "TypeError"
+        );
+    }
+
+    #[test]
+    fn test_ordering() {
+        // Reactions run in the order they were queued, one turn apart per
+        // link of a chain
+        assert_eq!(
+            eval_async("var a = Promise.resolve(); a.then(function () { log.push('a1') }).then(function () { log.push('a2') }); a.then(function () { log.push('b1') }).then(function () { log.push('b2') })"),
+            "a1,b1,a2,b2"
+        );
+        // Only the first call to resolve or reject counts
+        assert_eq!(
+            eval_async("new Promise(function (resolve, reject) { resolve(1); reject(2); resolve(3) }).then(function (v) { log.push(v) })"),
+            "1"
+        );
+    }
+
+    #[test]
+    fn test_thenables() {
+        assert_eq!(
+            eval_async("Promise.resolve({ then: function (resolve) { resolve(42) } }).then(function (v) { log.push(v) })"),
+            "42"
+        );
+        assert_eq!(
+            eval_async("var p = Promise.resolve(1); log.push(Promise.resolve(p) === p); Promise.resolve(p).then(function (v) { log.push(v) })"),
+            "true,1"
+        );
+        assert_eq!(
+            eval_async("var resolve; var p = new Promise(function (r) { resolve = r }); resolve(p); p.catch(function (e) { log.push(e.message) })"),
+            "Chaining cycle detected for promise #<Promise>"
+        );
+    }
+
+    #[test]
+    fn test_finally() {
+        assert_eq!(
+            eval_async("Promise.resolve(1).finally(function () { log.push('f'); return 2 }).then(function (v) { log.push(v) })"),
+            "f,1"
+        );
+        assert_eq!(
+            eval_async("Promise.reject('x').finally(function () { log.push('f') }).catch(function (e) { log.push(e) })"),
+            "f,x"
+        );
+        assert_eq!(
+            eval_async("Promise.resolve(1).finally(function () { throw 'y' }).catch(function (e) { log.push(e) })"),
+            "y"
+        );
+    }
+
+    #[test]
+    fn test_combinators() {
+        assert_eq!(
+            eval_async("var later = Promise.resolve().then(function () { return 'b' }); Promise.all([later, 'a', Promise.resolve('c')]).then(function (v) { log.push(v.join('')) })"),
+            "bac"
+        );
+        assert_eq!(
+            eval_async("Promise.all([]).then(function (v) { log.push(v.length) })"),
+            "0"
+        );
+        assert_eq!(
+            eval_async(
+                "Promise.all([1, Promise.reject('no')]).catch(function (e) { log.push(e) })"
+            ),
+            "no"
+        );
+        assert_eq!(
+            eval_async("Promise.allSettled([1, Promise.reject('no')]).then(function (v) { v.forEach(function (r) { log.push(r.status, 'value' in r ? r.value : r.reason) }) })"),
+            "fulfilled,1,rejected,no"
+        );
+        assert_eq!(
+            eval_async("Promise.any([Promise.reject(1), 2]).then(function (v) { log.push(v) })"),
+            "2"
+        );
+        assert_eq!(
+            eval_async("Promise.any([Promise.reject(1), Promise.reject(2)]).catch(function (e) { log.push(e.name, e.errors.join('')) })"),
+            "AggregateError,12"
+        );
+        assert_eq!(
+            eval_async("Promise.race([Promise.resolve().then(function () { return 'slow' }), 'fast']).then(function (v) { log.push(v) })"),
+            "fast"
+        );
+        assert_eq!(
+            eval_async("Promise.all(5).catch(function (e) { log.push(e.message) })"),
+            "5 is not iterable"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        assert_eq!(
+            vm.eval("new Promise(1)"),
+            Err(RuntimeError::TypeError(
+                "Promise resolver 1 is not a function".to_string()
+            ))
+        );
+        assert_eq!(
+            vm.eval("Promise(function () {})"),
+            Err(RuntimeError::TypeError(
+                "Constructor Promise requires 'new'".to_string()
+            ))
+        );
+        vm.eval("var then = Promise.resolve().then").unwrap();
+        assert_eq!(
+            vm.eval("then()"),
+            Err(RuntimeError::TypeError(
+                "Method Promise.prototype.then called on incompatible receiver undefined"
+                    .to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_host_promises() {
+        let mut vm = VM::default();
+        let promise = vm.create_promise().unwrap();
+        vm.set_global("pending", promise.clone());
+        vm.eval("var result; pending.then(function (v) { result = v })")
+            .unwrap();
+        vm.run_microtasks().unwrap();
+        assert_eq!(vm.get_global("result"), Some(Value::Undefined));
+        vm.resolve_promise(&promise, Value::Number(7.0)).unwrap();
+        assert!(vm.has_pending_microtasks());
+        vm.run_microtasks().unwrap();
+        assert!(!vm.has_pending_microtasks());
+        assert_eq!(vm.get_global("result"), Some(Value::Number(7.0)));
+        assert_eq!(
+            vm.resolve_promise(&Value::Null, Value::Undefined),
+            Err(RuntimeError::TypeError("null is not a promise".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_rejection_tracker() {
+        let mut vm = VM::default();
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let log = events.clone();
+        vm.set_rejection_tracker(Some(Box::new(move |event, _promise, reason| {
+            log.borrow_mut()
+                .push((event, reason.to_js_string().to_std_string_lossy()));
+        })));
+        vm.eval("var p = Promise.reject('a'); Promise.reject('b').catch(function () {})")
+            .unwrap();
+        vm.run_microtasks().unwrap();
+        vm.eval("p.catch(function () {})").unwrap();
+        vm.run_microtasks().unwrap();
+        assert_eq!(
+            *events.borrow(),
+            [
+                (RejectionEvent::Unhandled, "a".to_string()),
+                (RejectionEvent::Unhandled, "b".to_string()),
+                (RejectionEvent::Handled, "b".to_string()),
+                (RejectionEvent::Handled, "a".to_string()),
+            ]
+        );
+    }
+}
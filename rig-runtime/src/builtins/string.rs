@@ -0,0 +1,712 @@
+//! The `String` constructor and `String.prototype`.
+//!
+//! Strings are never boxed: methods run with the primitive as `this` and
+//! work on its UTF-16 code units, so lone surrogates survive every method
+//! except `normalize`'s composition of the text around them.
+
+use unicode_normalization::UnicodeNormalization;
+
+use super::{
+    arg, define_iterator, define_methods, define_statics, relative_index, to_integer, to_uint32,
+    MAX_STRING_LENGTH,
+};
+use crate::heap;
+use crate::iterator::Iteration;
+use crate::string::is_whitespace;
+use crate::{JsArray, JsString, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.string_prototype.clone();
+    define_methods(
+        &prototype,
+        &[
+            ("at", at),
+            ("charAt", char_at),
+            ("charCodeAt", char_code_at),
+            ("codePointAt", code_point_at),
+            ("endsWith", ends_with),
+            ("includes", includes),
+            ("indexOf", index_of),
+            ("lastIndexOf", last_index_of),
+            ("normalize", normalize),
+            ("padEnd", pad_end),
+            ("padStart", pad_start),
+            ("repeat", repeat),
+            ("replace", replace),
+            ("replaceAll", replace_all),
+            ("slice", slice),
+            ("split", split),
+            ("startsWith", starts_with),
+            ("substring", substring),
+            ("toLowerCase", to_lower_case),
+            ("toString", to_string),
+            ("toUpperCase", to_upper_case),
+            ("trim", trim),
+            ("trimEnd", trim_end),
+            ("trimStart", trim_start),
+            ("valueOf", to_string),
+        ],
+    );
+    let iterator = Value::from(NativeFunction::new("[Symbol.iterator]", iterator));
+    define_iterator(&prototype, iterator.clone());
+    vm.intrinsics.string_iterator = Some(iterator);
+    let ctor = NativeFunction::new("String", construct);
+    define_statics(
+        &ctor,
+        &[
+            ("fromCharCode", from_char_code),
+            ("fromCodePoint", from_code_point),
+        ],
+    );
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("String", ctor);
+}
+
+/// The code units of `this`, converting it to a string as the generic
+/// methods do.
+fn this_units(this: &Value, method: &str) -> Result<Vec<u16>, RuntimeError> {
+    match this {
+        Value::Undefined | Value::Null => Err(RuntimeError::TypeError(format!(
+            "String.prototype.{method} called on null or undefined"
+        ))),
+        Value::String(s) => Ok(s.code_units().collect()),
+        value => Ok(value.to_js_string().code_units().collect()),
+    }
+}
+
+fn units_of(value: &Value) -> Vec<u16> {
+    value.to_js_string().code_units().collect()
+}
+
+/// The first occurrence of `needle` at or after `from`.
+fn find(haystack: &[u16], needle: &[u16], from: usize) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    (from..=haystack.len() - needle.len()).find(|&i| haystack[i..].starts_with(needle))
+}
+
+/// The last occurrence of `needle` at or before `from`.
+fn rfind(haystack: &[u16], needle: &[u16], from: usize) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    let last = from.min(haystack.len() - needle.len());
+    (0..=last)
+        .rev()
+        .find(|&i| haystack[i..].starts_with(needle))
+}
+
+/// Clamps a position argument to `0..=len`.
+fn position(value: &Value, len: usize) -> usize {
+    to_integer(value).clamp(0.0, len as f64) as usize
+}
+
+/// Decodes code units into chars, keeping lone surrogates as `Err`.
+fn decode(units: &[u16]) -> impl Iterator<Item = Result<char, u16>> + '_ {
+    char::decode_utf16(units.iter().copied()).map(|c| c.map_err(|e| e.unpaired_surrogate()))
+}
+
+fn encode(chars: impl Iterator<Item = Result<char, u16>>) -> Vec<u16> {
+    let mut units = Vec::new();
+    let mut buf = [0; 2];
+    for c in chars {
+        match c {
+            Ok(c) => units.extend_from_slice(c.encode_utf16(&mut buf)),
+            Err(unit) => units.push(unit),
+        }
+    }
+    units
+}
+
+/// Fails if a string of `len` code units is too long to build, and charges
+/// for it otherwise. Used before building results that may be huge.
+fn reserve(vm: &mut VM, len: usize, latin1: bool) -> Result<(), RuntimeError> {
+    if len > MAX_STRING_LENGTH {
+        return Err(RuntimeError::RangeError(
+            "Invalid string length".to_string(),
+        ));
+    }
+    vm.charge(heap::string_size(len, latin1))
+}
+
+fn construct(_vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::String(match args.first() {
+        Some(value) => value.to_js_string(),
+        None => JsString::from(""),
+    }))
+}
+
+fn from_char_code(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units: Vec<u16> = args.iter().map(|arg| to_uint32(arg) as u16).collect();
+    vm.new_string(&units)
+}
+
+fn from_code_point(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let mut chars = Vec::with_capacity(args.len());
+    for arg in args {
+        let n = arg.to_number();
+        if n.fract() != 0.0 || !(0.0..=0x10ffff as f64).contains(&n) {
+            return Err(RuntimeError::RangeError(format!(
+                "Invalid code point {}",
+                arg.to_js_string()
+            )));
+        }
+        // Surrogate code points are allowed and become lone code units
+        let n = n as u32;
+        chars.push(char::from_u32(n).ok_or(n as u16));
+    }
+    vm.new_string(&encode(chars.into_iter()))
+}
+
+fn at(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "at")?;
+    let n = to_integer(&arg(args, 0));
+    let index = if n < 0.0 { units.len() as f64 + n } else { n };
+    Ok(match units.get(index as usize) {
+        Some(&unit) if index >= 0.0 => Value::String(JsString::from_code_unit(unit)),
+        _ => Value::Undefined,
+    })
+}
+
+/// The code unit at the position given by the first argument.
+fn unit_at(units: &[u16], args: &[Value]) -> Option<u16> {
+    let n = to_integer(&arg(args, 0));
+    if n < 0.0 {
+        return None;
+    }
+    units.get(n as usize).copied()
+}
+
+fn char_at(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "charAt")?;
+    Ok(Value::String(match unit_at(&units, args) {
+        Some(unit) => JsString::from_code_unit(unit),
+        None => JsString::from(""),
+    }))
+}
+
+fn char_code_at(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "charCodeAt")?;
+    Ok(Value::Number(
+        unit_at(&units, args).map_or(f64::NAN, |unit| unit as f64),
+    ))
+}
+
+fn code_point_at(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "codePointAt")?;
+    let n = to_integer(&arg(args, 0));
+    if n < 0.0 || n >= units.len() as f64 {
+        return Ok(Value::Undefined);
+    }
+    let index = n as usize;
+    let code_point = match decode(&units[index..]).next() {
+        Some(Ok(c)) => c as u32,
+        _ => units[index] as u32,
+    };
+    Ok(Value::Number(code_point as f64))
+}
+
+fn ends_with(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "endsWith")?;
+    let search = units_of(&arg(args, 0));
+    let end = match arg(args, 1) {
+        Value::Undefined => units.len(),
+        end => position(&end, units.len()),
+    };
+    Ok(Value::Boolean(units[..end].ends_with(&search)))
+}
+
+fn includes(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "includes")?;
+    let search = units_of(&arg(args, 0));
+    let start = position(&arg(args, 1), units.len());
+    Ok(Value::Boolean(find(&units, &search, start).is_some()))
+}
+
+fn index_of(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "indexOf")?;
+    let search = units_of(&arg(args, 0));
+    let start = position(&arg(args, 1), units.len());
+    Ok(Value::Number(
+        find(&units, &search, start).map_or(-1.0, |i| i as f64),
+    ))
+}
+
+/// `String.prototype[Symbol.iterator]`, which walks the string by code
+/// point.
+fn iterator(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let string = match this {
+        Value::Undefined | Value::Null => {
+            return Err(RuntimeError::TypeError(
+                "String.prototype[Symbol.iterator] called on null or undefined".to_string(),
+            ))
+        }
+        Value::String(s) => s,
+        value => value.to_js_string(),
+    };
+    let prototype = Value::Object(vm.intrinsics.string_iterator_prototype.clone());
+    vm.new_iterator(prototype, Iteration::string(string))
+}
+
+fn last_index_of(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "lastIndexOf")?;
+    let search = units_of(&arg(args, 0));
+    // A missing or NaN position searches from the end
+    let from = arg(args, 1).to_number();
+    let start = if from.is_nan() {
+        units.len()
+    } else {
+        position(&Value::Number(from), units.len())
+    };
+    Ok(Value::Number(
+        rfind(&units, &search, start).map_or(-1.0, |i| i as f64),
+    ))
+}
+
+fn normalize(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "normalize")?;
+    let form = match arg(args, 0) {
+        Value::Undefined => "NFC".to_string(),
+        form => form.to_js_string().to_std_string_lossy(),
+    };
+    let normalize: fn(&str) -> String = match form.as_str() {
+        "NFC" => |s| s.nfc().collect(),
+        "NFD" => |s| s.nfd().collect(),
+        "NFKC" => |s| s.nfkc().collect(),
+        "NFKD" => |s| s.nfkd().collect(),
+        _ => {
+            return Err(RuntimeError::RangeError(
+                "The normalization form should be one of NFC, NFD, NFKC, NFKD.".to_string(),
+            ))
+        }
+    };
+    // Lone surrogates are left in place, splitting the text around them
+    // into runs that are normalized separately
+    let mut result = Vec::with_capacity(units.len());
+    let mut run = String::new();
+    for c in decode(&units) {
+        match c {
+            Ok(c) => run.push(c),
+            Err(unit) => {
+                result.extend(normalize(&run).encode_utf16());
+                run.clear();
+                result.push(unit);
+            }
+        }
+    }
+    result.extend(normalize(&run).encode_utf16());
+    vm.new_string(&result)
+}
+
+fn pad(
+    vm: &mut VM,
+    this: Value,
+    args: &[Value],
+    method: &str,
+    at_start: bool,
+) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, method)?;
+    let max_length = to_integer(&arg(args, 0));
+    let filler = match arg(args, 1) {
+        Value::Undefined => vec![b' ' as u16],
+        filler => units_of(&filler),
+    };
+    if max_length <= units.len() as f64 || filler.is_empty() {
+        return Ok(Value::String(JsString::from_utf16(&units)));
+    }
+    let latin1 = units.iter().chain(&filler).all(|&u| u < 0x100);
+    reserve(vm, max_length.min(usize::MAX as f64) as usize, latin1)?;
+    let fill_len = max_length as usize - units.len();
+    let padding = filler.iter().copied().cycle().take(fill_len);
+    let result: Vec<u16> = if at_start {
+        padding.chain(units.iter().copied()).collect()
+    } else {
+        units.iter().copied().chain(padding).collect()
+    };
+    Ok(Value::String(JsString::from_utf16(&result)))
+}
+
+fn pad_end(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    pad(vm, this, args, "padEnd", false)
+}
+
+fn pad_start(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    pad(vm, this, args, "padStart", true)
+}
+
+fn repeat(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "repeat")?;
+    let count = arg(args, 0);
+    let n = to_integer(&count);
+    if n < 0.0 || n.is_infinite() {
+        return Err(RuntimeError::RangeError(format!(
+            "Invalid count value: {}",
+            count.to_js_string()
+        )));
+    }
+    let len = (units.len() as f64 * n).min(usize::MAX as f64) as usize;
+    let latin1 = units.iter().all(|&u| u < 0x100);
+    reserve(vm, len, latin1)?;
+    Ok(Value::String(JsString::from_utf16(
+        &units.repeat(n as usize),
+    )))
+}
+
+/// Expands the `$` patterns of a replacement string for a match at
+/// `position`.
+fn substitute(
+    replacement: &[u16],
+    matched: &[u16],
+    subject: &[u16],
+    position: usize,
+    result: &mut Vec<u16>,
+) {
+    let mut i = 0;
+    while i < replacement.len() {
+        let unit = replacement[i];
+        if unit == b'$' as u16 && i + 1 < replacement.len() {
+            let consumed = match replacement[i + 1] {
+                0x24 => {
+                    result.push(b'$' as u16);
+                    true
+                }
+                0x26 => {
+                    result.extend_from_slice(matched);
+                    true
+                }
+                0x60 => {
+                    result.extend_from_slice(&subject[..position]);
+                    true
+                }
+                0x27 => {
+                    result.extend_from_slice(&subject[position + matched.len()..]);
+                    true
+                }
+                _ => false,
+            };
+            if consumed {
+                i += 2;
+                continue;
+            }
+        }
+        result.push(unit);
+        i += 1;
+    }
+}
+
+fn replace_matches(
+    vm: &mut VM,
+    this: Value,
+    args: &[Value],
+    method: &str,
+    all: bool,
+) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, method)?;
+    let pattern = units_of(&arg(args, 0));
+    let replacement = arg(args, 1);
+    let template = match replacement {
+        Value::Function(_) | Value::NativeFunction(_) => None,
+        ref value => Some(units_of(value)),
+    };
+
+    let mut positions = Vec::new();
+    let mut from = 0;
+    while let Some(position) = find(&units, &pattern, from) {
+        positions.push(position);
+        if !all {
+            break;
+        }
+        from = position + pattern.len().max(1);
+        if from > units.len() {
+            break;
+        }
+    }
+
+    let subject = Value::String(JsString::from_utf16(&units));
+    let mut result = Vec::with_capacity(units.len());
+    let mut end = 0;
+    for position in positions {
+        result.extend_from_slice(&units[end..position]);
+        let matched = &units[position..position + pattern.len()];
+        match &template {
+            Some(template) => substitute(template, matched, &units, position, &mut result),
+            None => {
+                let value = vm.call_function(
+                    &replacement,
+                    Value::Undefined,
+                    &[
+                        Value::String(JsString::from_utf16(matched)),
+                        Value::Number(position as f64),
+                        subject.clone(),
+                    ],
+                )?;
+                result.extend(value.to_js_string().code_units());
+            }
+        }
+        end = position + pattern.len();
+    }
+    result.extend_from_slice(&units[end..]);
+    vm.new_string(&result)
+}
+
+fn replace(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    replace_matches(vm, this, args, "replace", false)
+}
+
+fn replace_all(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    replace_matches(vm, this, args, "replaceAll", true)
+}
+
+fn slice(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "slice")?;
+    let len = units.len() as u32;
+    let start = relative_index(&arg(args, 0), len, 0) as usize;
+    let end = relative_index(&arg(args, 1), len, len) as usize;
+    Ok(Value::String(JsString::from_utf16(
+        &units[start..end.max(start)],
+    )))
+}
+
+fn split(vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "split")?;
+    let limit = match arg(args, 1) {
+        Value::Undefined => u32::MAX,
+        limit => to_uint32(&limit),
+    } as usize;
+    let separator = match arg(args, 0) {
+        Value::Undefined if limit > 0 => {
+            return vm.new_array(JsArray::from(vec![Value::String(JsString::from_utf16(
+                &units,
+            ))]))
+        }
+        separator => units_of(&separator),
+    };
+
+    let mut parts = Vec::new();
+    if limit == 0 {
+        // Nothing to collect
+    } else if separator.is_empty() {
+        parts.extend(
+            units
+                .iter()
+                .take(limit)
+                .map(|&unit| Value::String(JsString::from_code_unit(unit))),
+        );
+    } else {
+        let mut start = 0;
+        while let Some(position) = find(&units, &separator, start) {
+            parts.push(Value::String(JsString::from_utf16(&units[start..position])));
+            start = position + separator.len();
+            if parts.len() == limit {
+                break;
+            }
+        }
+        if parts.len() < limit {
+            parts.push(Value::String(JsString::from_utf16(&units[start..])));
+        }
+    }
+    for part in &parts {
+        if let Value::String(s) = part {
+            vm.charge(heap::string_size(s.len(), s.is_latin1()))?;
+        }
+    }
+    vm.new_array(JsArray::from(parts))
+}
+
+fn starts_with(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "startsWith")?;
+    let search = units_of(&arg(args, 0));
+    let start = position(&arg(args, 1), units.len());
+    Ok(Value::Boolean(units[start..].starts_with(&search)))
+}
+
+fn substring(_vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "substring")?;
+    let start = position(&arg(args, 0), units.len());
+    let end = match arg(args, 1) {
+        Value::Undefined => units.len(),
+        end => position(&end, units.len()),
+    };
+    Ok(Value::String(JsString::from_utf16(
+        &units[start.min(end)..start.max(end)],
+    )))
+}
+
+fn to_lower_case(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "toLowerCase")?;
+    let chars = decode(&units).flat_map(|c| -> Box<dyn Iterator<Item = Result<char, u16>>> {
+        match c {
+            Ok(c) => Box::new(c.to_lowercase().map(Ok)),
+            Err(unit) => Box::new(std::iter::once(Err(unit))),
+        }
+    });
+    vm.new_string(&encode(chars))
+}
+
+fn to_upper_case(vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, "toUpperCase")?;
+    let chars = decode(&units).flat_map(|c| -> Box<dyn Iterator<Item = Result<char, u16>>> {
+        match c {
+            Ok(c) => Box::new(c.to_uppercase().map(Ok)),
+            Err(unit) => Box::new(std::iter::once(Err(unit))),
+        }
+    });
+    vm.new_string(&encode(chars))
+}
+
+fn to_string(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    match this {
+        Value::String(_) => Ok(this),
+        _ => Err(RuntimeError::TypeError(
+            "String.prototype.toString requires that 'this' be a String".to_string(),
+        )),
+    }
+}
+
+/// Strips whitespace from either end of `this`.
+fn trim_units(this: Value, method: &str, start: bool, end: bool) -> Result<Value, RuntimeError> {
+    let units = this_units(&this, method)?;
+    let mut slice = &units[..];
+    if start {
+        let first = slice.iter().position(|&u| !is_whitespace(u));
+        slice = &slice[first.unwrap_or(slice.len())..];
+    }
+    if end {
+        let last = slice.iter().rposition(|&u| !is_whitespace(u));
+        slice = &slice[..last.map_or(0, |i| i + 1)];
+    }
+    Ok(Value::String(JsString::from_utf16(slice)))
+}
+
+fn trim(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    trim_units(this, "trim", true, true)
+}
+
+fn trim_end(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    trim_units(this, "trimEnd", false, true)
+}
+
+fn trim_start(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    trim_units(this, "trimStart", true, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    fn eval_err(source: &str) -> RuntimeError {
+        let mut vm = VM::default();
+        match vm.eval(source) {
+            Ok(value) => panic!("{source}: expected an error, got {value:?}"),
+            Err(err) => err,
+        }
+    }
+
+    #[test]
+    fn test_characters() {
+        assert_eq!(eval("'abc'.length + 'abc'[1]"), "3b");
+        assert_eq!(
+            eval("['abc'.charAt(1), 'abc'.charAt(5), 'abc'.at(-1), 'abc'.at(3)].join()"),
+            "b,,c,"
+        );
+        assert_eq!(eval("'abc'.charCodeAt(0) + 'abc'.charCodeAt(9)"), "NaN");
+        assert_eq!(
+            eval("'\u{1F600}'.codePointAt(0) + ',' + '\u{1F600}'.codePointAt(1)"),
+            "128512,56832"
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        assert_eq!(
+            eval("['abcabc'.indexOf('c'), 'abcabc'.indexOf('c', 3), 'abcabc'.lastIndexOf('a'), 'abc'.indexOf('')].join()"),
+            "2,5,3,0"
+        );
+        assert_eq!(
+            eval("['abc'.includes('bc'), 'abc'.startsWith('b', 1), 'abc'.endsWith('b', 2), 'abc'.endsWith('a')].join()"),
+            "true,true,true,false"
+        );
+        assert_eq!(eval("'abc'.lastIndexOf('c', 1)"), "-1");
+    }
+
+    #[test]
+    fn test_substrings() {
+        assert_eq!(
+            eval("['hello'.slice(1, -1), 'hello'.slice(-3), 'hello'.substring(4, 1), 'hello'.substring(-1, 2)].join()"),
+            "ell,llo,ell,he"
+        );
+        assert_eq!(
+            eval(
+                "'a,b,,c'.split(',').length + '|' + 'abc'.split('') + '|' + 'a b c'.split(' ', 2)"
+            ),
+            "4|a,b,c|a,b"
+        );
+        assert_eq!(eval("''.split(',').length + ''.split('').length"), "1");
+        assert_eq!(eval("'abc'.split().length"), "1");
+    }
+
+    #[test]
+    fn test_transforms() {
+        assert_eq!(eval("'  \\t x \\n'.trim() + '|'"), "x|");
+        assert_eq!(
+            eval("'  x  '.trimStart() + '|' + '  x  '.trimEnd() + '|'"),
+            "x  |  x|"
+        );
+        assert_eq!(
+            eval("'5'.padStart(3, '0') + '5'.padEnd(4, 'ab')"),
+            "0055aba"
+        );
+        assert_eq!(eval("'ab'.repeat(3) + 'x'.repeat(0)"), "ababab");
+        assert_eq!(
+            eval("'Straße'.toUpperCase() + 'ÀB'.toLowerCase()"),
+            "STRASSEàb"
+        );
+        assert_eq!(
+            eval("'e\u{301}'.normalize() === '\u{e9}' && '\u{e9}'.normalize('NFD').length == 2"),
+            "true"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        assert_eq!(eval("'a-b-c'.replace('-', '+')"), "a+b-c");
+        assert_eq!(eval("'a-b-c'.replaceAll('-', '+')"), "a+b+c");
+        assert_eq!(eval("'abc'.replace('b', '[$&|$`|$\\'|$$]')"), "a[b|a|c|$]c");
+        assert_eq!(eval("'ab'.replaceAll('', '.')"), ".a.b.");
+        assert_eq!(
+            eval("'a1b1'.replaceAll('1', function (m, i) { return i; })"),
+            "a1b3"
+        );
+    }
+
+    #[test]
+    fn test_statics() {
+        assert_eq!(eval("String.fromCharCode(104, 105, 65536 + 33)"), "hi!");
+        assert_eq!(eval("String.fromCodePoint(128512).length"), "2");
+        assert_eq!(eval("String(12) + String() + String(null)"), "12null");
+        assert_eq!(eval("'x'.constructor === String"), "true");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(matches!(
+            eval_err("'x'.repeat(-1)"),
+            RuntimeError::RangeError(msg) if msg == "Invalid count value: -1"
+        ));
+        assert!(matches!(
+            eval_err("String.fromCodePoint(1.5)"),
+            RuntimeError::RangeError(msg) if msg == "Invalid code point 1.5"
+        ));
+        assert!(matches!(
+            eval_err("'x'.normalize('nfc')"),
+            RuntimeError::RangeError(_)
+        ));
+        assert!(matches!(
+            eval_err("var trim = 'x'.trim; trim()"),
+            RuntimeError::TypeError(_)
+        ));
+    }
+}
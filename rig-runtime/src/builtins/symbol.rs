@@ -0,0 +1,223 @@
+//! The `Symbol` function and `Symbol.prototype`.
+
+use super::{arg, define_methods, define_statics, define_to_string_tag};
+use crate::heap::{self, SYMBOL_SIZE};
+use crate::{JsString, JsSymbol, NativeFunction, RuntimeError, Value, VM};
+
+pub(super) fn install(vm: &mut VM) {
+    let prototype = vm.intrinsics.symbol_prototype.clone();
+    define_methods(
+        &prototype,
+        &[("toString", to_string), ("valueOf", value_of)],
+    );
+    define_to_string_tag(&prototype, "Symbol");
+    let ctor = NativeFunction::non_constructor("Symbol", construct);
+    define_statics(&ctor, &[("for", for_key), ("keyFor", key_for)]);
+    for (name, symbol) in [
+        ("asyncIterator", JsSymbol::async_iterator()),
+        ("hasInstance", JsSymbol::has_instance()),
+        ("iterator", JsSymbol::iterator()),
+        ("toPrimitive", JsSymbol::to_primitive()),
+        ("toStringTag", JsSymbol::to_string_tag()),
+    ] {
+        ctor.set_property(name, symbol);
+    }
+    ctor.set_property("prototype", Value::Object(prototype.clone()));
+    prototype.borrow_mut().insert("constructor", ctor.clone());
+    vm.set_global("Symbol", ctor);
+}
+
+/// The `description` of a symbol, for the accessor on `Symbol.prototype`.
+pub(super) fn description(receiver: &Value) -> Option<Value> {
+    match receiver {
+        Value::Symbol(symbol) => Some(match symbol.description() {
+            Some(description) => Value::String(description.clone()),
+            None => Value::Undefined,
+        }),
+        _ => None,
+    }
+}
+
+fn this_symbol(this: &Value, method: &str) -> Result<JsSymbol, RuntimeError> {
+    match this {
+        Value::Symbol(symbol) => Ok(symbol.clone()),
+        _ => Err(RuntimeError::TypeError(format!(
+            "Symbol.prototype.{method} requires that 'this' be a Symbol"
+        ))),
+    }
+}
+
+/// Converts a description or registry key to a string, which a symbol
+/// refuses.
+fn to_string_arg(value: &Value) -> Result<JsString, RuntimeError> {
+    match value {
+        Value::Symbol(_) => Err(RuntimeError::TypeError(
+            "Cannot convert a Symbol value to a string".to_string(),
+        )),
+        value => Ok(value.to_js_string()),
+    }
+}
+
+impl VM {
+    /// Makes a symbol, charging for its storage.
+    fn new_symbol(&mut self, description: Option<JsString>) -> Result<JsSymbol, RuntimeError> {
+        let len = description.as_ref().map_or(0, JsString::len);
+        self.charge(SYMBOL_SIZE + heap::string_size(2 * len + 8, true))?;
+        Ok(JsSymbol::new(description))
+    }
+}
+
+fn construct(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let description = match arg(args, 0) {
+        Value::Undefined => None,
+        value => Some(to_string_arg(&value)?),
+    };
+    Ok(Value::Symbol(vm.new_symbol(description)?))
+}
+
+fn for_key(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let key = to_string_arg(&arg(args, 0))?;
+    if let Some(symbol) = vm.symbol_registry.get(&key) {
+        return Ok(Value::Symbol(symbol.clone()));
+    }
+    let symbol = vm.new_symbol(Some(key.clone()))?;
+    vm.symbol_registry.insert(key, symbol.clone());
+    Ok(Value::Symbol(symbol))
+}
+
+fn key_for(vm: &mut VM, _this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+    let Value::Symbol(symbol) = arg(args, 0) else {
+        return Err(RuntimeError::TypeError(format!(
+            "{} is not a symbol",
+            arg(args, 0).to_js_string()
+        )));
+    };
+    // Only registered symbols have a key, and their key is their
+    // description
+    let registered = symbol
+        .description()
+        .and_then(|key| vm.symbol_registry.get(key))
+        .is_some_and(|found| *found == symbol);
+    match symbol.description() {
+        Some(key) if registered => Ok(Value::String(key.clone())),
+        _ => Ok(Value::Undefined),
+    }
+}
+
+fn to_string(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    let symbol = this_symbol(&this, "toString")?;
+    Ok(Value::String(symbol.descriptive_string().clone()))
+}
+
+fn value_of(_vm: &mut VM, this: Value, _args: &[Value]) -> Result<Value, RuntimeError> {
+    Ok(Value::Symbol(this_symbol(&this, "valueOf")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtins::eval;
+
+    #[test]
+    fn test_symbols() {
+        assert_eq!(
+            eval("var s = Symbol('a'); [typeof s, String(s), s.description, Symbol().description, s === Symbol('a'), s === s].join()"),
+            "symbol,Symbol(a),a,,false,true"
+        );
+        assert_eq!(
+            eval("[Symbol.for('k') === Symbol.for('k'), Symbol.keyFor(Symbol.for('k')), Symbol.keyFor(Symbol('k'))].join()"),
+            "true,k,"
+        );
+        assert_eq!(
+            eval("[Symbol.iterator.toString(), Object.prototype.toString.call(Symbol()), Symbol('x').valueOf().description].join()"),
+            "Symbol(Symbol.iterator),[object Symbol],x"
+        );
+    }
+
+    #[test]
+    fn test_symbol_keys() {
+        let source = "
+            var s = Symbol('s');
+            var o = { a: 1 };
+            o[s] = 2;
+            var copy = Object.assign({}, o);
+            [o[s], s in o, Object.keys(o).join(), Object.getOwnPropertySymbols(o).length,
+             copy[s], JSON.stringify(o), JSON.stringify([s]), delete o[s], s in o].join();
+        ";
+        assert_eq!(eval(source), "2,true,a,1,2,{\"a\":1},[null],true,false");
+        assert_eq!(
+            eval("var a = []; a[Symbol.iterator] = 1; a[Symbol.iterator] + Object.getOwnPropertyNames(a).join()"),
+            "1length"
+        );
+    }
+
+    #[test]
+    fn test_well_known_symbols() {
+        let source = "
+            var o = {};
+            o[Symbol.toPrimitive] = function (hint) { return hint === 'default' ? 40 : 0; };
+            o + 2;
+        ";
+        assert_eq!(eval(source), "42");
+        assert_eq!(
+            eval("var o = { valueOf: function () { return 1; } }; [o + 1, [1, 2] + '', ({}) + ''].join(' ')"),
+            "2 1,2 [object Object]"
+        );
+        let source = "
+            var o = {};
+            o[Symbol.toStringTag] = 'Custom';
+            [o.toString(), new Map().toString(), Math.toString(), JSON.toString()].join();
+        ";
+        assert_eq!(
+            eval(source),
+            "[object Custom],[object Map],[object Math],[object JSON]"
+        );
+        let source = "
+            var Even = {};
+            Even[Symbol.hasInstance] = function (n) { return n % 2 === 0; };
+            [2 instanceof Even, 3 instanceof Even, [] instanceof Array, [] instanceof Object,
+             new Map() instanceof Map, new Map() instanceof Set, 1 instanceof Number].join();
+        ";
+        assert_eq!(eval(source), "true,false,true,true,true,false,false");
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut vm = VM::default();
+        for (source, message) in [
+            ("Symbol() + ''", "Cannot convert a Symbol value to a string"),
+            ("Symbol() + 1", "Cannot convert a Symbol value to a number"),
+            (
+                "Symbol(Symbol())",
+                "Cannot convert a Symbol value to a string",
+            ),
+            ("Symbol.keyFor('k')", "k is not a symbol"),
+            ("new Symbol()", "Symbol is not a constructor"),
+            (
+                "var f = Symbol.prototype.toString; f()",
+                "Symbol.prototype.toString requires that 'this' be a Symbol",
+            ),
+            (
+                "var o = {}; o[Symbol.toPrimitive] = function () { return {}; }; o + 1",
+                "Cannot convert object to primitive value",
+            ),
+            (
+                "1 instanceof 1",
+                "Right-hand side of 'instanceof' is not an object",
+            ),
+            (
+                "1 instanceof {}",
+                "Right-hand side of 'instanceof' is not callable",
+            ),
+            (
+                "[] instanceof (() => {})",
+                "Function has non-object prototype 'undefined' in instanceof check",
+            ),
+        ] {
+            match vm.eval(source) {
+                Err(RuntimeError::TypeError(err)) => assert_eq!(err, message, "{source}"),
+                other => panic!("{source}: {other:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,702 @@
+//! Classes, `new` and `this`.
+//!
+//! A function's `this` value lives in its scope under a name no script can
+//! write, so that arrow functions find the one of the function they were
+//! created in by looking outwards. `new` also binds `new.target` there: the
+//! class the object is being made for. A derived class constructor starts
+//! with `new.target` bound but not `this`, until `super()` makes the object
+//! through the class extended.
+//!
+//! A class is its constructor function, made by `CreateClass` together
+//! with the prototype its instances inherit from. Besides the class it
+//! extends, it keeps the function that initializes the fields of each
+//! instance, which runs once the instance exists: before a base class
+//! constructor runs, or as `super()` returns in a derived one.
+//!
+//! Private names are symbols made afresh each time a class is evaluated.
+//! They key private members, which objects keep apart from their
+//! properties, so that only code inside the class can reach them and
+//! reaching them on an object the class did not initialize throws.
+//!
+//! Getters and setters are held as an accessor object in place of the
+//! property's value, which reading and writing the property call through.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rig_bytecode::FunctionKind;
+
+use crate::array::Key;
+use crate::heap::{CLOSURE_SIZE, ENTRY_SIZE, FRAME_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::symbol::JsSymbol;
+use crate::{Atom, Closure, Frame, JsObject, Properties, RuntimeError, Scope, Value, VM};
+
+/// The variable holding a function's `this` value.
+pub(crate) const THIS: &str = "this";
+
+/// The variable holding the class `new` was applied to, in the scope of a
+/// constructor.
+pub(crate) const NEW_TARGET: &str = "new.target";
+
+/// What constructing a class takes besides its constructor.
+#[derive(Debug, Clone)]
+pub(crate) struct Class {
+    /// The class extended, or null for `extends null`; `None` for a base
+    /// class
+    parent: Option<Value>,
+    /// The function initializing the fields of each instance, or undefined
+    fields: Value,
+    /// Set when the class has no constructor of its own
+    implicit: bool,
+}
+
+impl Class {
+    pub fn parent(&self) -> Option<&Value> {
+        self.parent.as_ref()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.parent.iter().cloned().chain([self.fields.clone()])
+    }
+}
+
+/// The getter and setter of an accessor property, either of which may be
+/// undefined.
+#[derive(Debug, Clone)]
+pub(crate) struct Accessor {
+    get: Value,
+    set: Value,
+}
+
+impl Accessor {
+    pub fn values(&self) -> [Value; 2] {
+        [self.get.clone(), self.set.clone()]
+    }
+}
+
+/// How `new` goes on once the object it makes is set up.
+enum Construction {
+    /// The class has no constructor to run, so this is the object
+    Done(Value),
+    /// The constructor runs in these scopes
+    Run(Vec<Scope>),
+}
+
+/// Whether `new` may be applied to `value`.
+pub(crate) fn is_constructor(value: &Value) -> bool {
+    match value {
+        Value::Function(closure) => matches!(
+            closure.kind,
+            FunctionKind::Normal | FunctionKind::ClassConstructor
+        ),
+        Value::NativeFunction(func) => func.is_constructor(),
+        _ => false,
+    }
+}
+
+/// Whether `value` is a property of `holder` that `for-in` skips: class
+/// members and accessors are not enumerable, nor is the constructor of a
+/// prototype.
+pub(crate) fn is_hidden_member(value: &Value, holder: &Value) -> bool {
+    match value {
+        Value::Function(closure) => {
+            matches!(
+                closure.kind,
+                FunctionKind::Method | FunctionKind::ClassConstructor
+            ) || closure.properties.borrow().get(&Atom::intern("prototype")) == Some(holder)
+        }
+        Value::Object(obj) => matches!(obj.borrow().kind(), ObjectKind::Accessor(_)),
+        _ => false,
+    }
+}
+
+/// The private name `key` is, if it is one.
+fn private_name(key: &Value) -> Option<&JsSymbol> {
+    match key {
+        Value::Symbol(symbol) if symbol.is_private() => Some(symbol),
+        _ => None,
+    }
+}
+
+fn not_a_constructor(value: &Value) -> RuntimeError {
+    let name = match value {
+        // Built-ins are named, like `new Symbol()` names them
+        Value::NativeFunction(func) => func.name().clone(),
+        _ => value.to_js_string(),
+    };
+    RuntimeError::TypeError(format!("{name} is not a constructor"))
+}
+
+fn uninitialized_this() -> RuntimeError {
+    RuntimeError::ReferenceError(
+        "Must call super constructor in derived class before accessing 'this' or returning from derived constructor"
+            .to_string(),
+    )
+}
+
+impl VM {
+    /// The `this` value of the innermost function that binds one. Arrow
+    /// functions and the top level bind none, leaving undefined.
+    pub(crate) fn this_value(&self) -> Result<Value, RuntimeError> {
+        let this = Atom::intern(THIS);
+        let new_target = Atom::intern(NEW_TARGET);
+        for scope in self.scopes.iter().rev() {
+            let scope = scope.borrow();
+            if let Some(value) = scope.get(&this) {
+                return Ok(value.clone());
+            }
+            // A derived class constructor before `super()`
+            if scope.contains_key(&new_target) {
+                return Err(uninitialized_this());
+            }
+        }
+        Ok(Value::Undefined)
+    }
+
+    /// Constructs the function in `func_reg`, for `Construct`. A
+    /// constructor body runs in a new frame whose result, or else the new
+    /// object, lands in `func_reg`.
+    pub(crate) fn construct(&mut self, func_reg: u8, args: Vec<Value>) -> Result<(), RuntimeError> {
+        let ctor = self.registers[func_reg as usize].clone();
+        let closure = match &ctor {
+            Value::Function(closure) if is_constructor(&ctor) => closure.clone(),
+            Value::NativeFunction(func) if func.is_constructor() => {
+                let result = func.construct(self, &args)?;
+                self.registers[func_reg as usize] = result;
+                return Ok(());
+            }
+            _ => return Err(not_a_constructor(&ctor)),
+        };
+        let scopes = match self.begin_construct(&closure, &args, &ctor)? {
+            Construction::Done(this) => {
+                self.registers[func_reg as usize] = this;
+                return Ok(());
+            }
+            Construction::Run(scopes) => scopes,
+        };
+        self.check_stack(false)?;
+        self.charge(FRAME_SIZE)?;
+        let mut registers = vec![Value::Undefined; 256];
+        let count = args.len().min(registers.len());
+        registers[..count].clone_from_slice(&args[..count]);
+        self.call_stack.push(Frame {
+            return_pc: self.pc,
+            registers: std::mem::replace(&mut self.registers, registers),
+            scopes: std::mem::replace(&mut self.scopes, scopes),
+            result_reg: func_reg,
+            host_call: false,
+            constructing: Some(closure.clone()),
+            strict_mode: std::mem::take(&mut self.strict_mode),
+        });
+        self.pc = closure.entry;
+        Ok(())
+    }
+
+    /// Constructs `ctor` from Rust, making an object for `new_target`,
+    /// which differs from `ctor` when a derived class calls `super()`.
+    pub(crate) fn construct_with(
+        &mut self,
+        ctor: &Value,
+        args: &[Value],
+        new_target: &Value,
+    ) -> Result<Value, RuntimeError> {
+        match ctor {
+            Value::Function(closure) if is_constructor(ctor) => {
+                match self.begin_construct(closure, args, new_target)? {
+                    Construction::Done(this) => Ok(this),
+                    Construction::Run(scopes) => {
+                        self.call_host(closure.entry + 1, scopes, args, Some(closure.clone()))
+                    }
+                }
+            }
+            Value::NativeFunction(func) if func.is_constructor() => {
+                let result = func.construct(self, args)?;
+                if ctor == new_target {
+                    return Ok(result);
+                }
+                // An object a built-in makes for a subclass inherits from the
+                // subclass. Functions keep their intrinsic prototype, as they
+                // do for `Object.setPrototypeOf`.
+                let prototype = self.get_property(new_target, &Value::from("prototype"))?;
+                match &result {
+                    _ if !prototype.is_object() => {}
+                    Value::Object(obj) => obj.borrow_mut().set_prototype(prototype),
+                    Value::Array(arr) => arr.borrow_mut().set_prototype(prototype),
+                    _ if result.is_object() => {
+                        return Err(RuntimeError::TypeError(format!(
+                            "Cannot change the prototype of {}",
+                            result.type_of()
+                        )))
+                    }
+                    _ => {}
+                }
+                Ok(result)
+            }
+            Value::Null => Err(RuntimeError::TypeError(
+                "Super constructor null of anonymous class is not a constructor".to_string(),
+            )),
+            _ => Err(not_a_constructor(ctor)),
+        }
+    }
+
+    /// Sets up the object `new` makes with `closure`, unless the class
+    /// extends another, whose constructor makes it when `super()` is called.
+    fn begin_construct(
+        &mut self,
+        closure: &Closure,
+        args: &[Value],
+        new_target: &Value,
+    ) -> Result<Construction, RuntimeError> {
+        let class = closure.class.as_ref();
+        let scope: Scope = Rc::new(RefCell::new(HashMap::new()));
+        match class.and_then(|class| class.parent.as_ref()) {
+            Some(parent) if class.is_some_and(|class| class.implicit) => {
+                // The default constructor passes its arguments on
+                let this = self.construct_with(parent, args, new_target)?;
+                self.initialize_fields(closure, &this)?;
+                return Ok(Construction::Done(this));
+            }
+            Some(_) => {}
+            None => {
+                let prototype = self.get_property(new_target, &Value::from("prototype"))?;
+                self.charge(OBJECT_SIZE)?;
+                let obj = if prototype.is_object() {
+                    JsObject::with_prototype(prototype)
+                } else {
+                    JsObject::new()
+                };
+                let this = Value::Object(Rc::new(RefCell::new(obj)));
+                self.initialize_fields(closure, &this)?;
+                if class.is_some_and(|class| class.implicit) {
+                    return Ok(Construction::Done(this));
+                }
+                scope.borrow_mut().insert(Atom::intern(THIS), this);
+            }
+        }
+        self.charge(OBJECT_SIZE + 2 * ENTRY_SIZE)?;
+        scope
+            .borrow_mut()
+            .insert(Atom::intern(NEW_TARGET), new_target.clone());
+        let mut scopes = closure.scopes.clone();
+        scopes.push(scope);
+        Ok(Construction::Run(scopes))
+    }
+
+    /// Runs the field initializer of the class `closure` is the constructor
+    /// of, if it has one, on a new instance.
+    fn initialize_fields(&mut self, closure: &Closure, this: &Value) -> Result<(), RuntimeError> {
+        match closure.class.as_ref().map(|class| class.fields.clone()) {
+            Some(fields) if fields.is_function() => {
+                self.call_function(&fields, this.clone(), &[])?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The value `new` gives once the constructor `closure` returns
+    /// `result`: an object it returns, or else the object it made.
+    pub(crate) fn construct_result(
+        &self,
+        closure: &Closure,
+        result: Value,
+    ) -> Result<Value, RuntimeError> {
+        if result.is_object() {
+            return Ok(result);
+        }
+        let derived = closure
+            .class
+            .as_ref()
+            .is_some_and(|class| class.parent.is_some());
+        if derived && !result.is_undefined() {
+            return Err(RuntimeError::TypeError(
+                "Derived constructors may only return object or undefined".to_string(),
+            ));
+        }
+        self.scopes
+            .last()
+            .and_then(|scope| scope.borrow().get(&Atom::intern(THIS)).cloned())
+            .ok_or_else(uninitialized_this)
+    }
+
+    /// Calls the constructor of the class the derived class in `func_reg`
+    /// extends, for `SuperCall`, and binds the object it makes as `this`.
+    pub(crate) fn super_call(&mut self, func_reg: u8) -> Result<(), RuntimeError> {
+        let Value::Function(class) = self.registers[func_reg as usize].clone() else {
+            return Err(RuntimeError::Internal(
+                "super() outside a class".to_string(),
+            ));
+        };
+        let args = self.spread_args(func_reg as usize + 1)?;
+        let parent = class
+            .class
+            .as_ref()
+            .and_then(|class| class.parent.clone())
+            .unwrap_or(Value::Null);
+        // The scope of the constructor, which arrow functions inside it
+        // also reach
+        let new_target = Atom::intern(NEW_TARGET);
+        let Some(scope) = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.borrow().contains_key(&new_target))
+            .cloned()
+        else {
+            return Err(RuntimeError::Internal(
+                "super() outside a constructor".to_string(),
+            ));
+        };
+        let target = scope
+            .borrow()
+            .get(&new_target)
+            .cloned()
+            .unwrap_or(Value::Undefined);
+        let this = self.construct_with(&parent, &args, &target)?;
+        let this_name = Atom::intern(THIS);
+        if scope.borrow().contains_key(&this_name) {
+            return Err(RuntimeError::ReferenceError(
+                "Super constructor may only be called once".to_string(),
+            ));
+        }
+        self.charge(ENTRY_SIZE)?;
+        scope.borrow_mut().insert(this_name, this.clone());
+        self.initialize_fields(&class, &this)?;
+        self.registers[func_reg as usize] = this;
+        Ok(())
+    }
+
+    /// Makes a class from the constructor in `reg`, for `CreateClass`.
+    pub(crate) fn create_class(
+        &mut self,
+        reg: u8,
+        extends: bool,
+        implicit: bool,
+    ) -> Result<(), RuntimeError> {
+        let reg = reg as usize;
+        let Value::Function(ctor) = self.registers[reg].clone() else {
+            return Err(RuntimeError::Internal(
+                "Class constructor is not a function".to_string(),
+            ));
+        };
+        let parent = extends.then(|| self.registers[reg + 1].clone());
+        let prototype = match &parent {
+            None => JsObject::new(),
+            Some(Value::Null) => JsObject::with_prototype(Value::Null),
+            Some(parent) if is_constructor(parent) => {
+                let prototype = self.get_property(parent, &Value::from("prototype"))?;
+                if !prototype.is_object() && !prototype.is_null() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "Class extends value does not have valid prototype property {}",
+                        prototype.to_js_string()
+                    )));
+                }
+                JsObject::with_prototype(prototype)
+            }
+            Some(parent) => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Class extends value {} is not a constructor or null",
+                    parent.to_js_string()
+                )))
+            }
+        };
+        self.charge(
+            CLOSURE_SIZE
+                + ctor.scopes.len() * std::mem::size_of::<Scope>()
+                + OBJECT_SIZE
+                + 2 * ENTRY_SIZE,
+        )?;
+        let prototype = Value::Object(Rc::new(RefCell::new(prototype)));
+        let mut properties = Properties::new();
+        properties.insert(Atom::intern("prototype"), prototype.clone());
+        let class = Value::Function(Rc::new(Closure {
+            entry: ctor.entry,
+            kind: ctor.kind,
+            script: ctor.script.clone(),
+            scopes: ctor.scopes.clone(),
+            properties: RefCell::new(properties),
+            private: RefCell::default(),
+            class: Some(Class {
+                parent,
+                fields: self.registers[reg + 2].clone(),
+                implicit,
+            }),
+        }));
+        if let Value::Object(prototype) = &prototype {
+            prototype.borrow_mut().insert("constructor", class.clone());
+        }
+        self.registers[reg] = class;
+        self.registers[reg + 1] = prototype;
+        Ok(())
+    }
+
+    /// Defines `obj[key]` as a class member or field, for `DefineOwn`.
+    pub(crate) fn define_own(
+        &mut self,
+        obj: &Value,
+        key: &Value,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        if let Some(name) = private_name(key) {
+            return self.add_private(obj, name, value);
+        }
+        let properties = match obj {
+            Value::Object(obj) => {
+                let key = Key::new(key).into_atom();
+                if !obj.borrow().properties().contains_key(&key) {
+                    self.charge(ENTRY_SIZE)?;
+                }
+                obj.borrow_mut().properties_mut().insert(key, value);
+                return Ok(());
+            }
+            Value::Function(closure) => &closure.properties,
+            // Arrays have no accessors to bypass
+            _ => return self.set_property(obj, key, value),
+        };
+        let key = Key::new(key).into_atom();
+        if !properties.borrow().contains_key(&key) {
+            self.charge(ENTRY_SIZE)?;
+        }
+        properties.borrow_mut().insert(key, value);
+        Ok(())
+    }
+
+    /// Defines the getter or setter of `obj[key]`, for `DefineGetter` and
+    /// `DefineSetter`, keeping the other half of an accessor already there.
+    pub(crate) fn define_accessor(
+        &mut self,
+        obj: &Value,
+        key: &Value,
+        func: Value,
+        is_getter: bool,
+    ) -> Result<(), RuntimeError> {
+        let private = private_name(key);
+        let existing = match private {
+            Some(name) => self.private_member(obj, name),
+            None => self.own_property(obj, &Key::new(key)),
+        };
+        let mut accessor = match (existing.as_ref().and_then(as_accessor), private) {
+            // A getter and setter for the same private name are one member
+            (Some(accessor), Some(name)) => {
+                let taken = if is_getter {
+                    &accessor.get
+                } else {
+                    &accessor.set
+                };
+                if !taken.is_undefined() {
+                    return Err(twice(name));
+                }
+                private_members(obj, |members| {
+                    members.shift_remove(&Atom::from_symbol(name));
+                });
+                accessor
+            }
+            (Some(accessor), None) => accessor,
+            (None, _) => Accessor {
+                get: Value::Undefined,
+                set: Value::Undefined,
+            },
+        };
+        if is_getter {
+            accessor.get = func;
+        } else {
+            accessor.set = func;
+        }
+        self.charge(OBJECT_SIZE)?;
+        let prototype = Value::Object(self.intrinsics.object_prototype.clone());
+        let value = Value::Object(Rc::new(RefCell::new(JsObject::with_kind(
+            prototype,
+            ObjectKind::Accessor(accessor),
+        ))));
+        self.define_own(obj, key, value)
+    }
+
+    /// Reads `value` as a property of `receiver`, calling its getter if it
+    /// is an accessor.
+    pub(crate) fn read_accessor(
+        &mut self,
+        value: Value,
+        receiver: &Value,
+    ) -> Result<Value, RuntimeError> {
+        match as_accessor(&value) {
+            Some(accessor) if accessor.get.is_undefined() => Ok(Value::Undefined),
+            Some(accessor) => self.call_function(&accessor.get, receiver.clone(), &[]),
+            None => Ok(value),
+        }
+    }
+
+    /// Writes `obj[key] = value` through a setter, if `obj` has or inherits
+    /// an accessor for `key`. Returns whether it did.
+    pub(crate) fn write_accessor(
+        &mut self,
+        obj: &Value,
+        key: &Key,
+        value: Value,
+    ) -> Result<bool, RuntimeError> {
+        let accessor = match self.lookup(obj, key).as_ref().and_then(as_accessor) {
+            Some(accessor) => accessor,
+            None => return Ok(false),
+        };
+        if accessor.set.is_undefined() {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot set property {} of {} which has only a getter",
+                key.clone().into_atom(),
+                obj.to_js_string()
+            )));
+        }
+        self.call_function(&accessor.set, obj.clone(), &[value])?;
+        Ok(true)
+    }
+
+    /// Reads `super[key]`, for `GetSuper`.
+    pub(crate) fn get_super(&mut self, home: &Value, key: &Value) -> Result<Value, RuntimeError> {
+        let this = self.this_value()?;
+        let prototype = self.prototype_of(home);
+        if prototype.is_null() {
+            return Ok(Value::Undefined);
+        }
+        let value = self
+            .lookup(&prototype, &Key::new(key))
+            .unwrap_or(Value::Undefined);
+        self.read_accessor(value, &this)
+    }
+
+    /// Writes `super[key] = value`, for `SetSuper`: through an inherited
+    /// setter, or else onto `this`.
+    pub(crate) fn set_super(
+        &mut self,
+        home: &Value,
+        key: &Value,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let this = self.this_value()?;
+        let prototype = self.prototype_of(home);
+        let found = self.lookup(&prototype, &Key::new(key));
+        match found.as_ref().and_then(as_accessor) {
+            Some(accessor) if !accessor.set.is_undefined() => {
+                self.call_function(&accessor.set, this, &[value])?;
+                Ok(())
+            }
+            _ => self.define_own(&this, key, value),
+        }
+    }
+
+    /// Reads a private member, for `obj.#name`.
+    pub(crate) fn get_private(
+        &mut self,
+        obj: &Value,
+        name: &JsSymbol,
+    ) -> Result<Value, RuntimeError> {
+        let Some(value) = self.private_member(obj, name) else {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot read private member {} from an object whose class did not declare it",
+                name.descriptive_string()
+            )));
+        };
+        match as_accessor(&value) {
+            Some(accessor) if accessor.get.is_undefined() => Err(RuntimeError::TypeError(format!(
+                "'{}' was defined without a getter",
+                name.descriptive_string()
+            ))),
+            Some(accessor) => self.call_function(&accessor.get, obj.clone(), &[]),
+            None => Ok(value),
+        }
+    }
+
+    /// Writes a private member, for `obj.#name = value`.
+    pub(crate) fn set_private(
+        &mut self,
+        obj: &Value,
+        name: &JsSymbol,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let Some(existing) = self.private_member(obj, name) else {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot write private member {} to an object whose class did not declare it",
+                name.descriptive_string()
+            )));
+        };
+        match as_accessor(&existing) {
+            Some(accessor) if accessor.set.is_undefined() => Err(RuntimeError::TypeError(format!(
+                "'{}' was defined without a setter",
+                name.descriptive_string()
+            ))),
+            Some(accessor) => {
+                self.call_function(&accessor.set, obj.clone(), &[value])?;
+                Ok(())
+            }
+            None => {
+                private_members(obj, |members| {
+                    members.insert(Atom::from_symbol(name), value);
+                });
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether `obj` has the private member `name`, for `#name in obj`.
+    pub(crate) fn has_private(&self, obj: &Value, name: &JsSymbol) -> bool {
+        self.private_member(obj, name).is_some()
+    }
+
+    fn private_member(&self, obj: &Value, name: &JsSymbol) -> Option<Value> {
+        let key = Atom::from_symbol(name);
+        match obj {
+            Value::Object(obj) => obj.borrow().private_members()?.get(&key).cloned(),
+            Value::Function(closure) => closure.private.borrow().get(&key).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Adds a private member, which `obj` must not have yet.
+    fn add_private(
+        &mut self,
+        obj: &Value,
+        name: &JsSymbol,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        if self.private_member(obj, name).is_some() {
+            return Err(twice(name));
+        }
+        self.charge(ENTRY_SIZE)?;
+        let added = private_members(obj, |members| {
+            members.insert(Atom::from_symbol(name), value);
+        });
+        match added {
+            Some(()) => Ok(()),
+            None => Err(RuntimeError::TypeError(format!(
+                "Cannot add private member {} to {}",
+                name.descriptive_string(),
+                obj.to_js_string()
+            ))),
+        }
+    }
+}
+
+/// Runs `f` on the private members of `obj`, unless it cannot have any.
+fn private_members<R>(obj: &Value, f: impl FnOnce(&mut Properties) -> R) -> Option<R> {
+    match obj {
+        Value::Object(obj) => Some(f(obj.borrow_mut().private_members_mut())),
+        Value::Function(closure) => Some(f(&mut closure.private.borrow_mut())),
+        _ => None,
+    }
+}
+
+fn as_accessor(value: &Value) -> Option<Accessor> {
+    match value {
+        Value::Object(obj) => match obj.borrow().kind() {
+            ObjectKind::Accessor(accessor) => Some(accessor.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn twice(name: &JsSymbol) -> RuntimeError {
+    RuntimeError::TypeError(format!(
+        "Cannot initialize {} twice on the same object",
+        name.descriptive_string()
+    ))
+}
@@ -0,0 +1,302 @@
+//! Storage for `Map`, `Set`, `WeakMap` and `WeakSet`.
+//!
+//! Maps and sets iterate in insertion order and keep iterating correctly
+//! while entries are added or deleted. Deleting leaves a hole that iteration
+//! skips; once holes make up half the table it is compacted, and the
+//! positions of live iterations are moved along with their entries.
+//!
+//! Weak collections hold their keys by weak reference, so that an entry
+//! goes away with its key. A value that refers back to its own key keeps
+//! the key alive, and so the entry too.
+
+use std::any::Any;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+
+use crate::Value;
+
+/// A key compared by SameValueZero: like `===`, except that NaN equals
+/// itself.
+#[derive(Debug, Clone)]
+struct MapKey(Value);
+
+impl MapKey {
+    fn new(key: &Value) -> MapKey {
+        match key {
+            // -0 and +0 are the same key, and a map hands back +0
+            Value::Number(n) if *n == 0.0 => MapKey(Value::Number(0.0)),
+            Value::Number(n) if n.is_nan() => MapKey(Value::Number(f64::NAN)),
+            key => MapKey(key.clone()),
+        }
+    }
+}
+
+impl PartialEq for MapKey {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Value::Number(a), Value::Number(b)) if a.is_nan() && b.is_nan() => true,
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl Eq for MapKey {}
+
+impl Hash for MapKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Keys are normalized, so equal keys have equal bits
+        self.0.hash(state)
+    }
+}
+
+/// The entries of a `Map` or `Set`, in insertion order. A set stores its
+/// values as keys, each mapped to undefined.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct OrderedTable {
+    /// Entries in insertion order, with `None` where one was deleted
+    entries: Vec<Option<(Value, Value)>>,
+    /// Position of each key in `entries`
+    positions: HashMap<MapKey, usize>,
+    /// Positions of the iterations in progress
+    cursors: Vec<Weak<Cell<usize>>>,
+}
+
+impl OrderedTable {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let position = *self.positions.get(&MapKey::new(key))?;
+        self.entries[position].as_ref().map(|(_, value)| value)
+    }
+
+    pub fn has(&self, key: &Value) -> bool {
+        self.positions.contains_key(&MapKey::new(key))
+    }
+
+    /// Sets the value of `key`, keeping its position if it is present.
+    /// Returns whether a new entry was added.
+    pub fn insert(&mut self, key: &Value, value: Value) -> bool {
+        let key = MapKey::new(key);
+        if let Some(&position) = self.positions.get(&key) {
+            if let Some(entry) = &mut self.entries[position] {
+                entry.1 = value;
+            }
+            return false;
+        }
+        self.positions.insert(key.clone(), self.entries.len());
+        self.entries.push(Some((key.0, value)));
+        true
+    }
+
+    /// Deletes `key`, returning whether it was present.
+    pub fn remove(&mut self, key: &Value) -> bool {
+        let Some(position) = self.positions.remove(&MapKey::new(key)) else {
+            return false;
+        };
+        self.entries[position] = None;
+        if self.entries.len() >= 16 && self.len() * 2 <= self.entries.len() {
+            self.compact();
+        }
+        true
+    }
+
+    /// Deletes every entry. Iterations in progress carry on with entries
+    /// added afterwards.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.positions.clear();
+        self.cursors.retain(|cursor| match cursor.upgrade() {
+            Some(cursor) => {
+                cursor.set(0);
+                true
+            }
+            None => false,
+        });
+    }
+
+    /// Starts an iteration at the first entry. The table keeps the cursor
+    /// up to date for as long as the caller holds it.
+    pub fn cursor(&mut self) -> Rc<Cell<usize>> {
+        let cursor = Rc::new(Cell::new(0));
+        self.cursors.retain(|cursor| cursor.strong_count() > 0);
+        self.cursors.push(Rc::downgrade(&cursor));
+        cursor
+    }
+
+    /// The entry at `cursor`, advancing past it, or `None` at the end.
+    pub fn next(&self, cursor: &Cell<usize>) -> Option<(Value, Value)> {
+        let mut position = cursor.get();
+        while position < self.entries.len() {
+            position += 1;
+            if let Some(entry) = &self.entries[position - 1] {
+                cursor.set(position);
+                return Some(entry.clone());
+            }
+        }
+        cursor.set(position);
+        None
+    }
+
+    /// The entries, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (&Value, &Value)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|(key, value)| (key, value))
+    }
+
+    /// Removes the holes left by deleted entries.
+    fn compact(&mut self) {
+        // The number of live entries before each position, which is where
+        // the entry or cursor there moves to
+        let mut moved_to = Vec::with_capacity(self.entries.len() + 1);
+        let mut live = 0;
+        for entry in &self.entries {
+            moved_to.push(live);
+            live += entry.is_some() as usize;
+        }
+        moved_to.push(live);
+        self.cursors.retain(|cursor| match cursor.upgrade() {
+            Some(cursor) => {
+                cursor.set(moved_to[cursor.get().min(self.entries.len())]);
+                true
+            }
+            None => false,
+        });
+        self.entries.retain(Option::is_some);
+        for position in self.positions.values_mut() {
+            *position = moved_to[*position];
+        }
+    }
+}
+
+/// The entries of a `WeakMap` or `WeakSet`, keyed by the identity of
+/// objects that may be dropped at any time.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WeakTable {
+    /// Holding the weak reference keeps the allocation, and with it the
+    /// address, from being reused by another object
+    entries: HashMap<*const (), (Weak<dyn Any>, Value)>,
+    /// Number of entries after the last sweep
+    swept_len: usize,
+}
+
+impl WeakTable {
+    /// The identity of `key`, or `None` if it cannot be held weakly.
+    fn identity(key: &Value) -> Option<(*const (), Weak<dyn Any>)> {
+        let weak: Weak<dyn Any> = match key {
+            Value::Object(obj) => Rc::downgrade(obj) as Weak<dyn Any>,
+            Value::Array(arr) => Rc::downgrade(arr) as Weak<dyn Any>,
+            Value::Function(closure) => Rc::downgrade(closure) as Weak<dyn Any>,
+            Value::NativeFunction(func) => func.downgrade(),
+            _ => return None,
+        };
+        Some((weak.as_ptr() as *const (), weak))
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        let (ptr, _) = WeakTable::identity(key)?;
+        self.entries.get(&ptr).map(|(_, value)| value)
+    }
+
+    pub fn has(&self, key: &Value) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Sets the value of `key`, which must be an object. Returns whether a
+    /// new entry was added.
+    pub fn insert(&mut self, key: &Value, value: Value) -> bool {
+        let Some((ptr, weak)) = WeakTable::identity(key) else {
+            return false;
+        };
+        let added = self.entries.insert(ptr, (weak, value)).is_none();
+        // Sweep whenever the table has doubled, so dead entries cost
+        // amortized constant time
+        if self.entries.len() >= 2 * self.swept_len.max(8) {
+            self.sweep();
+        }
+        added
+    }
+
+    pub fn remove(&mut self, key: &Value) -> bool {
+        match WeakTable::identity(key) {
+            Some((ptr, _)) => self.entries.remove(&ptr).is_some(),
+            None => false,
+        }
+    }
+
+    /// The values of entries whose keys are still alive.
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries
+            .values()
+            .filter(|(key, _)| key.strong_count() > 0)
+            .map(|(_, value)| value)
+    }
+
+    /// Drops the entries whose keys are gone.
+    pub fn sweep(&mut self) {
+        self.entries.retain(|_, (key, _)| key.strong_count() > 0);
+        self.swept_len = self.entries.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_value_zero_keys() {
+        let mut table = OrderedTable::default();
+        assert!(table.insert(&Value::Number(f64::NAN), Value::Number(1.0)));
+        assert!(table.insert(&Value::Number(-0.0), Value::Number(2.0)));
+        assert!(!table.insert(&Value::Number(0.0), Value::Number(3.0)));
+        assert_eq!(
+            table.get(&Value::Number(f64::NAN)),
+            Some(&Value::Number(1.0))
+        );
+        assert_eq!(table.len(), 2);
+        let (key, _) = table.iter().nth(1).unwrap();
+        assert!(matches!(key, Value::Number(n) if n.is_sign_positive()));
+    }
+
+    #[test]
+    fn test_cursor_survives_compaction() {
+        let mut table = OrderedTable::default();
+        for i in 0..32 {
+            table.insert(&Value::Number(i as f64), Value::Undefined);
+        }
+        let cursor = table.cursor();
+        for _ in 0..20 {
+            table.next(&cursor);
+        }
+        for i in 0..30 {
+            table.remove(&Value::Number(i as f64));
+        }
+        assert!(table.entries.len() < 32);
+        let rest: Vec<_> = std::iter::from_fn(|| table.next(&cursor)).collect();
+        assert_eq!(
+            rest,
+            [
+                (Value::Number(30.0), Value::Undefined),
+                (Value::Number(31.0), Value::Undefined)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weak_entries_go_with_their_keys() {
+        let mut table = WeakTable::default();
+        let key = Value::Array(Rc::new(std::cell::RefCell::new(crate::JsArray::new())));
+        assert!(!table.insert(&Value::Number(1.0), Value::Undefined));
+        assert!(table.insert(&key, Value::Number(1.0)));
+        assert!(table.has(&key));
+        drop(key);
+        assert_eq!(table.values().count(), 0);
+        table.sweep();
+        assert!(table.entries.is_empty());
+    }
+}
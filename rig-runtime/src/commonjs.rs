@@ -0,0 +1,459 @@
+//! A CommonJS `require` for scripts written for Node.
+//!
+//! `CommonJsLoader::install` defines a global `require` on a VM. Each module
+//! runs once, wrapped in a function that receives `exports`, `require`,
+//! `module`, `__filename` and `__dirname`, and is then served from a cache
+//! that scripts can see as `require.cache`. The loader is built on the
+//! embedding API and the VM's own JSON parser, so it stays out of the way
+//! of hosts that do not install it and of scripts that replace `JSON`.
+//!
+//! Specifiers are resolved the way Node resolves them: relative paths from
+//! the directory of the requiring module, trying the path itself, then with
+//! `.js` and `.json` added, then as a directory with a `package.json` or an
+//! `index` file; other specifiers are looked up in the `node_modules`
+//! directories of that directory and its ancestors. Unlike Node, lookups
+//! never leave the root directory the loader was made with.
+
+use std::cell::RefCell;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+
+use rig_parser::ast::{Expr, Function, Program, Stmt};
+
+use crate::builtins;
+use crate::{Atom, JsObject, NativeFunction, RuntimeError, Value, VM};
+
+/// The parameters of the function each module's code is wrapped in.
+const WRAPPER_PARAMS: [&str; 5] = ["exports", "require", "module", "__filename", "__dirname"];
+
+/// Loads CommonJS modules from files below a root directory. Cloning
+/// shares the module cache.
+#[derive(Clone)]
+pub struct CommonJsLoader(Rc<Loader>);
+
+struct Loader {
+    root: PathBuf,
+    /// Module objects by filename
+    cache: Rc<RefCell<JsObject>>,
+}
+
+impl CommonJsLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        CommonJsLoader(Rc::new(Loader {
+            root: normalize(&root.into()),
+            cache: Rc::new(RefCell::new(JsObject::new())),
+        }))
+    }
+
+    /// Defines a global `require` on `vm` that resolves specifiers from the
+    /// root directory.
+    pub fn install(&self, vm: &mut VM) {
+        let require = self.require_function(self.0.root.clone());
+        vm.set_global("require", require);
+    }
+
+    /// Requires `specifier` from the root directory, returning the exports
+    /// of the module.
+    pub fn require(&self, vm: &mut VM, specifier: &str) -> Result<Value, RuntimeError> {
+        self.require_from(vm, &self.0.root, specifier)
+    }
+
+    /// The filename `specifier` resolves to from the directory `dir`, taken
+    /// from the root if it is relative, or `None` if there is no such
+    /// module.
+    pub fn resolve(&self, vm: &mut VM, specifier: &str, dir: &Path) -> Option<PathBuf> {
+        let root = &self.0.root;
+        let dir = normalize(&root.join(dir));
+        if is_path(specifier) {
+            let path = match specifier.strip_prefix('/') {
+                Some(path) => root.join(path),
+                None => dir.join(specifier),
+            };
+            return self.load_path(vm, &normalize(&path));
+        }
+        let mut dir = Some(dir.as_path());
+        while let Some(current) = dir.filter(|dir| dir.starts_with(root)) {
+            if !current.ends_with("node_modules") {
+                let path = normalize(&current.join("node_modules").join(specifier));
+                if let Some(found) = self.load_path(vm, &path) {
+                    return Some(found);
+                }
+            }
+            dir = current.parent();
+        }
+        None
+    }
+
+    /// The `require` function of a module in the directory `dir`.
+    fn require_function(&self, dir: PathBuf) -> Value {
+        let require = {
+            let loader = self.clone();
+            let dir = dir.clone();
+            NativeFunction::new("require", move |vm, _this, args| {
+                let specifier = specifier_arg(args)?;
+                loader.require_from(vm, &dir, &specifier)
+            })
+        };
+        let resolve = {
+            let loader = self.clone();
+            NativeFunction::new("resolve", move |vm, _this, args| {
+                let specifier = specifier_arg(args)?;
+                match loader.resolve(vm, &specifier, &dir) {
+                    Some(filename) => Ok(Value::from(filename.to_string_lossy().into_owned())),
+                    None => Err(not_found(&specifier)),
+                }
+            })
+        };
+        require.set_property("resolve", resolve);
+        require.set_property("cache", Value::Object(self.0.cache.clone()));
+        require.into()
+    }
+
+    fn require_from(
+        &self,
+        vm: &mut VM,
+        dir: &Path,
+        specifier: &str,
+    ) -> Result<Value, RuntimeError> {
+        let filename = self
+            .resolve(vm, specifier, dir)
+            .ok_or_else(|| not_found(specifier))?;
+        let key = filename.to_string_lossy().into_owned();
+        // A module required while it runs, through a cycle, hands out the
+        // exports it has so far
+        let cached = self.0.cache.borrow().get(&key).cloned();
+        if let Some(module) = cached {
+            return Ok(exports_of(&module));
+        }
+
+        let mut module = JsObject::new();
+        module.insert("id", key.as_str());
+        module.insert("filename", key.as_str());
+        module.insert("loaded", false);
+        module.insert(
+            "exports",
+            Value::Object(Rc::new(RefCell::new(JsObject::new()))),
+        );
+        let module = Rc::new(RefCell::new(module));
+        self.0
+            .cache
+            .borrow_mut()
+            .insert(&key, Value::Object(module.clone()));
+        if let Err(err) = self.evaluate(vm, &filename, &module) {
+            // Requiring a module that threw runs it again
+            self.0
+                .cache
+                .borrow_mut()
+                .properties_mut()
+                .shift_remove(&Atom::intern(&key));
+            return Err(err);
+        }
+        module.borrow_mut().insert("loaded", true);
+        Ok(exports_of(&Value::Object(module)))
+    }
+
+    /// Runs the module in `filename`, or parses it if it is JSON, setting
+    /// the exports of `module`.
+    fn evaluate(
+        &self,
+        vm: &mut VM,
+        filename: &Path,
+        module: &Rc<RefCell<JsObject>>,
+    ) -> Result<(), RuntimeError> {
+        let source = std::fs::read_to_string(filename).map_err(|err| {
+            RuntimeError::TypeError(format!(
+                "Cannot load module '{}': {}",
+                filename.display(),
+                err
+            ))
+        })?;
+        if filename.extension().is_some_and(|ext| ext == "json") {
+            let exports = json_parse(vm, &source)?;
+            module.borrow_mut().insert("exports", exports);
+            return Ok(());
+        }
+        let body = rig_parser::parse_function_body(&source)
+            .map_err(|err| RuntimeError::SyntaxError(err.to_string()))?;
+        let wrapper = Function {
+            name: None,
+            params: WRAPPER_PARAMS
+                .iter()
+                .map(|param| param.to_string())
+                .collect(),
+            body,
+            is_arrow: false,
+            is_generator: false,
+            is_async: false,
+        };
+        let script = Program {
+            body: vec![Stmt::Expr(Expr::Function(Box::new(wrapper)))],
+        };
+        let wrapper = vm.run_script(&script)?;
+        let dir = filename.parent().unwrap_or(&self.0.root).to_path_buf();
+        let args = [
+            exports_of(&Value::Object(module.clone())),
+            self.require_function(dir.clone()),
+            Value::Object(module.clone()),
+            Value::from(filename.to_string_lossy().into_owned()),
+            Value::from(dir.to_string_lossy().into_owned()),
+        ];
+        vm.call_function(&wrapper, Value::Undefined, &args)?;
+        Ok(())
+    }
+
+    /// The file `path` names, with `.js` or `.json` added, or the module of
+    /// the directory `path` names.
+    fn load_path(&self, vm: &mut VM, path: &Path) -> Option<PathBuf> {
+        if !path.starts_with(&self.0.root) {
+            return None;
+        }
+        as_file(path).or_else(|| self.as_directory(vm, path))
+    }
+
+    /// The file the `main` field of the directory's `package.json` names,
+    /// or else its `index` file.
+    fn as_directory(&self, vm: &mut VM, path: &Path) -> Option<PathBuf> {
+        let main = std::fs::read_to_string(path.join("package.json"))
+            .ok()
+            .and_then(|text| json_parse(vm, &text).ok())
+            .and_then(|package| match package {
+                Value::Object(package) => package.borrow().get("main").cloned(),
+                _ => None,
+            });
+        if let Some(Value::String(main)) = main {
+            let main = normalize(&path.join(main.to_std_string_lossy()));
+            if main.starts_with(&self.0.root) {
+                if let Some(found) = as_file(&main).or_else(|| index(&main)) {
+                    return Some(found);
+                }
+            }
+        }
+        index(path)
+    }
+}
+
+/// Whether a specifier names a path rather than a package.
+fn is_path(specifier: &str) -> bool {
+    matches!(specifier, "." | "..")
+        || specifier.starts_with("./")
+        || specifier.starts_with("../")
+        || specifier.starts_with('/')
+}
+
+fn as_file(path: &Path) -> Option<PathBuf> {
+    if path.is_file() {
+        return Some(path.to_path_buf());
+    }
+    ["js", "json"].into_iter().find_map(|extension| {
+        let mut name = OsString::from(path.as_os_str());
+        name.push(".");
+        name.push(extension);
+        let path = PathBuf::from(name);
+        path.is_file().then_some(path)
+    })
+}
+
+fn index(path: &Path) -> Option<PathBuf> {
+    ["index.js", "index.json"]
+        .into_iter()
+        .map(|name| path.join(name))
+        .find(|path| path.is_file())
+}
+
+/// Removes `.` and `..` from a path without touching the filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The current `module.exports` of a module object.
+fn exports_of(module: &Value) -> Value {
+    match module {
+        Value::Object(module) => module
+            .borrow()
+            .get("exports")
+            .cloned()
+            .unwrap_or(Value::Undefined),
+        _ => Value::Undefined,
+    }
+}
+
+fn json_parse(vm: &mut VM, text: &str) -> Result<Value, RuntimeError> {
+    let text: Vec<u16> = text.encode_utf16().collect();
+    builtins::parse_json(vm, &text)
+}
+
+fn specifier_arg(args: &[Value]) -> Result<String, RuntimeError> {
+    match args.first() {
+        Some(Value::String(s)) if !s.is_empty() => Ok(s.to_std_string_lossy()),
+        _ => Err(RuntimeError::TypeError(
+            "The \"id\" argument must be a non-empty string".to_string(),
+        )),
+    }
+}
+
+/// The error Node throws for a module it cannot find.
+fn not_found(specifier: &str) -> RuntimeError {
+    let mut error = JsObject::new();
+    error.insert("name", "Error");
+    error.insert("message", format!("Cannot find module '{}'", specifier));
+    error.insert("code", "MODULE_NOT_FOUND");
+    RuntimeError::Thrown(Value::Object(Rc::new(RefCell::new(error))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` below a fresh directory named after `name`.
+    fn temp_tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rig-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for (path, contents) in files {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    fn vm_with(root: &Path) -> VM {
+        let mut vm = VM::default();
+        CommonJsLoader::new(root.join("app")).install(&mut vm);
+        vm.eval("var log = []").unwrap();
+        vm
+    }
+
+    #[test]
+    fn test_require() {
+        let root = temp_tree(
+            "require",
+            &[
+                (
+                    "app/main.js",
+                    "var lib = require('./lib'); \
+                     log.push(lib.name, require('./data.json').n, require('pkg')(), \
+                     require('./dir').index, require('./lib.js') === lib, \
+                     require.cache[require.resolve('./lib')].loaded, require('./sub/deep')); \
+                     module.exports = log.join();",
+                ),
+                (
+                    "app/lib.js",
+                    "exports.name = 'lib'; \
+                     log.push(module.id === __filename, __filename.endsWith('lib.js'), \
+                     __dirname === require.resolve('./main').slice(0, -8));",
+                ),
+                ("app/data.json", "{\"n\": 1}"),
+                ("app/dir/index.js", "exports.index = 'index';"),
+                (
+                    "app/sub/deep.js",
+                    "module.exports = require('pkg')() + ' deep';",
+                ),
+                (
+                    "app/node_modules/pkg/package.json",
+                    "{\"main\": \"./src/main\"}",
+                ),
+                (
+                    "app/node_modules/pkg/src/main.js",
+                    "module.exports = function () { return require('../package.json').main; };",
+                ),
+                ("outside.js", "module.exports = 'outside';"),
+            ],
+        );
+        let loader = CommonJsLoader::new(root.join("app"));
+        let mut vm = VM::default();
+        loader.install(&mut vm);
+        vm.eval("var log = []").unwrap();
+        assert_eq!(
+            loader.require(&mut vm, "./main").unwrap(),
+            Value::from("true,true,true,lib,1,./src/main,index,true,true,./src/main deep")
+        );
+        // Modules run once
+        assert_eq!(
+            vm.eval("require('./main') === require('/main.js') && log.length")
+                .unwrap(),
+            Value::from(10.0)
+        );
+        assert_eq!(
+            vm.eval(
+                "var out = []; for (var id of ['../outside', 'missing', '/../outside.js']) { \
+                 try { require(id); } catch (e) { out.push(e.code + ' ' + e.message); } } \
+                 out.join('; ')"
+            )
+            .unwrap(),
+            Value::from(
+                "MODULE_NOT_FOUND Cannot find module '../outside'; \
+                 MODULE_NOT_FOUND Cannot find module 'missing'; \
+                 MODULE_NOT_FOUND Cannot find module '/../outside.js'"
+            )
+        );
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_cycles_and_errors() {
+        let root = temp_tree(
+            "require-cycles",
+            &[
+                (
+                    "app/a.js",
+                    "exports.early = 'a'; var b = require('./b'); exports.late = b.seen;",
+                ),
+                ("app/b.js", "exports.seen = require('./a').early;"),
+                ("app/throws.js", "log.push('run'); null.x;"),
+                ("app/syntax.js", "var = 1;"),
+                ("app/escape.js", "}); log.push('escaped'); (function () {"),
+                ("app/returns.js", "exports.a = 1; return; exports.b = 2;"),
+                ("app/data.json", "{\"n\": 1}"),
+            ],
+        );
+        let mut vm = vm_with(&root);
+        assert_eq!(vm.eval("require('./a').late").unwrap(), Value::from("a"));
+        // A module that threw is not cached, so it runs again
+        assert_eq!(
+            vm.eval(
+                "for (var i = 0; i < 2; i++) { try { require('./throws'); } catch (e) {} } \
+                 log.join()"
+            )
+            .unwrap(),
+            Value::from("run,run")
+        );
+        // JSON modules do not depend on the global `JSON`
+        assert_eq!(
+            vm.eval("JSON = undefined; require('./data.json').n")
+                .unwrap(),
+            Value::from(1.0)
+        );
+        assert!(matches!(
+            vm.eval("require('./syntax')"),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+        // The source is compiled as a function body, so it cannot close
+        // the function early
+        assert!(matches!(
+            vm.eval("require('./escape')"),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+        assert_eq!(
+            vm.eval("var r = require('./returns'); log.join() + ' ' + r.a + ' ' + r.b")
+                .unwrap(),
+            Value::from("run,run 1 undefined")
+        );
+        assert!(vm
+            .eval("require(1)")
+            .unwrap_err()
+            .to_string()
+            .contains("The \"id\" argument must be a non-empty string"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,3385 @@
+//! Compiles parsed scripts to bytecode.
+//!
+//! Variables live in scope objects and are addressed by name, so closures
+//! work by capturing the scope chain. A block that declares `let`, `const`
+//! or a class gets a scope of its own while it runs, as does a catch
+//! clause, and a `for` loop whose head declares `let` variables copies its
+//! scope for each iteration. Registers only ever hold temporaries.
+//!
+//! Which declaration a name refers to is known from the source, so
+//! assignments to constants are found while compiling. A `let` or `const`
+//! variable reads as undefined until its declaration runs, where
+//! JavaScript would throw.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rig_bytecode::{FunctionKind, Instruction};
+use rig_parser::ast::*;
+
+use crate::{JsString, RuntimeError, Value};
+
+/// Code and constants for one script, addressed as if they were appended to
+/// a VM's existing program and constant pool.
+pub(crate) struct Compiled {
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Value>,
+}
+
+pub(crate) fn compile_script(
+    program: &Program,
+    code_base: usize,
+    const_base: usize,
+) -> Result<Compiled, RuntimeError> {
+    let mut compiler = Compiler::new(code_base, const_base, true);
+    compiler.script(program)?;
+    compiler.finish()
+}
+
+/// Compiles a module as if it were loaded at the start of an empty program,
+/// for the VM to relocate when it links the module. The code declares the
+/// module's variables and functions and returns; the body proper starts at
+/// the returned index.
+pub(crate) fn compile_module(module: &Module) -> Result<(Compiled, usize), RuntimeError> {
+    let mut compiler = Compiler::new(0, 0, false);
+    let body = compiler.module(module)?;
+    Ok((compiler.finish()?, body))
+}
+
+/// The names of the variables, functions and classes `body` declares in
+/// its own scope.
+pub(crate) fn declared_names(body: &[Stmt]) -> Vec<&str> {
+    let mut vars = Vec::new();
+    collect_declarations(body, &mut vars);
+    vars.extend(lexical_declarations(body).into_iter().map(|(name, _)| name));
+    vars
+}
+
+/// Register holding the completion value of a script
+const COMPLETION_REG: u8 = 0;
+
+/// Variables in the scope of a class body holding the class and its
+/// prototype, named so that scripts cannot reach them
+const CLASS_VAR: &str = "%class";
+const PROTOTYPE_VAR: &str = "%prototype";
+
+/// Code compiled after the body that creates it.
+#[derive(Clone, Copy)]
+enum Pending<'a> {
+    Function(&'a Function, FunctionKind),
+    /// The scope around a named function expression, a function of its own
+    /// that binds the name for the body to call itself by
+    NamedFunction(&'a Function),
+    /// The body of a class, a function of its own so that its private
+    /// names and hidden variables get a scope
+    Class(&'a Class),
+    /// The constructor of a class that declares none
+    Constructor(&'a Class),
+    /// The function initializing the fields of each instance of a class, or
+    /// its static fields when set
+    Fields(&'a Class, bool),
+}
+
+/// A function still to be compiled, with what it needs from the code that
+/// creates it.
+struct Deferred<'a> {
+    pending: Pending<'a>,
+    /// What `super` refers to in it
+    home: Option<Home>,
+    /// Whether it is strict code
+    strict: bool,
+    /// The declarations it sees
+    bindings: Option<Rc<Bindings<'a>>>,
+    /// The `Closure` instruction that needs its address
+    closure_at: usize,
+}
+
+/// The names a scope declares, each marked if it is a constant, and the
+/// scope around it.
+struct Bindings<'a> {
+    names: HashMap<&'a str, bool>,
+    parent: Option<Rc<Bindings<'a>>>,
+}
+
+/// Where `super` looks from a class member, and the arrow functions inside
+/// it.
+#[derive(Clone, Copy)]
+struct Home {
+    /// The variable holding the object whose prototype `super.x` reads
+    var: &'static str,
+    /// Set in a derived class constructor, where `super()` may be called
+    derived_constructor: bool,
+}
+
+struct Loop {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+    /// Number of enclosing `try` statements outside the loop
+    tries: usize,
+    /// Number of block scopes entered where `break` and `continue` jump to
+    blocks: usize,
+    /// Whether the loop is a `for-of`, whose iterator is closed by the
+    /// handler just inside `tries` on `break` but not on `continue`
+    closes_iterator: bool,
+}
+
+/// A `try` statement whose handler is installed while its block runs, or
+/// the handler of a `for-of` loop.
+#[derive(Clone)]
+struct Try<'a> {
+    finalizer: Option<&'a [Stmt]>,
+    /// Register of the iterator to close when leaving a `for-of` loop
+    iterator: Option<u8>,
+    /// Whether the loop is a `for await`, whose iterator is closed by
+    /// awaiting the result of its `return` method
+    is_async: bool,
+    /// Number of block scopes entered outside the statement, and the
+    /// declarations its `finally` block sees
+    blocks: usize,
+    bindings: Option<Rc<Bindings<'a>>>,
+}
+
+struct Compiler<'a> {
+    code_base: usize,
+    const_base: usize,
+    code: Vec<Instruction>,
+    constants: Vec<Value>,
+    strings: HashMap<Vec<u16>, u32>,
+    numbers: HashMap<u64, u32>,
+    /// Functions still to be compiled
+    pending: Vec<Deferred<'a>>,
+    /// What `super` refers to in the function being compiled
+    home: Option<Home>,
+    next_reg: usize,
+    is_script: bool,
+    /// Set while compiling strict code, which functions inherit from the
+    /// code around them
+    strict: bool,
+    /// Set while compiling an async generator, where `yield` and `return`
+    /// await their operands
+    is_async_generator: bool,
+    loops: Vec<Loop>,
+    tries: Vec<Try<'a>>,
+    /// The declarations in scope where code is being compiled
+    bindings: Option<Rc<Bindings<'a>>>,
+    /// Number of block scopes entered in the function being compiled
+    blocks: usize,
+    /// Set while binding the names a `const` declaration initializes
+    initializing: bool,
+}
+
+fn syntax_error(message: &str) -> RuntimeError {
+    RuntimeError::SyntaxError(message.to_string())
+}
+
+fn already_declared(name: &str) -> RuntimeError {
+    syntax_error(&format!("Identifier '{name}' has already been declared"))
+}
+
+impl<'a> Compiler<'a> {
+    fn new(code_base: usize, const_base: usize, is_script: bool) -> Self {
+        Compiler {
+            code_base,
+            const_base,
+            code: Vec::new(),
+            constants: Vec::new(),
+            strings: HashMap::new(),
+            numbers: HashMap::new(),
+            pending: Vec::new(),
+            home: None,
+            next_reg: 0,
+            is_script,
+            strict: false,
+            is_async_generator: false,
+            loops: Vec::new(),
+            tries: Vec::new(),
+            bindings: None,
+            blocks: 0,
+            initializing: false,
+        }
+    }
+
+    /// Compiles the functions still pending, laying each body out after the
+    /// last, and returns the finished code.
+    fn finish(mut self) -> Result<Compiled, RuntimeError> {
+        let mut next = 0;
+        while next < self.pending.len() {
+            let Deferred {
+                pending,
+                home,
+                strict,
+                ref bindings,
+                closure_at,
+            } = self.pending[next];
+            self.home = home;
+            self.strict = strict;
+            self.bindings = bindings.clone();
+            match pending {
+                Pending::Function(function, kind) => self.function(function, kind, closure_at)?,
+                Pending::NamedFunction(function) => self.named_function(function, closure_at)?,
+                Pending::Class(class) => self.class_body(class, closure_at)?,
+                Pending::Constructor(class) => {
+                    let name = class.name.as_deref().unwrap_or("");
+                    self.begin_function(name, 0, FunctionKind::ClassConstructor, closure_at)?;
+                    self.emit(Instruction::Return {
+                        start_reg: 0,
+                        count: 0,
+                    });
+                }
+                Pending::Fields(class, is_static) => self.fields(class, is_static, closure_at)?,
+            }
+            next += 1;
+        }
+        Ok(Compiled {
+            code: self.code,
+            constants: self.constants,
+        })
+    }
+
+    fn script(&mut self, program: &'a Program) -> Result<(), RuntimeError> {
+        self.emit(Instruction::LoadUndefined {
+            reg: COMPLETION_REG,
+        });
+        self.next_reg = COMPLETION_REG as usize + 1;
+        self.directives(&program.body);
+        self.hoist(&program.body, &[])?;
+        for stmt in &program.body {
+            self.statement(stmt)?;
+        }
+        self.emit(Instruction::Return {
+            start_reg: COMPLETION_REG,
+            count: 1,
+        });
+        Ok(())
+    }
+
+    /// Compiles a module's declarations, then its body, which runs as an
+    /// async function if it awaits at the top level. Returns where the body
+    /// starts.
+    fn module(&mut self, module: &'a Module) -> Result<usize, RuntimeError> {
+        // Module code is always strict
+        self.strict = true;
+        self.hoist(&module.body, &[])?;
+        self.emit(Instruction::Return {
+            start_reg: 0,
+            count: 0,
+        });
+        let body = self.here();
+        self.emit(Instruction::UseStrict);
+        if module.has_top_level_await {
+            self.emit(Instruction::CreateAsync);
+        }
+        self.statements(&module.body)?;
+        self.emit(Instruction::Return {
+            start_reg: 0,
+            count: 0,
+        });
+        Ok(body)
+    }
+
+    /// Starts compiling a function body, emitting its header and pointing
+    /// the `Closure` instruction at `closure_at` to it.
+    fn begin_function(
+        &mut self,
+        name: &str,
+        param_count: usize,
+        kind: FunctionKind,
+        closure_at: usize,
+    ) -> Result<(), RuntimeError> {
+        self.is_script = false;
+        self.is_async_generator = false;
+        self.loops.clear();
+        self.tries.clear();
+        self.blocks = 0;
+        self.next_reg = 0;
+
+        let header = self.code.len();
+        let name_idx = self.name(name);
+        let param_count =
+            u8::try_from(param_count).map_err(|_| syntax_error("Too many parameters"))?;
+        self.emit(Instruction::DeclareFunc {
+            reg: 0,
+            name_idx,
+            param_count,
+            kind,
+        });
+        if let Instruction::Closure { reg, .. } = self.code[closure_at] {
+            self.code[closure_at] = Instruction::Closure {
+                reg,
+                func_idx: (self.code_base + header) as u32,
+            };
+        }
+        if self.strict {
+            self.emit(Instruction::UseStrict);
+        }
+        Ok(())
+    }
+
+    fn function(
+        &mut self,
+        function: &'a Function,
+        kind: FunctionKind,
+        closure_at: usize,
+    ) -> Result<(), RuntimeError> {
+        let name = function.name.as_deref().unwrap_or("");
+        self.begin_function(name, function.params.len(), kind, closure_at)?;
+        self.is_async_generator = function.is_async && function.is_generator;
+
+        self.directives(&function.body);
+        // Arguments arrive in the first registers; move them into the scope
+        // before anything else can use those registers
+        for (i, param) in function.params.iter().enumerate() {
+            let name = self.name(param);
+            self.emit(Instruction::DeclareVar { name_idx: name });
+            self.emit(Instruction::SetScope {
+                var_idx: name,
+                src: i as u8,
+            });
+        }
+        self.hoist(&function.body, &function.params)?;
+        match (function.is_async, function.is_generator) {
+            (false, true) => {
+                self.emit(Instruction::CreateGenerator);
+            }
+            (true, false) => {
+                self.emit(Instruction::CreateAsync);
+            }
+            (true, true) => {
+                self.emit(Instruction::CreateAsyncGenerator);
+            }
+            (false, false) => {}
+        }
+        for stmt in &function.body {
+            self.statement(stmt)?;
+        }
+        self.emit(Instruction::Return {
+            start_reg: 0,
+            count: 0,
+        });
+        Ok(())
+    }
+
+    fn directives(&mut self, body: &[Stmt]) {
+        if self.strict {
+            return;
+        }
+        if let Some(Stmt::Expr(Expr::String(s))) = body.first() {
+            if *s == "use strict".encode_utf16().collect::<Vec<_>>() {
+                self.strict = true;
+                self.emit(Instruction::UseStrict);
+            }
+        }
+    }
+
+    /// Declares every `var` and function in `body` up front, as JavaScript
+    /// hoists them to the top of the enclosing function, along with the
+    /// `let` and `const` variables and classes at its top level.
+    fn hoist(&mut self, body: &'a [Stmt], params: &'a [String]) -> Result<(), RuntimeError> {
+        let mut vars = Vec::new();
+        collect_declarations(body, &mut vars);
+        let lexical = lexical_declarations(body);
+        let mut names: HashMap<_, _> = params.iter().map(|name| (name.as_str(), false)).collect();
+        for &var in &vars {
+            names.insert(var, false);
+        }
+        for &(name, constant) in &lexical {
+            if names.insert(name, constant).is_some() {
+                return Err(already_declared(name));
+            }
+        }
+        self.push_bindings(names);
+        for var in vars
+            .into_iter()
+            .chain(lexical.into_iter().map(|(name, _)| name))
+        {
+            let name = self.name(var);
+            self.emit(Instruction::DeclareVar { name_idx: name });
+        }
+        self.declare_functions(body)
+    }
+
+    /// Creates the functions declared at the top level of `body`, which
+    /// are ready as soon as it is entered. Those in a block are assigned to
+    /// the variables hoisted for them, but see the block's scope.
+    fn declare_functions(&mut self, body: &'a [Stmt]) -> Result<(), RuntimeError> {
+        for function in function_declarations(body) {
+            let reg = self.alloc()?;
+            self.closure(function, reg);
+            let name = self.name(function.name.as_deref().unwrap_or(""));
+            self.emit(Instruction::SetScope {
+                var_idx: name,
+                src: reg,
+            });
+            self.free(reg);
+        }
+        Ok(())
+    }
+
+    /// Makes `names` the innermost declarations code sees.
+    fn push_bindings(&mut self, names: HashMap<&'a str, bool>) {
+        self.bindings = Some(Rc::new(Bindings {
+            names,
+            parent: self.bindings.take(),
+        }));
+    }
+
+    /// Whether `name` refers to a constant where code is being compiled.
+    fn is_constant(&self, name: &str) -> bool {
+        let mut bindings = self.bindings.as_deref();
+        while let Some(scope) = bindings {
+            if let Some(&constant) = scope.names.get(name) {
+                return constant;
+            }
+            bindings = scope.parent.as_deref();
+        }
+        false
+    }
+
+    /// Enters a block scope declaring `names`, the `let` and `const`
+    /// variables and classes of a block with whether each is a constant.
+    fn enter_block(&mut self, names: Vec<(&'a str, bool)>) -> Result<(), RuntimeError> {
+        let mut declared = HashMap::new();
+        for &(name, constant) in &names {
+            if declared.insert(name, constant).is_some() {
+                return Err(already_declared(name));
+            }
+        }
+        self.emit(Instruction::PushScope);
+        self.blocks += 1;
+        self.push_bindings(declared);
+        for (name, _) in names {
+            let name_idx = self.name(name);
+            self.emit(Instruction::DeclareVar { name_idx });
+        }
+        Ok(())
+    }
+
+    fn leave_block(&mut self) {
+        self.emit(Instruction::PopScope);
+        self.blocks -= 1;
+        self.bindings = self
+            .bindings
+            .take()
+            .and_then(|bindings| bindings.parent.clone());
+    }
+
+    /// Emits what a jump from inside `from` block scopes to a place inside
+    /// `to` of them needs to leave the ones in between.
+    fn pop_scopes(&mut self, from: usize, to: usize) {
+        for _ in to..from {
+            self.emit(Instruction::PopScope);
+        }
+    }
+
+    /// Compiles a block, in a scope of its own if it declares anything
+    /// besides `var` variables and functions.
+    fn block(&mut self, body: &'a [Stmt]) -> Result<(), RuntimeError> {
+        let names = lexical_declarations(body);
+        let scoped = !names.is_empty();
+        if scoped {
+            self.enter_block(names)?;
+        }
+        self.declare_functions(body)?;
+        self.statements(body)?;
+        if scoped {
+            self.leave_block();
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    fn here(&self) -> usize {
+        self.code.len()
+    }
+
+    /// Emits a forward jump to be patched once its target is known.
+    fn jump(&mut self) -> usize {
+        self.emit(Instruction::Jmp { offset: 0 })
+    }
+
+    fn jump_if(&mut self, cond: u8) -> usize {
+        self.emit(Instruction::JmpIf { cond, offset: 0 })
+    }
+
+    /// Emits a jump taken when `cond` is falsy.
+    fn jump_if_not(&mut self, cond: u8) -> usize {
+        self.emit(Instruction::JmpIf { cond, offset: 1 });
+        self.jump()
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let offset = target as i32 - at as i32 - 1;
+        match &mut self.code[at] {
+            Instruction::Jmp { offset: o }
+            | Instruction::JmpIf { offset: o, .. }
+            | Instruction::EnterTry { offset: o, .. }
+            | Instruction::IteratorNext { offset: o, .. }
+            | Instruction::ForInNext { offset: o, .. }
+            | Instruction::IteratorValue { offset: o, .. }
+            | Instruction::Yield { offset: o, .. }
+            | Instruction::YieldDelegate { offset: o, .. } => *o = offset,
+            _ => unreachable!("patching a non-jump instruction"),
+        }
+    }
+
+    fn jump_to(&mut self, target: usize) {
+        let at = self.jump();
+        self.patch(at, target);
+    }
+
+    fn alloc(&mut self) -> Result<u8, RuntimeError> {
+        if self.next_reg > u8::MAX as usize {
+            return Err(syntax_error("Expression too complex"));
+        }
+        self.next_reg += 1;
+        Ok((self.next_reg - 1) as u8)
+    }
+
+    /// Releases `reg` and every register allocated after it.
+    fn free(&mut self, reg: u8) {
+        self.next_reg = reg as usize;
+    }
+
+    fn string_constant(&mut self, units: &[u16]) -> u32 {
+        if let Some(&idx) = self.strings.get(units) {
+            return idx;
+        }
+        let idx = (self.const_base + self.constants.len()) as u32;
+        self.constants
+            .push(Value::String(JsString::from_utf16(units)));
+        self.strings.insert(units.to_vec(), idx);
+        idx
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        self.string_constant(&name.encode_utf16().collect::<Vec<_>>())
+    }
+
+    fn number_constant(&mut self, n: f64) -> u32 {
+        if let Some(&idx) = self.numbers.get(&n.to_bits()) {
+            return idx;
+        }
+        let idx = (self.const_base + self.constants.len()) as u32;
+        self.constants.push(Value::Number(n));
+        self.numbers.insert(n.to_bits(), idx);
+        idx
+    }
+
+    fn closure(&mut self, function: &'a Function, reg: u8) {
+        let kind = if function.is_arrow {
+            FunctionKind::Arrow
+        } else if function.is_generator {
+            FunctionKind::Generator
+        } else if function.is_async {
+            FunctionKind::Async
+        } else {
+            FunctionKind::Normal
+        };
+        // Arrow functions see the `super` of the function around them
+        let home = if function.is_arrow { self.home } else { None };
+        self.defer(Pending::Function(function, kind), home, reg);
+    }
+
+    /// Compiles a function expression into `dst`. One with a name is made
+    /// by a function called on the spot, like a class, so that the name
+    /// is bound in a scope only the function sees.
+    fn function_expression(&mut self, function: &'a Function, dst: u8) {
+        if function.name.is_none() || function.is_arrow {
+            return self.closure(function, dst);
+        }
+        self.defer(Pending::NamedFunction(function), None, dst);
+        self.emit(Instruction::Call {
+            func_reg: dst,
+            arg_count: 0,
+        });
+    }
+
+    /// Compiles the scope around a named function expression, which
+    /// creates the function and binds its name to it.
+    fn named_function(
+        &mut self,
+        function: &'a Function,
+        closure_at: usize,
+    ) -> Result<(), RuntimeError> {
+        self.begin_function("", 0, FunctionKind::Arrow, closure_at)?;
+        let name = function.name.as_deref().unwrap_or("");
+        self.push_bindings(HashMap::from([(name, false)]));
+        let name_idx = self.name(name);
+        self.emit(Instruction::DeclareVar { name_idx });
+        let reg = self.alloc()?;
+        self.closure(function, reg);
+        self.emit(Instruction::SetScope {
+            var_idx: name_idx,
+            src: reg,
+        });
+        self.emit(Instruction::Return {
+            start_reg: reg,
+            count: 1,
+        });
+        Ok(())
+    }
+
+    /// Creates a function in `reg` whose code is compiled later.
+    fn defer(&mut self, pending: Pending<'a>, home: Option<Home>, reg: u8) {
+        let at = self.emit(Instruction::Closure { reg, func_idx: 0 });
+        self.pending.push(Deferred {
+            pending,
+            home,
+            strict: self.strict,
+            bindings: self.bindings.clone(),
+            closure_at: at,
+        });
+    }
+
+    /// Compiles a class declaration or expression, leaving the class in
+    /// `dst`. Its body runs as a function called on the spot.
+    fn class(&mut self, class: &'a Class, dst: u8) {
+        self.defer(Pending::Class(class), self.home, dst);
+        self.emit(Instruction::Call {
+            func_reg: dst,
+            arg_count: 0,
+        });
+    }
+
+    /// Compiles the body of a class: it makes the private names, the
+    /// constructor and the prototype, defines the methods and accessors,
+    /// initializes the static fields and returns the class.
+    fn class_body(&mut self, class: &'a Class, closure_at: usize) -> Result<(), RuntimeError> {
+        let name = class.name.as_deref().unwrap_or("");
+        self.begin_function(name, 0, FunctionKind::Arrow, closure_at)?;
+        // Inside the class its name is a constant
+        if let Some(name) = &class.name {
+            self.push_bindings(HashMap::from([(name.as_str(), true)]));
+        }
+        let mut vars = vec![CLASS_VAR.to_string(), PROTOTYPE_VAR.to_string()];
+        vars.extend(class.name.clone());
+        let mut private_names = Vec::new();
+        for (i, member) in class.members.iter().enumerate() {
+            if let ClassKey::Private(name) = &member.key {
+                if !private_names.contains(&name) {
+                    private_names.push(name);
+                }
+            }
+            if needs_member_var(member) {
+                vars.push(member_var(i));
+            }
+        }
+        for var in vars
+            .iter()
+            .map(String::as_str)
+            .chain(private_names.iter().map(|n| n.as_str()))
+        {
+            let name_idx = self.name(var);
+            self.emit(Instruction::DeclareVar { name_idx });
+        }
+        let ctor = self.alloc()?;
+        let prototype = self.alloc()?;
+        let fields = self.alloc()?;
+        for name in private_names {
+            let name_idx = self.name(name);
+            self.emit(Instruction::CreatePrivateName {
+                dst: ctor,
+                name_idx,
+            });
+            self.emit(Instruction::SetScope {
+                var_idx: name_idx,
+                src: ctor,
+            });
+        }
+
+        match &class.constructor {
+            Some(function) => {
+                let home = Home {
+                    var: PROTOTYPE_VAR,
+                    derived_constructor: class.extends.is_some(),
+                };
+                let pending = Pending::Function(function, FunctionKind::ClassConstructor);
+                self.defer(pending, Some(home), ctor);
+            }
+            None => self.defer(Pending::Constructor(class), None, ctor),
+        }
+        if let Some(extends) = &class.extends {
+            self.expr(extends, prototype)?;
+        }
+        let has_fields = class.members.iter().any(|member| {
+            !member.is_static
+                && (matches!(member.kind, ClassMemberKind::Field(_))
+                    || matches!(member.key, ClassKey::Private(_)))
+        });
+        if has_fields {
+            let home = Home {
+                var: PROTOTYPE_VAR,
+                derived_constructor: false,
+            };
+            self.defer(Pending::Fields(class, false), Some(home), fields);
+        } else {
+            self.emit(Instruction::LoadUndefined { reg: fields });
+        }
+        self.emit(Instruction::CreateClass {
+            reg: ctor,
+            extends: class.extends.is_some(),
+            implicit: class.constructor.is_none(),
+        });
+        let names = [Some(CLASS_VAR), Some(PROTOTYPE_VAR), class.name.as_deref()];
+        for (name, src) in names.into_iter().zip([ctor, prototype, ctor]) {
+            if let Some(name) = name {
+                let var_idx = self.name(name);
+                self.emit(Instruction::SetScope { var_idx, src });
+            }
+        }
+
+        let key = self.alloc()?;
+        let value = self.alloc()?;
+        for (i, member) in class.members.iter().enumerate() {
+            let function = match &member.kind {
+                ClassMemberKind::Method(function)
+                | ClassMemberKind::Getter(function)
+                | ClassMemberKind::Setter(function) => function,
+                // Computed field names are evaluated once, with the methods
+                ClassMemberKind::Field(_) => {
+                    if let ClassKey::Public(PropertyKey::Computed(expr)) = &member.key {
+                        self.expr(expr, key)?;
+                        let var_idx = self.name(&member_var(i));
+                        self.emit(Instruction::SetScope { var_idx, src: key });
+                    }
+                    continue;
+                }
+            };
+            let (obj, var) = if member.is_static {
+                (ctor, CLASS_VAR)
+            } else {
+                (prototype, PROTOTYPE_VAR)
+            };
+            match &member.key {
+                ClassKey::Public(PropertyKey::Named(name)) => {
+                    let const_idx = self.name(name);
+                    self.emit(Instruction::LoadConst {
+                        reg: key,
+                        const_idx,
+                    });
+                }
+                ClassKey::Public(PropertyKey::Computed(expr)) => self.expr(expr, key)?,
+                ClassKey::Private(name) => {
+                    let var_idx = self.name(name);
+                    self.emit(Instruction::GetScope { dst: key, var_idx });
+                }
+            }
+            let home = Home {
+                var,
+                derived_constructor: false,
+            };
+            self.defer(
+                Pending::Function(function, FunctionKind::Method),
+                Some(home),
+                value,
+            );
+            // Each instance gets its private methods as its fields are
+            // initialized
+            if needs_member_var(member) {
+                let var_idx = self.name(&member_var(i));
+                self.emit(Instruction::SetScope {
+                    var_idx,
+                    src: value,
+                });
+                continue;
+            }
+            self.emit(define(&member.kind, obj, key, value));
+        }
+        if class
+            .members
+            .iter()
+            .any(|member| member.is_static && matches!(member.kind, ClassMemberKind::Field(_)))
+        {
+            // Static fields are initialized with the class as `this`
+            let home = Home {
+                var: CLASS_VAR,
+                derived_constructor: false,
+            };
+            self.defer(Pending::Fields(class, true), Some(home), key);
+            self.emit(Instruction::Move {
+                dst: value,
+                src: ctor,
+            });
+            self.emit(Instruction::CallMethod {
+                func_reg: key,
+                arg_count: 0,
+            });
+        }
+        self.emit(Instruction::Return {
+            start_reg: ctor,
+            count: 1,
+        });
+        Ok(())
+    }
+
+    /// Compiles the function that initializes the fields of each instance
+    /// of a class, after giving it the class's private methods, or the
+    /// function that initializes the static fields.
+    fn fields(
+        &mut self,
+        class: &'a Class,
+        is_static: bool,
+        closure_at: usize,
+    ) -> Result<(), RuntimeError> {
+        self.begin_function("", 0, FunctionKind::Method, closure_at)?;
+        let this = self.alloc()?;
+        let key = self.alloc()?;
+        let value = self.alloc()?;
+        self.emit(Instruction::LoadThis { dst: this });
+        for (i, member) in class.members.iter().enumerate() {
+            if is_static || !needs_member_var(member) {
+                continue;
+            }
+            // Private methods and accessors
+            if let ClassKey::Private(name) = &member.key {
+                let var_idx = self.name(name);
+                self.emit(Instruction::GetScope { dst: key, var_idx });
+                let var_idx = self.name(&member_var(i));
+                self.emit(Instruction::GetScope {
+                    dst: value,
+                    var_idx,
+                });
+                self.emit(define(&member.kind, this, key, value));
+            }
+        }
+        for (i, member) in class.members.iter().enumerate() {
+            let ClassMemberKind::Field(init) = &member.kind else {
+                continue;
+            };
+            if member.is_static != is_static {
+                continue;
+            }
+            match &member.key {
+                ClassKey::Public(PropertyKey::Named(name)) => {
+                    let const_idx = self.name(name);
+                    self.emit(Instruction::LoadConst {
+                        reg: key,
+                        const_idx,
+                    });
+                }
+                ClassKey::Public(PropertyKey::Computed(_)) => {
+                    let var_idx = self.name(&member_var(i));
+                    self.emit(Instruction::GetScope { dst: key, var_idx });
+                }
+                ClassKey::Private(name) => {
+                    let var_idx = self.name(name);
+                    self.emit(Instruction::GetScope { dst: key, var_idx });
+                }
+            }
+            match init {
+                Some(init) => self.expr(init, value)?,
+                None => {
+                    self.emit(Instruction::LoadUndefined { reg: value });
+                }
+            }
+            self.emit(Instruction::DefineOwn {
+                obj: this,
+                key,
+                value,
+            });
+        }
+        self.emit(Instruction::Return {
+            start_reg: 0,
+            count: 0,
+        });
+        Ok(())
+    }
+
+    /// Loads the object `super` looks up from into `reg`.
+    fn super_home(&mut self, reg: u8) -> Result<(), RuntimeError> {
+        let home = self
+            .home
+            .ok_or_else(|| syntax_error("'super' keyword unexpected here"))?;
+        let var_idx = self.name(home.var);
+        self.emit(Instruction::GetScope { dst: reg, var_idx });
+        Ok(())
+    }
+
+    /// Loads the key of `super.name` or `super[key]` into `reg`.
+    fn super_key(&mut self, key: &'a PropertyKey, reg: u8) -> Result<(), RuntimeError> {
+        match key {
+            PropertyKey::Named(name) => {
+                let const_idx = self.name(name);
+                self.emit(Instruction::LoadConst { reg, const_idx });
+            }
+            PropertyKey::Computed(expr) => self.expr(expr, reg)?,
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, stmt: &'a Stmt) -> Result<(), RuntimeError> {
+        match stmt {
+            Stmt::Var { kind, decls } => {
+                for decl in decls {
+                    if let Some(init) = &decl.init {
+                        let reg = self.alloc()?;
+                        self.expr(init, reg)?;
+                        self.initializing = *kind == VarKind::Const;
+                        self.bind(&decl.target, reg)?;
+                        self.initializing = false;
+                        self.free(reg);
+                    }
+                }
+            }
+            // Created when the enclosing body or block was entered
+            Stmt::Function(_) => {}
+            Stmt::Class(class) => {
+                let reg = self.alloc()?;
+                self.class(class, reg);
+                let var_idx = self.name(class.name.as_deref().unwrap_or(""));
+                self.emit(Instruction::SetScope { var_idx, src: reg });
+                self.free(reg);
+            }
+            Stmt::Return(arg) => match arg {
+                Some(arg) => {
+                    let reg = self.alloc()?;
+                    self.expr(arg, reg)?;
+                    if self.is_async_generator {
+                        self.emit(Instruction::Await { dst: reg, src: reg });
+                    }
+                    self.exit_tries(0)?;
+                    self.emit(Instruction::Return {
+                        start_reg: reg,
+                        count: 1,
+                    });
+                    self.free(reg);
+                }
+                None => {
+                    self.exit_tries(0)?;
+                    self.emit(Instruction::Return {
+                        start_reg: 0,
+                        count: 0,
+                    });
+                }
+            },
+            Stmt::If {
+                test,
+                consequent,
+                alternate,
+            } => {
+                let reg = self.alloc()?;
+                self.expr(test, reg)?;
+                self.free(reg);
+                let to_else = self.jump_if_not(reg);
+                // Either branch may be a lone function declaration
+                self.block(std::slice::from_ref(&**consequent))?;
+                if let Some(alternate) = alternate {
+                    let to_end = self.jump();
+                    self.patch(to_else, self.here());
+                    self.block(std::slice::from_ref(&**alternate))?;
+                    self.patch(to_end, self.here());
+                } else {
+                    self.patch(to_else, self.here());
+                }
+            }
+            Stmt::While { test, body } => {
+                let start = self.here();
+                let reg = self.alloc()?;
+                self.expr(test, reg)?;
+                self.free(reg);
+                let exit = self.jump_if_not(reg);
+                self.loop_body(body, start, exit)?;
+            }
+            Stmt::DoWhile { body, test } => {
+                let start = self.here();
+                self.begin_loop(false);
+                self.statement(body)?;
+                let continue_target = self.here();
+                let reg = self.alloc()?;
+                self.expr(test, reg)?;
+                self.free(reg);
+                let back = self.jump_if(reg);
+                self.patch(back, start);
+                self.end_loop(continue_target, self.here());
+            }
+            Stmt::For {
+                init,
+                test,
+                update,
+                body,
+            } => {
+                // Variables the head declares with `let` are copied for
+                // each iteration, after the initializer and each update
+                let (names, per_iteration) = match init.as_deref() {
+                    Some(Stmt::Var { kind, decls }) if *kind != VarKind::Var => {
+                        let mut names = Vec::new();
+                        for decl in decls {
+                            collect_names(&decl.target, &mut names);
+                        }
+                        let constant = *kind == VarKind::Const;
+                        let names = names.into_iter().map(|name| (name, constant)).collect();
+                        (names, !constant)
+                    }
+                    _ => (Vec::new(), false),
+                };
+                let scoped = !names.is_empty();
+                if scoped {
+                    self.enter_block(names)?;
+                }
+                if let Some(init) = init {
+                    self.statement(init)?;
+                }
+                if per_iteration {
+                    self.emit(Instruction::CopyScope);
+                }
+                let start = self.here();
+                let exit = match test {
+                    Some(test) => {
+                        let reg = self.alloc()?;
+                        self.expr(test, reg)?;
+                        self.free(reg);
+                        Some(self.jump_if_not(reg))
+                    }
+                    None => None,
+                };
+                self.begin_loop(false);
+                self.statement(body)?;
+                let continue_target = self.here();
+                if per_iteration {
+                    self.emit(Instruction::CopyScope);
+                }
+                if let Some(update) = update {
+                    let reg = self.alloc()?;
+                    self.expr(update, reg)?;
+                    self.free(reg);
+                }
+                self.jump_to(start);
+                if let Some(exit) = exit {
+                    self.patch(exit, self.here());
+                }
+                self.end_loop(continue_target, self.here());
+                if scoped {
+                    self.leave_block();
+                }
+            }
+            Stmt::ForOf {
+                kind,
+                target,
+                iterable,
+                body,
+                is_await,
+            } => self.for_of(*kind, target, iterable, body, *is_await)?,
+            Stmt::ForIn {
+                kind,
+                target,
+                object,
+                body,
+            } => self.for_in(*kind, target, object, body)?,
+            Stmt::Break => {
+                let lp = self
+                    .loops
+                    .last()
+                    .ok_or_else(|| syntax_error("Illegal break statement"))?;
+                let (tries, blocks) = (lp.tries, lp.blocks);
+                let from = self.exit_tries(tries)?;
+                self.pop_scopes(from, blocks);
+                let at = self.jump();
+                self.loops.last_mut().unwrap().breaks.push(at);
+            }
+            Stmt::Continue => {
+                let lp = self
+                    .loops
+                    .last()
+                    .ok_or_else(|| syntax_error("Illegal continue statement"))?;
+                let tries = lp.tries + lp.closes_iterator as usize;
+                let blocks = lp.blocks;
+                let from = self.exit_tries(tries)?;
+                self.pop_scopes(from, blocks);
+                let at = self.jump();
+                self.loops.last_mut().unwrap().continues.push(at);
+            }
+            Stmt::Throw(arg) => {
+                let reg = self.alloc()?;
+                self.expr(arg, reg)?;
+                self.emit(Instruction::Throw { src: reg });
+                self.free(reg);
+            }
+            Stmt::Try {
+                block,
+                handler,
+                finalizer,
+            } => self.try_statement(block, handler.as_ref(), finalizer.as_deref())?,
+            Stmt::Block(body) => self.block(body)?,
+            Stmt::Expr(expr) => {
+                if self.is_script {
+                    self.expr(expr, COMPLETION_REG)?;
+                } else {
+                    let reg = self.alloc()?;
+                    self.expr(expr, reg)?;
+                    self.free(reg);
+                }
+            }
+            Stmt::Empty => {}
+            // Imports are bound when the module is linked
+            Stmt::Import(_) => {}
+            Stmt::Export(export) => match export {
+                ExportDecl::Declaration(decl) => self.statement(decl)?,
+                ExportDecl::DefaultExpr(expr) => {
+                    let reg = self.alloc()?;
+                    self.expr(expr, reg)?;
+                    let var_idx = self.name("default");
+                    self.emit(Instruction::SetScope { var_idx, src: reg });
+                    self.free(reg);
+                }
+                ExportDecl::DefaultFunction(_)
+                | ExportDecl::Named { .. }
+                | ExportDecl::All { .. } => {}
+            },
+        }
+        Ok(())
+    }
+
+    fn statements(&mut self, body: &'a [Stmt]) -> Result<(), RuntimeError> {
+        for stmt in body {
+            self.statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Compiles `try`. With a `finally` block, the block is copied onto
+    /// every way out: normal completion, the end of the catch block, a
+    /// rethrow, and any `break`, `continue` or `return` that leaves it.
+    fn try_statement(
+        &mut self,
+        block: &'a [Stmt],
+        handler: Option<&'a CatchClause>,
+        finalizer: Option<&'a [Stmt]>,
+    ) -> Result<(), RuntimeError> {
+        let exc_reg = self.alloc()?;
+        let enter = self.emit(Instruction::EnterTry { offset: 0, exc_reg });
+        self.tries.push(Try {
+            finalizer,
+            iterator: None,
+            is_async: false,
+            blocks: self.blocks,
+            bindings: self.bindings.clone(),
+        });
+        self.block(block)?;
+        self.tries.pop();
+        self.emit(Instruction::LeaveTry);
+        self.block(finalizer.unwrap_or_default())?;
+        let mut to_end = vec![self.jump()];
+
+        self.patch(enter, self.here());
+        match handler {
+            Some(handler) => {
+                // Exceptions from the catch block still run `finally`
+                let rethrow = match finalizer {
+                    Some(_) => {
+                        self.tries.push(Try {
+                            finalizer,
+                            iterator: None,
+                            is_async: false,
+                            blocks: self.blocks,
+                            bindings: self.bindings.clone(),
+                        });
+                        Some(self.emit(Instruction::EnterTry { offset: 0, exc_reg }))
+                    }
+                    None => None,
+                };
+                // The parameter shares the scope of the catch block
+                let mut names = lexical_declarations(&handler.body);
+                if let Some(param) = &handler.param {
+                    names.insert(0, (param.as_str(), false));
+                }
+                let scoped = !names.is_empty();
+                if scoped {
+                    self.enter_block(names)?;
+                }
+                if let Some(param) = &handler.param {
+                    let var_idx = self.name(param);
+                    self.emit(Instruction::SetScope {
+                        var_idx,
+                        src: exc_reg,
+                    });
+                }
+                self.declare_functions(&handler.body)?;
+                self.statements(&handler.body)?;
+                if scoped {
+                    self.leave_block();
+                }
+                if let Some(rethrow) = rethrow {
+                    self.tries.pop();
+                    self.emit(Instruction::LeaveTry);
+                    self.block(finalizer.unwrap_or_default())?;
+                    to_end.push(self.jump());
+                    self.patch(rethrow, self.here());
+                    self.block(finalizer.unwrap_or_default())?;
+                    self.emit(Instruction::Throw { src: exc_reg });
+                }
+            }
+            None => {
+                self.block(finalizer.unwrap_or_default())?;
+                self.emit(Instruction::Throw { src: exc_reg });
+            }
+        }
+        for at in to_end {
+            self.patch(at, self.here());
+        }
+        self.free(exc_reg);
+        Ok(())
+    }
+
+    /// Removes the handlers of the `try` statements entered since `level`,
+    /// innermost first, running their `finally` blocks and closing the
+    /// iterators of `for-of` loops as a jump out of them requires. Returns
+    /// the number of block scopes the jump is left inside.
+    fn exit_tries(&mut self, level: usize) -> Result<usize, RuntimeError> {
+        let tries = self.tries.clone();
+        let (blocks, bindings) = (self.blocks, self.bindings.clone());
+        while self.tries.len() > level {
+            let Some(Try {
+                finalizer,
+                iterator,
+                is_async,
+                blocks: outside,
+                bindings: seen,
+            }) = self.tries.pop()
+            else {
+                break;
+            };
+            self.pop_scopes(self.blocks, outside);
+            self.blocks = outside;
+            self.bindings = seen;
+            self.emit(Instruction::LeaveTry);
+            if let Some(iter) = iterator {
+                self.close_iterator(iter, is_async)?;
+            }
+            self.block(finalizer.unwrap_or_default())?;
+        }
+        let left_inside = self.blocks;
+        self.tries = tries;
+        self.blocks = blocks;
+        self.bindings = bindings;
+        Ok(left_inside)
+    }
+
+    fn begin_loop(&mut self, closes_iterator: bool) {
+        self.loops.push(Loop {
+            breaks: Vec::new(),
+            continues: Vec::new(),
+            tries: self.tries.len(),
+            blocks: self.blocks,
+            closes_iterator,
+        });
+    }
+
+    /// Compiles the body of a loop whose condition sits at `start` and
+    /// whose exit jump is `exit`.
+    fn loop_body(&mut self, body: &'a Stmt, start: usize, exit: usize) -> Result<(), RuntimeError> {
+        self.begin_loop(false);
+        self.statement(body)?;
+        self.jump_to(start);
+        self.patch(exit, self.here());
+        self.end_loop(start, self.here());
+        Ok(())
+    }
+
+    fn end_loop(&mut self, continue_target: usize, break_target: usize) {
+        let Some(lp) = self.loops.pop() else {
+            return;
+        };
+        for at in lp.continues {
+            self.patch(at, continue_target);
+        }
+        for at in lp.breaks {
+            self.patch(at, break_target);
+        }
+    }
+
+    /// Compiles `for (target of iterable) body`, or `for await` when
+    /// `is_await` is set. The loop runs under a handler that closes the
+    /// iterator if the body throws, and `break` or `return` close it on the
+    /// way out.
+    fn for_of(
+        &mut self,
+        kind: Option<VarKind>,
+        target: &'a Pattern,
+        iterable: &'a Expr,
+        body: &'a Stmt,
+        is_await: bool,
+    ) -> Result<(), RuntimeError> {
+        let iter = self.alloc()?;
+        let value = self.alloc()?;
+        self.expr(iterable, iter)?;
+        if is_await {
+            self.emit(Instruction::GetAsyncIterator {
+                dst: iter,
+                src: iter,
+            });
+        } else {
+            self.emit(Instruction::GetIterator {
+                dst: iter,
+                src: iter,
+            });
+        }
+        let enter = self.emit(Instruction::EnterTry {
+            offset: 0,
+            exc_reg: value,
+        });
+        self.begin_loop(true);
+        self.tries.push(Try {
+            finalizer: None,
+            iterator: Some(iter),
+            is_async: is_await,
+            blocks: self.blocks,
+            bindings: self.bindings.clone(),
+        });
+        let start = self.here();
+        let next = if is_await {
+            self.async_iterator_next(value, iter)
+        } else {
+            self.emit(Instruction::IteratorNext {
+                dst: value,
+                iter,
+                offset: 0,
+            })
+        };
+        self.loop_iteration(kind, target, value, body)?;
+        self.jump_to(start);
+        self.patch(next, self.here());
+        self.tries.pop();
+        self.emit(Instruction::LeaveTry);
+        let to_end = self.jump();
+        self.patch(enter, self.here());
+        self.close_and_rethrow(iter, value, is_await)?;
+        self.patch(to_end, self.here());
+        self.end_loop(start, self.here());
+        self.free(iter);
+        Ok(())
+    }
+
+    /// Emits the steps of a `for await` loop that take the next value from
+    /// the iterator in `iter` into `dst`, returning the jump taken once it
+    /// is done.
+    fn async_iterator_next(&mut self, dst: u8, iter: u8) -> usize {
+        self.emit(Instruction::AsyncIteratorNext { dst, iter });
+        self.emit(Instruction::Await { dst, src: dst });
+        self.emit(Instruction::IteratorValue {
+            dst,
+            iter,
+            offset: 0,
+        })
+    }
+
+    /// Emits the closing of the iterator in `iter` by a jump out of its
+    /// loop, awaiting the result for a `for await` loop.
+    fn close_iterator(&mut self, iter: u8, is_async: bool) -> Result<(), RuntimeError> {
+        if is_async {
+            let reg = self.alloc()?;
+            self.emit(Instruction::AsyncIteratorClose { dst: reg, iter });
+            self.emit(Instruction::Await { dst: reg, src: reg });
+            self.free(reg);
+        } else {
+            self.emit(Instruction::IteratorClose { iter });
+        }
+        Ok(())
+    }
+
+    /// Compiles `yield*` in an async generator, whose result is in `dst`,
+    /// as a loop that yields each value of its async iterator. Only `next`
+    /// is passed on: resuming with `return` closes the iterator on the way
+    /// out, and with `throw` closes it and throws from here.
+    fn async_yield_delegate(&mut self, dst: u8) -> Result<(), RuntimeError> {
+        let iter = self.alloc()?;
+        self.emit(Instruction::GetAsyncIterator {
+            dst: iter,
+            src: dst,
+        });
+        let enter = self.emit(Instruction::EnterTry {
+            offset: 0,
+            exc_reg: dst,
+        });
+        self.tries.push(Try {
+            finalizer: None,
+            iterator: Some(iter),
+            is_async: true,
+            blocks: self.blocks,
+            bindings: self.bindings.clone(),
+        });
+        let start = self.here();
+        let done = self.async_iterator_next(dst, iter);
+        let at = self.emit(Instruction::Yield {
+            dst,
+            src: dst,
+            offset: 0,
+        });
+        self.jump_to(start);
+        self.patch(at, self.here());
+        self.emit(Instruction::Await { dst, src: dst });
+        self.exit_tries(0)?;
+        self.emit(Instruction::Return {
+            start_reg: dst,
+            count: 1,
+        });
+        self.patch(done, self.here());
+        self.tries.pop();
+        self.emit(Instruction::LeaveTry);
+        let to_end = self.jump();
+        self.patch(enter, self.here());
+        self.close_and_rethrow(iter, dst, true)?;
+        self.patch(to_end, self.here());
+        self.free(iter);
+        Ok(())
+    }
+
+    /// Compiles `for (target in object) body`. The keys are listed before
+    /// the loop starts, and need no closing when it stops early.
+    fn for_in(
+        &mut self,
+        kind: Option<VarKind>,
+        target: &'a Pattern,
+        object: &'a Expr,
+        body: &'a Stmt,
+    ) -> Result<(), RuntimeError> {
+        let iter = self.alloc()?;
+        let key = self.alloc()?;
+        self.expr(object, iter)?;
+        self.emit(Instruction::ForInPrepare {
+            dst: iter,
+            src: iter,
+        });
+        self.begin_loop(false);
+        let start = self.here();
+        let next = self.emit(Instruction::ForInNext {
+            dst: key,
+            iter,
+            offset: 0,
+        });
+        self.loop_iteration(kind, target, key, body)?;
+        self.jump_to(start);
+        self.patch(next, self.here());
+        self.end_loop(start, self.here());
+        self.free(iter);
+        Ok(())
+    }
+
+    /// Compiles one iteration of a `for-of` or `for-in` loop, which binds
+    /// the value in `value` to `target` and runs `body`. Variables the head
+    /// declares with `let` or `const` get a new scope each time.
+    fn loop_iteration(
+        &mut self,
+        kind: Option<VarKind>,
+        target: &'a Pattern,
+        value: u8,
+        body: &'a Stmt,
+    ) -> Result<(), RuntimeError> {
+        let constant = match kind {
+            Some(VarKind::Let) => false,
+            Some(VarKind::Const) => true,
+            _ => {
+                self.bind(target, value)?;
+                return self.statement(body);
+            }
+        };
+        let mut names = Vec::new();
+        collect_names(target, &mut names);
+        self.enter_block(names.into_iter().map(|name| (name, constant)).collect())?;
+        self.initializing = constant;
+        self.bind(target, value)?;
+        self.initializing = false;
+        self.statement(body)?;
+        self.leave_block();
+        Ok(())
+    }
+
+    /// Emits the handler that closes the iterator in `iter` after an
+    /// exception, then rethrows the exception in `exc_reg`. An exception
+    /// from closing the iterator is dropped in favour of the first.
+    fn close_and_rethrow(
+        &mut self,
+        iter: u8,
+        exc_reg: u8,
+        is_async: bool,
+    ) -> Result<(), RuntimeError> {
+        let ignored = self.alloc()?;
+        let enter = self.emit(Instruction::EnterTry {
+            offset: 0,
+            exc_reg: ignored,
+        });
+        self.close_iterator(iter, is_async)?;
+        self.emit(Instruction::LeaveTry);
+        self.patch(enter, self.here());
+        self.emit(Instruction::Throw { src: exc_reg });
+        self.free(ignored);
+        Ok(())
+    }
+
+    /// Stores the value in `src` into `target`, taking it apart if it is an
+    /// array or object pattern.
+    fn bind(&mut self, target: &'a Pattern, src: u8) -> Result<(), RuntimeError> {
+        match target {
+            Pattern::Ident(name) if !self.initializing && self.is_constant(name) => {
+                self.emit(Instruction::ThrowConstAssignment);
+            }
+            Pattern::Ident(name) => {
+                let var_idx = self.name(name);
+                self.emit(Instruction::SetScope { var_idx, src });
+            }
+            Pattern::Expr(expr) => {
+                let mark = self.next_reg as u8;
+                let target = self.target(expr)?;
+                self.store_target(&target, src);
+                self.free(mark);
+            }
+            Pattern::Array { elements, rest } => self.bind_array(elements, rest.as_deref(), src)?,
+            Pattern::Object { properties, rest } => {
+                let value = self.alloc()?;
+                // A rest element leaves out every key taken before it, so
+                // each key then keeps a register of its own
+                let excluded = self.alloc()?;
+                let mut key = excluded;
+                for (i, (name, element)) in properties.iter().enumerate() {
+                    if rest.is_some() && i > 0 {
+                        key = self.alloc()?;
+                    }
+                    match name {
+                        PropertyKey::Named(name) => {
+                            let const_idx = self.name(name);
+                            self.emit(Instruction::LoadConst {
+                                reg: key,
+                                const_idx,
+                            });
+                        }
+                        PropertyKey::Computed(expr) => self.expr(expr, key)?,
+                    }
+                    self.emit(Instruction::GetProp {
+                        dst: value,
+                        obj: src,
+                        key,
+                    });
+                    self.bind_element(element, value)?;
+                }
+                if let Some(rest) = rest {
+                    self.emit(Instruction::CopyRest {
+                        dst: value,
+                        src,
+                        excluded,
+                        count: properties.len() as u8,
+                    });
+                    self.bind(rest, value)?;
+                }
+                self.free(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Binds an array pattern by stepping an iterator over `src`, which is
+    /// closed afterwards unless the pattern used it up.
+    fn bind_array(
+        &mut self,
+        elements: &'a [Option<PatternElement>],
+        rest: Option<&'a Pattern>,
+        src: u8,
+    ) -> Result<(), RuntimeError> {
+        let iter = self.alloc()?;
+        let value = self.alloc()?;
+        self.emit(Instruction::GetIterator { dst: iter, src });
+        let enter = self.emit(Instruction::EnterTry {
+            offset: 0,
+            exc_reg: value,
+        });
+        for element in elements {
+            self.emit(Instruction::IteratorNext {
+                dst: value,
+                iter,
+                offset: 0,
+            });
+            if let Some(element) = element {
+                self.bind_element(element, value)?;
+            }
+        }
+        if let Some(rest) = rest {
+            let array = self.alloc()?;
+            let index = self.alloc()?;
+            let one = self.alloc()?;
+            self.emit(Instruction::NewArray { reg: array });
+            self.load_number(index, 0.0);
+            self.load_number(one, 1.0);
+            self.append_all(array, index, one, iter)?;
+            self.bind(rest, array)?;
+            self.free(array);
+        }
+        self.emit(Instruction::LeaveTry);
+        self.emit(Instruction::IteratorClose { iter });
+        let to_end = self.jump();
+        self.patch(enter, self.here());
+        self.close_and_rethrow(iter, value, false)?;
+        self.patch(to_end, self.here());
+        self.free(iter);
+        Ok(())
+    }
+
+    /// Binds one element of a pattern, substituting its default if the
+    /// value in `value` is undefined.
+    fn bind_element(&mut self, element: &'a PatternElement, value: u8) -> Result<(), RuntimeError> {
+        if let Some(default) = &element.default {
+            let test = self.alloc()?;
+            self.emit(Instruction::LoadUndefined { reg: test });
+            self.emit(Instruction::Eq {
+                dst: test,
+                a: value,
+                b: test,
+            });
+            self.free(test);
+            let skip = self.jump_if_not(test);
+            self.expr(default, value)?;
+            self.patch(skip, self.here());
+        }
+        self.bind(&element.target, value)
+    }
+
+    /// Appends the rest of the values of the iterator in `iter` to `array`,
+    /// at the position in `index`; `one` holds the step.
+    fn append_all(&mut self, array: u8, index: u8, one: u8, iter: u8) -> Result<(), RuntimeError> {
+        let value = self.alloc()?;
+        let start = self.here();
+        let next = self.emit(Instruction::IteratorNext {
+            dst: value,
+            iter,
+            offset: 0,
+        });
+        self.emit(Instruction::SetElem {
+            array,
+            index,
+            value,
+        });
+        self.emit(Instruction::Add {
+            dst: index,
+            a: index,
+            b: one,
+        });
+        self.jump_to(start);
+        self.patch(next, self.here());
+        self.free(value);
+        Ok(())
+    }
+
+    /// Writes `!src` to `dst`.
+    fn not(&mut self, dst: u8, src: u8) {
+        self.emit(Instruction::JmpIf {
+            cond: src,
+            offset: 2,
+        });
+        self.emit(Instruction::LoadBool {
+            reg: dst,
+            value: true,
+        });
+        self.emit(Instruction::Jmp { offset: 1 });
+        self.emit(Instruction::LoadBool {
+            reg: dst,
+            value: false,
+        });
+    }
+
+    fn binary(&mut self, op: BinaryOp, dst: u8, a: u8, b: u8) {
+        self.emit(match op {
+            BinaryOp::Add => Instruction::Add { dst, a, b },
+            BinaryOp::Sub => Instruction::Sub { dst, a, b },
+            BinaryOp::Mul => Instruction::Mul { dst, a, b },
+            BinaryOp::Div => Instruction::Div { dst, a, b },
+            BinaryOp::Mod => Instruction::Mod { dst, a, b },
+            BinaryOp::Pow => Instruction::Pow { dst, a, b },
+            BinaryOp::StrictEq | BinaryOp::StrictNotEq => Instruction::Eq { dst, a, b },
+            BinaryOp::Eq | BinaryOp::NotEq => Instruction::LooseEq { dst, a, b },
+            BinaryOp::Lt => Instruction::Lt { dst, a, b },
+            BinaryOp::Le => Instruction::Le { dst, a, b },
+            BinaryOp::Gt => Instruction::Lt { dst, a: b, b: a },
+            BinaryOp::Ge => Instruction::Le { dst, a: b, b: a },
+            BinaryOp::InstanceOf => Instruction::InstanceOf {
+                dst,
+                obj: a,
+                ctor: b,
+            },
+            BinaryOp::In => Instruction::In {
+                dst,
+                key: a,
+                obj: b,
+            },
+        });
+        if matches!(op, BinaryOp::NotEq | BinaryOp::StrictNotEq) {
+            self.not(dst, dst);
+        }
+    }
+
+    /// Compiles a binary expression. In a chain such as `a + b + c` each
+    /// left operand is another binary expression, so the chain is walked in
+    /// a loop that keeps the running value in `dst`, using two registers
+    /// however long it is.
+    fn binary_chain(&mut self, expr: &'a Expr, dst: u8) -> Result<(), RuntimeError> {
+        let mut operands = Vec::new();
+        let mut left = expr;
+        while let Expr::Binary {
+            op,
+            left: inner,
+            right,
+        } = left
+        {
+            operands.push((*op, &**right));
+            left = inner;
+        }
+        self.expr(left, dst)?;
+        let reg = self.alloc()?;
+        for (op, right) in operands.into_iter().rev() {
+            self.expr(right, reg)?;
+            self.binary(op, dst, dst, reg);
+        }
+        self.free(reg);
+        Ok(())
+    }
+
+    /// Compiles `expr`, leaving its value in `dst`.
+    fn expr(&mut self, expr: &'a Expr, dst: u8) -> Result<(), RuntimeError> {
+        match expr {
+            Expr::Number(n) => {
+                let const_idx = self.number_constant(*n);
+                self.emit(Instruction::LoadConst {
+                    reg: dst,
+                    const_idx,
+                });
+            }
+            Expr::String(s) => {
+                let const_idx = self.string_constant(s);
+                self.emit(Instruction::LoadConst {
+                    reg: dst,
+                    const_idx,
+                });
+            }
+            Expr::Bool(value) => {
+                self.emit(Instruction::LoadBool {
+                    reg: dst,
+                    value: *value,
+                });
+            }
+            Expr::Null => {
+                self.emit(Instruction::LoadNull { reg: dst });
+            }
+            Expr::Ident(name) if name == "undefined" => {
+                self.emit(Instruction::LoadUndefined { reg: dst });
+            }
+            Expr::Ident(name) => {
+                let var_idx = self.name(name);
+                self.emit(Instruction::GetScope { dst, var_idx });
+            }
+            Expr::Array(elements) => {
+                let elements: Vec<_> = elements.iter().map(Option::as_ref).collect();
+                self.array(&elements, dst)?;
+            }
+            Expr::Object(properties) => {
+                self.emit(Instruction::NewObject { reg: dst });
+                let key = self.alloc()?;
+                let value = self.alloc()?;
+                for (name, init) in properties {
+                    match name {
+                        PropertyKey::Named(name) => {
+                            let const_idx = self.name(name);
+                            self.emit(Instruction::LoadConst {
+                                reg: key,
+                                const_idx,
+                            });
+                        }
+                        PropertyKey::Computed(expr) => self.expr(expr, key)?,
+                    }
+                    self.expr(init, value)?;
+                    self.emit(Instruction::SetProp {
+                        obj: dst,
+                        key,
+                        value,
+                    });
+                }
+                self.free(key);
+            }
+            Expr::Function(function) => self.function_expression(function, dst),
+            Expr::Unary {
+                op: UnaryOp::Delete,
+                arg,
+            } => self.delete(arg, dst)?,
+            Expr::Unary { op, arg } => {
+                // `typeof` of an undeclared name is "undefined", not an error
+                if let (UnaryOp::TypeOf, Expr::Ident(name)) = (op, &**arg) {
+                    let var_idx = self.name(name);
+                    self.emit(Instruction::TypeOfScope { dst, var_idx });
+                    return Ok(());
+                }
+                let reg = self.alloc()?;
+                self.expr(arg, reg)?;
+                match op {
+                    UnaryOp::Neg => {
+                        self.emit(Instruction::Neg { dst, a: reg });
+                    }
+                    UnaryOp::Not => self.not(dst, reg),
+                    UnaryOp::TypeOf => {
+                        self.emit(Instruction::TypeOf { dst, src: reg });
+                    }
+                    UnaryOp::Void => {
+                        self.emit(Instruction::LoadUndefined { reg: dst });
+                    }
+                    UnaryOp::Delete => unreachable!("delete is compiled separately"),
+                }
+                self.free(reg);
+            }
+            Expr::Binary { .. } => self.binary_chain(expr, dst)?,
+            Expr::Logical { op, left, right } => {
+                self.expr(left, dst)?;
+                let to_end = match op {
+                    LogicalOp::And => self.jump_if_not(dst),
+                    LogicalOp::Or => self.jump_if(dst),
+                    LogicalOp::Nullish => {
+                        let test = self.alloc()?;
+                        self.emit(Instruction::LoadNull { reg: test });
+                        self.emit(Instruction::Eq {
+                            dst: test,
+                            a: dst,
+                            b: test,
+                        });
+                        let is_null = self.jump_if(test);
+                        self.emit(Instruction::LoadUndefined { reg: test });
+                        self.emit(Instruction::Eq {
+                            dst: test,
+                            a: dst,
+                            b: test,
+                        });
+                        let is_undefined = self.jump_if(test);
+                        self.free(test);
+                        let to_end = self.jump();
+                        self.patch(is_null, self.here());
+                        self.patch(is_undefined, self.here());
+                        to_end
+                    }
+                };
+                self.expr(right, dst)?;
+                self.patch(to_end, self.here());
+            }
+            Expr::Conditional {
+                test,
+                consequent,
+                alternate,
+            } => {
+                let reg = self.alloc()?;
+                self.expr(test, reg)?;
+                self.free(reg);
+                let to_else = self.jump_if_not(reg);
+                self.expr(consequent, dst)?;
+                let to_end = self.jump();
+                self.patch(to_else, self.here());
+                self.expr(alternate, dst)?;
+                self.patch(to_end, self.here());
+            }
+            Expr::Assign { op, target, value } => self.assign(*op, target, value, dst)?,
+            Expr::Update { op, prefix, target } => self.update(*op, *prefix, target, dst)?,
+            Expr::Call { callee, args } => {
+                // The callee, `this` for a method call and the arguments must
+                // sit in consecutive registers
+                let func_reg = self.alloc()?;
+                let method = match &**callee {
+                    Expr::Member { object, property } => {
+                        let this = self.alloc()?;
+                        self.expr(object, this)?;
+                        let const_idx = self.name(property);
+                        self.emit(Instruction::LoadConst {
+                            reg: func_reg,
+                            const_idx,
+                        });
+                        self.emit(Instruction::GetProp {
+                            dst: func_reg,
+                            obj: this,
+                            key: func_reg,
+                        });
+                        true
+                    }
+                    Expr::Index { object, index } => {
+                        let this = self.alloc()?;
+                        self.expr(object, this)?;
+                        self.expr(index, func_reg)?;
+                        self.emit(Instruction::GetElem {
+                            dst: func_reg,
+                            array: this,
+                            index: func_reg,
+                        });
+                        true
+                    }
+                    Expr::PrivateMember { object, name } => {
+                        let this = self.alloc()?;
+                        self.expr(object, this)?;
+                        let var_idx = self.name(name);
+                        self.emit(Instruction::GetScope {
+                            dst: func_reg,
+                            var_idx,
+                        });
+                        self.emit(Instruction::GetProp {
+                            dst: func_reg,
+                            obj: this,
+                            key: func_reg,
+                        });
+                        true
+                    }
+                    // `super.method()` calls the inherited method on `this`
+                    Expr::SuperProperty(key) => {
+                        let this = self.alloc()?;
+                        self.emit(Instruction::LoadThis { dst: this });
+                        let home = self.alloc()?;
+                        self.super_home(home)?;
+                        self.super_key(key, func_reg)?;
+                        self.emit(Instruction::GetSuper {
+                            dst: func_reg,
+                            home,
+                            key: func_reg,
+                        });
+                        self.free(home);
+                        true
+                    }
+                    _ => {
+                        self.expr(callee, func_reg)?;
+                        false
+                    }
+                };
+                if args.iter().any(|arg| matches!(arg, Expr::Spread(_))) {
+                    // The arguments go in an array after `this`
+                    if !method {
+                        let this = self.alloc()?;
+                        self.emit(Instruction::LoadUndefined { reg: this });
+                    }
+                    let array = self.alloc()?;
+                    let args: Vec<_> = args.iter().map(Some).collect();
+                    self.array(&args, array)?;
+                    self.emit(Instruction::CallSpread { func_reg });
+                    if func_reg != dst {
+                        self.emit(Instruction::Move { dst, src: func_reg });
+                    }
+                    self.free(func_reg);
+                    return Ok(());
+                }
+                for arg in args {
+                    let reg = self.alloc()?;
+                    self.expr(arg, reg)?;
+                }
+                let arg_count =
+                    u8::try_from(args.len()).map_err(|_| syntax_error("Too many arguments"))?;
+                self.emit(if method {
+                    Instruction::CallMethod {
+                        func_reg,
+                        arg_count,
+                    }
+                } else {
+                    Instruction::Call {
+                        func_reg,
+                        arg_count,
+                    }
+                });
+                if func_reg != dst {
+                    self.emit(Instruction::Move { dst, src: func_reg });
+                }
+                self.free(func_reg);
+            }
+            Expr::Member { object, property } => {
+                let obj = self.alloc()?;
+                let key = self.alloc()?;
+                self.expr(object, obj)?;
+                let const_idx = self.name(property);
+                self.emit(Instruction::LoadConst {
+                    reg: key,
+                    const_idx,
+                });
+                self.emit(Instruction::GetProp { dst, obj, key });
+                self.free(obj);
+            }
+            Expr::Index { object, index } => {
+                let array = self.alloc()?;
+                let idx = self.alloc()?;
+                self.expr(object, array)?;
+                self.expr(index, idx)?;
+                self.emit(Instruction::GetElem {
+                    dst,
+                    array,
+                    index: idx,
+                });
+                self.free(array);
+            }
+            Expr::PrivateMember { object, name } => {
+                let obj = self.alloc()?;
+                let key = self.alloc()?;
+                self.expr(object, obj)?;
+                let var_idx = self.name(name);
+                self.emit(Instruction::GetScope { dst: key, var_idx });
+                self.emit(Instruction::GetProp { dst, obj, key });
+                self.free(obj);
+            }
+            // Only the left operand of `in`, which looks the name up
+            Expr::PrivateName(name) => {
+                let var_idx = self.name(name);
+                self.emit(Instruction::GetScope { dst, var_idx });
+            }
+            Expr::This => {
+                self.emit(Instruction::LoadThis { dst });
+            }
+            Expr::New { callee, args } => {
+                let func_reg = self.alloc()?;
+                self.expr(callee, func_reg)?;
+                if args.iter().any(|arg| matches!(arg, Expr::Spread(_))) {
+                    let array = self.alloc()?;
+                    let args: Vec<_> = args.iter().map(Some).collect();
+                    self.array(&args, array)?;
+                    self.emit(Instruction::ConstructSpread { func_reg });
+                } else {
+                    for arg in args {
+                        let reg = self.alloc()?;
+                        self.expr(arg, reg)?;
+                    }
+                    let arg_count =
+                        u8::try_from(args.len()).map_err(|_| syntax_error("Too many arguments"))?;
+                    self.emit(Instruction::Construct {
+                        func_reg,
+                        arg_count,
+                    });
+                }
+                if func_reg != dst {
+                    self.emit(Instruction::Move { dst, src: func_reg });
+                }
+                self.free(func_reg);
+            }
+            Expr::SuperCall(args) => {
+                if !self.home.is_some_and(|home| home.derived_constructor) {
+                    return Err(syntax_error("'super' keyword unexpected here"));
+                }
+                let func_reg = self.alloc()?;
+                let var_idx = self.name(CLASS_VAR);
+                self.emit(Instruction::GetScope {
+                    dst: func_reg,
+                    var_idx,
+                });
+                let array = self.alloc()?;
+                let args: Vec<_> = args.iter().map(Some).collect();
+                self.array(&args, array)?;
+                self.emit(Instruction::SuperCall { func_reg });
+                if func_reg != dst {
+                    self.emit(Instruction::Move { dst, src: func_reg });
+                }
+                self.free(func_reg);
+            }
+            Expr::SuperProperty(key) => {
+                let home = self.alloc()?;
+                let reg = self.alloc()?;
+                self.super_home(home)?;
+                self.super_key(key, reg)?;
+                self.emit(Instruction::GetSuper {
+                    dst,
+                    home,
+                    key: reg,
+                });
+                self.free(home);
+            }
+            Expr::Class(class) => self.class(class, dst),
+            Expr::AssignPattern { target, value } => {
+                self.expr(value, dst)?;
+                // A default value in a `const` declaration may assign
+                let initializing = std::mem::take(&mut self.initializing);
+                self.bind(target, dst)?;
+                self.initializing = initializing;
+            }
+            Expr::Sequence(exprs) => {
+                for expr in exprs {
+                    self.expr(expr, dst)?;
+                }
+            }
+            Expr::Yield { argument, delegate } => {
+                match argument {
+                    Some(argument) => self.expr(argument, dst)?,
+                    None => {
+                        self.emit(Instruction::LoadUndefined { reg: dst });
+                    }
+                }
+                if self.is_async_generator {
+                    if *delegate {
+                        return self.async_yield_delegate(dst);
+                    }
+                    self.emit(Instruction::Await { dst, src: dst });
+                }
+                let at = if *delegate {
+                    let iter = self.alloc()?;
+                    self.emit(Instruction::GetIterator {
+                        dst: iter,
+                        src: dst,
+                    });
+                    let at = self.emit(Instruction::YieldDelegate {
+                        dst,
+                        iter,
+                        offset: 0,
+                    });
+                    self.free(iter);
+                    at
+                } else {
+                    self.emit(Instruction::Yield {
+                        dst,
+                        src: dst,
+                        offset: 0,
+                    })
+                };
+                // Resuming with `return` returns from where the generator
+                // is suspended, running any `finally` blocks on the way out
+                let skip = self.jump();
+                self.patch(at, self.here());
+                if self.is_async_generator {
+                    self.emit(Instruction::Await { dst, src: dst });
+                }
+                self.exit_tries(0)?;
+                self.emit(Instruction::Return {
+                    start_reg: dst,
+                    count: 1,
+                });
+                self.patch(skip, self.here());
+            }
+            Expr::Await(arg) => {
+                self.expr(arg, dst)?;
+                self.emit(Instruction::Await { dst, src: dst });
+            }
+            // The parser only allows spreads where they are compiled above
+            Expr::Spread(_) => return Err(syntax_error("Unexpected token '...'")),
+        }
+        Ok(())
+    }
+
+    /// Compiles the elements of an array literal, or spread call arguments,
+    /// into a new array in `dst`.
+    fn array(&mut self, elements: &[Option<&'a Expr>], dst: u8) -> Result<(), RuntimeError> {
+        self.emit(Instruction::NewArray { reg: dst });
+        let index = self.alloc()?;
+        let value = self.alloc()?;
+        if !elements.iter().any(|e| matches!(e, Some(Expr::Spread(_)))) {
+            for (i, element) in elements.iter().enumerate() {
+                // Elided elements are left as holes
+                let Some(element) = element else {
+                    continue;
+                };
+                self.load_number(index, i as f64);
+                self.expr(element, value)?;
+                self.emit(Instruction::SetElem {
+                    array: dst,
+                    index,
+                    value,
+                });
+            }
+            // Trailing holes still count towards the length
+            if let Some(None) = elements.last() {
+                self.load_number(value, elements.len() as f64);
+                self.set_length(dst, index, value);
+            }
+            self.free(index);
+            return Ok(());
+        }
+        // Past a spread the positions are only known at run time, so count
+        // them in a register
+        let one = self.alloc()?;
+        self.load_number(index, 0.0);
+        self.load_number(one, 1.0);
+        for element in elements {
+            match element {
+                Some(Expr::Spread(items)) => {
+                    self.expr(items, value)?;
+                    self.emit(Instruction::GetIterator {
+                        dst: value,
+                        src: value,
+                    });
+                    self.append_all(dst, index, one, value)?;
+                    continue;
+                }
+                Some(element) => {
+                    self.expr(element, value)?;
+                    self.emit(Instruction::SetElem {
+                        array: dst,
+                        index,
+                        value,
+                    });
+                }
+                None => {}
+            }
+            self.emit(Instruction::Add {
+                dst: index,
+                a: index,
+                b: one,
+            });
+        }
+        if let Some(None) = elements.last() {
+            self.set_length(dst, value, index);
+        }
+        self.free(index);
+        Ok(())
+    }
+
+    fn load_number(&mut self, reg: u8, n: f64) {
+        let const_idx = self.number_constant(n);
+        self.emit(Instruction::LoadConst { reg, const_idx });
+    }
+
+    /// Sets the length of the array in `array` to the number in `len`,
+    /// using `key` for the property name.
+    fn set_length(&mut self, array: u8, key: u8, len: u8) {
+        let const_idx = self.name("length");
+        self.emit(Instruction::LoadConst {
+            reg: key,
+            const_idx,
+        });
+        self.emit(Instruction::SetProp {
+            obj: array,
+            key,
+            value: len,
+        });
+    }
+
+    /// Compiles `delete arg`. Only properties can be deleted; variables
+    /// cannot, and deleting any other value just evaluates it.
+    fn delete(&mut self, arg: &'a Expr, dst: u8) -> Result<(), RuntimeError> {
+        match arg {
+            Expr::Member { .. } | Expr::Index { .. } => {
+                let (obj, key) = match self.target(arg)? {
+                    Target::Prop { obj, key } => (obj, key),
+                    Target::Elem { array, index } => (array, index),
+                    Target::Scope(_) | Target::Constant(_) | Target::Super { .. } => unreachable!(),
+                };
+                self.emit(Instruction::Delete { dst, obj, key });
+                self.free(obj);
+            }
+            Expr::Ident(_) => {
+                self.emit(Instruction::LoadBool {
+                    reg: dst,
+                    value: false,
+                });
+            }
+            _ => {
+                self.expr(arg, dst)?;
+                self.emit(Instruction::LoadBool {
+                    reg: dst,
+                    value: true,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the object and key of a member or index target into fresh
+    /// registers, so the target can be read and written without
+    /// re-evaluating them.
+    fn target(&mut self, target: &'a Expr) -> Result<Target, RuntimeError> {
+        match target {
+            Expr::Ident(name) if self.is_constant(name) => Ok(Target::Constant(self.name(name))),
+            Expr::Ident(name) => Ok(Target::Scope(self.name(name))),
+            Expr::Member { object, property } => {
+                let obj = self.alloc()?;
+                let key = self.alloc()?;
+                self.expr(object, obj)?;
+                let const_idx = self.name(property);
+                self.emit(Instruction::LoadConst {
+                    reg: key,
+                    const_idx,
+                });
+                Ok(Target::Prop { obj, key })
+            }
+            Expr::Index { object, index } => {
+                let array = self.alloc()?;
+                let idx = self.alloc()?;
+                self.expr(object, array)?;
+                self.expr(index, idx)?;
+                Ok(Target::Elem { array, index: idx })
+            }
+            Expr::PrivateMember { object, name } => {
+                let obj = self.alloc()?;
+                let key = self.alloc()?;
+                self.expr(object, obj)?;
+                let var_idx = self.name(name);
+                self.emit(Instruction::GetScope { dst: key, var_idx });
+                Ok(Target::Prop { obj, key })
+            }
+            Expr::SuperProperty(key) => {
+                let home = self.alloc()?;
+                let reg = self.alloc()?;
+                self.super_home(home)?;
+                self.super_key(key, reg)?;
+                Ok(Target::Super { home, key: reg })
+            }
+            _ => Err(syntax_error("Invalid assignment target")),
+        }
+    }
+
+    fn load_target(&mut self, target: &Target, dst: u8) {
+        self.emit(match *target {
+            Target::Scope(var_idx) | Target::Constant(var_idx) => {
+                Instruction::GetScope { dst, var_idx }
+            }
+            Target::Prop { obj, key } => Instruction::GetProp { dst, obj, key },
+            Target::Elem { array, index } => Instruction::GetElem { dst, array, index },
+            Target::Super { home, key } => Instruction::GetSuper { dst, home, key },
+        });
+    }
+
+    fn store_target(&mut self, target: &Target, src: u8) {
+        self.emit(match *target {
+            Target::Scope(var_idx) => Instruction::SetScope { var_idx, src },
+            Target::Constant(_) => Instruction::ThrowConstAssignment,
+            Target::Prop { obj, key } => Instruction::SetProp {
+                obj,
+                key,
+                value: src,
+            },
+            Target::Elem { array, index } => Instruction::SetElem {
+                array,
+                index,
+                value: src,
+            },
+            Target::Super { home, key } => Instruction::SetSuper {
+                home,
+                key,
+                value: src,
+            },
+        });
+    }
+
+    fn assign(
+        &mut self,
+        op: Option<BinaryOp>,
+        target: &'a Expr,
+        value: &'a Expr,
+        dst: u8,
+    ) -> Result<(), RuntimeError> {
+        let mark = self.next_reg as u8;
+        let target = self.target(target)?;
+        match op {
+            Some(op) => {
+                let current = self.alloc()?;
+                let rhs = self.alloc()?;
+                self.load_target(&target, current);
+                self.expr(value, rhs)?;
+                self.binary(op, dst, current, rhs);
+            }
+            None => self.expr(value, dst)?,
+        }
+        self.store_target(&target, dst);
+        self.free(mark);
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        op: UpdateOp,
+        prefix: bool,
+        target: &'a Expr,
+        dst: u8,
+    ) -> Result<(), RuntimeError> {
+        let mark = self.next_reg as u8;
+        let target = self.target(target)?;
+        let old = self.alloc()?;
+        let one = self.alloc()?;
+        let new = self.alloc()?;
+        self.load_target(&target, old);
+        let const_idx = self.number_constant(1.0);
+        self.emit(Instruction::LoadConst {
+            reg: one,
+            const_idx,
+        });
+        self.emit(match op {
+            UpdateOp::Increment => Instruction::Add {
+                dst: new,
+                a: old,
+                b: one,
+            },
+            UpdateOp::Decrement => Instruction::Sub {
+                dst: new,
+                a: old,
+                b: one,
+            },
+        });
+        self.store_target(&target, new);
+        self.emit(Instruction::Move {
+            dst,
+            src: if prefix { new } else { old },
+        });
+        self.free(mark);
+        Ok(())
+    }
+}
+
+enum Target {
+    Scope(u32),
+    /// A constant, which assigning to throws
+    Constant(u32),
+    Prop {
+        obj: u8,
+        key: u8,
+    },
+    Elem {
+        array: u8,
+        index: u8,
+    },
+    /// `super.name` or `super[key]`, with the object `super` looks up from
+    Super {
+        home: u8,
+        key: u8,
+    },
+}
+
+/// Whether a class member is held in a variable of the class body between
+/// being evaluated and being defined: a computed field name, or a private
+/// method or accessor, which each instance gets.
+fn needs_member_var(member: &ClassMember) -> bool {
+    match (&member.key, &member.kind) {
+        (ClassKey::Public(PropertyKey::Computed(_)), ClassMemberKind::Field(_)) => true,
+        (ClassKey::Private(_), ClassMemberKind::Field(_)) => false,
+        (ClassKey::Private(_), _) => !member.is_static,
+        _ => false,
+    }
+}
+
+/// The variable `needs_member_var` holds the `index`th member of a class
+/// in.
+fn member_var(index: usize) -> String {
+    format!("%member{}", index)
+}
+
+/// Defines a class member of `kind` on `obj`.
+fn define(kind: &ClassMemberKind, obj: u8, key: u8, value: u8) -> Instruction {
+    match kind {
+        ClassMemberKind::Getter(_) => Instruction::DefineGetter { obj, key, value },
+        ClassMemberKind::Setter(_) => Instruction::DefineSetter { obj, key, value },
+        _ => Instruction::DefineOwn { obj, key, value },
+    }
+}
+
+/// Collects the names declared with `var` or as functions in `body`,
+/// without descending into nested functions.
+fn collect_declarations<'a>(body: &'a [Stmt], vars: &mut Vec<&'a str>) {
+    for stmt in body {
+        match stmt {
+            Stmt::Var {
+                kind: VarKind::Var,
+                decls,
+            } => {
+                for decl in decls {
+                    collect_names(&decl.target, vars);
+                }
+            }
+            Stmt::Function(function) | Stmt::Export(ExportDecl::DefaultFunction(function)) => {
+                if let Some(name) = &function.name {
+                    if !vars.contains(&name.as_str()) {
+                        vars.push(name);
+                    }
+                }
+            }
+            Stmt::If {
+                consequent,
+                alternate,
+                ..
+            } => {
+                collect_declarations(std::slice::from_ref(consequent), vars);
+                if let Some(alternate) = alternate {
+                    collect_declarations(std::slice::from_ref(alternate), vars);
+                }
+            }
+            Stmt::While { body, .. } | Stmt::DoWhile { body, .. } => {
+                collect_declarations(std::slice::from_ref(body), vars);
+            }
+            Stmt::For { init, body, .. } => {
+                if let Some(init) = init {
+                    collect_declarations(std::slice::from_ref(init), vars);
+                }
+                collect_declarations(std::slice::from_ref(body), vars);
+            }
+            Stmt::ForOf {
+                kind, target, body, ..
+            }
+            | Stmt::ForIn {
+                kind, target, body, ..
+            } => {
+                if *kind == Some(VarKind::Var) {
+                    collect_names(target, vars);
+                }
+                collect_declarations(std::slice::from_ref(body), vars);
+            }
+            Stmt::Try {
+                block,
+                handler,
+                finalizer,
+            } => {
+                collect_declarations(block, vars);
+                if let Some(handler) = handler {
+                    collect_declarations(&handler.body, vars);
+                }
+                if let Some(finalizer) = finalizer {
+                    collect_declarations(finalizer, vars);
+                }
+            }
+            Stmt::Block(body) => collect_declarations(body, vars),
+            Stmt::Export(ExportDecl::Declaration(decl)) => {
+                collect_declarations(std::slice::from_ref(decl), vars);
+            }
+            // The value of `export default` is held in a variable its code
+            // cannot name
+            Stmt::Export(ExportDecl::DefaultExpr(_)) if !vars.contains(&"default") => {
+                vars.push("default");
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The functions `body` declares at its top level.
+fn function_declarations(body: &[Stmt]) -> Vec<&Function> {
+    body.iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Function(function) | Stmt::Export(ExportDecl::DefaultFunction(function)) => {
+                Some(function)
+            }
+            Stmt::Export(ExportDecl::Declaration(decl)) => match &**decl {
+                Stmt::Function(function) => Some(function),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The names `body` declares at its top level with `let`, `const` or
+/// `class`, which are scoped to it, each marked if it is a constant.
+fn lexical_declarations(body: &[Stmt]) -> Vec<(&str, bool)> {
+    let mut names = Vec::new();
+    for stmt in body {
+        let stmt = match stmt {
+            Stmt::Export(ExportDecl::Declaration(decl)) => decl,
+            stmt => stmt,
+        };
+        match stmt {
+            Stmt::Var { kind, decls } if *kind != VarKind::Var => {
+                let mut declared = Vec::new();
+                for decl in decls {
+                    collect_names(&decl.target, &mut declared);
+                }
+                let constant = *kind == VarKind::Const;
+                names.extend(declared.into_iter().map(|name| (name, constant)));
+            }
+            Stmt::Class(Class {
+                name: Some(name), ..
+            }) => names.push((name.as_str(), false)),
+            _ => {}
+        }
+    }
+    names
+}
+
+/// Collects the names a declaration binds.
+fn collect_names<'a>(target: &'a Pattern, vars: &mut Vec<&'a str>) {
+    match target {
+        Pattern::Ident(name) => {
+            if !vars.contains(&name.as_str()) {
+                vars.push(name);
+            }
+        }
+        Pattern::Expr(_) => {}
+        Pattern::Array { elements, rest } => {
+            for element in elements.iter().flatten() {
+                collect_names(&element.target, vars);
+            }
+            if let Some(rest) = rest {
+                collect_names(rest, vars);
+            }
+        }
+        Pattern::Object { properties, rest } => {
+            for (_, element) in properties {
+                collect_names(&element.target, vars);
+            }
+            if let Some(rest) = rest {
+                collect_names(rest, vars);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builtins::eval_async;
+    use crate::{RuntimeError, Value, VM};
+
+    fn eval(source: &str) -> Result<Value, RuntimeError> {
+        VM::default().eval(source)
+    }
+
+    #[test]
+    fn test_loops() {
+        let source = "
+            var total = 0;
+            for (var i = 0; i < 10; i++) {
+                if (i % 2) continue;
+                if (i > 6) break;
+                total += i;
+            }
+            var j = 0;
+            while (true) { if (++j >= 3) break; }
+            do { j--; } while (j > 0);
+            total + j;
+        ";
+        assert_eq!(eval(source), Ok(Value::Number(12.0)));
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(eval("1 + 2 * 3 ** 2"), Ok(Value::Number(19.0)));
+        assert_eq!(eval("3 > 2 && 2 >= 2"), Ok(Value::Boolean(true)));
+        assert_eq!(eval("null ?? 0 || 'x'"), Ok(Value::from("x")));
+        assert_eq!(eval("1 !== 1 ? 'a' : 'b'"), Ok(Value::from("b")));
+        assert_eq!(eval("typeof undefined"), Ok(Value::from("undefined")));
+        let source = "
+            var x, o = { valueOf: function () { return 2; } };
+            [undefined == null, x == null, 0 == null, '' == undefined, '1' == 1, 1 != '1',
+             true == 1, '0' == false, 0 / 0 == 0 / 0, o == 2, o == '2', o == o, o == {},
+             [1] == '1', null != undefined].join();
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "true,true,false,false,true,false,true,true,false,true,true,true,false,true,false"
+            ))
+        );
+        assert_eq!(
+            eval("var f = function () { return typeof missing === 'undefined'; }; [typeof missing, f(), typeof f, typeof Math].join()"),
+            Ok(Value::from("undefined,true,function,object"))
+        );
+        assert!(matches!(
+            eval("typeof missing.x"),
+            Err(RuntimeError::ReferenceError(_))
+        ));
+    }
+
+    #[test]
+    fn test_long_operator_chains() {
+        let sum = vec!["1"; 1000].join(" + ");
+        assert_eq!(eval(&sum), Ok(Value::Number(1000.0)));
+        let mixed = vec!["2 * x - 1"; 1000].join(" + ");
+        assert_eq!(
+            eval(&format!("var x = 1; {mixed} === 1000")),
+            Ok(Value::Boolean(true))
+        );
+        let concat = vec!["'ab'"; 1000].join(" + ");
+        assert_eq!(
+            eval(&format!("({concat}).length")),
+            Ok(Value::Number(2000.0))
+        );
+    }
+
+    #[test]
+    fn test_objects_and_arrays() {
+        let source = "
+            var point = { x: 1, y: 2, ['z']: 3 };
+            point.x += 10;
+            var list = [point.x, point.y];
+            list[2] = point.z;
+            list[0] + list[1] + list[2];
+        ";
+        assert_eq!(eval(source), Ok(Value::Number(16.0)));
+    }
+
+    #[test]
+    fn test_method_calls() {
+        let source = "
+            var math = { twice: function (x) { return x * 2; } };
+            var methods = ['twice'];
+            math.twice(3) + math[methods[0]](4);
+        ";
+        assert_eq!(eval(source), Ok(Value::Number(14.0)));
+    }
+
+    #[test]
+    fn test_hoisting_and_recursion() {
+        let source = "
+            var result = fib(10);
+            function fib(n) { return n < 2 ? n : fib(n - 1) + fib(n - 2); }
+            var arrow = (a, b) => a * b;
+            arrow(result, 2);
+        ";
+        assert_eq!(eval(source), Ok(Value::Number(110.0)));
+    }
+
+    #[test]
+    fn test_named_function_expressions() {
+        let source = "
+            var f = function fact(n) { return n <= 1 ? 1 : n * fact(n - 1); };
+            var o = { x: 2, m: function m() { return this.x + typeof m; } };
+            var g = function* count(n) { yield n; if (n) { yield* count(n - 1); } };
+            [f(5), typeof fact, o.m(), Array.from(g(2)).join('')].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("120,undefined,2function,210")));
+    }
+
+    #[test]
+    fn test_block_scoping() {
+        let source = "
+            var fns = [];
+            for (let i = 0; i < 3; i++) { fns.push(() => i); }
+            for (const x of ['a', 'b']) { fns.push(() => x); }
+            for (let k in { p: 1, q: 2 }) { fns.push(function () { return k; }); }
+            let q = 1;
+            { let q = 2; class C {} fns.push(() => q + typeof C); }
+            try { throw 'e'; } catch (q) { fns.push(() => q); }
+            fns.map(f => f()).join() + ':' + q + typeof C;
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from("0,1,2,a,b,p,q,2function,e:1undefined"))
+        );
+        let source = "
+            var out = [];
+            for (let i = 0; i < 5; i++) {
+                let j = i * 2;
+                if (i === 1) { continue; }
+                try { if (i === 3) { break; } } finally { out.push(j); }
+            }
+            for (const x of [1, 2]) { try { throw x; } catch (e) { let x = e * 10; out.push(x); } }
+            const o = { n: 1 };
+            o.n = 2;
+            out.push(o.n, typeof i, typeof j);
+            out.join();
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from("0,4,6,10,20,2,undefined,undefined"))
+        );
+        let source = "
+            var v = 'outer', out = [];
+            try { { let v = 'inner'; throw 0; } } catch (e) { out.push(v); }
+            { let x = 1; function g() { return x; } }
+            function* gen() { for (let i = 0; i < 2; i++) { yield () => i; } }
+            out.push(g(), Array.from(gen()).map(f => f()).join(''));
+            out.join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("outer,1,01")));
+    }
+
+    #[test]
+    fn test_const_assignment() {
+        for source in [
+            "const c = 1; c = 2;",
+            "const c = 1; c += 1;",
+            "const c = 1; c++;",
+            "const [a] = [1]; [a] = [2];",
+            "const c = 1; function f() { c = 2; } f();",
+            "for (const x of [1]) { x = 2; }",
+            "for (const i = 0; i < 1; i++) {}",
+        ] {
+            assert_eq!(
+                eval(&format!(
+                    "try {{ {source} }} catch (e) {{ e.name + ': ' + e.message; }}"
+                )),
+                Ok(Value::from("TypeError: Assignment to constant variable.")),
+                "{source}"
+            );
+        }
+        assert_eq!(
+            eval("const c = 1; function f(c) { c = 2; return c; } { let c = 3; c = 4; } f() + c"),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(
+            eval("let a = 1; let a = 2;"),
+            Err(RuntimeError::SyntaxError(
+                "Identifier 'a' has already been declared".to_string()
+            ))
+        );
+        assert!(matches!(
+            eval("const c;"),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        assert!(matches!(eval("break;"), Err(RuntimeError::SyntaxError(_))));
+    }
+
+    #[test]
+    fn test_finally() {
+        let source = "
+            var log = '';
+            function f(x) {
+                try {
+                    if (x) return 'early';
+                    log += 'a';
+                } finally {
+                    log += 'f';
+                }
+                return 'late';
+            }
+            var r = f(true) + f(false);
+            log += r;
+            for (var i = 0; i < 3; i++) {
+                try {
+                    if (i == 1) continue;
+                    if (i == 2) break;
+                    log += i;
+                } finally {
+                    log += '!';
+                }
+            }
+            try {
+                try { throw 'x'; } finally { log += 'g'; }
+            } catch (e) {
+                log += e;
+            }
+            try {
+                try { throw 'y'; } catch (e) { throw e + 'z'; } finally { log += 'h'; }
+            } catch (e) {
+                log += e;
+            }
+            log;
+        ";
+        assert_eq!(eval(source), Ok(Value::from("fafearlylate0!!!gxhyz")));
+    }
+
+    #[test]
+    fn test_for_of() {
+        let source = "
+            var log = [];
+            for (var x of [1, , 3]) log.push(x);
+            for (const c of 'a\u{1F600}') log.push(c.length);
+            for (let [k, v] of new Map([['a', 1], ['b', 2]])) { if (k == 'a') continue; log.push(k + v); }
+            var s = new Set([1, 2, 3]);
+            for (var n of s) { if (n == 1) s.delete(2); log.push(n); }
+            log.join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("1,,3,1,2,b2,1,3")));
+    }
+
+    #[test]
+    fn test_for_of_closes_iterators() {
+        let source = "
+            var log = '';
+            function counter() {
+                var i = 0;
+                var it = {
+                    next: function () { return { value: i++, done: i > 3 }; },
+                    return: function () { log += 'R'; return {}; },
+                };
+                it[Symbol.iterator] = function () { return it; };
+                return it;
+            }
+            for (var x of counter()) log += x;
+            for (var x of counter()) { if (x == 1) break; log += x; }
+            for (var x of counter()) { if (x == 0) continue; log += x; }
+            function f() { for (var x of counter()) return x; }
+            log += f();
+            try { for (var x of counter()) throw 'T'; } catch (e) { log += e; }
+            var [first] = counter();
+            log += first;
+            log;
+        ";
+        assert_eq!(eval(source), Ok(Value::from("0120R120RTR0")));
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let source = "
+            var [a, , b = 5, ...rest] = [1, 2, undefined, 4, 5];
+            var { x, y: { z = 'dz' } = {}, w = a } = { x: 'x' };
+            var p = 1, q = 2;
+            [p, q] = [q, p];
+            var o = {};
+            ({ x: o.x, y: o['y'] } = { x: 3, y: 4 });
+            [a, b, rest.join('+'), x, z, w, p, q, o.x + o.y].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("1,5,4+5,x,dz,1,2,1,7")));
+        let source = "
+            var s = Symbol('s');
+            var source = Object.setPrototypeOf({ a: 1, b: 2, 0: 'zero', c: 3 }, { inherited: 4 });
+            source[s] = 5;
+            var key = 'b';
+            var { a, [key]: b, 0: zero, ...rest } = source;
+            const { ...copy } = [7, 8];
+            [a, b, zero, Object.keys(rest).join('+'), rest[s], rest.inherited,
+             Object.keys(copy).join('+'), copy.length].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("1,2,zero,c,5,,0+1,")));
+        assert_eq!(
+            eval("var { ...rest } = null"),
+            Err(RuntimeError::TypeError(
+                "Cannot destructure 'null' as it is null.".to_string()
+            ))
+        );
+        assert!(matches!(
+            eval("var [a] = 1"),
+            Err(RuntimeError::TypeError(message)) if message == "1 is not iterable"
+        ));
+    }
+
+    #[test]
+    fn test_spread() {
+        let source = "
+            function sum(a, b, c) { return a + b + c; }
+            var list = [2, 3];
+            var copy = [1, ...list, ...'ab', , ];
+            var out = [10];
+            out.push(...list);
+            [sum(...list, 4), sum(1, ...[2, 3, 4]), out.join(), copy.length, copy.join()].join(';');
+        ";
+        assert_eq!(eval(source), Ok(Value::from("9;6;10,2,3;6;1,2,3,a,b,")));
+    }
+
+    #[test]
+    fn test_for_in() {
+        let source = "
+            var parent = { inherited: 1, shadowed: 2 };
+            var child = Object.setPrototypeOf({}, parent);
+            child.b = 1; child[2] = 0; child.shadowed = 3; child[1] = 0; child.a = 0;
+            var keys = [];
+            for (var k in child) keys.push(k);
+            var list = [];
+            for (const i in ['x', , 'z']) list.push(i);
+            for (var c in 'hi') list.push(c);
+            for (var n in null) list.push(n);
+            var o = { p: 1, q: 2, r: 3 };
+            for (var key in o) { if (key == 'p') { delete o.q; o.s = 4; } list.push(key); }
+            [keys.join(), list.join()].join(' ');
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from("1,2,b,shadowed,a,inherited 0,2,0,1,p,r"))
+        );
+        assert_eq!(
+            eval("var seen = ''; for (var k in []) seen += k; Object.prototype.extra = 1; for (var k in {}) seen += k; seen"),
+            Ok(Value::from("extra"))
+        );
+    }
+
+    #[test]
+    fn test_generators() {
+        let source = "
+            function* range(n) { for (var i = 0; i < n; i++) yield i; return 'end'; }
+            function* map(items, f) { for (const x of items) yield f(x); }
+            var squares = map(range(4), x => x * x);
+            var r = range(1);
+            var steps = [JSON.stringify(r.next()), JSON.stringify(r.next()), JSON.stringify(r.next())];
+            function* echo() { var got = yield 'first'; while (true) got = yield got * 2; }
+            var e = echo();
+            e.next('ignored');
+            [Array.from(squares).join(), steps.join(' '), e.next(5).value, e.next(21).value].join('; ');
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "0,1,4,9; {\"value\":0,\"done\":false} {\"value\":\"end\",\"done\":true} {\"done\":true}; 10; 42"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_generator_return_and_throw() {
+        let source = "
+            var log = [];
+            function* g() {
+                try {
+                    yield 1;
+                    yield 2;
+                } catch (e) {
+                    log.push('caught ' + e);
+                    yield 3;
+                } finally {
+                    log.push('cleanup');
+                }
+            }
+            var a = g();
+            a.next();
+            log.push(JSON.stringify(a.return('r')), JSON.stringify(a.next()));
+            var b = g();
+            b.next();
+            log.push(b.throw('x').value, b.next().done);
+            var c = g();
+            try { c.throw('early'); } catch (e) { log.push(e, c.next().done); }
+            for (var v of g()) break;
+            log.join();
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "cleanup,{\"value\":\"r\",\"done\":true},{\"done\":true},caught x,cleanup,3,true,early,true,cleanup"
+            ))
+        );
+        assert!(matches!(
+            eval("var g; function* f() { g.next(); } g = f(); g.next()"),
+            Err(RuntimeError::TypeError(message)) if message == "Generator is already running"
+        ));
+    }
+
+    #[test]
+    fn test_yield_delegation() {
+        let source = "
+            var log = [];
+            function* inner() {
+                try {
+                    var x = yield 'a';
+                    log.push('got ' + x);
+                    yield 'b';
+                } finally {
+                    log.push('inner done');
+                }
+                return 'result';
+            }
+            function* outer() {
+                var r = yield* inner();
+                log.push(r);
+                yield* [1, 2];
+                yield* 'hi';
+            }
+            var o = outer();
+            var values = [o.next().value, o.next('x').value];
+            for (var v of o) values.push(v);
+            var p = outer();
+            p.next();
+            log.push(JSON.stringify(p.return('stop')));
+            [values.join(), log.join()].join('; ');
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "a,b,1,2,h,i; got x,inner done,result,inner done,{\"value\":\"stop\",\"done\":true}"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_async_functions() {
+        let source = "
+            async function add(a, b) { log.push('start'); return await a + await b; }
+            const twice = async x => (await x) * 2;
+            var o = { async m() { return 'method'; } };
+            add(Promise.resolve(1), 2).then(v => log.push('sum ' + v));
+            twice(5).then(v => log.push('twice ' + v));
+            o.m().then(v => log.push(v));
+            log.push('sync');
+        ";
+        assert_eq!(eval_async(source), "start,sync,method,twice 10,sum 3");
+        let source = "
+            async function fails() { await null; throw 'bad'; }
+            async function recovers() {
+                try { await fails(); } catch (e) { log.push('caught ' + e); } finally { log.push('finally'); }
+                try { await Promise.reject('again'); } catch (e) { return e; }
+            }
+            recovers().then(v => log.push(v));
+            fails().catch(e => log.push('rejected ' + e));
+        ";
+        assert_eq!(eval_async(source), "caught bad,finally,rejected bad,again");
+    }
+
+    #[test]
+    fn test_for_await() {
+        let source = "
+            async function* count(n) { for (let i = 0; i < n; i++) yield i; }
+            async function run() {
+                for await (const x of count(3)) log.push(x);
+                for await (const x of [Promise.resolve('a'), 'b']) log.push(x);
+                var it = {
+                    next() { return Promise.resolve({ value: 'v', done: false }); },
+                    return() { log.push('closed'); return Promise.resolve({ done: true }); },
+                };
+                it[Symbol.asyncIterator] = () => it;
+                for await (const x of it) { log.push(x); break; }
+                try {
+                    for await (const x of it) throw 'thrown';
+                } catch (e) {
+                    log.push(e);
+                }
+            }
+            run();
+        ";
+        assert_eq!(eval_async(source), "0,1,2,a,b,v,closed,closed,thrown");
+        assert!(matches!(
+            eval("async function f() { for await (var x in []) ; }"),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+    }
+
+    #[test]
+    fn test_async_generators() {
+        let source = "
+            async function* g() {
+                try {
+                    var x = yield Promise.resolve(1);
+                    log.push('got ' + x);
+                    yield 2;
+                } finally {
+                    log.push('cleanup');
+                }
+                return 'end';
+            }
+            function show(p) { return p.then(r => log.push(JSON.stringify(r)), e => log.push('error ' + e)); }
+            var a = g();
+            show(a.next());
+            show(a.next('x'));
+            show(a.return(Promise.resolve('r')));
+            show(a.next());
+            var b = g();
+            show(b.next());
+            show(b.throw('t'));
+            var c = g();
+            show(c.return('early'));
+            async function* outer() { var r = yield* g(); log.push('inner ' + r); yield* ['s']; }
+            async function collect() {
+                var values = [];
+                for await (const v of outer()) values.push(v);
+                log.push(values.join());
+            }
+            collect();
+        ";
+        assert_eq!(
+            eval_async(source),
+            [
+                "got x",
+                "cleanup",
+                r#"{"value":1,"done":false}"#,
+                r#"{"value":1,"done":false}"#,
+                "error t",
+                r#"{"value":"early","done":true}"#,
+                r#"{"value":2,"done":false}"#,
+                "cleanup",
+                "got undefined",
+                r#"{"value":"r","done":true}"#,
+                r#"{"done":true}"#,
+                "cleanup",
+                "inner end",
+                "1,2,s",
+            ]
+            .join(",")
+        );
+    }
+
+    #[test]
+    fn test_new_and_this() {
+        let source = "
+            function Point(x, y) { this.x = x; this.y = y; }
+            Point.prototype.sum = function () { return this.x + this.y; };
+            var p = new Point(1, 2);
+            var q = new Point(...[3, 4]);
+            function Wrapper() { this.ignored = true; return { wrapped: true }; }
+            var o = { name: 'o', get() { return () => this.name; } };
+            var keys = [];
+            for (var k in p) keys.push(k);
+            [p.sum() + q.sum(), p instanceof Point, p.constructor === Point,
+             new Wrapper().wrapped, o.get()(), keys.join()].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("10,true,true,true,o,x,y,sum")));
+        assert_eq!(
+            eval("var f = () => 1; new f()"),
+            Err(RuntimeError::TypeError(
+                "function () { [bytecode] } is not a constructor".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_classes() {
+        let source = "
+            class Counter {
+                count = 0;
+                step;
+                constructor(step) { this.step = step; }
+                increment() { this.count += this.step; return this; }
+                get double() { return this.count * 2; }
+                set double(value) { this.count = value / 2; }
+                static zero = new Counter(0);
+                static of(step) { return new this(step); }
+            }
+            var c = Counter.of(5).increment().increment();
+            var before = c.double;
+            c.double = 4;
+            var keys = [];
+            for (var k in c) keys.push(k);
+            var Named = class Inner { who() { return Inner.name === undefined && typeof Inner; } };
+            [before, c.count, keys.join(), Counter.zero.step, typeof Counter,
+             new Named().who(), Object.getPrototypeOf(c) === Counter.prototype].join();
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from("20,2,count,step,0,function,function,true"))
+        );
+        assert_eq!(
+            eval("class A {} A()"),
+            Err(RuntimeError::TypeError(
+                "Class constructor A cannot be invoked without 'new'".to_string()
+            ))
+        );
+        assert_eq!(
+            eval("class A { get x() { return 1; } } var a = new A(); a.x = 2"),
+            Err(RuntimeError::TypeError(
+                "Cannot set property x of [object Object] which has only a getter".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_inheritance_and_super() {
+        let source = "
+            class Animal {
+                legs = 4;
+                constructor(name) { this.name = name; }
+                speak() { return this.name + ' makes a sound'; }
+                get description() { return this.name + ' has ' + this.legs + ' legs'; }
+                static create(name) { return new this(name); }
+            }
+            class Bird extends Animal {
+                legs = 2;
+                constructor(name) {
+                    super(name + ' the bird');
+                    this.wings = 2;
+                }
+                speak() { return super.speak() + ' and tweets'; }
+                get description() { return super.description + ' and ' + this.wings + ' wings'; }
+            }
+            class Parrot extends Bird {
+                speak() { const base = () => super.speak(); return base() + '!'; }
+            }
+            var p = Parrot.create('Polly');
+            [p.speak(), p.description, p instanceof Animal, p.constructor === Parrot,
+             Object.getPrototypeOf(Parrot) === Bird].join('; ');
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "Polly the bird makes a sound and tweets!; \
+                 Polly the bird has 2 legs and 2 wings; true; true; true"
+            ))
+        );
+        let source = "
+            class Tagged extends Map { tag = 't'; }
+            var t = new Tagged([[1, 2]]);
+            [t.get(1), t.tag, t instanceof Tagged, t instanceof Map].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("2,t,true,true")));
+        let source = "
+            class List extends Array { first() { return this[0]; } }
+            var l = new List();
+            l.push(1, 2);
+            [l instanceof List, l instanceof Array, Array.isArray(l), l.first(), l.length].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("true,true,true,1,2")));
+    }
+
+    #[test]
+    fn test_derived_constructors() {
+        let this_before_super = "Must call super constructor in derived class before accessing 'this' or returning from derived constructor";
+        for (source, error) in [
+            (
+                "class A {} class B extends A { constructor() { this.x = 1; super(); } } new B()",
+                RuntimeError::ReferenceError(this_before_super.to_string()),
+            ),
+            (
+                "class A {} class B extends A { constructor() {} } new B()",
+                RuntimeError::ReferenceError(this_before_super.to_string()),
+            ),
+            (
+                "class A {} class B extends A { constructor() { super(); super(); } } new B()",
+                RuntimeError::ReferenceError(
+                    "Super constructor may only be called once".to_string(),
+                ),
+            ),
+            (
+                "class A {} class B extends A { constructor() { super(); return 1; } } new B()",
+                RuntimeError::TypeError(
+                    "Derived constructors may only return object or undefined".to_string(),
+                ),
+            ),
+            (
+                "class B extends 1 {}",
+                RuntimeError::TypeError(
+                    "Class extends value 1 is not a constructor or null".to_string(),
+                ),
+            ),
+            (
+                "class A { m() { super(); } }",
+                RuntimeError::SyntaxError("'super' keyword unexpected here".to_string()),
+            ),
+            (
+                "function f() { return super.x; }",
+                RuntimeError::SyntaxError("'super' keyword unexpected here".to_string()),
+            ),
+        ] {
+            assert_eq!(eval(source), Err(error), "{source}");
+        }
+        let source = "
+            class A { constructor() { this.order = ['A']; } }
+            class B extends A {
+                field = this.order.push('field');
+                constructor() {
+                    const init = () => super();
+                    init();
+                    this.order.push('B');
+                }
+            }
+            class C extends A { constructor() { super(); return { replaced: true }; } }
+            class D extends null { constructor() { return Object.setPrototypeOf({}, D.prototype); } }
+            [new B().order.join(), new C().replaced, new D() instanceof D].join();
+        ";
+        assert_eq!(eval(source), Ok(Value::from("A,field,B,true,true")));
+    }
+
+    #[test]
+    fn test_private_members() {
+        let source = "
+            class Account {
+                #balance = 0;
+                static #count = 0;
+                constructor() { Account.#count++; }
+                deposit(amount) { this.#check(amount); this.#balance += amount; return this; }
+                #check(amount) { if (amount <= 0) throw 'invalid'; }
+                get #formatted() { return '$' + this.#balance; }
+                get summary() { return this.#formatted; }
+                static count() { return Account.#count; }
+                static isAccount(o) { return #balance in o; }
+                equals(other) { return this.#balance === other.#balance; }
+            }
+            var a = new Account().deposit(5).deposit(10);
+            var b = new Account().deposit(15);
+            var caught;
+            try { a.deposit(-1); } catch (e) { caught = e; }
+            [a.summary, Account.count(), Account.isAccount(a), Account.isAccount({}),
+             a.equals(b), caught, Object.keys(a).length].join();
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from("$15,2,true,false,true,invalid,0"))
+        );
+        for (source, message) in [
+            (
+                "class A { #x = 1; static read(o) { return o.#x; } } A.read({})",
+                "Cannot read private member #x from an object whose class did not declare it",
+            ),
+            (
+                "class A { #x; static write(o) { o.#x = 1; } } A.write({})",
+                "Cannot write private member #x to an object whose class did not declare it",
+            ),
+            (
+                "class A { constructor(o) { return o; } }
+                 class B extends A { #x = 1; }
+                 var o = {}; new B(o); new B(o)",
+                "Cannot initialize #x twice on the same object",
+            ),
+            (
+                "class A { get #x() { return 1; } m() { this.#x = 2; } } new A().m()",
+                "'#x' was defined without a setter",
+            ),
+        ] {
+            assert_eq!(
+                eval(source),
+                Err(RuntimeError::TypeError(message.to_string())),
+                "{source}"
+            );
+        }
+        // Each evaluation of a class makes new private names
+        let source = "
+            function make() { return class { #secret = 1; static read(o) { return o.#secret; } }; }
+            var First = make(), Second = make();
+            try { First.read(new Second()); } catch (e) { e.message; }
+        ";
+        assert_eq!(
+            eval(source),
+            Ok(Value::from(
+                "Cannot read private member #secret from an object whose class did not declare it"
+            ))
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use std::fmt;
+
+use crate::Value;
+
+/// An error raised while executing a program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// An operation was applied to a value of the wrong type
+    TypeError(String),
+    /// A numeric argument was outside the allowed range
+    RangeError(String),
+    /// A name could not be resolved
+    ReferenceError(String),
+    /// Source passed to `eval` could not be parsed or compiled
+    SyntaxError(String),
+    /// An arbitrary value thrown by a native function
+    Thrown(Value),
+    /// The script was stopped through an `InterruptHandle`
+    Interrupted,
+    /// The script used up the instruction budget set with `VM::set_fuel`
+    OutOfFuel,
+    /// The heap is full: live values plus a new allocation would exceed the
+    /// limit set with `VM::set_heap_limit`
+    OutOfMemory,
+    /// The program itself is malformed, e.g. a jump out of bounds
+    Internal(String),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::TypeError(msg) => write!(f, "TypeError: {}", msg),
+            RuntimeError::RangeError(msg) => write!(f, "RangeError: {}", msg),
+            RuntimeError::ReferenceError(msg) => write!(f, "ReferenceError: {}", msg),
+            RuntimeError::SyntaxError(msg) => write!(f, "SyntaxError: {}", msg),
+            RuntimeError::Thrown(value) => write!(f, "Uncaught {}", value.to_js_string()),
+            RuntimeError::Interrupted => write!(f, "Interrupted: script execution was interrupted"),
+            RuntimeError::OutOfFuel => write!(f, "OutOfFuel: instruction budget exhausted"),
+            RuntimeError::OutOfMemory => write!(f, "OutOfMemory: heap limit exceeded"),
+            RuntimeError::Internal(msg) => write!(f, "InternalError: {}", msg),
+        }
+    }
+}
+
+impl RuntimeError {
+    /// Whether scripts can handle this error with `catch`. Errors that stop
+    /// a script for the host's sake, or that mean the VM itself is broken,
+    /// skip every handler and `finally` block.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeError::Interrupted
+                | RuntimeError::OutOfFuel
+                | RuntimeError::OutOfMemory
+                | RuntimeError::Internal(_)
+        )
+    }
+}
+
+impl std::error::Error for RuntimeError {}
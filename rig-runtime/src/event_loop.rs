@@ -0,0 +1,387 @@
+//! An event loop for hosts that give scripts timers.
+//!
+//! `EventLoop::install` defines `setTimeout`, `setInterval`, `clearTimeout`,
+//! `clearInterval` and `queueMicrotask` on a VM. The loop then runs the
+//! callback of each timer as it falls due, one macrotask at a time, and
+//! runs the microtasks a macrotask queued before moving on to the next.
+//!
+//! Time comes from a `Clock`. `SystemClock` follows real time, while a
+//! `VirtualClock` only moves when told to or when the loop waits for a
+//! timer, which it then reaches at once, so tests run instantly and the
+//! same way every time.
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crate::{NativeFunction, RuntimeError, Value, VM};
+
+/// The longest delay a timer accepts, in milliseconds. Longer ones count
+/// as a millisecond, as in Node.
+const MAX_DELAY: f64 = 2147483647.0;
+
+/// A source of time for an `EventLoop`.
+pub trait Clock {
+    /// The time elapsed since some fixed point. It never goes backwards.
+    fn now(&self) -> Duration;
+
+    /// Returns once `now` has reached `deadline`.
+    fn sleep_until(&self, deadline: Duration);
+}
+
+/// A clock that follows real time, sleeping the thread to wait.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    /// A clock that starts at zero now.
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        if let Some(wait) = deadline.checked_sub(self.now()) {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// A clock that starts at zero and only moves forward when advanced, or
+/// when asked to sleep, which jumps straight to the deadline. Clones share
+/// the same time, so a test can keep one to read and advance the clock
+/// the loop uses.
+#[derive(Debug, Clone, Default)]
+pub struct VirtualClock(Rc<Cell<Duration>>);
+
+impl VirtualClock {
+    pub fn new() -> Self {
+        VirtualClock::default()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.0.set(self.0.get() + duration);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Duration {
+        self.0.get()
+    }
+
+    fn sleep_until(&self, deadline: Duration) {
+        self.0.set(self.0.get().max(deadline));
+    }
+}
+
+/// A callback scheduled by `setTimeout` or `setInterval`.
+struct Timer {
+    id: u32,
+    callback: Value,
+    args: Vec<Value>,
+    /// The period of an interval, which is scheduled again each time it
+    /// runs
+    interval: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Timers {
+    /// Pending timers by deadline, then by the order they were scheduled in
+    queue: BTreeMap<(Duration, u64), Timer>,
+    last_id: u32,
+    last_seq: u64,
+}
+
+impl Timers {
+    fn schedule(&mut self, deadline: Duration, timer: Timer) {
+        self.last_seq += 1;
+        self.queue.insert((deadline, self.last_seq), timer);
+    }
+
+    fn cancel(&mut self, id: u32) {
+        let key = self.queue.iter().find(|(_, timer)| timer.id == id);
+        if let Some(&key) = key.map(|(key, _)| key) {
+            self.queue.remove(&key);
+        }
+    }
+}
+
+/// Runs the timers scripts schedule on a VM, and the microtasks they queue.
+pub struct EventLoop {
+    clock: Rc<dyn Clock>,
+    timers: Rc<RefCell<Timers>>,
+}
+
+impl EventLoop {
+    /// An event loop with no timers, telling time by `clock`.
+    pub fn new(clock: impl Clock + 'static) -> Self {
+        EventLoop {
+            clock: Rc::new(clock),
+            timers: Rc::default(),
+        }
+    }
+
+    /// Defines the global timer functions and `queueMicrotask` on `vm`,
+    /// scheduling timers on this loop.
+    pub fn install(&self, vm: &mut VM) {
+        for (name, repeat) in [("setTimeout", false), ("setInterval", true)] {
+            let clock = self.clock.clone();
+            let timers = self.timers.clone();
+            let function = NativeFunction::new(name, move |_vm, _this, args| {
+                let callback = args.first().cloned().unwrap_or(Value::Undefined);
+                if !callback.is_function() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "{} is not a function",
+                        callback.to_js_string()
+                    )));
+                }
+                let delay = delay(args.get(1).unwrap_or(&Value::Undefined));
+                let mut timers = timers.borrow_mut();
+                timers.last_id += 1;
+                let id = timers.last_id;
+                let timer = Timer {
+                    id,
+                    callback,
+                    args: args.get(2..).unwrap_or_default().to_vec(),
+                    interval: repeat.then_some(delay),
+                };
+                timers.schedule(clock.now() + delay, timer);
+                Ok(Value::Number(id.into()))
+            });
+            vm.set_global(name, function);
+        }
+        for name in ["clearTimeout", "clearInterval"] {
+            let timers = self.timers.clone();
+            let function = NativeFunction::new(name, move |_vm, _this, args| {
+                // Ids are small positive integers, so anything else matches
+                // no timer
+                let id = args.first().map_or(0.0, Value::to_number);
+                if id.fract() == 0.0 && id >= 1.0 && id <= u32::MAX.into() {
+                    timers.borrow_mut().cancel(id as u32);
+                }
+                Ok(Value::Undefined)
+            });
+            vm.set_global(name, function);
+        }
+        let function = NativeFunction::new("queueMicrotask", |vm, _this, args| {
+            let callback = args.first().cloned().unwrap_or(Value::Undefined);
+            vm.queue_microtask(callback)?;
+            Ok(Value::Undefined)
+        });
+        vm.set_global("queueMicrotask", function);
+    }
+
+    /// Runs microtasks and timers until there are none left, waiting on
+    /// the clock for each timer to fall due. An interval keeps the loop
+    /// going until it is cleared.
+    ///
+    /// An error thrown by a callback stops the loop and is returned; the
+    /// loop can be run again to carry on with the remaining work.
+    pub fn run(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        loop {
+            vm.run_microtasks()?;
+            let Some(deadline) = self.next_deadline() else {
+                return Ok(());
+            };
+            self.clock.sleep_until(deadline);
+            self.run_timer(vm)?;
+        }
+    }
+
+    /// Runs microtasks and the timers that are due by now, without
+    /// waiting. Hosts with a loop of their own call this whenever they
+    /// reach `next_deadline`. Errors are returned as by `run`.
+    pub fn run_due(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        let now = self.clock.now();
+        vm.run_microtasks()?;
+        while self.next_deadline().is_some_and(|deadline| deadline <= now) {
+            self.run_timer(vm)?;
+            vm.run_microtasks()?;
+        }
+        Ok(())
+    }
+
+    /// When the next timer falls due, by the loop's clock, or `None` if
+    /// there are no timers.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        let timers = self.timers.borrow();
+        timers.queue.keys().next().map(|&(deadline, _)| deadline)
+    }
+
+    /// Whether the loop has nothing left to do: no timers are pending and
+    /// `vm` has no microtasks queued.
+    pub fn is_idle(&self, vm: &VM) -> bool {
+        self.timers.borrow().queue.is_empty() && !vm.has_pending_microtasks()
+    }
+
+    /// Runs the callback of the first timer, scheduling it again first if
+    /// it is an interval so that the callback can clear it.
+    fn run_timer(&mut self, vm: &mut VM) -> Result<(), RuntimeError> {
+        let (callback, args) = {
+            let mut timers = self.timers.borrow_mut();
+            let Some((_, timer)) = timers.queue.pop_first() else {
+                return Ok(());
+            };
+            let call = (timer.callback.clone(), timer.args.clone());
+            if let Some(interval) = timer.interval {
+                timers.schedule(self.clock.now() + interval, timer);
+            }
+            call
+        };
+        vm.call_function(&callback, Value::Undefined, &args)
+            .map(drop)
+    }
+}
+
+/// The delay a timer function was given, in milliseconds. As in Node, a
+/// delay that is not at least a millisecond, or is too long, counts as one
+/// millisecond, and fractions are dropped.
+fn delay(value: &Value) -> Duration {
+    let ms = value.to_number();
+    let ms = if (1.0..=MAX_DELAY).contains(&ms) {
+        ms.trunc()
+    } else {
+        1.0
+    };
+    Duration::from_millis(ms as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A VM with timers on a virtual clock, and an array `log` to record
+    /// what runs.
+    fn setup() -> (VM, EventLoop, VirtualClock) {
+        let clock = VirtualClock::new();
+        let event_loop = EventLoop::new(clock.clone());
+        let mut vm = VM::default();
+        event_loop.install(&mut vm);
+        vm.eval("var log = []").unwrap();
+        (vm, event_loop, clock)
+    }
+
+    fn log(vm: &mut VM) -> String {
+        vm.eval("log.join()")
+            .unwrap()
+            .to_js_string()
+            .to_std_string_lossy()
+    }
+
+    #[test]
+    fn test_ordering() {
+        let (mut vm, mut event_loop, clock) = setup();
+        vm.eval(
+            "
+            setTimeout(() => log.push('b'), 20);
+            setTimeout((x, y) => {
+                log.push(x + y);
+                Promise.resolve().then(() => log.push('micro'));
+                queueMicrotask(() => log.push('queued'));
+            }, 10, 'a', '!');
+            setTimeout(() => log.push('c'), 20);
+            setTimeout(() => log.push('zero'));
+            Promise.resolve().then(() => log.push('first'));
+            log.push('sync');
+            ",
+        )
+        .unwrap();
+        assert!(!event_loop.is_idle(&vm));
+        event_loop.run(&mut vm).unwrap();
+        assert!(event_loop.is_idle(&vm));
+        assert_eq!(log(&mut vm), "sync,first,zero,a!,micro,queued,b,c");
+        assert_eq!(clock.now(), Duration::from_millis(20));
+    }
+
+    #[test]
+    fn test_intervals() {
+        let (mut vm, mut event_loop, clock) = setup();
+        vm.eval(
+            "
+            var ticks = 0;
+            var id = setInterval(() => {
+                log.push('tick ' + ++ticks);
+                if (ticks == 3) clearInterval(id);
+            }, 100);
+            var cancelled = setTimeout(() => log.push('never'), 50);
+            clearTimeout(cancelled);
+            clearTimeout('nonsense');
+            ",
+        )
+        .unwrap();
+        assert_eq!(event_loop.next_deadline(), Some(Duration::from_millis(100)));
+        event_loop.run(&mut vm).unwrap();
+        assert_eq!(log(&mut vm), "tick 1,tick 2,tick 3");
+        assert_eq!(clock.now(), Duration::from_millis(300));
+        assert_eq!(event_loop.next_deadline(), None);
+    }
+
+    #[test]
+    fn test_run_due() {
+        let (mut vm, mut event_loop, clock) = setup();
+        vm.eval("setTimeout(() => log.push(1), 5); setTimeout(() => log.push(2), 15)")
+            .unwrap();
+        event_loop.run_due(&mut vm).unwrap();
+        assert_eq!(log(&mut vm), "");
+        clock.advance(Duration::from_millis(10));
+        event_loop.run_due(&mut vm).unwrap();
+        assert_eq!(log(&mut vm), "1");
+        assert_eq!(event_loop.next_deadline(), Some(Duration::from_millis(15)));
+        assert!(!event_loop.is_idle(&vm));
+    }
+
+    #[test]
+    fn test_errors() {
+        let (mut vm, mut event_loop, _clock) = setup();
+        assert_eq!(
+            vm.eval("setTimeout('code')"),
+            Err(RuntimeError::TypeError(
+                "code is not a function".to_string()
+            ))
+        );
+        assert_eq!(
+            vm.eval("queueMicrotask()"),
+            Err(RuntimeError::TypeError(
+                "undefined is not a function".to_string()
+            ))
+        );
+        vm.eval("setTimeout(() => { throw 'boom'; }); setTimeout(() => log.push('after'))")
+            .unwrap();
+        assert_eq!(
+            event_loop.run(&mut vm),
+            Err(RuntimeError::Thrown(Value::from("boom")))
+        );
+        event_loop.run(&mut vm).unwrap();
+        assert_eq!(log(&mut vm), "after");
+    }
+
+    #[test]
+    fn test_system_clock() {
+        let clock = SystemClock::new();
+        let mut event_loop = EventLoop::new(clock);
+        let mut vm = VM::default();
+        event_loop.install(&mut vm);
+        vm.eval("var done = false; setTimeout(() => { done = true; }, 2)")
+            .unwrap();
+        event_loop.run(&mut vm).unwrap();
+        assert_eq!(vm.get_global("done"), Some(Value::Boolean(true)));
+        assert!(clock.now() >= Duration::from_millis(2));
+    }
+}
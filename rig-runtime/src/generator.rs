@@ -0,0 +1,292 @@
+//! Generators.
+//!
+//! Calling a generator function runs it as far as `CreateGenerator`, which
+//! moves its frame out of the VM into a generator object: the registers,
+//! scopes and program counter, and the handlers of any `try` it is inside.
+//! Each resumption pushes the frame back onto the call stack and runs it
+//! until a `Yield` moves it out again or the function returns.
+//!
+//! Async functions and async generators suspend at `Await` the same way,
+//! and are resumed by promise reactions rather than by method calls.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use rig_bytecode::Instruction;
+
+use crate::heap::{FRAME_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::{Frame, Handler, JsObject, RuntimeError, Scope, Value, VM};
+
+/// Where a generator is in its run.
+#[derive(Debug, Clone)]
+pub(crate) enum GeneratorState {
+    /// Waiting to start, or to be resumed after a `yield`
+    Suspended(Box<SuspendedFrame>),
+    Running,
+    Done,
+}
+
+/// The frame of a generator that is not running.
+#[derive(Debug, Clone)]
+pub(crate) struct SuspendedFrame {
+    /// The instruction that suspended the frame
+    pc: usize,
+    registers: Vec<Value>,
+    scopes: Vec<Scope>,
+    /// Handlers the frame installed, whose depth is set again on resuming
+    handlers: Vec<Handler>,
+    strict_mode: bool,
+}
+
+impl SuspendedFrame {
+    pub fn pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn registers(&self) -> &[Value] {
+        &self.registers
+    }
+
+    pub fn scopes(&self) -> &[Scope] {
+        &self.scopes
+    }
+}
+
+/// How a generator is resumed, with the value passed in.
+#[derive(Debug, Clone)]
+pub(crate) enum Resume {
+    Next(Value),
+    Throw(Value),
+    Return(Value),
+}
+
+/// Where a resumed frame stopped.
+pub(crate) enum Resumed {
+    /// Suspended at a `yield`, with the result to hand out
+    Yielded(Value),
+    /// Suspended at an `await`, with the value awaited
+    Awaited(Value),
+    /// Finished, returning a value
+    Returned(Value),
+}
+
+/// The outcome of passing a resumption on to the iterator `yield*` is
+/// delegating to.
+pub(crate) enum Delegated {
+    /// The iterator produced a result object, to be handed out as it is
+    Yield(Value),
+    /// The iterator finished with a value
+    Done(Value),
+    /// The iterator finished because the generator is returning this value
+    Return(Value),
+}
+
+impl VM {
+    /// Executes `CreateGenerator`, returning the new generator from the
+    /// running function.
+    pub(crate) fn create_generator(&mut self) -> Result<Option<Value>, RuntimeError> {
+        self.charge(OBJECT_SIZE + FRAME_SIZE)?;
+        let frame = self.suspend();
+        let generator = JsObject::with_kind(
+            Value::Object(self.intrinsics.generator_prototype.clone()),
+            ObjectKind::Generator(GeneratorState::Suspended(Box::new(frame))),
+        );
+        Ok(self.pop_frame(Value::Object(Rc::new(RefCell::new(generator)))))
+    }
+
+    /// Suspends the running generator, handing `result` to the `next`,
+    /// `throw` or `return` call that resumed it.
+    pub(crate) fn yield_result(&mut self, result: Value) -> Option<Value> {
+        let frame = self.suspend();
+        self.suspended = Some(frame);
+        self.pop_frame(result)
+    }
+
+    /// Moves the current frame out of the VM, leaving the caller's frame to
+    /// be restored by `pop_frame`.
+    pub(crate) fn suspend(&mut self) -> SuspendedFrame {
+        // Nothing says when the frame is done with its code
+        if let Some(script) = self.script_at(self.pc) {
+            script.functions = None;
+        }
+        let depth = self.call_stack.len();
+        let keep = self.handlers.partition_point(|h| h.depth < depth);
+        SuspendedFrame {
+            pc: self.pc,
+            registers: std::mem::take(&mut self.registers),
+            scopes: std::mem::take(&mut self.scopes),
+            handlers: self.handlers.split_off(keep),
+            strict_mode: self.strict_mode,
+        }
+    }
+
+    /// Runs `generator` from where it was suspended until it yields or
+    /// returns, and makes the `{ value, done }` result. `method` names the
+    /// generator method resuming it, for errors.
+    pub(crate) fn resume_generator(
+        &mut self,
+        generator: &Value,
+        resume: Resume,
+        method: &str,
+    ) -> Result<Value, RuntimeError> {
+        let obj = match generator {
+            Value::Object(obj) if matches!(obj.borrow().kind(), ObjectKind::Generator(_)) => {
+                obj.clone()
+            }
+            _ => {
+                return Err(RuntimeError::TypeError(format!(
+                    "{method} method called on incompatible receiver {}",
+                    generator.to_js_string()
+                )))
+            }
+        };
+        match self.resume_frame(&obj, resume)? {
+            Resumed::Yielded(result) => Ok(result),
+            Resumed::Returned(value) => self.iterator_result(value, true),
+            Resumed::Awaited(_) => Err(RuntimeError::Internal(
+                "Generator suspended at an unexpected instruction".to_string(),
+            )),
+        }
+    }
+
+    /// Runs the frame suspended in `obj`, a generator, async generator or
+    /// async function, until it suspends again or returns.
+    pub(crate) fn resume_frame(
+        &mut self,
+        obj: &Rc<RefCell<JsObject>>,
+        resume: Resume,
+    ) -> Result<Resumed, RuntimeError> {
+        self.check_stack(true)?;
+        let state = match generator_state(obj.borrow_mut().kind_mut()) {
+            Some(state) => std::mem::replace(state, GeneratorState::Running),
+            None => unreachable!("resuming an object without a frame"),
+        };
+        let set_state = |state| {
+            if let Some(current) = generator_state(obj.borrow_mut().kind_mut()) {
+                *current = state;
+            }
+        };
+        let frame = match state {
+            GeneratorState::Suspended(frame) => frame,
+            GeneratorState::Running => {
+                set_state(GeneratorState::Running);
+                return Err(RuntimeError::TypeError(
+                    "Generator is already running".to_string(),
+                ));
+            }
+            GeneratorState::Done => {
+                set_state(GeneratorState::Done);
+                return self.finished(resume);
+            }
+        };
+        let instruction = self.program.get(frame.pc).cloned();
+        let at_start = matches!(
+            instruction,
+            Some(
+                Instruction::CreateGenerator
+                    | Instruction::CreateAsync
+                    | Instruction::CreateAsyncGenerator
+            )
+        );
+        // A generator that has not started finishes without running
+        if at_start && !matches!(resume, Resume::Next(_)) {
+            set_state(GeneratorState::Done);
+            return self.finished(resume);
+        }
+
+        let depth = self.call_stack.len();
+        self.call_stack.push(Frame {
+            return_pc: self.pc,
+            registers: std::mem::replace(&mut self.registers, frame.registers),
+            scopes: std::mem::replace(&mut self.scopes, frame.scopes),
+            result_reg: 0,
+            host_call: true,
+            constructing: None,
+            strict_mode: std::mem::replace(&mut self.strict_mode, frame.strict_mode),
+        });
+        self.handlers
+            .extend(frame.handlers.into_iter().map(|handler| Handler {
+                depth: depth + 1,
+                ..handler
+            }));
+        self.pc = frame.pc;
+        let mut thrown = None;
+        match (instruction, resume) {
+            _ if at_start => self.pc += 1,
+            (Some(Instruction::Yield { dst, offset, .. }), resume) => match resume {
+                Resume::Next(value) => {
+                    self.registers[dst as usize] = value;
+                    self.pc += 1;
+                }
+                Resume::Return(value) => {
+                    self.registers[dst as usize] = value;
+                    self.pc = (self.pc as i32 + offset + 1) as usize;
+                }
+                Resume::Throw(value) => thrown = Some(value),
+            },
+            // Runs again to pass the resumption on
+            (Some(Instruction::YieldDelegate { .. }), resume) => self.resume = Some(resume),
+            (Some(Instruction::Await { dst, .. }), Resume::Next(value)) => {
+                self.registers[dst as usize] = value;
+                self.pc += 1;
+            }
+            (Some(Instruction::Await { .. }), Resume::Throw(value)) => thrown = Some(value),
+            _ => {
+                self.unwind(depth);
+                set_state(GeneratorState::Done);
+                return Err(RuntimeError::Internal(
+                    "Generator suspended at an unexpected instruction".to_string(),
+                ));
+            }
+        }
+        let result = match thrown {
+            // Thrown from the `yield`, where the generator's handlers can
+            // catch it
+            Some(value) => self
+                .catch(RuntimeError::Thrown(value), depth + 1)
+                .and_then(|()| self.execute_until_return(false)),
+            None => self.execute_until_return(false),
+        };
+        if result.is_err() {
+            self.resume = None;
+            self.unwind(depth);
+        }
+        match self.suspended.take() {
+            Some(frame) => {
+                let awaiting =
+                    matches!(self.program.get(frame.pc), Some(Instruction::Await { .. }));
+                set_state(GeneratorState::Suspended(Box::new(frame)));
+                let result = result?;
+                Ok(if awaiting {
+                    Resumed::Awaited(result)
+                } else {
+                    Resumed::Yielded(result)
+                })
+            }
+            None => {
+                set_state(GeneratorState::Done);
+                Ok(Resumed::Returned(result?))
+            }
+        }
+    }
+
+    /// The outcome of resuming a generator that has finished.
+    fn finished(&mut self, resume: Resume) -> Result<Resumed, RuntimeError> {
+        match resume {
+            Resume::Next(_) => Ok(Resumed::Returned(Value::Undefined)),
+            Resume::Return(value) => Ok(Resumed::Returned(value)),
+            Resume::Throw(value) => Err(RuntimeError::Thrown(value)),
+        }
+    }
+}
+
+/// The state of the frame held by a generator, async generator or the
+/// hidden generator an async function runs as.
+pub(crate) fn generator_state(kind: &mut ObjectKind) -> Option<&mut GeneratorState> {
+    match kind {
+        ObjectKind::Generator(state) => Some(state),
+        ObjectKind::AsyncGenerator(generator) => Some(generator.state_mut()),
+        _ => None,
+    }
+}
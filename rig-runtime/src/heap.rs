@@ -0,0 +1,232 @@
+//! Accounting for the memory held by script values.
+//!
+//! Allocations are charged against a running total as the VM makes them.
+//! Memory is released by reference counting without the VM noticing, so when
+//! the total would pass the limit the live heap is measured by walking
+//! everything reachable from the VM, and the total is reset to that.
+
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use crate::generator::GeneratorState;
+use crate::object::ObjectKind;
+use crate::{Atom, Closure, JsString, Scope, Value};
+
+/// Bookkeeping overhead of an `Rc<RefCell<_>>` allocation.
+const RC_OVERHEAD: usize = 3 * size_of::<usize>();
+
+/// An empty object or scope.
+pub(crate) const OBJECT_SIZE: usize = RC_OVERHEAD + 48;
+
+/// One property of an object or binding of a scope, including hash table
+/// overhead.
+pub(crate) const ENTRY_SIZE: usize = size_of::<(Atom, Value)>() + 8;
+
+/// An empty array.
+pub(crate) const ARRAY_SIZE: usize = RC_OVERHEAD + size_of::<Vec<Value>>();
+
+/// One array element.
+pub(crate) const ELEMENT_SIZE: usize = size_of::<Value>();
+
+/// A symbol without its description.
+pub(crate) const SYMBOL_SIZE: usize = RC_OVERHEAD + 2 * size_of::<JsString>();
+
+/// A function value without the scopes it captures.
+pub(crate) const CLOSURE_SIZE: usize = 2 * size_of::<usize>() + size_of::<Closure>();
+
+/// The register file of a call frame.
+pub(crate) const FRAME_SIZE: usize = 256 * size_of::<Value>();
+
+/// A string of `len` code units, counting a rope as if it were flattened.
+pub(crate) fn string_size(len: usize, latin1: bool) -> usize {
+    RC_OVERHEAD + 48 + if latin1 { len } else { 2 * len }
+}
+
+/// Sums the sizes of values reachable from a set of roots, counting shared
+/// values once.
+#[derive(Default)]
+pub(crate) struct Sizer {
+    seen: HashSet<*const ()>,
+    /// Values still to visit; an explicit stack, since structures can be
+    /// nested deeper than the native stack allows
+    pending: Vec<Value>,
+    scopes: Vec<Scope>,
+    total: usize,
+}
+
+impl Sizer {
+    pub fn value(&mut self, value: &Value) {
+        self.pending.push(value.clone());
+        self.drain();
+    }
+
+    pub fn scope(&mut self, scope: &Scope) {
+        self.scopes.push(scope.clone());
+        self.drain();
+    }
+
+    pub fn registers(&mut self, registers: &[Value]) {
+        self.total += FRAME_SIZE;
+        for value in registers {
+            self.value(value);
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    fn first_visit(&mut self, ptr: *const ()) -> bool {
+        self.seen.insert(ptr)
+    }
+
+    fn drain(&mut self) {
+        loop {
+            if let Some(scope) = self.scopes.pop() {
+                if self.first_visit(Rc::as_ptr(&scope) as *const ()) {
+                    let scope = scope.borrow();
+                    self.total += OBJECT_SIZE + scope.len() * ENTRY_SIZE;
+                    self.pending.extend(scope.values().cloned());
+                }
+                continue;
+            }
+            let Some(value) = self.pending.pop() else {
+                return;
+            };
+            match &value {
+                Value::String(s) => self.string(s),
+                Value::Symbol(symbol) => {
+                    if self.first_visit(symbol.as_ptr()) {
+                        self.total += SYMBOL_SIZE;
+                        self.string(symbol.descriptive_string());
+                    }
+                }
+                Value::Object(obj) => {
+                    if self.first_visit(Rc::as_ptr(obj) as *const ()) {
+                        let obj = obj.borrow();
+                        let properties = obj.properties();
+                        self.total += OBJECT_SIZE + properties.len() * ENTRY_SIZE;
+                        self.pending.extend(properties.values().cloned());
+                        self.pending.extend(obj.prototype().cloned());
+                        if let Some(private) = obj.private_members() {
+                            self.total += private.len() * ENTRY_SIZE;
+                            self.pending.extend(private.values().cloned());
+                        }
+                        match obj.kind() {
+                            ObjectKind::Ordinary => {}
+                            ObjectKind::Map(table) | ObjectKind::Set(table) => {
+                                self.total += table.len() * ENTRY_SIZE;
+                                for (key, value) in table.iter() {
+                                    self.pending.push(key.clone());
+                                    self.pending.push(value.clone());
+                                }
+                            }
+                            // Keys are held weakly, so only the values count
+                            ObjectKind::WeakMap(table) | ObjectKind::WeakSet(table) => {
+                                let values: Vec<_> = table.values().cloned().collect();
+                                self.total += values.len() * ENTRY_SIZE;
+                                self.pending.extend(values);
+                            }
+                            ObjectKind::Iterator(iteration) => {
+                                self.pending.extend(iteration.values());
+                            }
+                            ObjectKind::Generator(state) => self.generator(state),
+                            ObjectKind::AsyncGenerator(generator) => {
+                                self.generator(generator.state());
+                                self.pending.extend(generator.values());
+                            }
+                            ObjectKind::Promise(promise) => {
+                                self.pending.extend(promise.values());
+                            }
+                            ObjectKind::ImportBinding(binding) => {
+                                self.scopes.push(binding.scope().clone());
+                            }
+                            ObjectKind::Namespace(bindings) => {
+                                self.total += bindings.len() * ENTRY_SIZE;
+                                self.scopes
+                                    .extend(bindings.values().map(|b| b.scope().clone()));
+                            }
+                            ObjectKind::Accessor(accessor) => {
+                                self.pending.extend(accessor.values());
+                            }
+                        }
+                    }
+                }
+                Value::Array(arr) => {
+                    if self.first_visit(Rc::as_ptr(arr) as *const ()) {
+                        let arr = arr.borrow();
+                        self.total += arr.heap_size();
+                        self.pending.extend(arr.iter().map(|(_, v)| v.clone()));
+                        self.pending
+                            .extend(arr.properties().map(|(_, v)| v.clone()));
+                        self.pending.extend(arr.prototype().cloned());
+                    }
+                }
+                Value::Function(closure) => {
+                    if self.first_visit(Rc::as_ptr(closure) as *const ()) {
+                        self.total += CLOSURE_SIZE + closure.scopes.len() * size_of::<Scope>();
+                        self.scopes.extend(closure.scopes.iter().cloned());
+                        for properties in [&closure.properties, &closure.private] {
+                            let properties = properties.borrow();
+                            self.total += properties.len() * ENTRY_SIZE;
+                            self.pending.extend(properties.values().cloned());
+                        }
+                        if let Some(class) = &closure.class {
+                            self.pending.extend(class.values());
+                        }
+                    }
+                }
+                Value::NativeFunction(func) => {
+                    if self.first_visit(func.as_ptr()) {
+                        let properties = func.properties();
+                        self.total += properties.len() * ENTRY_SIZE;
+                        self.pending.extend(properties.values().cloned());
+                    }
+                }
+                Value::Undefined | Value::Null | Value::Boolean(_) | Value::Number(_) => {}
+            }
+        }
+    }
+
+    fn generator(&mut self, state: &GeneratorState) {
+        if let GeneratorState::Suspended(frame) = state {
+            self.total += FRAME_SIZE;
+            self.pending.extend(frame.registers().iter().cloned());
+            self.scopes.extend(frame.scopes().iter().cloned());
+        }
+    }
+
+    fn string(&mut self, s: &JsString) {
+        if self.first_visit(s.as_ptr()) {
+            self.total += string_size(s.len(), s.is_latin1());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::JsArray;
+
+    #[test]
+    fn test_shared_and_cyclic_values_count_once() {
+        let arr = Rc::new(RefCell::new(JsArray::new()));
+        let s = Value::String("shared".into());
+        arr.borrow_mut().push(s.clone());
+        arr.borrow_mut().push(s.clone());
+        arr.borrow_mut().push(Value::Array(arr.clone()));
+        let value = Value::Array(arr.clone());
+
+        let mut sizer = Sizer::default();
+        sizer.value(&value);
+        sizer.value(&s);
+
+        let size = arr.borrow().heap_size();
+        assert_eq!(sizer.total(), size + string_size(6, true));
+        // Break the cycle so the test does not leak
+        arr.borrow_mut().delete(2);
+    }
+}
@@ -0,0 +1,46 @@
+//! Stopping a running script from another thread.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A handle that asks a VM to stop. It can be cloned and sent to other
+/// threads; the VM checks it before every instruction and fails with
+/// `RuntimeError::Interrupted` once it is set.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    /// Requests that the VM stop at the next instruction.
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_interrupted(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears a pending request, returning whether one was set.
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clones_share_state() {
+        let handle = InterruptHandle::default();
+        let remote = handle.clone();
+
+        std::thread::spawn(move || remote.interrupt())
+            .join()
+            .unwrap();
+
+        assert!(handle.is_interrupted());
+        assert!(handle.take());
+        assert!(!handle.is_interrupted());
+        assert!(!handle.take());
+    }
+}
@@ -0,0 +1,655 @@
+//! The iterator protocol, as `for-of`, spread and destructuring use it.
+//!
+//! An iteration in progress lives in an object whose internal state says
+//! what it walks. Arrays and strings whose `Symbol.iterator` is still the
+//! built-in one are walked directly, without an iterator object or result
+//! objects in between; the built-in iterators that scripts can see are
+//! stepped the same way. Anything else goes through its `next` method.
+//!
+//! `for-in` walks a list of keys taken up front, in an object of the same
+//! kind.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use indexmap::IndexSet;
+
+use crate::array::Key;
+use crate::builtins::own_property_names;
+use crate::class::is_hidden_member;
+use crate::generator::{Delegated, Resume};
+use crate::heap::{ELEMENT_SIZE, ENTRY_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::{Atom, JsArray, JsObject, JsString, JsSymbol, NativeFunction, RuntimeError, Value, VM};
+
+/// What the built-in iterators of arrays, maps and sets yield.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum IterationKind {
+    Keys,
+    Values,
+    /// `[key, value]` arrays
+    Entries,
+}
+
+/// The state of an iteration.
+#[derive(Debug, Clone)]
+pub(crate) struct Iteration {
+    source: Source,
+    /// Set once the iterator has finished or failed, after which it is
+    /// neither stepped nor closed again
+    done: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    /// Elements of an array, read as the iteration reaches them so that
+    /// elements added on the way are visited
+    Array {
+        array: Rc<RefCell<JsArray>>,
+        index: u32,
+        kind: IterationKind,
+    },
+    /// Code points of a string, keeping surrogate pairs together
+    String { string: JsString, index: usize },
+    /// Entries of a map or set, at a position the table keeps up to date
+    Table {
+        collection: Rc<RefCell<JsObject>>,
+        cursor: Rc<Cell<usize>>,
+        kind: IterationKind,
+    },
+    /// Any other iterator, stepped by calling its `next` method
+    Protocol { iterator: Value, next: Value },
+    /// A sync iterator used by `for await`, whose values are awaited
+    AsyncFromSync { iterator: Value },
+    /// Keys of an object for `for-in`, which are checked as they are
+    /// reached so that deleted ones are skipped
+    Keys {
+        object: Value,
+        keys: Vec<JsString>,
+        index: usize,
+    },
+}
+
+/// The outcome of one step, worked out while the iteration is borrowed and
+/// acted on once it is released.
+enum Step {
+    Done,
+    Value(Value),
+    Entry(Value, Value),
+    Call(Value, Value),
+}
+
+impl Iteration {
+    pub fn array(array: Rc<RefCell<JsArray>>, kind: IterationKind) -> Self {
+        Iteration::new(Source::Array {
+            array,
+            index: 0,
+            kind,
+        })
+    }
+
+    pub fn string(string: JsString) -> Self {
+        Iteration::new(Source::String { string, index: 0 })
+    }
+
+    /// Walks a map or set from `cursor`, which its table must have handed
+    /// out.
+    pub fn table(
+        collection: Rc<RefCell<JsObject>>,
+        cursor: Rc<Cell<usize>>,
+        kind: IterationKind,
+    ) -> Self {
+        Iteration::new(Source::Table {
+            collection,
+            cursor,
+            kind,
+        })
+    }
+
+    fn new(source: Source) -> Self {
+        Iteration {
+            source,
+            done: false,
+        }
+    }
+
+    /// The values the iteration holds on to, for measuring the heap.
+    pub fn values(&self) -> Vec<Value> {
+        match &self.source {
+            Source::Array { array, .. } => vec![Value::Array(array.clone())],
+            Source::String { string, .. } => vec![Value::String(string.clone())],
+            Source::Table { collection, .. } => vec![Value::Object(collection.clone())],
+            Source::Protocol { iterator, next } => vec![iterator.clone(), next.clone()],
+            Source::AsyncFromSync { iterator } => vec![iterator.clone()],
+            Source::Keys { object, keys, .. } => std::iter::once(object.clone())
+                .chain(keys.iter().cloned().map(Value::String))
+                .collect(),
+        }
+    }
+
+    fn step(&mut self) -> Step {
+        if self.done {
+            return Step::Done;
+        }
+        let step = match &mut self.source {
+            Source::Array { array, index, kind } => {
+                let array = array.borrow();
+                if *index >= array.len() {
+                    Step::Done
+                } else {
+                    let key = Value::Number(*index as f64);
+                    let value = array.get(*index).cloned().unwrap_or(Value::Undefined);
+                    *index += 1;
+                    match kind {
+                        IterationKind::Keys => Step::Value(key),
+                        IterationKind::Values => Step::Value(value),
+                        IterationKind::Entries => Step::Entry(key, value),
+                    }
+                }
+            }
+            Source::String { string, index } => {
+                if *index >= string.len() {
+                    Step::Done
+                } else {
+                    let start = *index;
+                    let high = string.code_unit_at(start).unwrap_or(0);
+                    let low = string.code_unit_at(start + 1).unwrap_or(0);
+                    *index += match (high, low) {
+                        (0xd800..=0xdbff, 0xdc00..=0xdfff) => 2,
+                        _ => 1,
+                    };
+                    Step::Value(Value::String(string.substring(start, *index)))
+                }
+            }
+            Source::Table {
+                collection,
+                cursor,
+                kind,
+            } => {
+                let collection = collection.borrow();
+                let (table, is_set) = match collection.kind() {
+                    ObjectKind::Map(table) => (table, false),
+                    ObjectKind::Set(table) => (table, true),
+                    _ => unreachable!("iterating a collection that is not a map or set"),
+                };
+                match table.next(cursor) {
+                    None => Step::Done,
+                    // A set stores each value as its key
+                    Some((key, value)) => {
+                        let value = if is_set { key.clone() } else { value };
+                        match kind {
+                            IterationKind::Keys => Step::Value(key),
+                            IterationKind::Values => Step::Value(value),
+                            IterationKind::Entries => Step::Entry(key, value),
+                        }
+                    }
+                }
+            }
+            Source::Protocol { iterator, next } => Step::Call(iterator.clone(), next.clone()),
+            // Stepped by `async_iterator_next` instead
+            Source::AsyncFromSync { .. } => Step::Done,
+            Source::Keys { keys, index, .. } => match keys.get(*index) {
+                Some(key) => {
+                    *index += 1;
+                    Step::Value(Value::String(key.clone()))
+                }
+                None => Step::Done,
+            },
+        };
+        // A step that fails partway also finishes the iteration, so the
+        // iterator is not closed afterwards
+        self.done = !matches!(step, Step::Value(_) | Step::Entry(..));
+        step
+    }
+}
+
+impl VM {
+    /// Gets an iterator over `value` for `IteratorNext`, calling its
+    /// `Symbol.iterator` method unless it is an array or string still using
+    /// the built-in one.
+    pub(crate) fn get_iterator(&mut self, value: &Value) -> Result<Value, RuntimeError> {
+        let method = self.iterator_method(value)?;
+        self.iterator_from_method(value, method)
+    }
+
+    /// The `Symbol.iterator` method of `value`, or undefined if it is not
+    /// iterable.
+    pub(crate) fn iterator_method(&mut self, value: &Value) -> Result<Value, RuntimeError> {
+        if value.is_undefined() || value.is_null() {
+            return Ok(Value::Undefined);
+        }
+        self.get_property(value, &Value::Symbol(JsSymbol::iterator()))
+    }
+
+    /// Gets an iterator over `value` from the `method` it was found to have.
+    pub(crate) fn iterator_from_method(
+        &mut self,
+        value: &Value,
+        method: Value,
+    ) -> Result<Value, RuntimeError> {
+        let iteration = match value {
+            Value::Array(array) if Some(&method) == self.intrinsics.array_values.as_ref() => {
+                Iteration::array(array.clone(), IterationKind::Values)
+            }
+            Value::String(string) if Some(&method) == self.intrinsics.string_iterator.as_ref() => {
+                Iteration::string(string.clone())
+            }
+            _ => {
+                if !method.is_function() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "{} is not iterable",
+                        value.to_js_string()
+                    )));
+                }
+                let iterator = self.call_function(&method, value.clone(), &[])?;
+                if !iterator.is_object() {
+                    return Err(RuntimeError::TypeError(
+                        "Result of the Symbol.iterator method is not an object".to_string(),
+                    ));
+                }
+                let next = self.get_property(&iterator, &Value::from("next"))?;
+                Iteration::new(Source::Protocol { iterator, next })
+            }
+        };
+        self.new_iterator(Value::Null, iteration)
+    }
+
+    /// Wraps an iteration in an object inheriting from `prototype`.
+    pub(crate) fn new_iterator(
+        &mut self,
+        prototype: Value,
+        iteration: Iteration,
+    ) -> Result<Value, RuntimeError> {
+        self.charge(OBJECT_SIZE)?;
+        Ok(Value::Object(Rc::new(RefCell::new(JsObject::with_kind(
+            prototype,
+            ObjectKind::Iterator(iteration),
+        )))))
+    }
+
+    /// Takes the next value from an iterator made by `get_iterator` or a
+    /// built-in, or `None` once it has finished.
+    pub(crate) fn iterator_next(&mut self, iter: &Value) -> Result<Option<Value>, RuntimeError> {
+        let step = match iter {
+            Value::Object(obj) => match obj.borrow_mut().kind_mut() {
+                ObjectKind::Iterator(iteration) => iteration.step(),
+                _ => return Err(not_an_iterator()),
+            },
+            _ => return Err(not_an_iterator()),
+        };
+        match step {
+            Step::Done => Ok(None),
+            Step::Value(value) => Ok(Some(value)),
+            Step::Entry(key, value) => Ok(Some(self.new_array(JsArray::from(vec![key, value]))?)),
+            Step::Call(iterator, next) => {
+                if !next.is_function() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "{} is not a function",
+                        next.to_js_string()
+                    )));
+                }
+                let result = self.call_function(&next, iterator, &[])?;
+                if !result.is_object() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "Iterator result {} is not an object",
+                        result.to_js_string()
+                    )));
+                }
+                if self
+                    .get_property(&result, &Value::from("done"))?
+                    .to_boolean()
+                {
+                    return Ok(None);
+                }
+                let value = self.get_property(&result, &Value::from("value"))?;
+                if let Value::Object(obj) = iter {
+                    if let ObjectKind::Iterator(iteration) = obj.borrow_mut().kind_mut() {
+                        iteration.done = false;
+                    }
+                }
+                Ok(Some(value))
+            }
+        }
+    }
+
+    /// Tells an iterator that has not finished that no more values will be
+    /// taken, by calling its `return` method if it has one.
+    pub(crate) fn iterator_close(&mut self, iter: &Value) -> Result<(), RuntimeError> {
+        let Value::Object(obj) = iter else {
+            return Err(not_an_iterator());
+        };
+        let iterator = match obj.borrow_mut().kind_mut() {
+            ObjectKind::Iterator(iteration) if iteration.done => return Ok(()),
+            ObjectKind::Iterator(iteration) => {
+                iteration.done = true;
+                match &iteration.source {
+                    Source::Protocol { iterator, .. } => iterator.clone(),
+                    _ => return Ok(()),
+                }
+            }
+            _ => return Err(not_an_iterator()),
+        };
+        let method = self.get_property(&iterator, &Value::from("return"))?;
+        if method.is_undefined() || method.is_null() {
+            return Ok(());
+        }
+        let result = self.call_function(&method, iterator, &[])?;
+        if !result.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Iterator result {} is not an object",
+                result.to_js_string()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Passes a resumption of a generator on to the iterator `yield*` is
+    /// delegating to, calling its `next`, `throw` or `return` method.
+    /// Built-in iterators only have `next`.
+    pub(crate) fn delegate(
+        &mut self,
+        iter: &Value,
+        resume: Resume,
+    ) -> Result<Delegated, RuntimeError> {
+        let protocol = match iter {
+            Value::Object(obj) => match obj.borrow().kind() {
+                ObjectKind::Iterator(Iteration {
+                    source: Source::Protocol { iterator, next },
+                    ..
+                }) => Some((iterator.clone(), next.clone())),
+                ObjectKind::Iterator(_) => None,
+                _ => return Err(not_an_iterator()),
+            },
+            _ => return Err(not_an_iterator()),
+        };
+        let Some((iterator, next)) = protocol else {
+            return match resume {
+                Resume::Next(_) => Ok(match self.iterator_next(iter)? {
+                    Some(value) => Delegated::Yield(self.iterator_result(value, false)?),
+                    None => Delegated::Done(Value::Undefined),
+                }),
+                Resume::Throw(_) => Err(no_throw_method()),
+                Resume::Return(value) => Ok(Delegated::Return(value)),
+            };
+        };
+        let (method, value) = match &resume {
+            Resume::Next(value) => (next, value.clone()),
+            Resume::Throw(value) => (
+                self.get_property(&iterator, &Value::from("throw"))?,
+                value.clone(),
+            ),
+            Resume::Return(value) => (
+                self.get_property(&iterator, &Value::from("return"))?,
+                value.clone(),
+            ),
+        };
+        if method.is_undefined() || method.is_null() {
+            match resume {
+                Resume::Next(_) => {}
+                // The iterator is closed before complaining that it cannot
+                // take the exception
+                Resume::Throw(_) => {
+                    self.iterator_close(iter)?;
+                    return Err(no_throw_method());
+                }
+                Resume::Return(value) => return Ok(Delegated::Return(value)),
+            }
+        }
+        if !method.is_function() {
+            return Err(RuntimeError::TypeError(format!(
+                "{} is not a function",
+                method.to_js_string()
+            )));
+        }
+        let result = self.call_function(&method, iterator, &[value])?;
+        if !result.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Iterator result {} is not an object",
+                result.to_js_string()
+            )));
+        }
+        if !self
+            .get_property(&result, &Value::from("done"))?
+            .to_boolean()
+        {
+            return Ok(Delegated::Yield(result));
+        }
+        let value = self.get_property(&result, &Value::from("value"))?;
+        Ok(match resume {
+            Resume::Return(_) => Delegated::Return(value),
+            _ => Delegated::Done(value),
+        })
+    }
+
+    /// Gets an iterator over `value` for `AsyncIteratorNext`, calling its
+    /// `Symbol.asyncIterator` method, or failing that wrapping its sync
+    /// iterator.
+    pub(crate) fn get_async_iterator(&mut self, value: &Value) -> Result<Value, RuntimeError> {
+        let method = if value.is_undefined() || value.is_null() {
+            Value::Undefined
+        } else {
+            self.get_property(value, &Value::Symbol(JsSymbol::async_iterator()))?
+        };
+        let source = if method.is_undefined() || method.is_null() {
+            let method = self.iterator_method(value)?;
+            if method.is_undefined() || method.is_null() {
+                return Err(RuntimeError::TypeError(format!(
+                    "{} is not async iterable",
+                    value.to_js_string()
+                )));
+            }
+            Source::AsyncFromSync {
+                iterator: self.iterator_from_method(value, method)?,
+            }
+        } else {
+            callable_method(&method)?;
+            let iterator = self.call_function(&method, value.clone(), &[])?;
+            if !iterator.is_object() {
+                return Err(RuntimeError::TypeError(
+                    "Result of the Symbol.asyncIterator method is not an object".to_string(),
+                ));
+            }
+            let next = self.get_property(&iterator, &Value::from("next"))?;
+            Source::Protocol { iterator, next }
+        };
+        self.new_iterator(Value::Null, Iteration::new(source))
+    }
+
+    /// Calls the `next` method of an iterator made by `get_async_iterator`,
+    /// returning the result to be awaited. A wrapped sync iterator gives a
+    /// promise for a result holding its awaited value.
+    pub(crate) fn async_iterator_next(&mut self, iter: &Value) -> Result<Value, RuntimeError> {
+        let source = match iter {
+            Value::Object(obj) => match obj.borrow_mut().kind_mut() {
+                // Finished until `iterator_value` sees the result
+                ObjectKind::Iterator(iteration) if !iteration.done => {
+                    iteration.done = true;
+                    iteration.source.clone()
+                }
+                ObjectKind::Iterator(_) => return self.iterator_result(Value::Undefined, true),
+                _ => return Err(not_an_iterator()),
+            },
+            _ => return Err(not_an_iterator()),
+        };
+        match source {
+            Source::Protocol { iterator, next } => {
+                callable_method(&next)?;
+                self.call_function(&next, iterator, &[])
+            }
+            Source::AsyncFromSync { iterator } => match self.iterator_next(&iterator)? {
+                Some(value) => {
+                    let promise = self.promise_resolve(value)?;
+                    let result = self.create_promise()?;
+                    let wrap = NativeFunction::new("", |vm, _this, args| {
+                        let value = args.first().cloned().unwrap_or(Value::Undefined);
+                        vm.iterator_result(value, false)
+                    });
+                    self.promise_then(
+                        &promise,
+                        wrap.into(),
+                        Value::Undefined,
+                        Some(result.clone()),
+                    )?;
+                    Ok(result)
+                }
+                None => self.iterator_result(Value::Undefined, true),
+            },
+            _ => Err(not_an_iterator()),
+        }
+    }
+
+    /// Takes apart the awaited `result` of `async_iterator_next`, giving
+    /// its value and whether the iterator is done.
+    pub(crate) fn iterator_value(
+        &mut self,
+        iter: &Value,
+        result: &Value,
+    ) -> Result<(Value, bool), RuntimeError> {
+        if !result.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Iterator result {} is not an object",
+                result.to_js_string()
+            )));
+        }
+        let done = self
+            .get_property(result, &Value::from("done"))?
+            .to_boolean();
+        let value = self.get_property(result, &Value::from("value"))?;
+        if !done {
+            if let Value::Object(obj) = iter {
+                if let ObjectKind::Iterator(iteration) = obj.borrow_mut().kind_mut() {
+                    iteration.done = false;
+                }
+            }
+        }
+        Ok((value, done))
+    }
+
+    /// Calls the `return` method of an iterator made by
+    /// `get_async_iterator` that has not finished, returning the result to
+    /// be awaited, or undefined if there is nothing to call.
+    pub(crate) fn async_iterator_close(&mut self, iter: &Value) -> Result<Value, RuntimeError> {
+        let Value::Object(obj) = iter else {
+            return Err(not_an_iterator());
+        };
+        let source = match obj.borrow_mut().kind_mut() {
+            ObjectKind::Iterator(iteration) if iteration.done => return Ok(Value::Undefined),
+            ObjectKind::Iterator(iteration) => {
+                iteration.done = true;
+                iteration.source.clone()
+            }
+            _ => return Err(not_an_iterator()),
+        };
+        match source {
+            Source::Protocol { iterator, .. } => {
+                let method = self.get_property(&iterator, &Value::from("return"))?;
+                if method.is_undefined() || method.is_null() {
+                    return Ok(Value::Undefined);
+                }
+                callable_method(&method)?;
+                self.call_function(&method, iterator, &[])
+            }
+            Source::AsyncFromSync { iterator } => {
+                self.iterator_close(&iterator)?;
+                Ok(Value::Undefined)
+            }
+            _ => Err(not_an_iterator()),
+        }
+    }
+
+    /// Lists the keys `for-in` visits in `value` for `for_in_next`: its own
+    /// enumerable string keys, then those of each object it inherits from
+    /// that are not already listed.
+    pub(crate) fn for_in_keys(&mut self, value: &Value) -> Result<Value, RuntimeError> {
+        let mut keys = Vec::new();
+        let mut seen = IndexSet::new();
+        let mut target = value.clone();
+        while !target.is_undefined() && !target.is_null() {
+            let builtin = self.intrinsics.is_prototype(&target);
+            for key in own_property_names(&target, false) {
+                // Built-in methods and class members are not enumerable,
+                // but still hide keys further up the chain
+                let hidden =
+                    match self.own_property(&target, &Key::new(&Value::String(key.clone()))) {
+                        Some(Value::NativeFunction(_)) => builtin,
+                        Some(value) => is_hidden_member(&value, &target),
+                        None => false,
+                    };
+                if seen.insert(Atom::from_js_string(&key)) && !hidden {
+                    keys.push(key);
+                }
+            }
+            target = self.prototype_of(&target);
+        }
+        self.charge(keys.len() * ELEMENT_SIZE)?;
+        let iteration = Iteration::new(Source::Keys {
+            object: value.clone(),
+            keys,
+            index: 0,
+        });
+        self.new_iterator(Value::Null, iteration)
+    }
+
+    /// Takes the next key from a `for_in_keys` iterator that `value` still
+    /// has, or `None` once the keys run out.
+    pub(crate) fn for_in_next(&mut self, iter: &Value) -> Result<Option<Value>, RuntimeError> {
+        let Value::Object(obj) = iter else {
+            return Err(not_an_iterator());
+        };
+        loop {
+            let (object, key) = match obj.borrow_mut().kind_mut() {
+                ObjectKind::Iterator(Iteration {
+                    source:
+                        Source::Keys {
+                            object,
+                            keys,
+                            index,
+                        },
+                    ..
+                }) => match keys.get(*index) {
+                    Some(key) => {
+                        *index += 1;
+                        (object.clone(), Value::String(key.clone()))
+                    }
+                    None => return Ok(None),
+                },
+                _ => return Err(not_an_iterator()),
+            };
+            if self.lookup(&object, &Key::new(&key)).is_some() {
+                return Ok(Some(key));
+            }
+        }
+    }
+
+    /// Makes the `{ value, done }` object a built-in `next` method returns.
+    pub(crate) fn iterator_result(
+        &mut self,
+        value: Value,
+        done: bool,
+    ) -> Result<Value, RuntimeError> {
+        self.charge(OBJECT_SIZE + 2 * ENTRY_SIZE)?;
+        let mut result = JsObject::new();
+        result.insert("value", value);
+        result.insert("done", done);
+        Ok(Value::Object(Rc::new(RefCell::new(result))))
+    }
+}
+
+fn callable_method(method: &Value) -> Result<(), RuntimeError> {
+    if method.is_function() {
+        Ok(())
+    } else {
+        Err(RuntimeError::TypeError(format!(
+            "{} is not a function",
+            method.to_js_string()
+        )))
+    }
+}
+
+fn no_throw_method() -> RuntimeError {
+    RuntimeError::TypeError("The iterator does not provide a 'throw' method".to_string())
+}
+
+fn not_an_iterator() -> RuntimeError {
+    RuntimeError::Internal("Iterator register holds no iterator".to_string())
+}
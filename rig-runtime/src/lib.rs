@@ -1,24 +1,70 @@
 use core::hash::Hash;
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::hash::Hasher;
+use std::ops::Range;
 
-use rig_bytecode::Instruction;
+use indexmap::IndexMap;
+use rig_bytecode::{FunctionKind, Instruction};
 
 use std::cell::RefCell;
 use std::rc::Rc;
 
+pub mod array;
+mod async_function;
+pub mod atom;
+mod builtins;
+mod class;
+mod collection;
+mod commonjs;
+mod compiler;
+pub mod error;
+mod event_loop;
+mod generator;
+mod heap;
+mod interrupt;
+mod iterator;
+mod module;
+pub mod native;
+mod number;
+pub mod object;
+mod promise;
+#[cfg(feature = "serde")]
+mod serde_value;
+pub mod string;
+pub mod symbol;
+
+pub use array::JsArray;
+use array::{is_length, Key};
+pub use atom::Atom;
+pub use commonjs::CommonJsLoader;
+pub use error::RuntimeError;
+pub use event_loop::{Clock, EventLoop, SystemClock, VirtualClock};
+pub use interrupt::InterruptHandle;
+pub use module::{CompiledModule, FileSystemLoader, ModuleLoader, ModuleSource};
+pub use native::NativeFunction;
+pub use object::JsObject;
+use object::ObjectKind;
+pub use promise::{RejectionEvent, RejectionTracker};
+#[cfg(feature = "serde")]
+pub use serde_value::{from_value, to_value};
+pub use string::JsString;
+pub use symbol::JsSymbol;
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Undefined,
     Null,
     Boolean(bool),
     Number(f64),
-    String(String),
-    Object(Rc<RefCell<HashMap<String, Value>>>),
-    Array(Rc<RefCell<Vec<Value>>>),
-    /// Function index in the constant pool
-    Function(usize),
+    String(JsString),
+    Symbol(JsSymbol),
+    Object(Rc<RefCell<JsObject>>),
+    Array(Rc<RefCell<JsArray>>),
+    /// Function defined in bytecode
+    Function(Rc<Closure>),
+    /// Function implemented by the host
+    NativeFunction(NativeFunction),
 }
 
 impl PartialEq for Value {
@@ -29,9 +75,11 @@ impl PartialEq for Value {
             (Value::Boolean(a), Value::Boolean(b)) => a == b,
             (Value::Number(a), Value::Number(b)) => a == b,
             (Value::String(a), Value::String(b)) => a == b,
+            (Value::Symbol(a), Value::Symbol(b)) => a == b,
             (Value::Object(a), Value::Object(b)) => Rc::ptr_eq(a, b),
             (Value::Array(a), Value::Array(b)) => Rc::ptr_eq(a, b),
-            (Value::Function(a), Value::Function(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => NativeFunction::ptr_eq(a, b),
             _ => false,
         }
     }
@@ -45,7 +93,6 @@ impl PartialOrd for Value {
             (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
             (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
             (Value::Boolean(a), Value::Boolean(b)) => Some(a.cmp(b)),
-            (Value::Function(a), Value::Function(b)) => Some(a.cmp(b)),
             _ => None,
         }
     }
@@ -58,50 +105,855 @@ impl Hash for Value {
             Value::Boolean(b) => b.hash(state),
             Value::Number(n) => n.to_bits().hash(state),
             Value::String(s) => s.hash(state),
+            Value::Symbol(symbol) => symbol.as_ptr().hash(state),
             Value::Object(o) => Rc::as_ptr(o).hash(state),
             Value::Array(a) => Rc::as_ptr(a).hash(state),
-            Value::Function(f) => f.hash(state),
+            Value::Function(f) => Rc::as_ptr(f).hash(state),
+            Value::NativeFunction(f) => f.as_ptr().hash(state),
             _ => {}
         }
     }
 }
 
+/// Properties of an object, in the order they were added.
+pub type Properties = IndexMap<Atom, Value>;
+
+/// A scope object mapping variable names to values
+pub type Scope = Rc<RefCell<HashMap<Atom, Value>>>;
+
+/// A bytecode function together with the scope chain it was created in.
+pub struct Closure {
+    /// Index of the instruction preceding the function body
+    pub entry: usize,
+    /// The kind declared by that instruction
+    kind: FunctionKind,
+    /// Keeps the script `eval` loaded the function's code from in the
+    /// program
+    script: Option<Rc<()>>,
+    scopes: Vec<Scope>,
+    /// Properties set on the function, such as `prototype` and the static
+    /// members of a class
+    properties: RefCell<Properties>,
+    /// Static private members of a class
+    private: RefCell<Properties>,
+    /// Set on a class, which is its constructor
+    class: Option<class::Class>,
+}
+
+impl std::fmt::Debug for Closure {
+    // The captured scopes usually contain the closure itself
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Closure")
+            .field("entry", &self.entry)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Value {
+    pub fn is_undefined(&self) -> bool {
+        matches!(self, Value::Undefined)
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Whether the value is an object of any kind, including arrays and
+    /// functions.
+    pub fn is_object(&self) -> bool {
+        matches!(
+            self,
+            Value::Object(_) | Value::Array(_) | Value::Function(_) | Value::NativeFunction(_)
+        )
+    }
+
+    /// The name the `typeof` operator gives the value's type.
+    pub fn type_of(&self) -> &'static str {
+        match self {
+            Value::Undefined => "undefined",
+            Value::Null => "object",
+            Value::Boolean(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Symbol(_) => "symbol",
+            Value::Object(_) | Value::Array(_) => "object",
+            Value::Function(_) | Value::NativeFunction(_) => "function",
+        }
+    }
+
+    pub fn is_function(&self) -> bool {
+        matches!(self, Value::Function(_) | Value::NativeFunction(_))
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&JsString> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the truthiness of the value, as used by conditional jumps.
+    pub fn to_boolean(&self) -> bool {
+        match self {
+            Value::Undefined | Value::Null => false,
+            Value::Boolean(b) => *b,
+            Value::Number(n) => *n != 0.0 && !n.is_nan(),
+            Value::String(s) => !s.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// Converts the value to a number, as unary `+` does. Objects convert
+    /// through their string form.
+    pub fn to_number(&self) -> f64 {
+        match self {
+            Value::Undefined => f64::NAN,
+            Value::Null => 0.0,
+            Value::Boolean(b) => *b as u8 as f64,
+            Value::Number(n) => *n,
+            other => string_to_number(&other.to_js_string()),
+        }
+    }
+
+    /// Converts the value to a string the way string concatenation does,
+    /// except that a symbol gives its descriptive string where
+    /// concatenation throws.
+    pub fn to_js_string(&self) -> JsString {
+        match self {
+            Value::Undefined => Atom::intern("undefined").into(),
+            Value::Null => Atom::intern("null").into(),
+            Value::Boolean(b) => Atom::intern(if *b { "true" } else { "false" }).into(),
+            Value::Number(n) => JsString::from(number::to_string(*n)),
+            Value::String(s) => s.clone(),
+            Value::Symbol(symbol) => symbol.descriptive_string().clone(),
+            Value::Object(_) => Atom::intern("[object Object]").into(),
+            Value::Array(arr) => array::join(arr, &JsString::from(",")),
+            Value::Function(_) => Atom::intern("function () { [bytecode] }").into(),
+            Value::NativeFunction(f) => JsString::from("function ")
+                .concat(f.name())
+                .concat(&JsString::from("() { [native code] }")),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<JsString> for Value {
+    fn from(s: JsString) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<JsSymbol> for Value {
+    fn from(symbol: JsSymbol) -> Self {
+        Value::Symbol(symbol)
+    }
+}
+
+impl From<NativeFunction> for Value {
+    fn from(f: NativeFunction) -> Self {
+        Value::NativeFunction(f)
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value
+            .as_bool()
+            .ok_or_else(|| type_mismatch(&value, "boolean"))
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        value
+            .as_number()
+            .ok_or_else(|| type_mismatch(&value, "number"))
+    }
+}
+
+impl TryFrom<Value> for JsString {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            _ => Err(type_mismatch(&value, "string")),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = RuntimeError;
+
+    /// Fails on strings with lone surrogates as well as non-strings.
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        JsString::try_from(value)?
+            .to_std_string()
+            .map_err(|_| RuntimeError::TypeError("string is not valid UTF-16".to_string()))
+    }
+}
+
+fn type_mismatch(value: &Value, expected: &str) -> RuntimeError {
+    RuntimeError::TypeError(format!("{} is not a {}", value.to_js_string(), expected))
+}
+
+/// Concatenates the operands of `+`, failing once the result would be
+/// longer than the longest string V8 allows.
+fn concat(x: &JsString, y: &JsString) -> Result<Value, RuntimeError> {
+    if x.len().saturating_add(y.len()) > builtins::MAX_STRING_LENGTH {
+        return Err(RuntimeError::RangeError(
+            "Invalid string length".to_string(),
+        ));
+    }
+    Ok(Value::String(x.concat(y)))
+}
+
+/// Parses a numeric string literal, allowing surrounding whitespace. Empty
+/// strings are zero; anything unparsable is NaN.
+fn string_to_number(s: &JsString) -> f64 {
+    let s = s.to_std_string_lossy();
+    let s = s.trim_matches(|c: char| u16::try_from(c).is_ok_and(string::is_whitespace));
+    if s.is_empty() {
+        return 0.0;
+    }
+    let radix = match s.get(..2) {
+        Some("0x" | "0X") => 16,
+        Some("0o" | "0O") => 8,
+        Some("0b" | "0B") => 2,
+        _ => 10,
+    };
+    if radix != 10 {
+        let digits = &s[2..];
+        if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+            return f64::NAN;
+        }
+        return digits.chars().fold(0.0, |n, c| {
+            n * radix as f64 + c.to_digit(radix).unwrap() as f64
+        });
+    }
+    let unsigned = s.strip_prefix(['+', '-']).unwrap_or(s);
+    if unsigned == "Infinity" {
+        return if s.starts_with('-') {
+            f64::NEG_INFINITY
+        } else {
+            f64::INFINITY
+        };
+    }
+    // Rust also accepts spellings such as "inf" and "NaN", which are not
+    // numeric literals
+    if unsigned
+        .chars()
+        .any(|c| !(c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-')))
+    {
+        return f64::NAN;
+    }
+    s.parse().unwrap_or(f64::NAN)
+}
+
+/// Caller state saved while a bytecode function runs.
+struct Frame {
+    return_pc: usize,
+    /// The caller's registers, swapped back in on return
+    registers: Vec<Value>,
+    /// The caller's scope chain
+    scopes: Vec<Scope>,
+    /// Caller register that receives the return value
+    result_reg: u8,
+    /// Set when the function was called from Rust, which receives the
+    /// return value instead of a register
+    host_call: bool,
+    /// The constructor `new` is running in this frame, whose object stands
+    /// in for a return value that is not one
+    constructing: Option<Rc<Closure>>,
+    /// Whether the caller is strict code
+    strict_mode: bool,
+}
+
+/// Most frames the call stack may hold. A call past it throws a catchable
+/// `RangeError`, so runaway recursion fails the way it does in browsers
+/// instead of eating memory.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// Most times native code, such as a callback from `Array.prototype.map`,
+/// may re-enter the interpreter at once. Each re-entry recurses on the
+/// native stack, which is far smaller than `MAX_CALL_DEPTH` frames.
+const MAX_HOST_DEPTH: usize = 64;
+
+/// An exception handler installed by `EnterTry`.
+#[derive(Debug, Clone)]
+struct Handler {
+    catch_pc: usize,
+    exc_reg: u8,
+    /// Call stack depth of the frame that installed the handler
+    depth: usize,
+    /// Length of the frame's scope chain when the handler was installed,
+    /// which leaves the blocks entered since
+    scopes: usize,
+}
+
+/// A script `eval` has loaded into the program.
+struct LoadedScript {
+    code: Range<usize>,
+    constants: Range<usize>,
+    /// Shared with every function made from the script's code, so the
+    /// script can be unloaded once only this clone is left, or `None` once
+    /// a frame running its code has been suspended, which keeps it for good
+    functions: Option<Rc<()>>,
+}
+
 pub struct VM {
-    /// Registers of the VM, all general-purpose
+    /// Registers of the current frame, all general-purpose
     registers: Vec<Value>,
     /// Constant pool of values
     constants: Vec<Value>,
     program: Vec<Instruction>,
+    /// Length of the program passed to `new`; code loaded by `eval` is
+    /// appended after it
+    script_len: usize,
+    /// Scripts loaded by `eval`, in the order they are in the program
+    scripts: Vec<LoadedScript>,
     pc: usize,
-    call_stack: Vec<usize>,
+    call_stack: Vec<Frame>,
+    /// How many calls to `execute_until_return` are running, one inside
+    /// another
+    host_depth: usize,
+    /// Variable names, indexed like the constant pool. Scope instructions
+    /// name their variable by the index of a string constant.
+    names: Vec<Option<Atom>>,
     /// Stack of scope objects, top of the stack is the current scope
     /// Global scope is at the bottom of the stack
-    scopes: Vec<Rc<RefCell<HashMap<String, Value>>>>,
+    scopes: Vec<Scope>,
+    /// Whether the running code is strict. Every function starts out
+    /// sloppy and its strict code turns this on with `UseStrict`.
     strict_mode: bool,
+    /// Instructions left before execution fails with `OutOfFuel`, or
+    /// `None` for no limit
+    fuel: Option<u64>,
+    interrupt: InterruptHandle,
+    handlers: Vec<Handler>,
+    heap_limit: Option<usize>,
+    /// Bytes allocated so far, an upper bound on the live heap size
+    heap_used: usize,
+    /// Bytes built-ins hold outside any value while they run, such as the
+    /// string `JSON.stringify` is building
+    scratch: usize,
+    intrinsics: builtins::Intrinsics,
+    /// State of `Math.random`
+    random: builtins::Random,
+    /// Symbols made by `Symbol.for`, by key
+    symbol_registry: HashMap<JsString, JsSymbol>,
+    /// The frame of a generator that has just yielded, until the call that
+    /// resumed it stores it back in the generator
+    suspended: Option<generator::SuspendedFrame>,
+    /// How a generator suspended at `YieldDelegate` is being resumed, until
+    /// the instruction runs again
+    resume: Option<generator::Resume>,
+    /// Promise jobs waiting for `run_microtasks`
+    jobs: VecDeque<promise::Job>,
+    rejection_tracker: Option<Box<RejectionTracker>>,
+    /// Modules loaded so far, in the order they were loaded
+    modules: Vec<module::ModuleRecord>,
+    /// Indices into `modules` by module name
+    module_ids: HashMap<String, usize>,
+    module_loader: Option<Rc<dyn ModuleLoader>>,
+}
+
+impl Default for VM {
+    /// An empty VM, ready for `eval`.
+    fn default() -> Self {
+        VM::new(Vec::new(), Vec::new())
+    }
+}
+
+impl Drop for VM {
+    fn drop(&mut self) {
+        // Built-in prototypes and constructors refer to each other
+        self.intrinsics.clear();
+    }
 }
 
 impl VM {
     pub fn new(program: Vec<Instruction>, constants: Vec<Value>) -> Self {
-        VM {
+        let mut vm = VM {
             registers: vec![Value::Undefined; 256],
-            constants,
+            constants: Vec::new(),
+            names: Vec::new(),
+            script_len: program.len(),
+            scripts: Vec::new(),
             program,
             pc: 0,
             call_stack: Vec::new(),
+            host_depth: 0,
             scopes: vec![Rc::new(RefCell::new(HashMap::new()))], // Global scope
             strict_mode: false,
+            fuel: None,
+            interrupt: InterruptHandle::default(),
+            handlers: Vec::new(),
+            heap_limit: None,
+            heap_used: 0,
+            scratch: 0,
+            intrinsics: builtins::Intrinsics::default(),
+            random: builtins::Random::new(),
+            symbol_registry: HashMap::new(),
+            suspended: None,
+            resume: None,
+            jobs: VecDeque::new(),
+            rejection_tracker: None,
+            modules: Vec::new(),
+            module_ids: HashMap::new(),
+            module_loader: None,
+        };
+        // Nothing is charged against a limit yet
+        let _ = vm.add_constants(constants);
+        builtins::install(&mut vm);
+        vm
+    }
+
+    /// Runs the program from the current instruction. Returns the value of a
+    /// top-level `Return`, or undefined if the program runs off its end.
+    pub fn run(&mut self) -> Result<Value, RuntimeError> {
+        let result = self.execute_until_return(true);
+        if result.is_err() {
+            self.unwind(0);
+        }
+        result
+    }
+
+    /// Limits how many instructions later calls to `run`, `eval` and
+    /// `call_function` may execute in total, or lifts the limit with `None`.
+    /// Once the budget is spent they fail with `RuntimeError::OutOfFuel`
+    /// until it is set again.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Instructions left in the budget, or `None` if there is no limit.
+    pub fn remaining_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Returns a handle other threads can use to stop this VM. An interrupt
+    /// fails the running script with `RuntimeError::Interrupted` and is
+    /// then cleared, so the VM can be used again.
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Limits the memory held by script values to about `limit` bytes, or
+    /// lifts the limit with `None`. A single allocation larger than the
+    /// limit throws a catchable `RangeError`; running out of room otherwise
+    /// fails with `RuntimeError::OutOfMemory`, which scripts cannot catch.
+    pub fn set_heap_limit(&mut self, limit: Option<usize>) {
+        self.heap_limit = limit;
+    }
+
+    pub fn heap_limit(&self) -> Option<usize> {
+        self.heap_limit
+    }
+
+    /// Reseeds `Math.random`, so that it produces the same sequence on
+    /// every run. By default it is seeded differently for each VM.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.random = builtins::Random::with_seed(seed);
+    }
+
+    /// Measures the memory held by values reachable from the VM.
+    pub fn heap_usage(&mut self) -> usize {
+        let mut sizer = heap::Sizer::default();
+        sizer.registers(&self.registers);
+        for frame in &self.call_stack {
+            sizer.registers(&frame.registers);
+            for scope in &frame.scopes {
+                sizer.scope(scope);
+            }
+        }
+        for scope in &self.scopes {
+            sizer.scope(scope);
+        }
+        for constant in &self.constants {
+            sizer.value(constant);
+        }
+        self.intrinsics.measure(&mut sizer);
+        for symbol in self.symbol_registry.values() {
+            sizer.value(&Value::Symbol(symbol.clone()));
+        }
+        for job in &self.jobs {
+            for value in job.values() {
+                sizer.value(&value);
+            }
+        }
+        for module in &self.modules {
+            sizer.scope(module.scope());
+            for value in module.values() {
+                sizer.value(&value);
+            }
+        }
+        self.heap_used = sizer.total() + self.scratch;
+        self.heap_used
+    }
+
+    /// Defines or overwrites a variable in the global scope.
+    pub fn set_global(&mut self, name: &str, value: impl Into<Value>) {
+        self.scopes[0]
+            .borrow_mut()
+            .insert(Atom::intern(name), value.into());
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.scopes[0].borrow().get(&Atom::intern(name)).cloned()
+    }
+
+    /// Calls a script or native function from Rust and returns its result.
+    /// On error, any frames the call pushed are unwound before returning.
+    pub fn call_function(
+        &mut self,
+        func: &Value,
+        this: Value,
+        args: &[Value],
+    ) -> Result<Value, RuntimeError> {
+        match func {
+            Value::Function(closure) => {
+                let scopes = self.function_scopes(closure, this)?;
+                self.call_host(closure.entry + 1, scopes, args, None)
+            }
+            Value::NativeFunction(func) => func.call(self, this, args),
+            _ => Err(RuntimeError::TypeError(format!(
+                "{} is not a function",
+                func.to_js_string()
+            ))),
+        }
+    }
+
+    /// Compiles and runs a script in the global scope, returning the value
+    /// of its last expression statement. The script's code and constants
+    /// are unloaded again once it has finished and no function made from
+    /// it is left.
+    pub fn eval(&mut self, source: &str) -> Result<Value, RuntimeError> {
+        let script =
+            rig_parser::parse(source).map_err(|err| RuntimeError::SyntaxError(err.to_string()))?;
+        self.run_script(&script)
+    }
+
+    /// Compiles and runs a parsed script the way `eval` does.
+    fn run_script(&mut self, script: &rig_parser::ast::Program) -> Result<Value, RuntimeError> {
+        let compiled = compiler::compile_script(script, self.program.len(), self.constants.len())?;
+        let const_base = self.constants.len();
+        self.add_constants(compiled.constants)?;
+        let entry = self.program.len();
+        self.program.extend(compiled.code);
+        self.scripts.push(LoadedScript {
+            code: entry..self.program.len(),
+            constants: const_base..self.constants.len(),
+            functions: Some(Rc::new(())),
+        });
+        let scopes = vec![self.scopes[0].clone()];
+        let result = self.call_host(entry, scopes, &[], None);
+        // Scripts loaded before this one may still be running unless no
+        // frame is left
+        let running = if self.call_stack.is_empty() { 0 } else { entry };
+        self.unload_scripts(running);
+        result
+    }
+
+    /// Unloads the scripts at the end of the program from `from` on that
+    /// no function or suspended frame needs any more.
+    fn unload_scripts(&mut self, from: usize) {
+        while let Some(script) = self.scripts.last() {
+            let unused = script.code.start >= from
+                && script.code.end == self.program.len()
+                && script
+                    .functions
+                    .as_ref()
+                    .is_some_and(|functions| Rc::strong_count(functions) == 1);
+            if !unused {
+                return;
+            }
+            self.program.truncate(script.code.start);
+            self.constants.truncate(script.constants.start);
+            self.names.truncate(script.constants.start);
+            self.scripts.pop();
+        }
+    }
+
+    /// The script `eval` loaded the code at `pc` from, if any.
+    fn script_at(&mut self, pc: usize) -> Option<&mut LoadedScript> {
+        let after = self
+            .scripts
+            .partition_point(|script| script.code.start <= pc);
+        self.scripts[..after]
+            .last_mut()
+            .filter(|script| script.code.contains(&pc))
+    }
+
+    /// Adds compiled constants to the pool, charging for the strings among
+    /// them that are not stored already.
+    fn add_constants(&mut self, constants: Vec<Value>) -> Result<(), RuntimeError> {
+        let mut names = Vec::with_capacity(constants.len());
+        let mut bytes = 0;
+        // String constants double as identifiers and property keys, so share
+        // their storage with the atom table up front.
+        let constants: Vec<_> = constants
+            .into_iter()
+            .map(|constant| match constant {
+                Value::String(s) => {
+                    let atom = Atom::from_js_string(&s);
+                    if atom.as_js_string().as_ptr() == s.as_ptr() {
+                        bytes += heap::string_size(s.len(), s.is_latin1());
+                    }
+                    let s = atom.as_js_string().clone();
+                    names.push(Some(atom));
+                    Value::String(s)
+                }
+                constant => {
+                    names.push(None);
+                    constant
+                }
+            })
+            .collect();
+        if bytes > 0 {
+            self.charge(bytes)?;
+        }
+        self.names.extend(names);
+        self.constants.extend(constants);
+        Ok(())
+    }
+
+    /// Runs the code at `pc` in a fresh frame until it returns to Rust,
+    /// constructing an object if `constructing` is set.
+    fn call_host(
+        &mut self,
+        pc: usize,
+        scopes: Vec<Scope>,
+        args: &[Value],
+        constructing: Option<Rc<Closure>>,
+    ) -> Result<Value, RuntimeError> {
+        self.check_stack(true)?;
+        let mut registers = vec![Value::Undefined; 256];
+        let count = args.len().min(registers.len());
+        registers[..count].clone_from_slice(&args[..count]);
+        let depth = self.call_stack.len();
+        self.call_stack.push(Frame {
+            return_pc: self.pc,
+            registers: std::mem::replace(&mut self.registers, registers),
+            scopes: std::mem::replace(&mut self.scopes, scopes),
+            result_reg: 0,
+            host_call: true,
+            constructing,
+            strict_mode: std::mem::take(&mut self.strict_mode),
+        });
+        self.pc = pc;
+        let result = self.execute_until_return(false);
+        if result.is_err() {
+            self.unwind(depth);
+        }
+        result
+    }
+
+    /// Executes instructions until a `Return` hands a value back to Rust.
+    /// At the top level, running off the end of the script also stops.
+    fn execute_until_return(&mut self, top_level: bool) -> Result<Value, RuntimeError> {
+        // Handlers installed below this depth belong to code that called
+        // into Rust, and catch the error only once it has returned there
+        let floor = self.call_stack.len();
+        self.host_depth += 1;
+        let result = loop {
+            match self.step(top_level) {
+                Ok(Some(result)) => break Ok(result),
+                Ok(None) => {}
+                Err(err) => {
+                    if let Err(err) = self.catch(err, floor) {
+                        break Err(err);
+                    }
+                }
+            }
+        };
+        self.host_depth -= 1;
+        result
+    }
+
+    /// Fails once the call stack is full, or, for a call from native code,
+    /// once native code has re-entered the interpreter too many times.
+    pub(crate) fn check_stack(&self, host_call: bool) -> Result<(), RuntimeError> {
+        if self.call_stack.len() >= MAX_CALL_DEPTH || host_call && self.host_depth >= MAX_HOST_DEPTH
+        {
+            return Err(RuntimeError::RangeError(
+                "Maximum call stack size exceeded".to_string(),
+            ));
         }
+        Ok(())
     }
 
-    pub fn run(&mut self) {
-        while self.pc < self.program.len() {
-            let instruction = self.program[self.pc].clone();
-            self.execute(instruction);
+    fn step(&mut self, top_level: bool) -> Result<Option<Value>, RuntimeError> {
+        if top_level && self.call_stack.is_empty() && self.pc >= self.script_len {
+            return Ok(Some(Value::Undefined));
+        }
+        self.tick()?;
+        let Some(instruction) = self.program.get(self.pc).cloned() else {
+            return Err(RuntimeError::Internal(
+                "Program counter out of range".to_string(),
+            ));
+        };
+        let result = self.execute(instruction)?;
+        if result.is_none() {
             self.pc += 1;
         }
+        Ok(result)
+    }
+
+    /// Spends one instruction of fuel, failing if the budget is used up or
+    /// the VM has been interrupted. Built-ins that loop over input of any
+    /// size call it once per iteration.
+    pub(crate) fn tick(&mut self) -> Result<(), RuntimeError> {
+        if self.interrupt.take() {
+            return Err(RuntimeError::Interrupted);
+        }
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::OutOfFuel);
+            }
+            *fuel -= 1;
+        }
+        Ok(())
+    }
+
+    /// Transfers control to the innermost handler installed at or above
+    /// `floor`, or returns the error if there is none or it is uncatchable.
+    fn catch(&mut self, err: RuntimeError, floor: usize) -> Result<(), RuntimeError> {
+        let handler = match self.handlers.last() {
+            Some(handler) if handler.depth >= floor && err.is_catchable() => {
+                self.handlers.pop().unwrap()
+            }
+            _ => {
+                let keep = self.handlers.partition_point(|h| h.depth < floor);
+                self.handlers.truncate(keep);
+                return Err(err);
+            }
+        };
+        self.unwind(handler.depth);
+        match self.error_value(err) {
+            Ok(value) => {
+                self.registers[handler.exc_reg as usize] = value;
+                self.scopes.truncate(handler.scopes);
+                self.pc = handler.catch_pc;
+                Ok(())
+            }
+            Err(err) => self.catch(err, floor),
+        }
+    }
+
+    /// The value a `catch` block receives for `err`.
+    fn error_value(&mut self, err: RuntimeError) -> Result<Value, RuntimeError> {
+        let (name, message) = match err {
+            RuntimeError::Thrown(value) => return Ok(value),
+            RuntimeError::TypeError(msg) => ("TypeError", msg),
+            RuntimeError::RangeError(msg) => ("RangeError", msg),
+            RuntimeError::ReferenceError(msg) => ("ReferenceError", msg),
+            RuntimeError::SyntaxError(msg) => ("SyntaxError", msg),
+            err => return Err(err),
+        };
+        let message = JsString::from(message);
+        self.charge(
+            heap::OBJECT_SIZE
+                + 2 * heap::ENTRY_SIZE
+                + heap::string_size(message.len(), message.is_latin1()),
+        )?;
+        let mut error = JsObject::new();
+        error.insert("name", name);
+        error.insert("message", message);
+        Ok(Value::Object(Rc::new(RefCell::new(error))))
+    }
+
+    /// Accounts for an allocation of `bytes`, failing if it would take the
+    /// heap over its limit.
+    pub(crate) fn charge(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        let Some(limit) = self.heap_limit else {
+            self.heap_used = self.heap_used.saturating_add(bytes);
+            return Ok(());
+        };
+        if bytes > limit {
+            return Err(RuntimeError::RangeError(format!(
+                "Allocation of {} bytes exceeds the heap limit",
+                bytes
+            )));
+        }
+        if self.heap_used.saturating_add(bytes) > limit && self.heap_usage() + bytes > limit {
+            return Err(RuntimeError::OutOfMemory);
+        }
+        self.heap_used += bytes;
+        Ok(())
+    }
+
+    /// Charges for `bytes` of scratch space a built-in is about to use.
+    /// Unlike other allocations, they count as live until the built-in
+    /// hands them back with `release_scratch`.
+    pub(crate) fn reserve_scratch(&mut self, bytes: usize) -> Result<(), RuntimeError> {
+        self.charge(bytes)?;
+        self.scratch += bytes;
+        Ok(())
+    }
+
+    pub(crate) fn release_scratch(&mut self, bytes: usize) {
+        self.scratch -= bytes;
+    }
+
+    /// Pops frames above `depth`, restoring the state saved by the lowest,
+    /// and drops the handlers the popped frames installed.
+    fn unwind(&mut self, depth: usize) {
+        while self.call_stack.len() > depth {
+            let frame = self.call_stack.pop().unwrap();
+            self.pc = frame.return_pc;
+            self.registers = frame.registers;
+            self.scopes = frame.scopes;
+            self.strict_mode = frame.strict_mode;
+        }
+        let keep = self.handlers.partition_point(|h| h.depth <= depth);
+        self.handlers.truncate(keep);
     }
 
-    fn execute(&mut self, instruction: Instruction) {
+    /// Executes one instruction. Returns a value when control passes back
+    /// to Rust.
+    fn execute(&mut self, instruction: Instruction) -> Result<Option<Value>, RuntimeError> {
         match instruction {
             Instruction::LoadConst { reg, const_idx } => {
                 self.registers[reg as usize] = self.constants[const_idx as usize].clone();
@@ -119,40 +971,51 @@ impl VM {
                 self.registers[dst as usize] = self.registers[src as usize].clone();
             }
             Instruction::Add { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x + y);
+                let result = self.add(a, b)?;
+                if let Value::String(s) = &result {
+                    self.charge(heap::string_size(s.len(), s.is_latin1()))?;
+                }
                 self.registers[dst as usize] = result;
             }
             Instruction::Sub { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x - y);
+                let result = self.binary_op(a, b, |x, y| x - y)?;
                 self.registers[dst as usize] = result;
             }
             Instruction::Mul { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x * y);
+                let result = self.binary_op(a, b, |x, y| x * y)?;
                 self.registers[dst as usize] = result;
             }
             Instruction::Div { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x / y);
+                let result = self.binary_op(a, b, |x, y| x / y)?;
                 self.registers[dst as usize] = result;
             }
             Instruction::Mod { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x % y);
+                let result = self.binary_op(a, b, |x, y| x % y)?;
                 self.registers[dst as usize] = result;
             }
             Instruction::Pow { dst, a, b } => {
-                let result = self.binary_op(a, b, |x, y| x.powf(y));
+                let result = self.binary_op(a, b, number::pow)?;
                 self.registers[dst as usize] = result;
             }
             Instruction::Neg { dst, a } => {
                 if let Value::Number(x) = self.registers[a as usize] {
                     self.registers[dst as usize] = Value::Number(-x);
                 } else {
-                    panic!("Invalid type for negation");
+                    return Err(RuntimeError::TypeError(
+                        "Invalid type for negation".to_string(),
+                    ));
                 }
             }
             Instruction::Eq { dst, a, b } => {
                 let result = self.compare(a, b, |x, y| x == y);
                 self.registers[dst as usize] = Value::Boolean(result);
             }
+            Instruction::LooseEq { dst, a, b } => {
+                let a = self.registers[a as usize].clone();
+                let b = self.registers[b as usize].clone();
+                let result = self.loose_equals(a, b)?;
+                self.registers[dst as usize] = Value::Boolean(result);
+            }
             Instruction::Lt { dst, a, b } => {
                 let result = self.compare(a, b, |x, y| x < y);
                 self.registers[dst as usize] = Value::Boolean(result);
@@ -165,7 +1028,7 @@ impl VM {
                 self.pc = (self.pc as i32 + offset) as usize;
             }
             Instruction::JmpIf { cond, offset } => {
-                if let Value::Boolean(true) = self.registers[cond as usize] {
+                if self.registers[cond as usize].to_boolean() {
                     self.pc = (self.pc as i32 + offset) as usize;
                 }
             }
@@ -173,163 +1036,922 @@ impl VM {
                 func_reg,
                 arg_count,
             } => {
-                if let Value::Function(func_idx) = self.registers[func_reg as usize] {
-                    self.call_stack.push(self.pc);
-                    self.pc = func_idx;
-                    // Create new scope for function
-                    self.scopes.push(Rc::new(RefCell::new(HashMap::new())));
-                } else {
-                    panic!("Invalid function call");
-                }
+                // Arguments follow the function register, and the result
+                // replaces the function
+                let args = self.call_args(func_reg as usize + 1, arg_count)?;
+                self.call(func_reg, Value::Undefined, args)?;
+            }
+            Instruction::CallMethod {
+                func_reg,
+                arg_count,
+            } => {
+                let args = self.call_args(func_reg as usize + 2, arg_count)?;
+                let this = self.registers[func_reg as usize + 1].clone();
+                self.call(func_reg, this, args)?;
+            }
+            Instruction::CallSpread { func_reg } => {
+                let this = self.registers[func_reg as usize + 1].clone();
+                let args = self.spread_args(func_reg as usize + 2)?;
+                self.call(func_reg, this, args)?;
+            }
+            Instruction::Construct {
+                func_reg,
+                arg_count,
+            } => {
+                let args = self.call_args(func_reg as usize + 1, arg_count)?;
+                self.construct(func_reg, args)?;
             }
+            Instruction::ConstructSpread { func_reg } => {
+                let args = self.spread_args(func_reg as usize + 1)?;
+                self.construct(func_reg, args)?;
+            }
+            Instruction::SuperCall { func_reg } => self.super_call(func_reg)?,
             Instruction::Return { start_reg, count } => {
-                if let Some(return_addr) = self.call_stack.pop() {
-                    self.pc = return_addr;
-                    self.scopes.pop(); // Remove function scope
+                let mut result = if count > 0 {
+                    self.registers[start_reg as usize].clone()
                 } else {
-                    panic!("Return without call");
+                    Value::Undefined
+                };
+                if let Some(closure) = self
+                    .call_stack
+                    .last()
+                    .and_then(|frame| frame.constructing.clone())
+                {
+                    result = self.construct_result(&closure, result)?;
                 }
+                return Ok(self.pop_frame(result));
             }
             Instruction::NewObject { reg } => {
-                self.registers[reg as usize] = Value::Object(Rc::new(RefCell::new(HashMap::new())));
+                self.charge(heap::OBJECT_SIZE)?;
+                self.registers[reg as usize] =
+                    Value::Object(Rc::new(RefCell::new(JsObject::new())));
             }
             Instruction::GetProp { dst, obj, key } => {
-                if let (Value::Object(obj), Value::String(key)) =
-                    (&self.registers[obj as usize], &self.registers[key as usize])
-                {
-                    let obj_ref = obj.borrow();
-                    let value = obj_ref.get(key).unwrap_or(&Value::Undefined).clone();
-                    drop(obj_ref);
-                    self.registers[dst as usize] = value;
-                } else {
-                    panic!("Invalid GetProp operation");
-                }
+                let obj = self.registers[obj as usize].clone();
+                let key = self.registers[key as usize].clone();
+                self.registers[dst as usize] = self.get_property(&obj, &key)?;
             }
             Instruction::SetProp { obj, key, value } => {
-                if let (Value::Object(obj), Value::String(key)) =
-                    (&self.registers[obj as usize], &self.registers[key as usize])
-                {
-                    let mut obj_ref = obj.borrow_mut();
-                    obj_ref.insert(key.clone(), self.registers[value as usize].clone());
-                } else {
-                    panic!("Invalid SetProp operation");
-                }
+                let obj = self.registers[obj as usize].clone();
+                let key = self.registers[key as usize].clone();
+                self.set_property(&obj, &key, self.registers[value as usize].clone())?;
             }
             Instruction::Closure { reg, func_idx } => {
-                self.registers[reg as usize] = Value::Function(func_idx as usize);
+                self.registers[reg as usize] = self.closure(func_idx as usize)?;
             }
             Instruction::GetScope { dst, var_idx } => {
-                // Simplified scope handling
-                if let Some(scope) = self.scopes.last() {
-                    let scope_ref = scope.borrow();
-                    if let Some(value) = scope_ref.get(&format!("var_{}", var_idx)) {
-                        self.registers[dst as usize] = value.clone();
-                    } else {
-                        self.registers[dst as usize] = Value::Undefined;
+                let name = self.name(var_idx)?;
+                match self.variable(name) {
+                    Some(value) => self.registers[dst as usize] = value,
+                    None => {
+                        return Err(RuntimeError::ReferenceError(format!(
+                            "{} is not defined",
+                            name
+                        )))
                     }
-                } else {
-                    panic!("No active scope");
                 }
             }
             Instruction::SetScope { var_idx, src } => {
-                if let Some(scope) = self.scopes.last() {
-                    let mut scope_ref = scope.borrow_mut();
-                    scope_ref.insert(
-                        format!("var_{}", var_idx),
-                        self.registers[src as usize].clone(),
-                    );
-                } else {
-                    panic!("No active scope");
+                let name = self.name(var_idx)?.clone();
+                let value = self.registers[src as usize].clone();
+                let scope = self
+                    .scopes
+                    .iter()
+                    .rev()
+                    .find(|scope| scope.borrow().contains_key(&name));
+                match scope {
+                    Some(scope) => {
+                        if let Some(Value::Object(obj)) = scope.borrow().get(&name) {
+                            if let ObjectKind::ImportBinding(_) = obj.borrow().kind() {
+                                return Err(RuntimeError::TypeError(
+                                    "Assignment to constant variable.".to_string(),
+                                ));
+                            }
+                        }
+                        scope.borrow_mut().insert(name, value);
+                    }
+                    // Assigning to an undeclared name creates a global,
+                    // except in strict mode
+                    None if self.strict_mode => {
+                        return Err(RuntimeError::ReferenceError(format!(
+                            "{} is not defined",
+                            name
+                        )))
+                    }
+                    None => {
+                        self.charge(heap::ENTRY_SIZE)?;
+                        self.scopes[0].borrow_mut().insert(name, value);
+                    }
                 }
             }
             Instruction::NewArray { reg } => {
-                self.registers[reg as usize] = Value::Array(Rc::new(RefCell::new(Vec::new())));
+                self.charge(heap::ARRAY_SIZE)?;
+                self.registers[reg as usize] = Value::Array(Rc::new(RefCell::new(JsArray::new())));
             }
             Instruction::GetElem { dst, array, index } => {
-                if let (Value::Array(arr), Value::Number(fidx)) = (
-                    &self.registers[array as usize],
-                    &self.registers[index as usize],
-                ) {
-                    let idx = fidx.floor() as usize;
-                    let arr_ref = arr.borrow();
-                    let value = arr_ref.get(idx).unwrap_or(&Value::Undefined).clone();
-                    drop(arr_ref);
-                    self.registers[dst as usize] = value
-                } else {
-                    panic!("Invalid GetElem operation");
-                }
+                let array = self.registers[array as usize].clone();
+                let index = self.registers[index as usize].clone();
+                self.registers[dst as usize] = self.get_property(&array, &index)?;
             }
             Instruction::SetElem {
                 array,
                 index,
                 value,
             } => {
-                if let (Value::Array(arr), Value::Number(fidx)) = (
-                    &self.registers[array as usize],
-                    &self.registers[index as usize],
-                ) {
-                    let idx = fidx.floor() as usize;
-                    let mut arr_ref = arr.borrow_mut();
-                    if (idx) >= arr_ref.len() {
-                        arr_ref.resize(idx + 1, Value::Undefined);
-                    }
-                    arr_ref[idx] = self.registers[value as usize].clone();
-                } else {
-                    panic!("Invalid SetElem operation");
-                }
+                let array = self.registers[array as usize].clone();
+                let index = self.registers[index as usize].clone();
+                self.set_property(&array, &index, self.registers[value as usize].clone())?;
             }
             Instruction::TypeOf { dst, src } => {
-                self.registers[dst as usize] = Value::String(match self.registers[src as usize] {
-                    Value::Undefined => "undefined".to_string(),
-                    Value::Null => "object".to_string(),
-                    Value::Boolean(_) => "boolean".to_string(),
-                    Value::Number(_) => "number".to_string(),
-                    Value::String(_) => "string".to_string(),
-                    Value::Object(_) => "object".to_string(),
-                    Value::Array(_) => "object".to_string(),
-                    Value::Function(_) => "function".to_string(),
-                });
+                let type_name = self.registers[src as usize].type_of();
+                self.registers[dst as usize] = Value::String(Atom::intern(type_name).into());
+            }
+            Instruction::TypeOfScope { dst, var_idx } => {
+                let name = self.name(var_idx)?;
+                let type_name = self
+                    .variable(name)
+                    .map_or("undefined", |value| value.type_of());
+                self.registers[dst as usize] = Value::String(Atom::intern(type_name).into());
             }
             Instruction::InstanceOf { dst, obj, ctor } => {
-                // Simplified instanceof (just checks if obj is of type ctor)
-                self.registers[dst as usize] = Value::Boolean(matches!(
-                    (
-                        self.registers[obj as usize].clone(),
-                        self.registers[ctor as usize].clone()
-                    ),
-                    (Value::Object(_), Value::Function(_)) | (Value::Array(_), Value::Function(_))
-                ));
+                let obj = self.registers[obj as usize].clone();
+                let ctor = self.registers[ctor as usize].clone();
+                let result = self.instance_of(&obj, &ctor)?;
+                self.registers[dst as usize] = Value::Boolean(result);
             }
-            Instruction::DeclareFunc {
-                reg,
-                name_idx,
-                param_count,
-            } => {
+            Instruction::In { dst, key, obj } => {
+                let key = &self.registers[key as usize];
+                let obj = &self.registers[obj as usize];
+                if !obj.is_object() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "Cannot use 'in' operator to search for '{}' in {}",
+                        key.to_js_string(),
+                        obj.to_js_string()
+                    )));
+                }
+                let found = match key {
+                    Value::Symbol(name) if name.is_private() => self.has_private(obj, name),
+                    _ => self.lookup(obj, &Key::new(key)).is_some(),
+                };
+                self.registers[dst as usize] = Value::Boolean(found);
+            }
+            Instruction::Delete { dst, obj, key } => {
+                let key = Key::new(&self.registers[key as usize]);
+                let deleted = match &self.registers[obj as usize] {
+                    Value::Array(arr) => match key {
+                        Key::Index(idx) => {
+                            arr.borrow_mut().delete(idx);
+                            true
+                        }
+                        // The length cannot be deleted
+                        Key::Name(name) if is_length(&name) => false,
+                        Key::Name(name) => {
+                            arr.borrow_mut().delete_property(&name);
+                            true
+                        }
+                    },
+                    // The exports of a module namespace cannot be deleted
+                    Value::Object(obj)
+                        if matches!(obj.borrow().kind(), ObjectKind::Namespace(_)) =>
+                    {
+                        !obj.borrow().properties().contains_key(&key.into_atom())
+                    }
+                    Value::Object(obj) => {
+                        obj.borrow_mut()
+                            .properties_mut()
+                            .shift_remove(&key.into_atom());
+                        true
+                    }
+                    Value::NativeFunction(func) => {
+                        func.properties_mut().shift_remove(&key.into_atom());
+                        true
+                    }
+                    Value::Function(closure) => {
+                        closure
+                            .properties
+                            .borrow_mut()
+                            .shift_remove(&key.into_atom());
+                        true
+                    }
+                    Value::Undefined | Value::Null => {
+                        return Err(RuntimeError::TypeError(
+                            "Cannot convert undefined or null to object".to_string(),
+                        ))
+                    }
+                    _ => true,
+                };
+                self.registers[dst as usize] = Value::Boolean(deleted);
+            }
+            Instruction::DeclareFunc { reg, .. } => {
                 // For simplicity, we're just storing the function in a register
-                self.registers[reg as usize] = Value::Function(self.pc);
+                self.registers[reg as usize] = self.closure(self.pc)?;
                 // The actual function body would follow this instruction
             }
-            Instruction::DeclareVar { name_idx } => {
-                if let Some(scope) = self.scopes.last() {
-                    let mut scope_ref = scope.borrow_mut();
-                    scope_ref.insert(format!("var_{}", name_idx), Value::Undefined);
-                } else {
-                    panic!("No active scope");
-                }
+            Instruction::LoadThis { dst } => {
+                self.registers[dst as usize] = self.this_value()?;
             }
-            Instruction::UseStrict => {
+            Instruction::GetSuper { dst, home, key } => {
+                let home = self.registers[home as usize].clone();
+                let key = self.registers[key as usize].clone();
+                self.registers[dst as usize] = self.get_super(&home, &key)?;
+            }
+            Instruction::SetSuper { home, key, value } => {
+                let home = self.registers[home as usize].clone();
+                let key = self.registers[key as usize].clone();
+                self.set_super(&home, &key, self.registers[value as usize].clone())?;
+            }
+            Instruction::CreateClass {
+                reg,
+                extends,
+                implicit,
+            } => self.create_class(reg, extends, implicit)?,
+            Instruction::DefineOwn { obj, key, value } => {
+                let obj = self.registers[obj as usize].clone();
+                let key = self.registers[key as usize].clone();
+                self.define_own(&obj, &key, self.registers[value as usize].clone())?;
+            }
+            Instruction::DefineGetter { obj, key, value } => {
+                let obj = self.registers[obj as usize].clone();
+                let key = self.registers[key as usize].clone();
+                let getter = self.registers[value as usize].clone();
+                self.define_accessor(&obj, &key, getter, true)?;
+            }
+            Instruction::DefineSetter { obj, key, value } => {
+                let obj = self.registers[obj as usize].clone();
+                let key = self.registers[key as usize].clone();
+                let setter = self.registers[value as usize].clone();
+                self.define_accessor(&obj, &key, setter, false)?;
+            }
+            Instruction::CreatePrivateName { dst, name_idx } => {
+                self.charge(heap::SYMBOL_SIZE)?;
+                let name = self.name(name_idx)?.as_js_string().clone();
+                self.registers[dst as usize] = Value::Symbol(JsSymbol::private(name));
+            }
+            Instruction::CopyRest {
+                dst,
+                src,
+                excluded,
+                count,
+            } => {
+                let source = self.registers[src as usize].clone();
+                let excluded = self.registers[excluded as usize..][..count as usize].to_vec();
+                self.registers[dst as usize] = builtins::copy_rest(self, &source, &excluded)?;
+            }
+            Instruction::DeclareVar { name_idx } => {
+                let name = self.name(name_idx)?.clone();
+                if let Some(scope) = self.scopes.last().cloned() {
+                    // Redeclaring keeps the current value
+                    if !scope.borrow().contains_key(&name) {
+                        self.charge(heap::ENTRY_SIZE)?;
+                    }
+                    let mut scope_ref = scope.borrow_mut();
+                    scope_ref.entry(name).or_insert(Value::Undefined);
+                } else {
+                    return Err(RuntimeError::Internal("No active scope".to_string()));
+                }
+            }
+            Instruction::PushScope => {
+                self.scopes.push(Scope::default());
+            }
+            Instruction::PopScope => {
+                self.scopes.pop();
+            }
+            Instruction::CopyScope => {
+                let Some(scope) = self.scopes.last() else {
+                    return Err(RuntimeError::Internal("No active scope".to_string()));
+                };
+                let copy: Scope = Rc::new(RefCell::new(scope.borrow().clone()));
+                self.charge(copy.borrow().len() * heap::ENTRY_SIZE)?;
+                if let Some(scope) = self.scopes.last_mut() {
+                    *scope = copy;
+                }
+            }
+            Instruction::ThrowConstAssignment => {
+                return Err(RuntimeError::TypeError(
+                    "Assignment to constant variable.".to_string(),
+                ));
+            }
+            Instruction::UseStrict => {
                 self.strict_mode = true;
             }
+            Instruction::Throw { src } => {
+                return Err(RuntimeError::Thrown(self.registers[src as usize].clone()));
+            }
+            Instruction::EnterTry { offset, exc_reg } => {
+                self.handlers.push(Handler {
+                    catch_pc: (self.pc as i32 + offset + 1) as usize,
+                    exc_reg,
+                    depth: self.call_stack.len(),
+                    scopes: self.scopes.len(),
+                });
+            }
+            Instruction::LeaveTry => {
+                self.handlers.pop();
+            }
+            Instruction::GetIterator { dst, src } => {
+                let value = self.registers[src as usize].clone();
+                self.registers[dst as usize] = self.get_iterator(&value)?;
+            }
+            Instruction::IteratorNext { dst, iter, offset } => {
+                let iter = self.registers[iter as usize].clone();
+                match self.iterator_next(&iter)? {
+                    Some(value) => self.registers[dst as usize] = value,
+                    None => {
+                        self.registers[dst as usize] = Value::Undefined;
+                        self.pc = (self.pc as i32 + offset) as usize;
+                    }
+                }
+            }
+            Instruction::IteratorClose { iter } => {
+                let iter = self.registers[iter as usize].clone();
+                self.iterator_close(&iter)?;
+            }
+            Instruction::CreateGenerator => return self.create_generator(),
+            Instruction::Yield { src, .. } => {
+                let value = self.registers[src as usize].clone();
+                let result = self.iterator_result(value, false)?;
+                return Ok(self.yield_result(result));
+            }
+            Instruction::YieldDelegate { dst, iter, offset } => {
+                let resume = self
+                    .resume
+                    .take()
+                    .unwrap_or(generator::Resume::Next(Value::Undefined));
+                let iter = self.registers[iter as usize].clone();
+                match self.delegate(&iter, resume)? {
+                    generator::Delegated::Yield(result) => return Ok(self.yield_result(result)),
+                    generator::Delegated::Done(value) => self.registers[dst as usize] = value,
+                    generator::Delegated::Return(value) => {
+                        self.registers[dst as usize] = value;
+                        self.pc = (self.pc as i32 + offset) as usize;
+                    }
+                }
+            }
+            Instruction::CreateAsync => return self.create_async(),
+            Instruction::CreateAsyncGenerator => return self.create_async_generator(),
+            Instruction::Await { src, .. } => {
+                let value = self.registers[src as usize].clone();
+                return Ok(self.yield_result(value));
+            }
+            Instruction::GetAsyncIterator { dst, src } => {
+                let value = self.registers[src as usize].clone();
+                self.registers[dst as usize] = self.get_async_iterator(&value)?;
+            }
+            Instruction::AsyncIteratorNext { dst, iter } => {
+                let iter = self.registers[iter as usize].clone();
+                self.registers[dst as usize] = self.async_iterator_next(&iter)?;
+            }
+            Instruction::IteratorValue { dst, iter, offset } => {
+                let result = self.registers[dst as usize].clone();
+                let iter = self.registers[iter as usize].clone();
+                let (value, done) = self.iterator_value(&iter, &result)?;
+                self.registers[dst as usize] = value;
+                if done {
+                    self.pc = (self.pc as i32 + offset) as usize;
+                }
+            }
+            Instruction::AsyncIteratorClose { dst, iter } => {
+                let iter = self.registers[iter as usize].clone();
+                self.registers[dst as usize] = self.async_iterator_close(&iter)?;
+            }
+            Instruction::ForInPrepare { dst, src } => {
+                let value = self.registers[src as usize].clone();
+                self.registers[dst as usize] = self.for_in_keys(&value)?;
+            }
+            Instruction::ForInNext { dst, iter, offset } => {
+                let iter = self.registers[iter as usize].clone();
+                match self.for_in_next(&iter)? {
+                    Some(key) => self.registers[dst as usize] = key,
+                    None => {
+                        self.registers[dst as usize] = Value::Undefined;
+                        self.pc = (self.pc as i32 + offset) as usize;
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns from the current function with `result`, restoring the
+    /// caller's frame. Returns the result when control passes back to Rust.
+    fn pop_frame(&mut self, result: Value) -> Option<Value> {
+        let Some(frame) = self.call_stack.pop() else {
+            // Returning from the top level ends the program
+            return Some(result);
+        };
+        self.pc = frame.return_pc;
+        self.registers = frame.registers;
+        // Remove function scope
+        self.scopes = frame.scopes;
+        self.strict_mode = frame.strict_mode;
+        // Handlers of a `try` the function returned from
+        let depth = self.call_stack.len();
+        let keep = self.handlers.partition_point(|h| h.depth <= depth);
+        self.handlers.truncate(keep);
+        if frame.host_call {
+            return Some(result);
+        }
+        self.registers[frame.result_reg as usize] = result;
+        None
+    }
+
+    /// Creates a function whose body follows `entry`, closing over the
+    /// current scope chain.
+    fn closure(&mut self, entry: usize) -> Result<Value, RuntimeError> {
+        self.charge(heap::CLOSURE_SIZE + self.scopes.len() * std::mem::size_of::<Scope>())?;
+        let kind = match self.program.get(entry) {
+            Some(Instruction::DeclareFunc { kind, .. }) => *kind,
+            _ => FunctionKind::Normal,
+        };
+        let script = self
+            .script_at(entry)
+            .and_then(|script| script.functions.clone());
+        Ok(Value::Function(Rc::new(Closure {
+            entry,
+            kind,
+            script,
+            scopes: self.scopes.clone(),
+            properties: RefCell::default(),
+            private: RefCell::default(),
+            class: None,
+        })))
+    }
+
+    /// The scope chain a call to `closure` runs in: the scopes it closes
+    /// over, and a new scope binding `this` unless it is an arrow function.
+    fn function_scopes(&self, closure: &Closure, this: Value) -> Result<Vec<Scope>, RuntimeError> {
+        if closure.kind == FunctionKind::ClassConstructor {
+            let name = match self.program.get(closure.entry) {
+                Some(Instruction::DeclareFunc { name_idx, .. }) => {
+                    self.name(*name_idx).map(|name| name.to_string()).ok()
+                }
+                _ => None,
+            };
+            return Err(RuntimeError::TypeError(format!(
+                "Class constructor {} cannot be invoked without 'new'",
+                name.unwrap_or_default()
+            )));
+        }
+        let scope: Scope = Rc::new(RefCell::new(HashMap::new()));
+        if closure.kind != FunctionKind::Arrow {
+            scope.borrow_mut().insert(Atom::intern(class::THIS), this);
+        }
+        let mut scopes = closure.scopes.clone();
+        scopes.push(scope);
+        Ok(scopes)
+    }
+
+    /// The value of the variable `name`, looked up from the innermost scope
+    /// outwards, or `None` if no scope declares it.
+    fn variable(&self, name: &Atom) -> Option<Value> {
+        let value = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.borrow().get(name).cloned())?;
+        // An imported name reads the exporting module's variable
+        if let Value::Object(obj) = &value {
+            if let ObjectKind::ImportBinding(binding) = obj.borrow().kind() {
+                return Some(binding.get());
+            }
+        }
+        Some(value)
+    }
+
+    /// Looks up the variable name stored at `idx` in the constant pool.
+    fn name(&self, idx: u32) -> Result<&Atom, RuntimeError> {
+        match self.names.get(idx as usize) {
+            Some(Some(name)) => Ok(name),
+            _ => Err(RuntimeError::Internal(
+                "Invalid variable name index".to_string(),
+            )),
+        }
+    }
+
+    /// The elements of the array in register `reg`, as spread arguments.
+    fn spread_args(&self, reg: usize) -> Result<Vec<Value>, RuntimeError> {
+        let Value::Array(arr) = &self.registers[reg] else {
+            return Err(RuntimeError::Internal(
+                "Spread arguments are not an array".to_string(),
+            ));
+        };
+        let arr = arr.borrow();
+        Ok((0..arr.len())
+            .map(|i| arr.get(i).cloned().unwrap_or(Value::Undefined))
+            .collect())
+    }
+
+    /// Adds two values, concatenating if either operand is a string.
+    fn call_args(&self, first_arg: usize, arg_count: u8) -> Result<Vec<Value>, RuntimeError> {
+        match self
+            .registers
+            .get(first_arg..first_arg + arg_count as usize)
+        {
+            Some(args) => Ok(args.to_vec()),
+            None => Err(RuntimeError::Internal(
+                "Call arguments out of range".to_string(),
+            )),
+        }
+    }
+
+    /// Calls the function in `func_reg`. A bytecode function runs in a new
+    /// frame whose return value lands in `func_reg`; a native function
+    /// returns straight away.
+    fn call(&mut self, func_reg: u8, this: Value, args: Vec<Value>) -> Result<(), RuntimeError> {
+        match self.registers[func_reg as usize].clone() {
+            Value::Function(closure) => {
+                self.check_stack(false)?;
+                // Create new scope for function
+                let scopes = self.function_scopes(&closure, this)?;
+                // The callee's registers and scope
+                self.charge(heap::FRAME_SIZE + heap::OBJECT_SIZE + heap::ENTRY_SIZE)?;
+                let mut registers = vec![Value::Undefined; 256];
+                // Spread arguments may outnumber the registers
+                let count = args.len().min(registers.len());
+                registers[..count].clone_from_slice(&args[..count]);
+                self.call_stack.push(Frame {
+                    return_pc: self.pc,
+                    registers: std::mem::replace(&mut self.registers, registers),
+                    scopes: std::mem::replace(&mut self.scopes, scopes),
+                    result_reg: func_reg,
+                    host_call: false,
+                    constructing: None,
+                    strict_mode: std::mem::take(&mut self.strict_mode),
+                });
+                self.pc = closure.entry;
+            }
+            Value::NativeFunction(func) => {
+                let result = func.call(self, this, &args)?;
+                self.registers[func_reg as usize] = result;
+            }
+            _ => return Err(RuntimeError::TypeError("Invalid function call".to_string())),
+        }
+        Ok(())
+    }
+
+    /// Reads `obj[key]`, for both `GetProp` and `GetElem`, calling the
+    /// getter of an accessor property.
+    pub(crate) fn get_property(&mut self, obj: &Value, key: &Value) -> Result<Value, RuntimeError> {
+        if let Value::Undefined | Value::Null = obj {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot read properties of {} (reading '{}')",
+                obj.to_js_string(),
+                key.to_js_string()
+            )));
+        }
+        if let Value::Symbol(name) = key {
+            if name.is_private() {
+                return self.get_private(obj, name);
+            }
         }
+        let value = self.lookup(obj, &Key::new(key)).unwrap_or(Value::Undefined);
+        self.read_accessor(value, obj)
     }
 
-    fn binary_op<F>(&self, a: u8, b: u8, op: F) -> Value
+    /// Finds `key` on `obj` or the objects it inherits from.
+    fn lookup(&self, obj: &Value, key: &Key) -> Option<Value> {
+        let mut target = obj.clone();
+        loop {
+            if let Some(value) = self.own_property(&target, key) {
+                return Some(value);
+            }
+            if let Some(value) = self.intrinsics.accessor(&target, key, obj) {
+                return Some(value);
+            }
+            target = self.prototype_of(&target);
+            if target.is_null() {
+                return None;
+            }
+        }
+    }
+
+    /// Reads a property `obj` has itself rather than inherits.
+    pub(crate) fn own_property(&self, obj: &Value, key: &Key) -> Option<Value> {
+        match obj {
+            Value::Object(obj) => {
+                let obj = obj.borrow();
+                let key = key.clone().into_atom();
+                // Module namespaces read exports from the module's variables
+                if let ObjectKind::Namespace(bindings) = obj.kind() {
+                    if let Some(binding) = bindings.get(&key) {
+                        return Some(binding.get());
+                    }
+                }
+                obj.properties().get(&key).cloned()
+            }
+            Value::Array(arr) => {
+                let arr = arr.borrow();
+                match key {
+                    Key::Index(idx) => arr.get(*idx).cloned(),
+                    Key::Name(name) if is_length(name) => Some(Value::Number(arr.len() as f64)),
+                    Key::Name(name) => arr.property(name).cloned(),
+                }
+            }
+            Value::NativeFunction(func) => func.properties().get(&key.clone().into_atom()).cloned(),
+            Value::Function(closure) => {
+                let name = key.clone().into_atom();
+                if let Some(value) = closure.properties.borrow().get(&name) {
+                    return Some(value.clone());
+                }
+                // A plain function gets the prototype of the objects it
+                // constructs when it is first asked for
+                if closure.kind != FunctionKind::Normal || name != Atom::intern("prototype") {
+                    return None;
+                }
+                let mut prototype = JsObject::new();
+                prototype.insert("constructor", obj.clone());
+                let prototype = Value::Object(Rc::new(RefCell::new(prototype)));
+                closure
+                    .properties
+                    .borrow_mut()
+                    .insert(name, prototype.clone());
+                Some(prototype)
+            }
+            // Strings index by UTF-16 code unit, so this may yield half of a
+            // surrogate pair
+            Value::String(s) => match key {
+                Key::Index(idx) => s
+                    .code_unit_at(*idx as usize)
+                    .map(|unit| Value::String(JsString::from_code_unit(unit))),
+                Key::Name(name) if is_length(name) => Some(Value::Number(s.len() as f64)),
+                Key::Name(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The object `value` inherits from, or null. Primitives inherit from
+    /// the prototype of their type, as if boxed.
+    pub(crate) fn prototype_of(&self, value: &Value) -> Value {
+        let intrinsics = &self.intrinsics;
+        let prototype = match value {
+            Value::Undefined | Value::Null => return Value::Null,
+            Value::Object(obj) => match obj.borrow().prototype() {
+                Some(prototype) => return prototype.clone(),
+                None => &intrinsics.object_prototype,
+            },
+            Value::Array(arr) => match arr.borrow().prototype() {
+                Some(prototype) => return prototype.clone(),
+                None => &intrinsics.array_prototype,
+            },
+            Value::String(_) => &intrinsics.string_prototype,
+            Value::Number(_) => &intrinsics.number_prototype,
+            Value::Symbol(_) => &intrinsics.symbol_prototype,
+            // A class inherits the static members of the class it extends
+            Value::Function(closure) => match closure.class.as_ref().and_then(class::Class::parent)
+            {
+                Some(parent) if parent.is_object() => return parent.clone(),
+                _ => &intrinsics.function_prototype,
+            },
+            Value::NativeFunction(_) => &intrinsics.function_prototype,
+            Value::Boolean(_) => &intrinsics.boolean_prototype,
+        };
+        Value::Object(prototype.clone())
+    }
+
+    /// Writes `obj[key] = value`, for both `SetProp` and `SetElem`.
+    pub(crate) fn set_property(
+        &mut self,
+        obj: &Value,
+        key: &Value,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        if let Value::Symbol(name) = key {
+            if name.is_private() {
+                return self.set_private(obj, name, value);
+            }
+        }
+        match obj {
+            Value::Object(inner) => {
+                let key = Key::new(key);
+                if let ObjectKind::Namespace(_) = inner.borrow().kind() {
+                    return Err(RuntimeError::TypeError(format!(
+                        "Cannot assign to read only property '{}' of object '[object Module]'",
+                        key.into_atom()
+                    )));
+                }
+                if self.write_accessor(obj, &key, value.clone())? {
+                    return Ok(());
+                }
+                let key = key.into_atom();
+                if !inner.borrow().properties().contains_key(&key) {
+                    self.charge(heap::ENTRY_SIZE)?;
+                }
+                inner.borrow_mut().properties_mut().insert(key, value);
+            }
+            Value::Function(closure) => {
+                let key = Key::new(key);
+                if self.write_accessor(obj, &key, value.clone())? {
+                    return Ok(());
+                }
+                let key = key.into_atom();
+                if !closure.properties.borrow().contains_key(&key) {
+                    self.charge(heap::ENTRY_SIZE)?;
+                }
+                closure.properties.borrow_mut().insert(key, value);
+            }
+            Value::Array(arr) => match Key::new(key) {
+                Key::Index(idx) => {
+                    let cost = arr.borrow().set_cost(idx);
+                    self.charge(cost)?;
+                    arr.borrow_mut().set(idx, value);
+                }
+                Key::Name(name) if is_length(&name) => {
+                    let number = match self.coerce_primitive(value, "number")? {
+                        Value::Symbol(_) => {
+                            return Err(RuntimeError::TypeError(
+                                "Cannot convert a Symbol value to a number".to_string(),
+                            ))
+                        }
+                        value => value.to_number(),
+                    };
+                    let len = number as u32;
+                    if len as f64 != number {
+                        return Err(RuntimeError::RangeError("Invalid array length".to_string()));
+                    }
+                    let cost = arr.borrow().set_len_cost(len);
+                    self.charge(cost)?;
+                    arr.borrow_mut().set_len(len);
+                }
+                Key::Name(name) => {
+                    if arr.borrow().property(&name).is_none() {
+                        self.charge(heap::ENTRY_SIZE)?;
+                    }
+                    arr.borrow_mut().set_property(name, value);
+                }
+            },
+            Value::NativeFunction(func) => {
+                let key = Key::new(key).into_atom();
+                if !func.properties().contains_key(&key) {
+                    self.charge(heap::ENTRY_SIZE)?;
+                }
+                func.properties_mut().insert(key, value);
+            }
+            Value::Undefined | Value::Null => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot set properties of {} (setting '{}')",
+                    obj.to_js_string(),
+                    key.to_js_string()
+                )))
+            }
+            // Primitives have no properties of their own to write to
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, a: u8, b: u8) -> Result<Value, RuntimeError> {
+        let x = self.coerce_primitive(self.registers[a as usize].clone(), "default")?;
+        let y = self.coerce_primitive(self.registers[b as usize].clone(), "default")?;
+        match (&x, &y) {
+            (Value::Symbol(_), Value::String(_)) | (Value::String(_), Value::Symbol(_)) => Err(
+                RuntimeError::TypeError("Cannot convert a Symbol value to a string".to_string()),
+            ),
+            (Value::String(x), Value::String(y)) => concat(x, y),
+            (Value::String(x), y) => concat(x, &y.to_js_string()),
+            (x, Value::String(y)) => concat(&x.to_js_string(), y),
+            (Value::Number(x), Value::Number(y)) => Ok(Value::Number(x + y)),
+            (Value::Symbol(_), _) | (_, Value::Symbol(_)) => Err(RuntimeError::TypeError(
+                "Cannot convert a Symbol value to a number".to_string(),
+            )),
+            _ => Err(RuntimeError::TypeError(
+                "Invalid types for binary operation".to_string(),
+            )),
+        }
+    }
+
+    /// Converts an object operand to a primitive, through its
+    /// `Symbol.toPrimitive` method if it has one and otherwise through
+    /// `valueOf` and `toString`. `hint` is the type the operator prefers:
+    /// `"string"`, `"number"` or `"default"`.
+    ///
+    /// Functions have no `Function.prototype.toString` to call yet, so they
+    /// keep their fixed string form.
+    pub(crate) fn coerce_primitive(
+        &mut self,
+        value: Value,
+        hint: &str,
+    ) -> Result<Value, RuntimeError> {
+        if !matches!(value, Value::Object(_) | Value::Array(_)) {
+            return Ok(value);
+        }
+        let not_primitive =
+            || RuntimeError::TypeError("Cannot convert object to primitive value".to_string());
+        let exotic = self.get_property(&value, &Value::Symbol(JsSymbol::to_primitive()))?;
+        if !exotic.is_undefined() && !exotic.is_null() {
+            if !exotic.is_function() {
+                return Err(RuntimeError::TypeError(
+                    "Symbol.toPrimitive is not a function".to_string(),
+                ));
+            }
+            let result = self.call_function(&exotic, value, &[Value::from(hint)])?;
+            return if result.is_object() {
+                Err(not_primitive())
+            } else {
+                Ok(result)
+            };
+        }
+        let methods = match hint {
+            "string" => ["toString", "valueOf"],
+            _ => ["valueOf", "toString"],
+        };
+        for name in methods {
+            let method = self.get_property(&value, &Value::from(name))?;
+            if method.is_function() {
+                let result = self.call_function(&method, value.clone(), &[])?;
+                if !result.is_object() {
+                    return Ok(result);
+                }
+            }
+        }
+        Err(not_primitive())
+    }
+
+    /// The `==` operator. Null and undefined equal only each other; other
+    /// operands of different types are converted towards numbers, objects
+    /// through their primitive value, until the types match.
+    fn loose_equals(&mut self, mut a: Value, mut b: Value) -> Result<bool, RuntimeError> {
+        loop {
+            match (&a, &b) {
+                (Value::Undefined | Value::Null, Value::Undefined | Value::Null) => {
+                    return Ok(true)
+                }
+                (Value::Undefined | Value::Null, _) | (_, Value::Undefined | Value::Null) => {
+                    return Ok(false)
+                }
+                _ if std::mem::discriminant(&a) == std::mem::discriminant(&b) => return Ok(a == b),
+                (Value::Number(_), Value::String(_)) | (_, Value::Boolean(_)) => {
+                    b = Value::Number(b.to_number());
+                }
+                (Value::String(_), Value::Number(_)) | (Value::Boolean(_), _) => {
+                    a = Value::Number(a.to_number());
+                }
+                (Value::Number(_) | Value::String(_) | Value::Symbol(_), _) if b.is_object() => {
+                    b = self.coerce_primitive(b, "default")?;
+                    if b.is_object() {
+                        return Ok(false);
+                    }
+                }
+                (_, Value::Number(_) | Value::String(_) | Value::Symbol(_)) if a.is_object() => {
+                    a = self.coerce_primitive(a, "default")?;
+                    if a.is_object() {
+                        return Ok(false);
+                    }
+                }
+                _ => return Ok(false),
+            }
+        }
+    }
+
+    /// The `instanceof` operator: asks `ctor` through its
+    /// `Symbol.hasInstance` method if it has one, and otherwise looks for
+    /// `ctor.prototype` on the prototype chain of `obj`.
+    fn instance_of(&mut self, obj: &Value, ctor: &Value) -> Result<bool, RuntimeError> {
+        if !ctor.is_object() {
+            return Err(RuntimeError::TypeError(
+                "Right-hand side of 'instanceof' is not an object".to_string(),
+            ));
+        }
+        let has_instance = self.get_property(ctor, &Value::Symbol(JsSymbol::has_instance()))?;
+        if !has_instance.is_undefined() && !has_instance.is_null() {
+            let result =
+                self.call_function(&has_instance, ctor.clone(), std::slice::from_ref(obj))?;
+            return Ok(result.to_boolean());
+        }
+        if !ctor.is_function() {
+            return Err(RuntimeError::TypeError(
+                "Right-hand side of 'instanceof' is not callable".to_string(),
+            ));
+        }
+        if !obj.is_object() {
+            return Ok(false);
+        }
+        let prototype = self.get_property(ctor, &Value::from("prototype"))?;
+        if !prototype.is_object() {
+            return Err(RuntimeError::TypeError(format!(
+                "Function has non-object prototype '{}' in instanceof check",
+                prototype.to_js_string()
+            )));
+        }
+        let mut ancestor = self.prototype_of(obj);
+        while !ancestor.is_null() {
+            if ancestor == prototype {
+                return Ok(true);
+            }
+            ancestor = self.prototype_of(&ancestor);
+        }
+        Ok(false)
+    }
+
+    fn binary_op<F>(&self, a: u8, b: u8, op: F) -> Result<Value, RuntimeError>
     where
         F: Fn(f64, f64) -> f64,
     {
         match (&self.registers[a as usize], &self.registers[b as usize]) {
-            (Value::Number(x), Value::Number(y)) => Value::Number(op(*x, *y)),
-            _ => panic!("Invalid types for binary operation"),
+            (Value::Number(x), Value::Number(y)) => Ok(Value::Number(op(*x, *y))),
+            _ => Err(RuntimeError::TypeError(
+                "Invalid types for binary operation".to_string(),
+            )),
         }
     }
 
@@ -357,7 +1979,7 @@ mod tests {
         let constants = vec![Value::Number(10.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[1], Value::Number(10.0));
     }
@@ -378,7 +2000,7 @@ mod tests {
         let constants = vec![Value::Number(5.0), Value::Number(7.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[2], Value::Number(12.0));
     }
@@ -399,7 +2021,7 @@ mod tests {
         let constants = vec![Value::Number(10.0), Value::Number(3.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[2], Value::Number(7.0));
     }
@@ -417,75 +2039,280 @@ mod tests {
             },
             Instruction::Mul { dst: 2, a: 0, b: 1 },
         ];
-        let constants = vec![Value::Number(4.0), Value::Number(3.0)];
+        let constants = vec![Value::Number(4.0), Value::Number(3.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[2], Value::Number(12.0));
+    }
+
+    #[test]
+    fn test_div() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Div { dst: 2, a: 0, b: 1 },
+        ];
+        let constants = vec![Value::Number(8.0), Value::Number(2.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[2], Value::Number(4.0));
+    }
+
+    #[test]
+    fn test_mod() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Mod { dst: 2, a: 0, b: 1 },
+        ];
+        let constants = vec![Value::Number(10.0), Value::Number(3.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[2], Value::Number(1.0));
+    }
+
+    #[test]
+    fn test_neg() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::Neg { dst: 1, a: 0 },
+        ];
+        let constants = vec![Value::Number(5.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[1], Value::Number(-5.0));
+    }
+
+    #[test]
+    fn test_eq() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Eq { dst: 0, a: 0, b: 1 },
+        ];
+        let constants = vec![Value::Number(5.0), Value::Number(5.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[0], Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_lt() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Lt { dst: 0, a: 0, b: 1 },
+        ];
+        let constants = vec![Value::Number(3.0), Value::Number(5.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[0], Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_le() {
+        let program = vec![
+            Instruction::LoadConst {
+                reg: 0,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Le { dst: 0, a: 0, b: 1 },
+        ];
+        let constants = vec![Value::Number(5.0), Value::Number(5.0)];
+
+        let mut vm = VM::new(program, constants);
+        vm.run().unwrap();
+
+        assert_eq!(vm.registers[0], Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_array_operations() {
+        let program = vec![
+            Instruction::NewArray { reg: 0 },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 0,
+            },
+            Instruction::LoadConst {
+                reg: 2,
+                const_idx: 1,
+            },
+            Instruction::LoadConst {
+                reg: 3,
+                const_idx: 2,
+            },
+            Instruction::SetElem {
+                array: 0,
+                index: 1,
+                value: 1,
+            },
+            Instruction::SetElem {
+                array: 0,
+                index: 2,
+                value: 2,
+            },
+            Instruction::SetElem {
+                array: 0,
+                index: 3,
+                value: 3,
+            },
+            Instruction::GetElem {
+                dst: 4,
+                array: 0,
+                index: 2,
+            },
+        ];
+        let constants = vec![
+            Value::Number(10.0),
+            Value::Number(20.0),
+            Value::Number(30.0),
+        ];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[2], Value::Number(12.0));
+        assert_eq!(vm.registers[4], Value::Number(20.0));
     }
 
     #[test]
-    fn test_div() {
+    fn test_object_operations() {
         let program = vec![
+            Instruction::NewObject { reg: 0 },
             Instruction::LoadConst {
-                reg: 0,
+                reg: 1,
                 const_idx: 0,
             },
             Instruction::LoadConst {
-                reg: 1,
+                reg: 2,
                 const_idx: 1,
             },
-            Instruction::Div { dst: 2, a: 0, b: 1 },
+            Instruction::SetProp {
+                obj: 0,
+                key: 1,
+                value: 2,
+            },
+            Instruction::GetProp {
+                dst: 3,
+                obj: 0,
+                key: 1,
+            },
         ];
-        let constants = vec![Value::Number(8.0), Value::Number(2.0)];
+        let constants = vec![Value::String("key".into()), Value::Number(42.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[2], Value::Number(4.0));
+        assert_eq!(vm.registers[3], Value::Number(42.0));
     }
 
     #[test]
-    fn test_mod() {
+    fn test_for_in_instructions() {
         let program = vec![
             Instruction::LoadConst {
                 reg: 0,
                 const_idx: 0,
             },
+            Instruction::ForInPrepare { dst: 1, src: 0 },
+            Instruction::ForInNext {
+                dst: 2,
+                iter: 1,
+                offset: 0,
+            },
+            Instruction::ForInNext {
+                dst: 3,
+                iter: 1,
+                offset: 0,
+            },
+            // Finished, so skips the load
+            Instruction::ForInNext {
+                dst: 4,
+                iter: 1,
+                offset: 1,
+            },
             Instruction::LoadConst {
-                reg: 1,
-                const_idx: 1,
+                reg: 4,
+                const_idx: 0,
             },
-            Instruction::Mod { dst: 2, a: 0, b: 1 },
         ];
-        let constants = vec![Value::Number(10.0), Value::Number(3.0)];
+        let constants = vec![Value::String("ab".into())];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[2], Value::Number(1.0));
+        assert_eq!(vm.registers[2], Value::from("0"));
+        assert_eq!(vm.registers[3], Value::from("1"));
+        assert_eq!(vm.registers[4], Value::Undefined);
     }
 
     #[test]
-    fn test_neg() {
+    fn test_string_concat() {
         let program = vec![
             Instruction::LoadConst {
                 reg: 0,
                 const_idx: 0,
             },
-            Instruction::Neg { dst: 1, a: 0 },
+            Instruction::LoadConst {
+                reg: 1,
+                const_idx: 1,
+            },
+            Instruction::Add { dst: 2, a: 0, b: 1 },
+            Instruction::Add { dst: 3, a: 1, b: 0 },
         ];
-        let constants = vec![Value::Number(5.0)];
+        let constants = vec![Value::String("n = ".into()), Value::Number(42.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[1], Value::Number(-5.0));
+        assert_eq!(vm.registers[2], Value::String("n = 42".into()));
+        assert_eq!(vm.registers[3], Value::String("42n = ".into()));
     }
 
     #[test]
-    fn test_eq() {
+    fn test_string_index_code_units() {
         let program = vec![
             Instruction::LoadConst {
                 reg: 0,
@@ -495,39 +2322,60 @@ mod tests {
                 reg: 1,
                 const_idx: 1,
             },
-            Instruction::Eq { dst: 0, a: 0, b: 1 },
+            Instruction::GetElem {
+                dst: 2,
+                array: 0,
+                index: 1,
+            },
         ];
-        let constants = vec![Value::Number(5.0), Value::Number(5.0)];
+        let constants = vec![Value::String("a\u{1F600}".into()), Value::Number(1.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[0], Value::Boolean(true));
+        assert_eq!(
+            vm.registers[2],
+            Value::String(JsString::from_code_unit(0xD83D))
+        );
     }
 
     #[test]
-    fn test_lt() {
+    fn test_scope_variables() {
         let program = vec![
+            Instruction::DeclareVar { name_idx: 0 },
             Instruction::LoadConst {
                 reg: 0,
-                const_idx: 0,
-            },
-            Instruction::LoadConst {
-                reg: 1,
                 const_idx: 1,
             },
-            Instruction::Lt { dst: 0, a: 0, b: 1 },
+            Instruction::SetScope { var_idx: 0, src: 0 },
+            Instruction::GetScope { dst: 1, var_idx: 0 },
         ];
-        let constants = vec![Value::Number(3.0), Value::Number(5.0)];
+        let constants = vec![Value::String("answer".into()), Value::Number(42.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[0], Value::Boolean(true));
+        assert_eq!(vm.registers[1], Value::Number(42.0));
+        assert_eq!(
+            vm.scopes[0].borrow().get(&Atom::intern("answer")),
+            Some(&Value::Number(42.0))
+        );
     }
 
     #[test]
-    fn test_le() {
+    fn test_call_native_function() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let sink = log.clone();
+        let sum = NativeFunction::new("sum", move |_vm, this, args| {
+            sink.borrow_mut().push(this);
+            let mut total = 0.0;
+            for arg in args {
+                if let Value::Number(n) = arg {
+                    total += n;
+                }
+            }
+            Ok(Value::Number(total))
+        });
         let program = vec![
             Instruction::LoadConst {
                 reg: 0,
@@ -537,69 +2385,60 @@ mod tests {
                 reg: 1,
                 const_idx: 1,
             },
-            Instruction::Le { dst: 0, a: 0, b: 1 },
+            Instruction::LoadConst {
+                reg: 2,
+                const_idx: 2,
+            },
+            Instruction::Call {
+                func_reg: 0,
+                arg_count: 2,
+            },
+        ];
+        let constants = vec![
+            Value::NativeFunction(sum),
+            Value::Number(2.0),
+            Value::Number(3.0),
         ];
-        let constants = vec![Value::Number(5.0), Value::Number(5.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[0], Value::Boolean(true));
+        assert_eq!(vm.registers[0], Value::Number(5.0));
+        assert_eq!(*log.borrow(), vec![Value::Undefined]);
     }
 
     #[test]
-    fn test_array_operations() {
+    fn test_native_function_error() {
+        let fail = NativeFunction::new("fail", |_vm, _this, _args| {
+            Err(RuntimeError::Thrown(Value::String("boom".into())))
+        });
         let program = vec![
-            Instruction::NewArray { reg: 0 },
             Instruction::LoadConst {
-                reg: 1,
+                reg: 0,
                 const_idx: 0,
             },
-            Instruction::LoadConst {
-                reg: 2,
-                const_idx: 1,
-            },
-            Instruction::LoadConst {
-                reg: 3,
-                const_idx: 2,
-            },
-            Instruction::SetElem {
-                array: 0,
-                index: 1,
-                value: 1,
-            },
-            Instruction::SetElem {
-                array: 0,
-                index: 2,
-                value: 2,
-            },
-            Instruction::SetElem {
-                array: 0,
-                index: 3,
-                value: 3,
-            },
-            Instruction::GetElem {
-                dst: 4,
-                array: 0,
-                index: 2,
+            Instruction::Call {
+                func_reg: 0,
+                arg_count: 0,
             },
         ];
-        let constants = vec![
-            Value::Number(10.0),
-            Value::Number(20.0),
-            Value::Number(30.0),
-        ];
+        let constants = vec![Value::NativeFunction(fail)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
 
-        assert_eq!(vm.registers[4], Value::Number(20.0));
+        assert_eq!(
+            vm.run(),
+            Err(RuntimeError::Thrown(Value::String("boom".into())))
+        );
     }
 
     #[test]
-    fn test_object_operations() {
+    fn test_call_bytecode_function() {
         let program = vec![
-            Instruction::NewObject { reg: 0 },
+            Instruction::Closure {
+                reg: 0,
+                func_idx: 5,
+            },
             Instruction::LoadConst {
                 reg: 1,
                 const_idx: 0,
@@ -608,23 +2447,35 @@ mod tests {
                 reg: 2,
                 const_idx: 1,
             },
-            Instruction::SetProp {
-                obj: 0,
-                key: 1,
-                value: 2,
+            Instruction::Call {
+                func_reg: 0,
+                arg_count: 2,
             },
-            Instruction::GetProp {
-                dst: 3,
-                obj: 0,
-                key: 1,
+            Instruction::Jmp { offset: 3 },
+            Instruction::DeclareFunc {
+                reg: 0,
+                name_idx: 2,
+                param_count: 2,
+                kind: FunctionKind::Normal,
             },
+            Instruction::Add { dst: 2, a: 0, b: 1 },
+            Instruction::Return {
+                start_reg: 2,
+                count: 1,
+            },
+        ];
+        let constants = vec![
+            Value::Number(20.0),
+            Value::Number(22.0),
+            Value::String("add".into()),
         ];
-        let constants = vec![Value::String("key".to_string()), Value::Number(42.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
-        assert_eq!(vm.registers[3], Value::Number(42.0));
+        assert_eq!(vm.registers[0], Value::Number(42.0));
+        assert_eq!(vm.registers[1], Value::Number(20.0));
+        assert_eq!(vm.registers[2], Value::Number(22.0));
     }
 
     #[test]
@@ -649,7 +2500,7 @@ mod tests {
         let constants = vec![Value::Number(10.0), Value::Number(3.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[2], Value::Number(13.0));
         assert_eq!(vm.registers[3], Value::Number(7.0));
@@ -678,7 +2529,7 @@ mod tests {
         let constants = vec![Value::Number(5.0), Value::Number(10.0)];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[2], Value::Boolean(false));
         assert_eq!(vm.registers[3], Value::Boolean(true));
@@ -715,8 +2566,539 @@ mod tests {
         ];
 
         let mut vm = VM::new(program, constants);
-        vm.run();
+        vm.run().unwrap();
 
         assert_eq!(vm.registers[2], Value::Number(3.0));
     }
+
+    #[test]
+    fn test_eval_completion_value() {
+        let mut vm = VM::default();
+
+        assert_eq!(vm.eval("var x = 6; x * 7;"), Ok(Value::Number(42.0)));
+        assert_eq!(vm.get_global("x"), Some(Value::Number(6.0)));
+        assert_eq!(vm.eval("x += 1"), Ok(Value::Number(7.0)));
+        assert_eq!(vm.eval("var y;"), Ok(Value::Undefined));
+    }
+
+    #[test]
+    fn test_call_function_from_host() {
+        let mut vm = VM::default();
+        vm.eval("function add(a, b) { return a + b; }").unwrap();
+
+        let add = vm.get_global("add").unwrap();
+        let result = vm.call_function(&add, Value::Undefined, &["a".into(), 1.0.into()]);
+
+        assert_eq!(result, Ok(Value::from("a1")));
+        assert_eq!(
+            vm.call_function(&Value::Null, Value::Undefined, &[]),
+            Err(RuntimeError::TypeError(
+                "null is not a function".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_closures_capture_scope() {
+        let mut vm = VM::default();
+        let source = "
+            function counter() {
+                var n = 0;
+                return function () { n = n + 1; return n; };
+            }
+            var a = counter(), b = counter();
+            a(); a(); b();
+            a();
+        ";
+
+        assert_eq!(vm.eval(source), Ok(Value::Number(3.0)));
+        assert_eq!(vm.get_global("n"), None);
+    }
+
+    #[test]
+    fn test_globals_round_trip() {
+        let mut vm = VM::default();
+        vm.set_global("greeting", "hello");
+        vm.set_global("count", 2.0);
+        vm.eval("var message = greeting + ' world'; count = count * 2;")
+            .unwrap();
+
+        let message: String = vm.get_global("message").unwrap().try_into().unwrap();
+        let count: f64 = vm.get_global("count").unwrap().try_into().unwrap();
+        assert_eq!(message, "hello world");
+        assert_eq!(count, 4.0);
+        assert_eq!(
+            bool::try_from(Value::Number(1.0)),
+            Err(RuntimeError::TypeError("1 is not a boolean".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_eval_calls_native_function() {
+        let mut vm = VM::default();
+        let twice = NativeFunction::new("twice", |vm, _this, args| {
+            let f = args.first().cloned().unwrap_or(Value::Undefined);
+            let once = vm.call_function(&f, Value::Undefined, &[])?;
+            vm.call_function(&f, Value::Undefined, &[once])
+        });
+        vm.set_global("twice", twice);
+
+        let result = vm.eval("var calls = 0; twice(function () { calls++; return calls; })");
+
+        assert_eq!(result, Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_eval_errors() {
+        let mut vm = VM::default();
+
+        assert!(matches!(
+            vm.eval("var = 1;"),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+        assert_eq!(
+            vm.eval("missing + 1"),
+            Err(RuntimeError::ReferenceError(
+                "missing is not defined".to_string()
+            ))
+        );
+        assert_eq!(
+            vm.eval("'use strict'; undeclared = 1;"),
+            Err(RuntimeError::ReferenceError(
+                "undeclared is not defined".to_string()
+            ))
+        );
+        // Deep nesting fails to parse rather than overflowing the stack
+        assert!(matches!(
+            vm.eval(&format!("{}1", "- ".repeat(100_000))),
+            Err(RuntimeError::SyntaxError(_))
+        ));
+    }
+
+    #[test]
+    fn test_strict_mode_is_lexical() {
+        let mut vm = VM::default();
+        let not_defined = |name: &str| {
+            Err(RuntimeError::ReferenceError(format!(
+                "{name} is not defined"
+            )))
+        };
+        vm.eval(
+            "function sloppy() { leaked = 1; } \
+             function strict() { 'use strict'; sloppy(); return function () { inner = 1; }; } \
+             function* gen() { 'use strict'; yield 1; late = 1; } \
+             var it = gen(); it.next();",
+        )
+        .unwrap();
+        // Strict code makes the functions inside it strict, not its callees
+        let inner = vm.eval("strict()").unwrap();
+        assert_eq!(vm.get_global("leaked"), Some(Value::Number(1.0)));
+        assert_eq!(
+            vm.call_function(&inner, Value::Undefined, &[]),
+            not_defined("inner")
+        );
+        // A resumed generator is still strict
+        assert_eq!(vm.eval("it.next()"), not_defined("late"));
+        // Nor does a strict script make the next one strict
+        assert_eq!(vm.eval("'use strict'; 1"), Ok(Value::Number(1.0)));
+        assert_eq!(vm.eval("after = 2"), Ok(Value::Number(2.0)));
+    }
+
+    #[test]
+    fn test_host_call_error_unwinds() {
+        let mut vm = VM::default();
+        vm.eval("function inner() { return missing; } function outer() { return inner() + 1; }")
+            .unwrap();
+        let outer = vm.get_global("outer").unwrap();
+
+        assert!(vm.call_function(&outer, Value::Undefined, &[]).is_err());
+        assert!(vm.call_stack.is_empty());
+        vm.set_global("missing", 41.0);
+        assert_eq!(
+            vm.call_function(&outer, Value::Undefined, &[]),
+            Ok(Value::Number(42.0))
+        );
+    }
+
+    #[test]
+    fn test_fuel_limits_instructions() {
+        let mut vm = VM::default();
+        vm.set_fuel(Some(1000));
+
+        assert_eq!(vm.eval("while (true) {}"), Err(RuntimeError::OutOfFuel));
+        assert_eq!(vm.remaining_fuel(), Some(0));
+        assert!(vm.call_stack.is_empty());
+        assert_eq!(vm.eval("1"), Err(RuntimeError::OutOfFuel));
+
+        vm.set_fuel(Some(1000));
+        assert_eq!(
+            vm.eval("var i = 0; while (i < 10) i++; i"),
+            Ok(Value::Number(10.0))
+        );
+        assert!(vm.remaining_fuel().unwrap() < 1000);
+        vm.set_fuel(None);
+        assert_eq!(vm.eval("i + 1"), Ok(Value::Number(11.0)));
+    }
+
+    #[test]
+    fn test_fuel_covers_host_calls() {
+        let mut vm = VM::default();
+        let call = NativeFunction::new("call", |vm, _this, args| {
+            vm.call_function(&args[0], Value::Undefined, &[])
+        });
+        vm.set_global("call", call);
+        vm.set_fuel(Some(1000));
+
+        let result = vm.eval("call(function () { while (true) {} })");
+
+        assert_eq!(result, Err(RuntimeError::OutOfFuel));
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_interrupt_from_another_thread() {
+        let mut vm = VM::default();
+        let handle = vm.interrupt_handle();
+
+        let interrupter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            handle.interrupt();
+        });
+        let result = vm.eval("var n = 0; while (true) { n++; }");
+        interrupter.join().unwrap();
+
+        assert_eq!(result, Err(RuntimeError::Interrupted));
+        assert!(!vm.interrupt_handle().is_interrupted());
+        assert!(matches!(vm.get_global("n"), Some(Value::Number(n)) if n > 0.0));
+        assert_eq!(vm.eval("n = 1"), Ok(Value::Number(1.0)));
+    }
+
+    #[test]
+    fn test_catch_runtime_errors() {
+        let mut vm = VM::default();
+        let source = "
+            var caught = [];
+            try { missing; } catch (e) { caught[0] = e.name + ': ' + e.message; }
+            try { throw 42; } catch (e) { caught[1] = e; }
+            function fail() { null(); }
+            try { fail(); } catch (e) { caught[2] = e.name; }
+            caught[0] + ', ' + caught[1] + ', ' + caught[2];
+        ";
+
+        assert_eq!(
+            vm.eval(source),
+            Ok(Value::from(
+                "ReferenceError: missing is not defined, 42, TypeError"
+            ))
+        );
+        assert!(vm.handlers.is_empty());
+    }
+
+    #[test]
+    fn test_native_errors_are_catchable() {
+        let mut vm = VM::default();
+        let fail = NativeFunction::new("fail", |_vm, _this, args| {
+            Err(RuntimeError::Thrown(args[0].clone()))
+        });
+        let call = NativeFunction::new("call", |vm, _this, args| {
+            vm.call_function(&args[0], Value::Undefined, &[])
+        });
+        vm.set_global("fail", fail);
+        vm.set_global("call", call);
+
+        let source = "
+            var log = '';
+            try {
+                call(function () {
+                    try { fail('inner'); } catch (e) { log += e; }
+                    fail('outer');
+                });
+            } catch (e) { log += ' ' + e; }
+            log;
+        ";
+
+        assert_eq!(vm.eval(source), Ok(Value::from("inner outer")));
+        assert!(vm.handlers.is_empty());
+        assert!(vm.call_stack.is_empty());
+    }
+
+    #[test]
+    fn test_uncaught_throw() {
+        let mut vm = VM::default();
+
+        assert_eq!(
+            vm.eval("try { throw 1; } catch (e) { throw e + 1; }"),
+            Err(RuntimeError::Thrown(Value::Number(2.0)))
+        );
+        assert!(vm.handlers.is_empty());
+    }
+
+    #[test]
+    fn test_heap_limit_range_error_is_catchable() {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(1 << 20));
+
+        let source = "
+            var s = 'x';
+            var result;
+            try { for (;;) s = s + s; } catch (e) { result = e.name; }
+            result;
+        ";
+
+        assert_eq!(vm.eval(source), Ok(Value::from("RangeError")));
+        // Huge indices go to sparse storage rather than allocating up to them
+        assert_eq!(
+            vm.eval("var a = []; a[1e9] = 1; a[1e9]"),
+            Ok(Value::Number(1.0))
+        );
+        assert_eq!(
+            vm.eval("a.length = 4294967296"),
+            Err(RuntimeError::RangeError("Invalid array length".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_string_length_limit() {
+        let mut vm = VM::default();
+        let source = "
+            var s = 'x';
+            var result;
+            try { for (;;) s += s; } catch (e) { result = e.name + ': ' + e.message; }
+            [result, s.length];
+        ";
+
+        assert_eq!(
+            vm.eval(source).map(|v| v.to_js_string().to_string()),
+            Ok("RangeError: Invalid string length,268435456".to_string())
+        );
+    }
+
+    #[test]
+    fn test_call_stack_overflow_is_catchable() {
+        let mut vm = VM::default();
+        let overflow = Err(RuntimeError::RangeError(
+            "Maximum call stack size exceeded".to_string(),
+        ));
+
+        assert_eq!(vm.eval("function f() { f(); } f()"), overflow);
+        assert!(vm.call_stack.is_empty());
+        // Recursion through native callbacks is caught before the native
+        // stack runs out
+        assert_eq!(vm.eval("function g() { return [1].map(g); } g()"), overflow);
+        assert_eq!(vm.host_depth, 0);
+        assert_eq!(
+            vm.eval("function* gen() { yield* gen(); } [...gen()]"),
+            overflow
+        );
+        let source = "
+            var depth = 0;
+            function h() { depth++; h(); }
+            try { h(); } catch (e) { e.name + ' ' + (depth > 1000); }
+        ";
+        assert_eq!(vm.eval(source), Ok(Value::from("RangeError true")));
+        assert_eq!(
+            vm.eval("function k() { new k(); } try { k(); } catch (e) { e.message; }"),
+            Ok(Value::from("Maximum call stack size exceeded"))
+        );
+    }
+
+    #[test]
+    fn test_array_holes() {
+        let mut vm = VM::default();
+        let source = "
+            var a = [1, , undefined];
+            a[5] = 6;
+            var b = [1, 2, 3];
+            delete b[1];
+            [1 in a, 2 in a, 4 in a, '5' in a, 1 in b, 0 in b, a[1], a + '|' + b];
+        ";
+
+        let result = vm.eval(source).unwrap();
+        let Value::Array(result) = result else {
+            panic!("expected an array, got {result:?}");
+        };
+        let result: Vec<_> = result.borrow().iter().map(|(_, v)| v.clone()).collect();
+        assert_eq!(
+            result,
+            vec![
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Boolean(false),
+                Value::Boolean(true),
+                Value::Undefined,
+                Value::from("1,,,,,6|1,,3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_array_length() {
+        let mut vm = VM::default();
+        let source = "
+            var a = [1, 2, 3];
+            var lengths = [a.length, a['length']];
+            a.length = 1;
+            lengths[2] = a.length + ':' + a;
+            a[4] = 5;
+            lengths[3] = a.length + ':' + a;
+            a.length = 0;
+            lengths[4] = a.length + ':' + (0 in a) + ':' + a[4];
+            lengths[5] = [1, , ].length;
+            var b = [1, 2, 3];
+            b.length = '2';
+            lengths[6] = b.length;
+            b.length = { valueOf: function () { return 1; } };
+            lengths[7] = b + ':' + b.length;
+            lengths + '';
+        ";
+
+        assert_eq!(
+            vm.eval(source),
+            Ok(Value::from("3,3,1:1,5:1,,,,5,0:false:undefined,2,2,1:1"))
+        );
+        assert!(matches!(
+            vm.eval("a.length = '1.5'"),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert!(matches!(
+            vm.eval("a.length = -1"),
+            Err(RuntimeError::RangeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_array_index_keys() {
+        let mut vm = VM::default();
+        let source = "
+            var a = [];
+            a['1'] = 'one';
+            a[-1] = 'minus';
+            a['01'] = 'padded';
+            a[4294967295] = 'max';
+            var o = {};
+            o[1] = 'number';
+            [a.length, a[1], a['-1'], a[1.0], a['01'], a['4294967295'], o['1'], 'length' in a] + '';
+        ";
+
+        assert_eq!(
+            vm.eval(source),
+            Ok(Value::from("2,one,minus,one,padded,max,number,true"))
+        );
+        assert_eq!(vm.eval("'abc'.length + 'abc'['1']"), Ok(Value::from("3b")));
+        assert_eq!(
+            vm.eval("var u; u.x"),
+            Err(RuntimeError::TypeError(
+                "Cannot read properties of undefined (reading 'x')".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_in_and_delete_on_objects() {
+        let mut vm = VM::default();
+
+        assert_eq!(
+            vm.eval("var o = { x: 1 }; var had = 'x' in o; delete o.x; [had, 'x' in o] + ''"),
+            Ok(Value::from("true,false"))
+        );
+        assert!(matches!(
+            vm.eval("'x' in 'string'"),
+            Err(RuntimeError::TypeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_out_of_memory_is_uncatchable() {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(1 << 20));
+        let source = "
+            var list = [];
+            var caught = false;
+            try {
+                for (var i = 0; ; i++) list[i] = { index: i };
+            } catch (e) {
+                caught = true;
+            } finally {
+                caught = true;
+            }
+        ";
+
+        assert_eq!(vm.eval(source), Err(RuntimeError::OutOfMemory));
+        assert_eq!(vm.get_global("caught"), Some(Value::Boolean(false)));
+        assert!(vm.handlers.is_empty());
+
+        // Dropping the data frees room again
+        vm.eval("list = null").unwrap();
+        assert_eq!(vm.eval("var o = { a: 1 }; o.a"), Ok(Value::Number(1.0)));
+        assert!(vm.heap_usage() < 1 << 20);
+    }
+
+    #[test]
+    fn test_eval_unloads_finished_scripts() {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(1 << 20));
+        let (code, constants) = (vm.program.len(), vm.constants.len());
+
+        vm.eval("var n = 0;").unwrap();
+        for i in 0..5000 {
+            let source = format!("n += [{i}].map(x => x + 1)[0] - {i}; 'item' + {i};");
+            assert_eq!(vm.eval(&source), Ok(Value::from(format!("item{i}"))));
+        }
+        assert_eq!(vm.eval("n"), Ok(Value::Number(5000.0)));
+        assert_eq!((vm.program.len(), vm.constants.len()), (code, constants));
+
+        // Functions and suspended generators keep their script loaded
+        vm.eval("function f() { return 'f'; }").unwrap();
+        vm.eval("var g = (function* () { yield 1; yield 2; })(); g.next();")
+            .unwrap();
+        vm.eval("[1, 2, 3].forEach(x => x);").unwrap();
+        assert_eq!(vm.eval("f() + g.next().value"), Ok(Value::from("f2")));
+        assert!(vm.program.len() > code);
+    }
+
+    #[test]
+    fn test_eval_charges_constants() {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(1 << 20));
+        let literal = "x".repeat(2 << 20);
+
+        assert!(matches!(
+            vm.eval(&format!("var s = '{literal}';")),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert_eq!(vm.eval("var o = { a: 1 }; o.a"), Ok(Value::Number(1.0)));
+
+        // A literal that fits is freed along with the variable holding it
+        for c in ['a', 'b', 'c'] {
+            let literal = c.to_string().repeat(600 << 10);
+            vm.eval(&format!("var s = '{literal}';")).unwrap();
+            vm.eval("s = null").unwrap();
+        }
+        assert!(vm.heap_usage() < 1 << 20);
+    }
+
+    #[test]
+    fn test_garbage_does_not_count_against_limit() {
+        let mut vm = VM::default();
+        vm.set_heap_limit(Some(1 << 20));
+        let source = "
+            var s;
+            for (var i = 0; i < 20000; i++) { s = [i, i + 1, 'x' + i]; }
+            s[2];
+        ";
+
+        assert_eq!(vm.eval(source), Ok(Value::from("x19999")));
+    }
+
+    #[test]
+    fn test_heap_usage_tracks_strings() {
+        let mut vm = VM::default();
+        let before = vm.heap_usage();
+        vm.eval("var s = 'abcdefghijklmnopqrstuvwxyz0123456789'; s = s + s + s + s;")
+            .unwrap();
+
+        assert!(vm.heap_usage() >= before + 144);
+    }
 }
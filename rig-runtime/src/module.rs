@@ -0,0 +1,1130 @@
+//! ES modules.
+//!
+//! The host resolves and loads modules through a `ModuleLoader`. Importing
+//! a module loads every module it depends on that the VM has not seen,
+//! links each import to the variable it names in the exporting module, and
+//! then evaluates the modules that have not run yet, dependencies first.
+//!
+//! Each module has a scope of its own below the global scope. An imported
+//! name is bound in the importer's scope to a binding object that points at
+//! the exporter's variable, and `GetScope` reads through it, so imports see
+//! later assignments to what they import.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use indexmap::IndexMap;
+use rig_bytecode::Instruction;
+use rig_parser::ast::{ExportDecl, ImportSpecifier, Stmt};
+
+use crate::compiler;
+use crate::generator::Resume;
+use crate::heap::{ENTRY_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::{Atom, JsObject, JsSymbol, RuntimeError, Scope, Value, VM};
+
+/// Finds and loads the modules a script imports.
+pub trait ModuleLoader {
+    /// Resolves `specifier`, as written in the module named `referrer`, to
+    /// the name of a module. `referrer` is `None` for the specifier passed
+    /// to `VM::import_module`. Specifiers that resolve to the same name
+    /// refer to the same module.
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<String, RuntimeError>;
+
+    /// Loads the module named `name`.
+    fn load(&self, name: &str) -> Result<ModuleSource, RuntimeError>;
+}
+
+/// A module as a loader hands it to the VM.
+pub enum ModuleSource {
+    Source(String),
+    /// A module compiled ahead of time, for example one cached from an
+    /// earlier load
+    Compiled(CompiledModule),
+}
+
+/// A module compiled to bytecode, with the imports and exports it declares.
+#[derive(Debug, Clone)]
+pub struct CompiledModule {
+    /// Code addressed as if loaded at the start of an empty program. It
+    /// declares the module's variables and functions and returns.
+    code: Vec<Instruction>,
+    constants: Vec<Value>,
+    /// Where the body starts
+    body: usize,
+    /// Whether the body awaits at the top level
+    is_async: bool,
+    /// Specifiers of the modules imported or re-exported from, in the order
+    /// they first appear
+    requests: Vec<String>,
+    imports: Vec<ImportEntry>,
+    exports: Vec<ExportEntry>,
+}
+
+#[derive(Debug, Clone)]
+struct ImportEntry {
+    /// Index into `requests`
+    request: usize,
+    /// The name imported, or `None` for the namespace
+    imported: Option<String>,
+    local: String,
+}
+
+#[derive(Debug, Clone)]
+enum ExportEntry {
+    /// `export { local as exported }` and exported declarations
+    Local { exported: String, local: String },
+    /// `export { imported as exported } from ...`, or an imported name
+    /// exported again
+    Indirect {
+        exported: String,
+        request: usize,
+        imported: String,
+    },
+    /// `export * as exported from ...`, or an imported namespace exported
+    /// again
+    Namespace { exported: String, request: usize },
+    /// `export * from ...`
+    Star { request: usize },
+}
+
+impl ExportEntry {
+    fn exported(&self) -> Option<&str> {
+        match self {
+            ExportEntry::Local { exported, .. }
+            | ExportEntry::Indirect { exported, .. }
+            | ExportEntry::Namespace { exported, .. } => Some(exported),
+            ExportEntry::Star { .. } => None,
+        }
+    }
+}
+
+fn syntax_error(message: String) -> RuntimeError {
+    RuntimeError::SyntaxError(message)
+}
+
+impl CompiledModule {
+    /// Parses and compiles the source of a module.
+    pub fn compile(source: &str) -> Result<Self, RuntimeError> {
+        let module =
+            rig_parser::parse_module(source).map_err(|err| syntax_error(err.to_string()))?;
+        let (compiled, body) = compiler::compile_module(&module)?;
+        let mut requests = Vec::new();
+        let mut request = |source: &str| match requests.iter().position(|r| r == source) {
+            Some(idx) => idx,
+            None => {
+                requests.push(source.to_string());
+                requests.len() - 1
+            }
+        };
+        let mut imports = Vec::new();
+        let mut exports = Vec::new();
+        // Names exported from this module, before imported names are told
+        // apart from declared ones
+        let mut locals = Vec::new();
+        for stmt in &module.body {
+            match stmt {
+                Stmt::Import(decl) => {
+                    let request = request(&decl.source);
+                    for specifier in &decl.specifiers {
+                        let (imported, local) = match specifier {
+                            ImportSpecifier::Default(local) => (Some("default"), local),
+                            ImportSpecifier::Namespace(local) => (None, local),
+                            ImportSpecifier::Named { imported, local } => {
+                                (Some(imported.as_str()), local)
+                            }
+                        };
+                        imports.push(ImportEntry {
+                            request,
+                            imported: imported.map(str::to_string),
+                            local: local.clone(),
+                        });
+                    }
+                }
+                Stmt::Export(ExportDecl::Declaration(decl)) => {
+                    for name in compiler::declared_names(std::slice::from_ref(decl)) {
+                        locals.push((name, name));
+                    }
+                }
+                Stmt::Export(ExportDecl::DefaultFunction(function)) => {
+                    locals.push(("default", function.name.as_deref().unwrap_or("default")));
+                }
+                Stmt::Export(ExportDecl::DefaultExpr(_)) => locals.push(("default", "default")),
+                Stmt::Export(ExportDecl::Named {
+                    specifiers,
+                    source: None,
+                }) => {
+                    for specifier in specifiers {
+                        locals.push((&specifier.exported, &specifier.local));
+                    }
+                }
+                Stmt::Export(ExportDecl::Named {
+                    specifiers,
+                    source: Some(source),
+                }) => {
+                    let request = request(source);
+                    for specifier in specifiers {
+                        exports.push(ExportEntry::Indirect {
+                            exported: specifier.exported.clone(),
+                            request,
+                            imported: specifier.local.clone(),
+                        });
+                    }
+                }
+                Stmt::Export(ExportDecl::All { exported, source }) => {
+                    let request = request(source);
+                    exports.push(match exported {
+                        Some(exported) => ExportEntry::Namespace {
+                            exported: exported.clone(),
+                            request,
+                        },
+                        None => ExportEntry::Star { request },
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let declared = compiler::declared_names(&module.body);
+        for (i, import) in imports.iter().enumerate() {
+            let local = import.local.as_str();
+            if declared.contains(&local) || imports[..i].iter().any(|other| other.local == local) {
+                return Err(syntax_error(format!(
+                    "Identifier '{}' has already been declared",
+                    local
+                )));
+            }
+        }
+        for (exported, local) in locals {
+            let exported = exported.to_string();
+            let entry = match imports.iter().find(|import| import.local == local) {
+                Some(ImportEntry {
+                    request,
+                    imported: Some(imported),
+                    ..
+                }) => ExportEntry::Indirect {
+                    exported,
+                    request: *request,
+                    imported: imported.clone(),
+                },
+                Some(ImportEntry { request, .. }) => ExportEntry::Namespace {
+                    exported,
+                    request: *request,
+                },
+                None if declared.contains(&local) => ExportEntry::Local {
+                    exported,
+                    local: local.to_string(),
+                },
+                None => {
+                    return Err(syntax_error(format!(
+                        "Export '{}' is not defined in module",
+                        local
+                    )))
+                }
+            };
+            exports.push(entry);
+        }
+        for (i, export) in exports.iter().enumerate() {
+            let Some(name) = export.exported() else {
+                continue;
+            };
+            if exports[..i]
+                .iter()
+                .any(|other| other.exported() == Some(name))
+            {
+                return Err(syntax_error(format!("Duplicate export of '{}'", name)));
+            }
+        }
+
+        Ok(CompiledModule {
+            code: compiled.code,
+            constants: compiled.constants,
+            body,
+            is_async: module.has_top_level_await,
+            requests,
+            imports,
+            exports,
+        })
+    }
+}
+
+/// Loads modules from files below a root directory.
+///
+/// Module names are paths from the root, such as `/lib/util.js`. Specifiers
+/// are resolved like relative URLs: `/` starts from the root and `./` or
+/// `../` from the directory of the importing module. No specifier reaches
+/// outside the root.
+#[derive(Debug, Clone)]
+pub struct FileSystemLoader {
+    root: PathBuf,
+}
+
+impl FileSystemLoader {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        FileSystemLoader { root: root.into() }
+    }
+}
+
+impl ModuleLoader for FileSystemLoader {
+    fn resolve(&self, specifier: &str, referrer: Option<&str>) -> Result<String, RuntimeError> {
+        let base = match referrer {
+            _ if specifier.starts_with('/') => "",
+            Some(referrer) if specifier.starts_with("./") || specifier.starts_with("../") => {
+                &referrer[..referrer.rfind('/').unwrap_or(0)]
+            }
+            None if specifier.starts_with("./") || specifier.starts_with("../") => "",
+            _ => {
+                return Err(RuntimeError::TypeError(format!(
+                    "Failed to resolve module specifier \"{}\". Relative references must start with either \"/\", \"./\", or \"../\".",
+                    specifier
+                )))
+            }
+        };
+        let mut segments: Vec<&str> = base.split('/').filter(|s| !s.is_empty()).collect();
+        for segment in specifier.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => {
+                    segments.pop();
+                }
+                segment => segments.push(segment),
+            }
+        }
+        Ok(format!("/{}", segments.join("/")))
+    }
+
+    fn load(&self, name: &str) -> Result<ModuleSource, RuntimeError> {
+        let path = self.root.join(name.trim_start_matches('/'));
+        std::fs::read_to_string(path)
+            .map(ModuleSource::Source)
+            .map_err(|err| {
+                RuntimeError::TypeError(format!("Cannot load module '{}': {}", name, err))
+            })
+    }
+}
+
+/// A variable of one module as another module imports it.
+#[derive(Debug, Clone)]
+pub(crate) struct Binding {
+    scope: Scope,
+    name: Atom,
+}
+
+impl Binding {
+    pub fn get(&self) -> Value {
+        self.scope
+            .borrow()
+            .get(&self.name)
+            .cloned()
+            .unwrap_or(Value::Undefined)
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+}
+
+/// A module the VM has loaded.
+pub(crate) struct ModuleRecord {
+    name: String,
+    scope: Scope,
+    module: CompiledModule,
+    /// The module each request resolved to
+    dependencies: Vec<usize>,
+    /// Where the body starts in the VM's program, once the module's code
+    /// has been added to it
+    body: usize,
+    status: Status,
+    namespace: Option<Rc<RefCell<JsObject>>>,
+}
+
+enum Status {
+    /// Linked, but the body has not run
+    Linked,
+    /// The body is waiting at a top-level await; the promise settles when
+    /// it finishes
+    Evaluating(Value),
+    Evaluated,
+    /// The body threw this value
+    Failed(Value),
+}
+
+impl ModuleRecord {
+    /// The values the module holds on to, for measuring the heap.
+    pub fn values(&self) -> Vec<Value> {
+        let mut values: Vec<_> = self.module.constants.clone();
+        values.extend(self.namespace.clone().map(Value::Object));
+        if let Status::Evaluating(value) | Status::Failed(value) = &self.status {
+            values.push(value.clone());
+        }
+        values
+    }
+
+    pub fn scope(&self) -> &Scope {
+        &self.scope
+    }
+}
+
+/// What an exported name refers to.
+#[derive(Debug, Clone, PartialEq)]
+enum Target {
+    /// A variable of the module
+    Local(usize, String),
+    /// The namespace of the module
+    Namespace(usize),
+}
+
+enum Resolution {
+    Found(Target),
+    NotFound,
+    /// Several `export *` provide the name, for different targets
+    Ambiguous,
+}
+
+impl VM {
+    /// Sets the loader `import_module` and `import` declarations find
+    /// modules with, or removes it with `None`.
+    pub fn set_module_loader(&mut self, loader: Option<Box<dyn ModuleLoader>>) {
+        self.module_loader = loader.map(Rc::from);
+    }
+
+    /// Imports the module `specifier` resolves to, loading and linking any
+    /// modules it depends on that have not been loaded yet. Returns a
+    /// promise for the module's namespace, which settles once the module
+    /// and its dependencies have been evaluated; call `run_microtasks` to
+    /// let modules that await at the top level finish. Modules that fail to
+    /// load or link are not kept, so importing them again retries.
+    pub fn import_module(&mut self, specifier: &str) -> Result<Value, RuntimeError> {
+        let Some(loader) = self.module_loader.clone() else {
+            return Err(RuntimeError::TypeError(format!(
+                "Cannot import module '{}' without a module loader",
+                specifier
+            )));
+        };
+        let first_new = self.modules.len();
+        let entry = match self
+            .load_module(&*loader, specifier, None)
+            .and_then(|entry| self.link(first_new).map(|_| entry))
+        {
+            Ok(entry) => entry,
+            Err(err) => {
+                for record in self.modules.drain(first_new..) {
+                    self.module_ids.remove(&record.name);
+                }
+                return Err(err);
+            }
+        };
+        self.instantiate(first_new)?;
+        let promise = self.create_promise()?;
+        let mut order = Vec::new();
+        self.evaluation_order(entry, &mut order);
+        self.evaluate_modules(Rc::new(order), 0, entry, promise.clone())?;
+        Ok(promise)
+    }
+
+    /// Loads the module `specifier` names and the modules it imports from,
+    /// returning its index.
+    fn load_module(
+        &mut self,
+        loader: &dyn ModuleLoader,
+        specifier: &str,
+        referrer: Option<&str>,
+    ) -> Result<usize, RuntimeError> {
+        let name = loader.resolve(specifier, referrer)?;
+        if let Some(&id) = self.module_ids.get(&name) {
+            return Ok(id);
+        }
+        let module = match loader.load(&name)? {
+            ModuleSource::Source(source) => CompiledModule::compile(&source)?,
+            ModuleSource::Compiled(module) => module,
+        };
+        self.charge(OBJECT_SIZE)?;
+        let id = self.modules.len();
+        let requests = module.requests.clone();
+        self.module_ids.insert(name.clone(), id);
+        self.modules.push(ModuleRecord {
+            name: name.clone(),
+            scope: Rc::new(RefCell::new(HashMap::new())),
+            module,
+            dependencies: Vec::new(),
+            body: 0,
+            status: Status::Linked,
+            namespace: None,
+        });
+        for request in requests {
+            let dependency = self.load_module(loader, &request, Some(&name))?;
+            self.modules[id].dependencies.push(dependency);
+        }
+        Ok(id)
+    }
+
+    /// Checks that every name the modules from `first_new` on import can be
+    /// found.
+    fn link(&mut self, first_new: usize) -> Result<(), RuntimeError> {
+        for id in first_new..self.modules.len() {
+            let module = &self.modules[id].module;
+            let imports = module.imports.iter().filter_map(|import| {
+                let imported = import.imported.as_ref()?;
+                Some((import.request, imported.clone()))
+            });
+            let indirect = module.exports.iter().filter_map(|export| match export {
+                ExportEntry::Indirect {
+                    request, imported, ..
+                } => Some((*request, imported.clone())),
+                _ => None,
+            });
+            let names: Vec<_> = imports.chain(indirect).collect();
+            for (request, name) in names {
+                let dependency = self.modules[id].dependencies[request];
+                let message = match self.resolve_export(dependency, &name, &mut Vec::new()) {
+                    Resolution::Found(_) => continue,
+                    Resolution::NotFound => "does not provide an export named",
+                    Resolution::Ambiguous => "contains conflicting star exports for name",
+                };
+                return Err(syntax_error(format!(
+                    "The requested module '{}' {} '{}'",
+                    self.modules[id].module.requests[request], message, name
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Finds what the module `id` exports as `name`, following re-exports.
+    /// `seen` holds the lookups in progress, to break cycles.
+    fn resolve_export(&self, id: usize, name: &str, seen: &mut Vec<(usize, String)>) -> Resolution {
+        if seen.iter().any(|(m, n)| *m == id && n == name) {
+            return Resolution::NotFound;
+        }
+        seen.push((id, name.to_string()));
+        let record = &self.modules[id];
+        for export in &record.module.exports {
+            match export {
+                ExportEntry::Local { exported, local } if exported == name => {
+                    return Resolution::Found(Target::Local(id, local.clone()));
+                }
+                ExportEntry::Indirect {
+                    exported,
+                    request,
+                    imported,
+                } if exported == name => {
+                    return self.resolve_export(record.dependencies[*request], imported, seen);
+                }
+                ExportEntry::Namespace { exported, request } if exported == name => {
+                    return Resolution::Found(Target::Namespace(record.dependencies[*request]));
+                }
+                _ => {}
+            }
+        }
+        // `export *` never provides a default export
+        if name == "default" {
+            return Resolution::NotFound;
+        }
+        let mut found = None;
+        for export in &record.module.exports {
+            let ExportEntry::Star { request } = export else {
+                continue;
+            };
+            match self.resolve_export(record.dependencies[*request], name, seen) {
+                Resolution::Found(target) => match &found {
+                    Some(other) if *other != target => return Resolution::Ambiguous,
+                    _ => found = Some(target),
+                },
+                Resolution::Ambiguous => return Resolution::Ambiguous,
+                Resolution::NotFound => {}
+            }
+        }
+        found.map_or(Resolution::NotFound, Resolution::Found)
+    }
+
+    /// The names the module `id` exports, including those from `export *`.
+    fn export_names(&self, id: usize, visited: &mut Vec<usize>) -> Vec<String> {
+        if visited.contains(&id) {
+            return Vec::new();
+        }
+        visited.push(id);
+        let record = &self.modules[id];
+        let mut names: Vec<String> = record
+            .module
+            .exports
+            .iter()
+            .filter_map(|export| export.exported().map(str::to_string))
+            .collect();
+        for export in &record.module.exports {
+            if let ExportEntry::Star { request } = export {
+                for name in self.export_names(record.dependencies[*request], visited) {
+                    if name != "default" && !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+        names
+    }
+
+    /// Binds the imports of the modules from `first_new` on, then adds
+    /// their code to the program and declares their variables, so that
+    /// functions are ready before any module body runs.
+    fn instantiate(&mut self, first_new: usize) -> Result<(), RuntimeError> {
+        for id in first_new..self.modules.len() {
+            for import in self.modules[id].module.imports.clone() {
+                let dependency = self.modules[id].dependencies[import.request];
+                let local = Atom::intern(&import.local);
+                let binding = match &import.imported {
+                    None => {
+                        let namespace = self.module_namespace(dependency)?;
+                        self.charge(OBJECT_SIZE + ENTRY_SIZE)?;
+                        Binding {
+                            scope: Rc::new(RefCell::new(HashMap::from([(
+                                local.clone(),
+                                namespace,
+                            )]))),
+                            name: local.clone(),
+                        }
+                    }
+                    Some(imported) => {
+                        match self.resolve_export(dependency, imported, &mut Vec::new()) {
+                            Resolution::Found(target) => self.binding(target)?,
+                            _ => {
+                                return Err(RuntimeError::Internal(
+                                    "Import was not linked".to_string(),
+                                ))
+                            }
+                        }
+                    }
+                };
+                self.charge(OBJECT_SIZE + ENTRY_SIZE)?;
+                let binding = JsObject::with_kind(Value::Null, ObjectKind::ImportBinding(binding));
+                self.modules[id]
+                    .scope
+                    .borrow_mut()
+                    .insert(local, Value::Object(Rc::new(RefCell::new(binding))));
+            }
+        }
+        for id in first_new..self.modules.len() {
+            let code_base = self.program.len();
+            let const_base = self.constants.len() as u32;
+            let record = &mut self.modules[id];
+            let code = std::mem::take(&mut record.module.code);
+            let constants = std::mem::take(&mut record.module.constants);
+            record.body = code_base + record.module.body;
+            let scopes = vec![self.scopes[0].clone(), record.scope.clone()];
+            self.add_constants(constants)?;
+            self.program.extend(
+                code.into_iter()
+                    .map(|instruction| relocate(instruction, code_base as u32, const_base)),
+            );
+            self.call_host(code_base, scopes, &[], None)?;
+        }
+        Ok(())
+    }
+
+    /// A binding for what an exported name refers to.
+    fn binding(&mut self, target: Target) -> Result<Binding, RuntimeError> {
+        Ok(match target {
+            Target::Local(id, name) => Binding {
+                scope: self.modules[id].scope.clone(),
+                name: Atom::intern(&name),
+            },
+            Target::Namespace(id) => {
+                let namespace = self.module_namespace(id)?;
+                self.charge(OBJECT_SIZE + ENTRY_SIZE)?;
+                let name = Atom::intern("*namespace*");
+                Binding {
+                    scope: Rc::new(RefCell::new(HashMap::from([(name.clone(), namespace)]))),
+                    name,
+                }
+            }
+        })
+    }
+
+    /// The namespace object of the module `id`, made the first time it is
+    /// asked for. Its properties are the module's exports, in code unit
+    /// order, read live from the variables they refer to.
+    fn module_namespace(&mut self, id: usize) -> Result<Value, RuntimeError> {
+        if let Some(namespace) = &self.modules[id].namespace {
+            return Ok(Value::Object(namespace.clone()));
+        }
+        self.charge(OBJECT_SIZE)?;
+        let namespace = Rc::new(RefCell::new(JsObject::with_kind(
+            Value::Null,
+            ObjectKind::Namespace(IndexMap::new()),
+        )));
+        // Store it first, since an export may refer back to it
+        self.modules[id].namespace = Some(namespace.clone());
+        let mut names = self.export_names(id, &mut Vec::new());
+        names.sort_by(|a, b| a.encode_utf16().cmp(b.encode_utf16()));
+        let mut bindings = IndexMap::new();
+        for name in names {
+            // Ambiguous names are left out
+            if let Resolution::Found(target) = self.resolve_export(id, &name, &mut Vec::new()) {
+                let binding = self.binding(target)?;
+                bindings.insert(Atom::intern(&name), binding);
+            }
+        }
+        self.charge((bindings.len() + 1) * 2 * ENTRY_SIZE)?;
+        let mut obj = namespace.borrow_mut();
+        // The properties only list the exports; their values are read from
+        // the bindings
+        for name in bindings.keys() {
+            obj.properties_mut().insert(name.clone(), Value::Undefined);
+        }
+        obj.properties_mut().insert(
+            Atom::from_symbol(&JsSymbol::to_string_tag()),
+            Value::from("Module"),
+        );
+        *obj.kind_mut() = ObjectKind::Namespace(bindings);
+        drop(obj);
+        Ok(Value::Object(namespace))
+    }
+
+    /// The modules to evaluate for the module `id`, dependencies before the
+    /// modules that import them. A module in a cycle runs before the module
+    /// that first led to it.
+    fn evaluation_order(&self, id: usize, order: &mut Vec<usize>) {
+        if order.contains(&id) {
+            return;
+        }
+        order.push(id);
+        for &dependency in &self.modules[id].dependencies {
+            self.evaluation_order(dependency, order);
+        }
+        // Move the module after its dependencies
+        let at = order.iter().position(|&m| m == id).unwrap();
+        order.remove(at);
+        order.push(id);
+    }
+
+    /// Evaluates the modules in `order` from `next` on, one after another,
+    /// then settles `promise` with the namespace of `entry`, or with the
+    /// first error.
+    fn evaluate_modules(
+        &mut self,
+        order: Rc<Vec<usize>>,
+        mut next: usize,
+        entry: usize,
+        promise: Value,
+    ) -> Result<(), RuntimeError> {
+        while let Some(&id) = order.get(next) {
+            next += 1;
+            let record = &self.modules[id];
+            let waiting = match &record.status {
+                Status::Evaluated => continue,
+                Status::Failed(reason) => {
+                    let reason = reason.clone();
+                    return self.reject_promise(&promise, reason);
+                }
+                Status::Evaluating(evaluation) => evaluation.clone(),
+                Status::Linked => {
+                    let scopes = vec![self.scopes[0].clone(), record.scope.clone()];
+                    let is_async = record.module.is_async;
+                    match self.call_host(record.body, scopes, &[], None) {
+                        Ok(evaluation) if is_async => {
+                            self.modules[id].status = Status::Evaluating(evaluation.clone());
+                            evaluation
+                        }
+                        Ok(_) => {
+                            self.modules[id].status = Status::Evaluated;
+                            continue;
+                        }
+                        Err(err) => {
+                            let reason = self.error_value(err)?;
+                            self.modules[id].status = Status::Failed(reason.clone());
+                            return self.reject_promise(&promise, reason);
+                        }
+                    }
+                }
+            };
+            // Carry on once the module has finished awaiting
+            return self.await_value(waiting, move |vm, resume| match resume {
+                Resume::Throw(reason) => {
+                    vm.modules[id].status = Status::Failed(reason.clone());
+                    vm.reject_promise(&promise, reason)
+                }
+                Resume::Next(_) | Resume::Return(_) => {
+                    vm.modules[id].status = Status::Evaluated;
+                    vm.evaluate_modules(order.clone(), next, entry, promise.clone())
+                }
+            });
+        }
+        let namespace = self.module_namespace(entry)?;
+        self.resolve_promise(&promise, namespace)
+    }
+}
+
+/// Moves an instruction of a compiled module to where its code and
+/// constants were added to the VM's program.
+fn relocate(instruction: Instruction, code_base: u32, const_base: u32) -> Instruction {
+    match instruction {
+        Instruction::LoadConst { reg, const_idx } => Instruction::LoadConst {
+            reg,
+            const_idx: const_base + const_idx,
+        },
+        Instruction::Closure { reg, func_idx } => Instruction::Closure {
+            reg,
+            func_idx: code_base + func_idx,
+        },
+        Instruction::GetScope { dst, var_idx } => Instruction::GetScope {
+            dst,
+            var_idx: const_base + var_idx,
+        },
+        Instruction::TypeOfScope { dst, var_idx } => Instruction::TypeOfScope {
+            dst,
+            var_idx: const_base + var_idx,
+        },
+        Instruction::SetScope { var_idx, src } => Instruction::SetScope {
+            var_idx: const_base + var_idx,
+            src,
+        },
+        Instruction::DeclareFunc {
+            reg,
+            name_idx,
+            param_count,
+            kind,
+        } => Instruction::DeclareFunc {
+            reg,
+            name_idx: const_base + name_idx,
+            param_count,
+            kind,
+        },
+        Instruction::DeclareVar { name_idx } => Instruction::DeclareVar {
+            name_idx: const_base + name_idx,
+        },
+        Instruction::CreatePrivateName { dst, name_idx } => Instruction::CreatePrivateName {
+            dst,
+            name_idx: const_base + name_idx,
+        },
+        instruction => instruction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves modules from a table, naming each by its specifier.
+    #[derive(Default, Clone)]
+    struct TableLoader {
+        modules: Rc<RefCell<HashMap<String, String>>>,
+        precompile: bool,
+    }
+
+    impl TableLoader {
+        fn with(modules: &[(&str, &str)]) -> Self {
+            let loader = TableLoader::default();
+            for (name, source) in modules {
+                loader.add(name, source);
+            }
+            loader
+        }
+
+        fn add(&self, name: &str, source: &str) {
+            self.modules
+                .borrow_mut()
+                .insert(name.to_string(), source.to_string());
+        }
+    }
+
+    impl ModuleLoader for TableLoader {
+        fn resolve(
+            &self,
+            specifier: &str,
+            _referrer: Option<&str>,
+        ) -> Result<String, RuntimeError> {
+            Ok(specifier.to_string())
+        }
+
+        fn load(&self, name: &str) -> Result<ModuleSource, RuntimeError> {
+            let Some(source) = self.modules.borrow().get(name).cloned() else {
+                return Err(RuntimeError::TypeError(format!(
+                    "Cannot find module '{}'",
+                    name
+                )));
+            };
+            if self.precompile {
+                return CompiledModule::compile(&source).map(ModuleSource::Compiled);
+            }
+            Ok(ModuleSource::Source(source))
+        }
+    }
+
+    fn vm_with(loader: &TableLoader) -> VM {
+        let mut vm = VM::default();
+        vm.set_module_loader(Some(Box::new(loader.clone())));
+        vm.eval("var log = []").unwrap();
+        vm
+    }
+
+    /// Imports `specifier` and runs the jobs that follow, returning what the
+    /// modules logged and how the import settled.
+    fn import(vm: &mut VM, specifier: &str) -> String {
+        let promise = vm.import_module(specifier).unwrap();
+        vm.set_global("promise", promise);
+        vm.eval(
+            "promise.then(ns => { namespace = ns; log.push('done'); }, \
+             e => log.push('error ' + (e.message || e)))",
+        )
+        .unwrap();
+        vm.run_microtasks().unwrap();
+        let log = vm.eval("var out = log.join(); log = []; out").unwrap();
+        log.to_js_string().to_std_string_lossy()
+    }
+
+    #[test]
+    fn test_imports_and_exports() {
+        let loader = TableLoader::with(&[
+            (
+                "main",
+                "import def, { a, b as c } from 'lib'; import * as ns from 'lib'; \
+                 import { x, y as why } from 'reexport'; import * as re from 'reexport'; \
+                 import fn from 'fn'; \
+                 log.push(def, a, c, ns.a, x, why, fn()); \
+                 log.push(Object.keys(ns).join('|'), Object.keys(re).join('|')); \
+                 log.push(String(ns[Symbol.toStringTag]), re.nested.a, typeof ns);",
+            ),
+            (
+                "lib",
+                "export default 'def'; export const a = 1; let b = 2; export { b };",
+            ),
+            (
+                "reexport",
+                "export { a as x } from 'lib'; export * from 'more'; \
+                 export * as nested from 'lib';",
+            ),
+            ("more", "export const y = 'y'; export default 'hidden';"),
+            ("fn", "export default function () { return 'fn'; }"),
+        ]);
+        let mut vm = vm_with(&loader);
+        assert_eq!(
+            import(&mut vm, "main"),
+            "def,1,2,1,1,y,fn,a|b|default,nested|x|y,Module,1,object,done"
+        );
+        // Each module runs once, however often it is imported
+        assert_eq!(import(&mut vm, "lib"), "done");
+        assert_eq!(
+            vm.eval("Object.values(namespace).join()").unwrap(),
+            Value::from("1,2,def")
+        );
+    }
+
+    #[test]
+    fn test_live_bindings() {
+        let loader = TableLoader::with(&[
+            (
+                "counter",
+                "export let count = 0; export function increment() { count++; }",
+            ),
+            (
+                "main",
+                "import { count, increment } from 'counter'; import * as c from 'counter'; \
+                 log.push(count); increment(); log.push(count, c.count, JSON.stringify(c)); \
+                 try { count = 5; } catch (e) { log.push(e.message); } \
+                 try { c.count = 5; } catch (e) { log.push(e.message); } \
+                 log.push(delete c.count, c.count);",
+            ),
+        ]);
+        let mut vm = vm_with(&loader);
+        assert_eq!(
+            import(&mut vm, "main"),
+            "0,1,1,{\"count\":1},Assignment to constant variable.,\
+             Cannot assign to read only property 'count' of object '[object Module]',\
+             false,1,done"
+        );
+    }
+
+    #[test]
+    fn test_cycles() {
+        let loader = TableLoader::with(&[
+            (
+                "a",
+                "import { b } from 'b'; export function a() { return 'a'; } \
+                 log.push('a runs ' + b());",
+            ),
+            (
+                "b",
+                "import { a } from 'a'; export function b() { return 'b'; } \
+                 log.push('b runs ' + a());",
+            ),
+            (
+                "self",
+                "import * as self from 'self'; export const me = self.me; log.push(String(me));",
+            ),
+        ]);
+        let mut vm = vm_with(&loader);
+        // Functions are declared before any module runs
+        assert_eq!(import(&mut vm, "a"), "b runs a,a runs b,done");
+        assert_eq!(import(&mut vm, "b"), "done");
+        assert_eq!(import(&mut vm, "self"), "undefined,done");
+    }
+
+    #[test]
+    fn test_top_level_await() {
+        let loader = TableLoader::with(&[
+            (
+                "slow",
+                "log.push('slow start'); await null; log.push('slow end'); \
+                 export default await Promise.resolve('v');",
+            ),
+            ("sibling", "log.push('sibling');"),
+            (
+                "main",
+                "import value from 'slow'; import 'sibling'; log.push('main ' + value);",
+            ),
+            ("late", "await null; throw 'late';"),
+        ]);
+        let mut vm = vm_with(&loader);
+        vm.import_module("main").unwrap();
+        assert_eq!(vm.eval("log.join()").unwrap(), Value::from("slow start"));
+        vm.eval("log = []").unwrap();
+        // Importing again waits for the evaluation under way
+        assert_eq!(import(&mut vm, "main"), "slow end,sibling,main v,done");
+        assert_eq!(import(&mut vm, "late"), "error late");
+        assert_eq!(import(&mut vm, "late"), "error late");
+    }
+
+    #[test]
+    fn test_link_errors() {
+        let loader = TableLoader::with(&[
+            ("lib", "export const a = 1;"),
+            ("s1", "export const z = 1;"),
+            ("s2", "export const z = 2;"),
+            ("star", "export * from 's1'; export * from 's2';"),
+            ("missing", "import { nope } from 'lib';"),
+            ("conflict", "import { z } from 'star';"),
+            ("unloadable", "import 'nowhere';"),
+            (
+                "names",
+                "import * as n from 'star'; log.push(Object.keys(n).length);",
+            ),
+        ]);
+        let mut vm = vm_with(&loader);
+        let error = |vm: &mut VM, specifier| vm.import_module(specifier).unwrap_err().to_string();
+        assert!(error(&mut vm, "missing")
+            .contains("The requested module 'lib' does not provide an export named 'nope'"));
+        assert!(error(&mut vm, "conflict").contains(
+            "The requested module 'star' contains conflicting star exports for name 'z'"
+        ));
+        assert!(error(&mut vm, "unloadable").contains("Cannot find module 'nowhere'"));
+        // Ambiguous names are left out of the namespace
+        assert_eq!(import(&mut vm, "names"), "0,done");
+
+        // Modules that failed to link are loaded again next time
+        loader.add("nowhere", "log.push('found');");
+        assert_eq!(import(&mut vm, "unloadable"), "found,done");
+
+        let mut vm = VM::default();
+        assert!(vm.import_module("lib").is_err());
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        let loader = TableLoader::with(&[
+            ("throws", "log.push('throws'); null.x;"),
+            ("main", "import 'throws'; log.push('main');"),
+        ]);
+        let mut vm = vm_with(&loader);
+        assert_eq!(
+            import(&mut vm, "main"),
+            "throws,error Cannot read properties of null (reading 'x')"
+        );
+        // A module that threw keeps its error
+        assert_eq!(
+            import(&mut vm, "main"),
+            "error Cannot read properties of null (reading 'x')"
+        );
+    }
+
+    #[test]
+    fn test_strict_mode() {
+        let loader = TableLoader::with(&[
+            ("main", "undeclared = 1;"),
+            ("lib", "export function leak() { leaked = 1; }"),
+        ]);
+        let mut vm = vm_with(&loader);
+        assert_eq!(import(&mut vm, "main"), "error undeclared is not defined");
+        assert_eq!(import(&mut vm, "lib"), "done");
+        // Module functions stay strict when sloppy scripts call them
+        assert_eq!(
+            vm.eval("namespace.leak()"),
+            Err(RuntimeError::ReferenceError(
+                "leaked is not defined".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_compile_errors() {
+        let error = |source| CompiledModule::compile(source).unwrap_err().to_string();
+        assert!(error("export const a = 1; export { a };").contains("Duplicate export of 'a'"));
+        assert!(
+            error("export default 1; export default 2;").contains("Duplicate export of 'default'")
+        );
+        assert!(error("export { a };").contains("Export 'a' is not defined in module"));
+        assert!(error("import { a } from 'x'; var a;")
+            .contains("Identifier 'a' has already been declared"));
+        assert!(error("import a from 'x'; import { b as a } from 'y';")
+            .contains("Identifier 'a' has already been declared"));
+        assert!(error("return 1;").contains("SyntaxError"));
+    }
+
+    #[test]
+    fn test_compiled_modules() {
+        let mut loader = TableLoader::with(&[
+            (
+                "lib",
+                "var hidden = 'h'; export function f(x) { return hidden + x; }",
+            ),
+            ("main", "import { f } from 'lib'; log.push(f(1), f(2));"),
+        ]);
+        loader.precompile = true;
+        for _ in 0..2 {
+            let mut vm = vm_with(&loader);
+            vm.eval("var unrelated = [1, 2, 3].map(x => x * 2)")
+                .unwrap();
+            assert_eq!(import(&mut vm, "main"), "h1,h2,done");
+        }
+    }
+
+    #[test]
+    fn test_file_system_loader() {
+        let root = std::env::temp_dir().join(format!("rig-modules-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("lib")).unwrap();
+        std::fs::write(
+            root.join("main.js"),
+            "import { f } from './lib/f.js'; log.push(f);",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("lib/f.js"),
+            "import { g } from '../g.js'; import { g as same } from '/g.js'; \
+             export const f = 'f' + g + same;",
+        )
+        .unwrap();
+        std::fs::write(root.join("g.js"), "export const g = 'g';").unwrap();
+
+        let loader = FileSystemLoader::new(&root);
+        assert_eq!(loader.resolve("./a/../b.js", None).unwrap(), "/b.js");
+        assert_eq!(
+            loader.resolve("../../c.js", Some("/lib/f.js")).unwrap(),
+            "/c.js"
+        );
+        assert!(loader
+            .resolve("g.js", None)
+            .unwrap_err()
+            .to_string()
+            .contains("Failed to resolve module specifier \"g.js\""));
+
+        let mut vm = VM::default();
+        vm.set_module_loader(Some(Box::new(loader)));
+        vm.eval("var log = []").unwrap();
+        assert_eq!(import(&mut vm, "./main.js"), "fgg,done");
+        assert!(vm
+            .import_module("./none.js")
+            .unwrap_err()
+            .to_string()
+            .contains("Cannot load module '/none.js'"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
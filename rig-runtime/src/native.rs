@@ -0,0 +1,135 @@
+//! Functions implemented in Rust and callable from scripts.
+
+use std::any::Any;
+use std::cell::{Ref, RefCell, RefMut};
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use crate::{Atom, JsString, Properties, RuntimeError, Value, VM};
+
+/// Signature of a host callback: the VM, the `this` value and the arguments.
+pub type NativeFn = dyn Fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError>;
+
+/// A Rust function exposed to scripts. Cloning shares the same function, and
+/// two values are equal only if they are the same function.
+#[derive(Clone)]
+pub struct NativeFunction(Rc<NativeFunctionData>);
+
+/// Whether a native function may be called, constructed with `new`, or
+/// both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Invocation {
+    Any,
+    CallOnly,
+    ConstructOnly,
+}
+
+struct NativeFunctionData {
+    name: JsString,
+    func: Box<NativeFn>,
+    invocation: Invocation,
+    /// Properties such as `Array.isArray`
+    properties: RefCell<Properties>,
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, func: F) -> Self
+    where
+        F: Fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        NativeFunction::with_invocation(name, Invocation::Any, func)
+    }
+
+    /// A built-in constructor that throws when called without `new`, like
+    /// `Map`.
+    pub(crate) fn constructor<F>(name: &str, func: F) -> Self
+    where
+        F: Fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        NativeFunction::with_invocation(name, Invocation::ConstructOnly, func)
+    }
+
+    /// A built-in that throws when called with `new`, like `Symbol`.
+    pub(crate) fn non_constructor<F>(name: &str, func: F) -> Self
+    where
+        F: Fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        NativeFunction::with_invocation(name, Invocation::CallOnly, func)
+    }
+
+    fn with_invocation<F>(name: &str, invocation: Invocation, func: F) -> Self
+    where
+        F: Fn(&mut VM, Value, &[Value]) -> Result<Value, RuntimeError> + 'static,
+    {
+        NativeFunction(Rc::new(NativeFunctionData {
+            name: JsString::from(name),
+            func: Box::new(func),
+            invocation,
+            properties: RefCell::new(Properties::new()),
+        }))
+    }
+
+    pub fn name(&self) -> &JsString {
+        &self.0.name
+    }
+
+    pub fn call(&self, vm: &mut VM, this: Value, args: &[Value]) -> Result<Value, RuntimeError> {
+        if self.0.invocation == Invocation::ConstructOnly {
+            return Err(RuntimeError::TypeError(format!(
+                "Constructor {} requires 'new'",
+                self.0.name
+            )));
+        }
+        (self.0.func)(vm, this, args)
+    }
+
+    /// Runs the function for `new`. Callers check `is_constructor` first.
+    pub(crate) fn construct(&self, vm: &mut VM, args: &[Value]) -> Result<Value, RuntimeError> {
+        (self.0.func)(vm, Value::Undefined, args)
+    }
+
+    pub(crate) fn is_constructor(&self) -> bool {
+        self.0.invocation != Invocation::CallOnly
+    }
+
+    pub fn get_property(&self, name: &str) -> Option<Value> {
+        self.0.properties.borrow().get(&Atom::intern(name)).cloned()
+    }
+
+    /// Adds or overwrites a property of the function, as scripts do with
+    /// `f.name = value`.
+    pub fn set_property(&self, name: &str, value: impl Into<Value>) {
+        self.0
+            .properties
+            .borrow_mut()
+            .insert(Atom::intern(name), value.into());
+    }
+
+    pub(crate) fn properties(&self) -> Ref<'_, Properties> {
+        self.0.properties.borrow()
+    }
+
+    pub(crate) fn properties_mut(&self) -> RefMut<'_, Properties> {
+        self.0.properties.borrow_mut()
+    }
+
+    pub fn ptr_eq(a: &NativeFunction, b: &NativeFunction) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0) as *const ()
+    }
+
+    /// A reference that does not keep the function alive, pointing where
+    /// `as_ptr` does.
+    pub(crate) fn downgrade(&self) -> Weak<dyn Any> {
+        Rc::downgrade(&self.0) as Weak<dyn Any>
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NativeFunction").field(&self.0.name).finish()
+    }
+}
@@ -0,0 +1,330 @@
+//! Conversions from numbers to strings, following the algorithms the
+//! language specifies rather than Rust's formatting.
+//!
+//! Rust already finds the shortest digits that round-trip and can write the
+//! exact decimal expansion of any double, so both are taken from `format!`
+//! and only laid out and rounded here. JavaScript rounds ties away from zero
+//! where Rust rounds them to even, which is why rounding to a fixed number
+//! of digits starts from the exact expansion.
+
+/// Enough significant digits to write any double exactly.
+const EXACT_DIGITS: usize = 780;
+
+/// Splits a number written as `d.ddde±x` into its digits and the exponent
+/// of the first one.
+fn split_scientific(s: &str) -> (Vec<u8>, i32) {
+    let (mantissa, exponent) = s.split_once('e').unwrap();
+    let mut digits: Vec<u8> = mantissa.bytes().filter(|&b| b != b'.').collect();
+    while digits.len() > 1 && digits.last() == Some(&b'0') {
+        digits.pop();
+    }
+    (digits, exponent.parse().unwrap())
+}
+
+/// The shortest digits that read back as `x`, a positive finite number.
+fn shortest_digits(x: f64) -> (Vec<u8>, i32) {
+    split_scientific(&format!("{x:e}"))
+}
+
+/// Every significant digit of `x`, a positive finite number.
+fn exact_digits(x: f64) -> (Vec<u8>, i32) {
+    split_scientific(&format!("{x:.EXACT_DIGITS$e}"))
+}
+
+/// Keeps the first `count` digits, rounding half away from zero. Returns
+/// whether rounding carried into a new leading digit, in which case the
+/// result has one digit more than asked for.
+fn round_digits(digits: &[u8], count: isize) -> (Vec<u8>, bool) {
+    if count < 0 {
+        return (Vec::new(), false);
+    }
+    let count = count as usize;
+    let mut kept: Vec<u8> = digits.iter().take(count).copied().collect();
+    kept.resize(count, b'0');
+    if digits.get(count).is_some_and(|&d| d >= b'5') {
+        for digit in kept.iter_mut().rev() {
+            if *digit == b'9' {
+                *digit = b'0';
+            } else {
+                *digit += 1;
+                return (kept, false);
+            }
+        }
+        kept.insert(0, b'1');
+        return (kept, true);
+    }
+    (kept, false)
+}
+
+/// Writes `digits` as `d.ddde±x`.
+fn exponential(digits: &[u8], exponent: i32) -> String {
+    let mut s = String::with_capacity(digits.len() + 6);
+    s.push(digits[0] as char);
+    if digits.len() > 1 {
+        s.push('.');
+        s.extend(digits[1..].iter().map(|&d| d as char));
+    }
+    s.push('e');
+    s.push(if exponent < 0 { '-' } else { '+' });
+    s.push_str(&exponent.unsigned_abs().to_string());
+    s
+}
+
+fn ascii(digits: &[u8]) -> &str {
+    std::str::from_utf8(digits).unwrap()
+}
+
+/// Number::toString: the shortest round-trip digits, written in fixed
+/// notation for exponents from -7 to 20 and in exponential notation
+/// otherwise.
+pub(crate) fn to_string(x: f64) -> String {
+    if x.is_nan() {
+        return "NaN".to_string();
+    }
+    if x == 0.0 {
+        return "0".to_string();
+    }
+    if x < 0.0 {
+        return format!("-{}", to_string(-x));
+    }
+    if x.is_infinite() {
+        return "Infinity".to_string();
+    }
+    let (digits, exponent) = shortest_digits(x);
+    let k = digits.len() as i32;
+    // Position of the decimal point relative to the first digit
+    let n = exponent + 1;
+    let digits = ascii(&digits);
+    if k <= n && n <= 21 {
+        format!("{digits}{}", "0".repeat((n - k) as usize))
+    } else if 0 < n && n <= 21 {
+        let (int, frac) = digits.split_at(n as usize);
+        format!("{int}.{frac}")
+    } else if -6 < n && n <= 0 {
+        format!("0.{}{digits}", "0".repeat(-n as usize))
+    } else {
+        exponential(digits.as_bytes(), exponent)
+    }
+}
+
+/// Number.prototype.toString with a radix other than 10, writing as many
+/// fraction digits as it takes to tell `x` apart from its neighbours.
+pub(crate) fn to_radix_string(x: f64, radix: u32) -> String {
+    if !x.is_finite() || x == 0.0 {
+        return to_string(x);
+    }
+    const CHARS: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let radix_f = radix as f64;
+    let negative = x < 0.0;
+    let value = x.abs();
+
+    let mut integer = value.floor();
+    let mut fraction = value - integer;
+    // Half the distance to the next double: digits past this point are
+    // noise
+    let next = f64::from_bits(value.to_bits() + 1);
+    let mut delta = (0.5 * (next - value)).max(f64::from_bits(1));
+    let mut fraction_digits = Vec::new();
+    if fraction >= delta {
+        loop {
+            fraction *= radix_f;
+            delta *= radix_f;
+            let digit = fraction as usize;
+            fraction_digits.push(digit);
+            fraction -= digit as f64;
+            if (fraction > 0.5 || (fraction == 0.5 && digit & 1 == 1)) && fraction + delta > 1.0 {
+                // Round up, carrying into the integer part if need be
+                loop {
+                    match fraction_digits.pop() {
+                        None => {
+                            integer += 1.0;
+                            break;
+                        }
+                        Some(digit) if digit + 1 < radix as usize => {
+                            fraction_digits.push(digit + 1);
+                            break;
+                        }
+                        Some(_) => {}
+                    }
+                }
+                break;
+            }
+            if fraction < delta {
+                break;
+            }
+        }
+    }
+
+    // Digits below the precision of the integer part are written as zeros
+    let mut integer_digits = Vec::new();
+    while integer / radix_f >= 9007199254740992.0 {
+        integer /= radix_f;
+        integer_digits.push(b'0');
+    }
+    loop {
+        let remainder = integer % radix_f;
+        integer_digits.push(CHARS[remainder as usize]);
+        integer = (integer - remainder) / radix_f;
+        if integer <= 0.0 {
+            break;
+        }
+    }
+
+    let mut s = String::new();
+    if negative {
+        s.push('-');
+    }
+    s.extend(integer_digits.iter().rev().map(|&d| d as char));
+    if !fraction_digits.is_empty() {
+        s.push('.');
+        s.extend(fraction_digits.iter().map(|&d| CHARS[d] as char));
+    }
+    s
+}
+
+/// Number.prototype.toFixed for finite `x` below 1e21 in magnitude.
+pub(crate) fn to_fixed(x: f64, fraction_digits: usize) -> String {
+    let mut n = if x == 0.0 {
+        Vec::new()
+    } else {
+        let (digits, exponent) = exact_digits(x.abs());
+        round_digits(&digits, exponent as isize + 1 + fraction_digits as isize).0
+    };
+    // `n` is the result scaled by 10^fraction_digits
+    if n.len() <= fraction_digits {
+        let zeros = fraction_digits + 1 - n.len();
+        n.splice(0..0, std::iter::repeat_n(b'0', zeros));
+    }
+    let (int, frac) = n.split_at(n.len() - fraction_digits);
+    let sign = if x < 0.0 { "-" } else { "" };
+    if frac.is_empty() {
+        format!("{sign}{}", ascii(int))
+    } else {
+        format!("{sign}{}.{}", ascii(int), ascii(frac))
+    }
+}
+
+/// Number.prototype.toExponential for finite `x`, with the shortest
+/// round-trip digits if `fraction_digits` is `None`.
+pub(crate) fn to_exponential(x: f64, fraction_digits: Option<usize>) -> String {
+    let (digits, exponent) = match fraction_digits {
+        _ if x == 0.0 => (vec![b'0'; fraction_digits.unwrap_or(0) + 1], 0),
+        None => shortest_digits(x.abs()),
+        Some(f) => {
+            let (digits, exponent) = exact_digits(x.abs());
+            let (mut rounded, carried) = round_digits(&digits, f as isize + 1);
+            rounded.truncate(f + 1);
+            (rounded, exponent + carried as i32)
+        }
+    };
+    let sign = if x < 0.0 { "-" } else { "" };
+    format!("{sign}{}", exponential(&digits, exponent))
+}
+
+/// Number.prototype.toPrecision for finite `x`.
+pub(crate) fn to_precision(x: f64, precision: usize) -> String {
+    let (digits, exponent) = if x == 0.0 {
+        (vec![b'0'; precision], 0)
+    } else {
+        let (digits, exponent) = exact_digits(x.abs());
+        let (mut rounded, carried) = round_digits(&digits, precision as isize);
+        rounded.truncate(precision);
+        (rounded, exponent + carried as i32)
+    };
+    let sign = if x < 0.0 { "-" } else { "" };
+    if exponent < -6 || exponent >= precision as i32 {
+        return format!("{sign}{}", exponential(&digits, exponent));
+    }
+    let digits = ascii(&digits);
+    if exponent < 0 {
+        format!("{sign}0.{}{digits}", "0".repeat((-exponent - 1) as usize))
+    } else if exponent as usize + 1 == precision {
+        format!("{sign}{digits}")
+    } else {
+        let (int, frac) = digits.split_at(exponent as usize + 1);
+        format!("{sign}{int}.{frac}")
+    }
+}
+
+/// Number::exponentiate. Unlike `powf`, a base of ±1 with a NaN or
+/// infinite exponent gives NaN.
+pub(crate) fn pow(x: f64, y: f64) -> f64 {
+    if y.is_nan() || (x.abs() == 1.0 && y.is_infinite()) {
+        f64::NAN
+    } else {
+        x.powf(y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(to_string(0.1 + 0.2), "0.30000000000000004");
+        assert_eq!(to_string(-0.0), "0");
+        assert_eq!(to_string(123.0), "123");
+        assert_eq!(to_string(1e21), "1e+21");
+        assert_eq!(to_string(1e20), "100000000000000000000");
+        assert_eq!(to_string(1.5e-7), "1.5e-7");
+        assert_eq!(to_string(0.000001), "0.000001");
+        assert_eq!(to_string(-1.25e300), "-1.25e+300");
+        assert_eq!(to_string(5e-324), "5e-324");
+        assert_eq!(to_string(f64::MAX), "1.7976931348623157e+308");
+    }
+
+    #[test]
+    fn test_pow() {
+        assert!(pow(1.0, f64::NAN).is_nan());
+        assert!(pow(-1.0, f64::INFINITY).is_nan());
+        assert_eq!(pow(f64::NAN, 0.0), 1.0);
+        assert_eq!(pow(2.0, -1.0), 0.5);
+    }
+
+    #[test]
+    fn test_to_radix_string() {
+        assert_eq!(to_radix_string(255.0, 16), "ff");
+        assert_eq!(to_radix_string(-255.5, 2), "-11111111.1");
+        assert_eq!(
+            to_radix_string(0.1, 2),
+            "0.0001100110011001100110011001100110011001100110011001101"
+        );
+        assert_eq!(
+            to_radix_string(2f64.powi(60), 2),
+            format!("1{}", "0".repeat(60))
+        );
+        assert_eq!(to_radix_string(35.0, 36), "z");
+    }
+
+    #[test]
+    fn test_to_fixed() {
+        assert_eq!(to_fixed(1.005, 2), "1.00");
+        assert_eq!(to_fixed(2.5, 0), "3");
+        assert_eq!(to_fixed(0.5, 0), "1");
+        assert_eq!(to_fixed(0.0, 2), "0.00");
+        assert_eq!(to_fixed(-0.0001, 2), "-0.00");
+        assert_eq!(to_fixed(0.000001, 3), "0.000");
+        assert_eq!(to_fixed(99.99, 1), "100.0");
+        assert_eq!(to_fixed(123.456, 10), "123.4560000000");
+    }
+
+    #[test]
+    fn test_to_exponential() {
+        assert_eq!(to_exponential(123456.0, Some(2)), "1.23e+5");
+        assert_eq!(to_exponential(123456.0, None), "1.23456e+5");
+        assert_eq!(to_exponential(0.0, Some(2)), "0.00e+0");
+        assert_eq!(to_exponential(9.99, Some(1)), "1.0e+1");
+        assert_eq!(to_exponential(-0.00015, Some(0)), "-1e-4");
+    }
+
+    #[test]
+    fn test_to_precision() {
+        assert_eq!(to_precision(123.456, 4), "123.5");
+        assert_eq!(to_precision(0.000123, 2), "0.00012");
+        assert_eq!(to_precision(123456.0, 2), "1.2e+5");
+        assert_eq!(to_precision(0.0, 3), "0.00");
+        assert_eq!(to_precision(1e-7, 1), "1e-7");
+        assert_eq!(to_precision(99.5, 2), "1.0e+2");
+    }
+}
@@ -0,0 +1,140 @@
+//! Ordinary objects.
+//!
+//! An object holds its own properties in the order they were added, and
+//! the object it inherits from. Objects made outside a VM, for example by
+//! `to_value`, have no prototype of their own; they inherit from
+//! `Object.prototype` of whichever VM reads them.
+//!
+//! Objects made by built-in constructors such as `Map` carry internal state
+//! besides their properties, which only the built-ins can see.
+
+use indexmap::IndexMap;
+
+use crate::async_function::AsyncGenerator;
+use crate::class::Accessor;
+use crate::collection::{OrderedTable, WeakTable};
+use crate::generator::GeneratorState;
+use crate::iterator::Iteration;
+use crate::module::Binding;
+use crate::promise::Promise;
+use crate::{Atom, Properties, Value};
+
+/// The internal state of an object, telling ordinary objects apart from the
+/// ones built-in constructors make.
+#[derive(Debug, Clone, Default)]
+pub(crate) enum ObjectKind {
+    #[default]
+    Ordinary,
+    Map(OrderedTable),
+    Set(OrderedTable),
+    WeakMap(WeakTable),
+    WeakSet(WeakTable),
+    Iterator(Iteration),
+    Generator(GeneratorState),
+    AsyncGenerator(AsyncGenerator),
+    Promise(Promise),
+    /// A name one module imports from another, held in the importing
+    /// module's scope and never seen by scripts
+    ImportBinding(Binding),
+    /// A module namespace, with a binding for each export
+    Namespace(IndexMap<Atom, Binding>),
+    /// The getter and setter of an accessor property, held as the
+    /// property's value and never seen by scripts
+    Accessor(Accessor),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct JsObject {
+    properties: Properties,
+    /// `None` inherits from `Object.prototype`; otherwise another object or
+    /// null
+    prototype: Option<Value>,
+    kind: ObjectKind,
+    /// Private members, keyed by the private names of the classes that
+    /// added them
+    private: Option<Box<Properties>>,
+}
+
+impl JsObject {
+    /// An empty object inheriting from `Object.prototype`.
+    pub fn new() -> Self {
+        JsObject::default()
+    }
+
+    /// An empty object inheriting from `prototype`, which must be an object
+    /// or null.
+    pub fn with_prototype(prototype: Value) -> Self {
+        JsObject {
+            prototype: Some(prototype),
+            ..JsObject::default()
+        }
+    }
+
+    /// An empty object with internal state, inheriting from `prototype`.
+    pub(crate) fn with_kind(prototype: Value, kind: ObjectKind) -> Self {
+        JsObject {
+            prototype: Some(prototype),
+            kind,
+            ..JsObject::default()
+        }
+    }
+
+    pub fn properties(&self) -> &Properties {
+        &self.properties
+    }
+
+    pub fn properties_mut(&mut self) -> &mut Properties {
+        &mut self.properties
+    }
+
+    /// Looks up an own property by name.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.properties.get(&Atom::intern(key))
+    }
+
+    /// Sets an own property, keeping its position if it already exists.
+    pub fn insert(&mut self, key: &str, value: impl Into<Value>) -> Option<Value> {
+        self.properties.insert(Atom::intern(key), value.into())
+    }
+
+    /// The prototype set on this object, or `None` if it inherits from
+    /// `Object.prototype`.
+    pub fn prototype(&self) -> Option<&Value> {
+        self.prototype.as_ref()
+    }
+
+    pub(crate) fn set_prototype(&mut self, prototype: Value) {
+        self.prototype = Some(prototype);
+    }
+
+    pub(crate) fn private_members(&self) -> Option<&Properties> {
+        self.private.as_deref()
+    }
+
+    pub(crate) fn private_members_mut(&mut self) -> &mut Properties {
+        self.private.get_or_insert_with(Box::default)
+    }
+
+    pub(crate) fn kind(&self) -> &ObjectKind {
+        &self.kind
+    }
+
+    pub(crate) fn kind_mut(&mut self) -> &mut ObjectKind {
+        &mut self.kind
+    }
+}
+
+impl From<Properties> for JsObject {
+    fn from(properties: Properties) -> Self {
+        JsObject {
+            properties,
+            ..JsObject::default()
+        }
+    }
+}
+
+impl FromIterator<(Atom, Value)> for JsObject {
+    fn from_iter<I: IntoIterator<Item = (Atom, Value)>>(iter: I) -> Self {
+        JsObject::from(Properties::from_iter(iter))
+    }
+}
@@ -0,0 +1,403 @@
+//! Promises and the job queue that settles them.
+//!
+//! Settling a promise queues a job for each reaction registered with
+//! `then`, and calling `then` on a promise that has already settled queues
+//! one straight away. Jobs never run in the middle of a script: the host
+//! runs them with `VM::run_microtasks` once the script that queued them has
+//! returned.
+
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use crate::heap::{ENTRY_SIZE, OBJECT_SIZE};
+use crate::object::ObjectKind;
+use crate::{JsObject, NativeFunction, RuntimeError, Value, VM};
+
+/// What the rejection tracker is told about a promise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionEvent {
+    /// The promise was rejected while nothing was waiting on it
+    Unhandled,
+    /// A handler was added to a promise previously reported as unhandled
+    Handled,
+}
+
+/// Callback set with `VM::set_rejection_tracker`, given the event, the
+/// promise and the reason it was rejected with.
+pub type RejectionTracker = dyn FnMut(RejectionEvent, &Value, &Value);
+
+/// The internal state of a promise object.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Promise {
+    state: PromiseState,
+    /// Reactions waiting for the promise to settle
+    reactions: Vec<Reaction>,
+    /// Whether a reaction has ever been added, so a rejection is not
+    /// reported as unhandled
+    handled: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) enum PromiseState {
+    #[default]
+    Pending,
+    Fulfilled(Value),
+    Rejected(Value),
+}
+
+/// A pair of handlers added by `then`.
+#[derive(Debug, Clone)]
+pub(crate) struct Reaction {
+    on_fulfilled: Value,
+    on_rejected: Value,
+    /// The promise `then` returned, settled with the outcome of the
+    /// handler. `await` reacts without one.
+    derived: Option<Value>,
+}
+
+impl Promise {
+    /// The values the promise holds on to, for measuring the heap.
+    pub fn values(&self) -> Vec<Value> {
+        let mut values = Vec::new();
+        if let PromiseState::Fulfilled(value) | PromiseState::Rejected(value) = &self.state {
+            values.push(value.clone());
+        }
+        for reaction in &self.reactions {
+            values.push(reaction.on_fulfilled.clone());
+            values.push(reaction.on_rejected.clone());
+            values.extend(reaction.derived.clone());
+        }
+        values
+    }
+}
+
+/// Work queued to run once the current script has returned.
+#[derive(Debug)]
+pub(crate) enum Job {
+    /// Calls a reaction's handler with the value its promise settled with
+    Reaction {
+        handler: Value,
+        argument: Value,
+        /// Whether the promise was rejected, which a missing handler passes on
+        rejected: bool,
+        derived: Option<Value>,
+    },
+    /// Calls the `then` method of a thenable a promise was resolved with,
+    /// to follow it
+    ResolveThenable {
+        promise: Rc<RefCell<JsObject>>,
+        thenable: Value,
+        then: Value,
+    },
+    /// Calls a function passed to `VM::queue_microtask`
+    Callback(Value),
+}
+
+impl Job {
+    /// The values the job holds on to, for measuring the heap.
+    pub fn values(&self) -> Vec<Value> {
+        match self {
+            Job::Reaction {
+                handler,
+                argument,
+                derived,
+                ..
+            } => {
+                let mut values = vec![handler.clone(), argument.clone()];
+                values.extend(derived.clone());
+                values
+            }
+            Job::ResolveThenable {
+                promise,
+                thenable,
+                then,
+            } => vec![
+                Value::Object(promise.clone()),
+                thenable.clone(),
+                then.clone(),
+            ],
+            Job::Callback(callback) => vec![callback.clone()],
+        }
+    }
+}
+
+/// The promise object behind `value`, if it is one.
+pub(crate) fn as_promise(value: &Value) -> Option<Rc<RefCell<JsObject>>> {
+    match value {
+        Value::Object(obj) if matches!(obj.borrow().kind(), ObjectKind::Promise(_)) => {
+            Some(obj.clone())
+        }
+        _ => None,
+    }
+}
+
+fn not_a_promise(value: &Value) -> RuntimeError {
+    RuntimeError::TypeError(format!("{} is not a promise", value.to_js_string()))
+}
+
+impl VM {
+    /// Makes a pending promise, to be settled by `resolve_promise` or
+    /// `reject_promise` once the work it stands for is done.
+    pub fn create_promise(&mut self) -> Result<Value, RuntimeError> {
+        self.charge(OBJECT_SIZE)?;
+        Ok(Value::Object(Rc::new(RefCell::new(JsObject::with_kind(
+            Value::Object(self.intrinsics.promise_prototype.clone()),
+            ObjectKind::Promise(Promise::default()),
+        )))))
+    }
+
+    /// Resolves a promise with `resolution`, the way the `resolve` function
+    /// a promise executor receives does: a thenable is followed, anything
+    /// else fulfills the promise. Does nothing if the promise has settled.
+    pub fn resolve_promise(
+        &mut self,
+        promise: &Value,
+        resolution: Value,
+    ) -> Result<(), RuntimeError> {
+        let obj = as_promise(promise).ok_or_else(|| not_a_promise(promise))?;
+        if let Value::Object(target) = &resolution {
+            if Rc::ptr_eq(target, &obj) {
+                let reason = self.error_value(RuntimeError::TypeError(
+                    "Chaining cycle detected for promise #<Promise>".to_string(),
+                ))?;
+                return self.settle(&obj, PromiseState::Rejected(reason));
+            }
+        }
+        if !resolution.is_object() {
+            return self.settle(&obj, PromiseState::Fulfilled(resolution));
+        }
+        let then = match self.get_property(&resolution, &Value::from("then")) {
+            Ok(then) => then,
+            Err(err) => {
+                let reason = self.error_value(err)?;
+                return self.settle(&obj, PromiseState::Rejected(reason));
+            }
+        };
+        if !then.is_function() {
+            return self.settle(&obj, PromiseState::Fulfilled(resolution));
+        }
+        self.charge(ENTRY_SIZE)?;
+        self.jobs.push_back(Job::ResolveThenable {
+            promise: obj,
+            thenable: resolution,
+            then,
+        });
+        Ok(())
+    }
+
+    /// Rejects a promise with `reason`. Does nothing if it has settled.
+    pub fn reject_promise(&mut self, promise: &Value, reason: Value) -> Result<(), RuntimeError> {
+        let obj = as_promise(promise).ok_or_else(|| not_a_promise(promise))?;
+        self.settle(&obj, PromiseState::Rejected(reason))
+    }
+
+    /// Sets the callback told when a promise is rejected with no handler,
+    /// and when a handler is later added to one, or removes it with `None`.
+    /// Hosts usually report the promises still unhandled once
+    /// `run_microtasks` has returned.
+    pub fn set_rejection_tracker(&mut self, tracker: Option<Box<RejectionTracker>>) {
+        self.rejection_tracker = tracker;
+    }
+
+    /// Runs queued jobs, including those they queue in turn, until there
+    /// are none left. Call it after `run`, `eval` or `call_function` so
+    /// that promise reactions the script set up take place. An error a
+    /// script cannot catch stops the run and leaves the remaining jobs
+    /// queued.
+    pub fn run_microtasks(&mut self) -> Result<(), RuntimeError> {
+        while let Some(job) = self.jobs.pop_front() {
+            self.run_job(job)?;
+        }
+        Ok(())
+    }
+
+    /// Queues a call to `callback`, with no arguments, to run with the
+    /// promise jobs. An error it throws stops `run_microtasks`.
+    pub fn queue_microtask(&mut self, callback: Value) -> Result<(), RuntimeError> {
+        if !callback.is_function() {
+            return Err(RuntimeError::TypeError(format!(
+                "{} is not a function",
+                callback.to_js_string()
+            )));
+        }
+        self.charge(ENTRY_SIZE)?;
+        self.jobs.push_back(Job::Callback(callback));
+        Ok(())
+    }
+
+    /// Whether there are jobs waiting for `run_microtasks`.
+    pub fn has_pending_microtasks(&self) -> bool {
+        !self.jobs.is_empty()
+    }
+
+    fn run_job(&mut self, job: Job) -> Result<(), RuntimeError> {
+        match job {
+            Job::Reaction {
+                handler,
+                argument,
+                rejected,
+                derived,
+            } => {
+                let outcome = if handler.is_function() {
+                    self.call_function(&handler, Value::Undefined, &[argument])
+                } else if rejected {
+                    Err(RuntimeError::Thrown(argument))
+                } else {
+                    Ok(argument)
+                };
+                let Some(derived) = derived else {
+                    return outcome.map(drop);
+                };
+                match outcome {
+                    Ok(value) => self.resolve_promise(&derived, value),
+                    Err(err) => {
+                        let reason = self.error_value(err)?;
+                        self.reject_promise(&derived, reason)
+                    }
+                }
+            }
+            Job::ResolveThenable {
+                promise,
+                thenable,
+                then,
+            } => {
+                let (resolve, reject) = self.resolving_functions(&Value::Object(promise));
+                if let Err(err) = self.call_function(&then, thenable, &[resolve, reject.clone()]) {
+                    let reason = self.error_value(err)?;
+                    self.call_function(&reject, Value::Undefined, &[reason])?;
+                }
+                Ok(())
+            }
+            Job::Callback(callback) => self
+                .call_function(&callback, Value::Undefined, &[])
+                .map(drop),
+        }
+    }
+
+    /// The `resolve` and `reject` functions that settle `promise`. Only the
+    /// first call to either has any effect.
+    pub(crate) fn resolving_functions(&mut self, promise: &Value) -> (Value, Value) {
+        let already_resolved = Rc::new(Cell::new(false));
+        let resolve = {
+            let promise = promise.clone();
+            let already_resolved = already_resolved.clone();
+            NativeFunction::new("", move |vm, _this, args| {
+                if !already_resolved.replace(true) {
+                    let resolution = args.first().cloned().unwrap_or(Value::Undefined);
+                    vm.resolve_promise(&promise, resolution)?;
+                }
+                Ok(Value::Undefined)
+            })
+        };
+        let reject = {
+            let promise = promise.clone();
+            NativeFunction::new("", move |vm, _this, args| {
+                if !already_resolved.replace(true) {
+                    let reason = args.first().cloned().unwrap_or(Value::Undefined);
+                    vm.reject_promise(&promise, reason)?;
+                }
+                Ok(Value::Undefined)
+            })
+        };
+        (resolve.into(), reject.into())
+    }
+
+    /// Adds a reaction to `promise`, which must be a promise, queueing it
+    /// at once if the promise has settled.
+    pub(crate) fn promise_then(
+        &mut self,
+        promise: &Value,
+        on_fulfilled: Value,
+        on_rejected: Value,
+        derived: Option<Value>,
+    ) -> Result<(), RuntimeError> {
+        let obj = as_promise(promise).ok_or_else(|| not_a_promise(promise))?;
+        self.charge(ENTRY_SIZE)?;
+        let reaction = Reaction {
+            on_fulfilled,
+            on_rejected,
+            derived,
+        };
+        let (state, handled) = {
+            let mut obj = obj.borrow_mut();
+            let ObjectKind::Promise(data) = obj.kind_mut() else {
+                unreachable!("promise changed kind");
+            };
+            let handled = std::mem::replace(&mut data.handled, true);
+            if let PromiseState::Pending = data.state {
+                data.reactions.push(reaction);
+                return Ok(());
+            }
+            (data.state.clone(), handled)
+        };
+        if let PromiseState::Rejected(reason) = &state {
+            if !handled {
+                self.track_rejection(RejectionEvent::Handled, promise, reason);
+            }
+        }
+        self.queue_reaction(reaction, &state);
+        Ok(())
+    }
+
+    /// `value` if it is a promise, or else a new promise resolved with it.
+    pub(crate) fn promise_resolve(&mut self, value: Value) -> Result<Value, RuntimeError> {
+        if as_promise(&value).is_some() {
+            return Ok(value);
+        }
+        let promise = self.create_promise()?;
+        self.resolve_promise(&promise, value)?;
+        Ok(promise)
+    }
+
+    /// Fulfills or rejects a pending promise and queues its reactions.
+    fn settle(
+        &mut self,
+        obj: &Rc<RefCell<JsObject>>,
+        state: PromiseState,
+    ) -> Result<(), RuntimeError> {
+        let (reactions, handled) = {
+            let mut obj = obj.borrow_mut();
+            let ObjectKind::Promise(data) = obj.kind_mut() else {
+                unreachable!("promise changed kind");
+            };
+            if !matches!(data.state, PromiseState::Pending) {
+                return Ok(());
+            }
+            data.state = state.clone();
+            (std::mem::take(&mut data.reactions), data.handled)
+        };
+        if let PromiseState::Rejected(reason) = &state {
+            if !handled {
+                self.track_rejection(
+                    RejectionEvent::Unhandled,
+                    &Value::Object(obj.clone()),
+                    reason,
+                );
+            }
+        }
+        for reaction in reactions {
+            self.queue_reaction(reaction, &state);
+        }
+        Ok(())
+    }
+
+    fn queue_reaction(&mut self, reaction: Reaction, state: &PromiseState) {
+        let (handler, argument, rejected) = match state {
+            PromiseState::Fulfilled(value) => (reaction.on_fulfilled, value.clone(), false),
+            PromiseState::Rejected(reason) => (reaction.on_rejected, reason.clone(), true),
+            PromiseState::Pending => unreachable!("reaction to a pending promise"),
+        };
+        self.jobs.push_back(Job::Reaction {
+            handler,
+            argument,
+            rejected,
+            derived: reaction.derived,
+        });
+    }
+
+    fn track_rejection(&mut self, event: RejectionEvent, promise: &Value, reason: &Value) {
+        if let Some(tracker) = &mut self.rejection_tracker {
+            tracker(event, promise, reason);
+        }
+    }
+}
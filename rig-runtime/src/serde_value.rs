@@ -0,0 +1,1154 @@
+//! Conversions between script values and Rust types through serde.
+//!
+//! Structs and maps become objects, sequences and tuples become arrays, and
+//! enums use serde's externally tagged layout. `None` and `()` become
+//! `null`; `undefined` deserializes like `null`, and object properties
+//! holding `undefined` are treated as missing, as `JSON.stringify` does.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::ser::{self, Serialize, SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Deserializer};
+
+use crate::{Atom, JsArray, JsString, RuntimeError, Value};
+
+/// Largest integer a number can hold exactly, 2^53 - 1.
+const MAX_SAFE_INTEGER: f64 = 9007199254740991.0;
+
+/// Converts a Rust value into a script value.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, RuntimeError> {
+    value.serialize(ValueSerializer)
+}
+
+/// Converts a script value into a Rust value.
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, RuntimeError> {
+    T::deserialize(ValueDeserializer {
+        value,
+        ancestors: None,
+    })
+}
+
+impl ser::Error for RuntimeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RuntimeError::TypeError(msg.to_string())
+    }
+}
+
+impl de::Error for RuntimeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        RuntimeError::TypeError(msg.to_string())
+    }
+}
+
+fn circular() -> RuntimeError {
+    RuntimeError::TypeError("Converting circular structure".to_string())
+}
+
+fn js_to_string(s: &JsString) -> Result<String, RuntimeError> {
+    s.to_std_string()
+        .map_err(|_| RuntimeError::TypeError("string is not valid UTF-16".to_string()))
+}
+
+fn object_ptr(value: &Value) -> Option<*const ()> {
+    match value {
+        Value::Object(obj) => Some(Rc::as_ptr(obj) as *const ()),
+        Value::Array(arr) => Some(Rc::as_ptr(arr) as *const ()),
+        _ => None,
+    }
+}
+
+/// Objects currently being serialized, innermost first.
+struct Parents<'a> {
+    ptr: *const (),
+    next: Option<&'a Parents<'a>>,
+}
+
+impl Parents<'_> {
+    fn contains(&self, ptr: *const ()) -> bool {
+        self.ptr == ptr || self.next.is_some_and(|next| next.contains(ptr))
+    }
+}
+
+/// A value paired with the objects enclosing it, so cycles can be reported
+/// instead of overflowing the stack.
+struct SerializeValue<'a> {
+    value: &'a Value,
+    parents: Option<&'a Parents<'a>>,
+}
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeValue {
+            value: self,
+            parents: None,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl Serialize for SerializeValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if let Some(ptr) = object_ptr(self.value) {
+            if self.parents.is_some_and(|parents| parents.contains(ptr)) {
+                return Err(ser::Error::custom("Converting circular structure"));
+            }
+        }
+        let parents = object_ptr(self.value).map(|ptr| Parents {
+            ptr,
+            next: self.parents,
+        });
+        let child = |value| SerializeValue {
+            value,
+            parents: parents.as_ref(),
+        };
+        match self.value {
+            Value::Undefined | Value::Null => serializer.serialize_unit(),
+            Value::Boolean(b) => serializer.serialize_bool(*b),
+            Value::Number(n) => {
+                // Integral numbers go out as integers so formats like JSON
+                // print `1` rather than `1.0`
+                let integral = n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER;
+                if integral && !(*n == 0.0 && n.is_sign_negative()) {
+                    serializer.serialize_i64(*n as i64)
+                } else {
+                    serializer.serialize_f64(*n)
+                }
+            }
+            Value::String(s) => {
+                let s = s.to_std_string().map_err(ser::Error::custom)?;
+                serializer.serialize_str(&s)
+            }
+            Value::Array(arr) => {
+                let arr = arr.borrow();
+                let mut seq = serializer.serialize_seq(Some(arr.len() as usize))?;
+                for i in 0..arr.len() {
+                    // Holes serialize as null, as in JSON.stringify
+                    match arr.get(i) {
+                        Some(element) => seq.serialize_element(&child(element))?,
+                        None => seq.serialize_element(&())?,
+                    }
+                }
+                seq.end()
+            }
+            Value::Object(obj) => {
+                let obj = obj.borrow();
+                let entries: Vec<_> = obj
+                    .properties()
+                    .iter()
+                    .filter(|(k, v)| !k.is_symbol() && !v.is_undefined())
+                    .collect();
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(&key.to_string(), &child(value))?;
+                }
+                map.end()
+            }
+            Value::Symbol(_) => Err(ser::Error::custom("symbols cannot be serialized")),
+            Value::Function(_) | Value::NativeFunction(_) => {
+                Err(ser::Error::custom("functions cannot be serialized"))
+            }
+        }
+    }
+}
+
+/// Builds script values from any `Serialize` type.
+struct ValueSerializer;
+
+fn integer(n: impl TryInto<i64> + fmt::Display + Copy) -> Result<Value, RuntimeError> {
+    match n.try_into() {
+        Ok(i) if (i as f64).abs() <= MAX_SAFE_INTEGER => Ok(Value::Number(i as f64)),
+        _ => Err(RuntimeError::RangeError(format!(
+            "{} cannot be represented exactly as a number",
+            n
+        ))),
+    }
+}
+
+fn object(entries: Vec<(Atom, Value)>) -> Value {
+    Value::Object(Rc::new(RefCell::new(entries.into_iter().collect())))
+}
+
+fn array(elements: Vec<Value>) -> Value {
+    Value::Array(Rc::new(RefCell::new(JsArray::from(elements))))
+}
+
+impl Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = MapSerializer;
+    type SerializeStructVariant = VariantSerializer<MapSerializer>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, RuntimeError> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, RuntimeError> {
+        integer(v)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Value, RuntimeError> {
+        integer(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, RuntimeError> {
+        integer(v)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Value, RuntimeError> {
+        integer(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v.into()))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, RuntimeError> {
+        Ok(Value::Number(v))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, RuntimeError> {
+        Ok(Value::String((&*v.encode_utf8(&mut [0; 4])).into()))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, RuntimeError> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, RuntimeError> {
+        Ok(array(v.iter().map(|&b| Value::Number(b.into())).collect()))
+    }
+
+    fn serialize_none(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, RuntimeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, RuntimeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, RuntimeError> {
+        Ok(Value::Null)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, RuntimeError> {
+        Ok(Value::String(variant.into()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, RuntimeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, RuntimeError> {
+        Ok(object(vec![(Atom::intern(variant), to_value(value)?)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, RuntimeError> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, RuntimeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, RuntimeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<SeqSerializer>, RuntimeError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_seq(Some(len))?,
+        })
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<MapSerializer, RuntimeError> {
+        Ok(MapSerializer {
+            entries: Vec::new(),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer, RuntimeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<VariantSerializer<MapSerializer>, RuntimeError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+struct SeqSerializer(Vec<Value>);
+
+impl SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        self.0.push(to_value(value)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        Ok(array(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        SerializeSeq::end(self)
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(Atom, Value)>,
+    /// Key waiting for its value
+    key: Option<Atom>,
+}
+
+impl SerializeMap for MapSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RuntimeError> {
+        self.key = Some(key.serialize(KeySerializer)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        let key = self.key.take().ok_or_else(|| {
+            RuntimeError::Internal("map value serialized before its key".to_string())
+        })?;
+        self.entries.push((key, to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        Ok(object(self.entries))
+    }
+}
+
+impl ser::SerializeStruct for MapSerializer {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RuntimeError> {
+        self.entries.push((Atom::intern(key), to_value(value)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        SerializeMap::end(self)
+    }
+}
+
+/// Wraps the payload of a tuple or struct variant in `{ variant: payload }`.
+struct VariantSerializer<T> {
+    variant: &'static str,
+    inner: T,
+}
+
+impl<T> VariantSerializer<T> {
+    fn wrap(variant: &'static str, payload: Value) -> Result<Value, RuntimeError> {
+        Ok(object(vec![(Atom::intern(variant), payload)]))
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RuntimeError> {
+        SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        Self::wrap(self.variant, SerializeSeq::end(self.inner)?)
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<MapSerializer> {
+    type Ok = Value;
+    type Error = RuntimeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), RuntimeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value, RuntimeError> {
+        Self::wrap(self.variant, SerializeMap::end(self.inner)?)
+    }
+}
+
+/// Serializes map keys, which must become property names.
+struct KeySerializer;
+
+fn key_error() -> RuntimeError {
+    RuntimeError::TypeError("object keys must be strings or numbers".to_string())
+}
+
+fn number_key(n: impl fmt::Display) -> Result<Atom, RuntimeError> {
+    Ok(Atom::intern(&n.to_string()))
+}
+
+impl Serializer for KeySerializer {
+    type Ok = Atom;
+    type Error = RuntimeError;
+    type SerializeSeq = ser::Impossible<Atom, RuntimeError>;
+    type SerializeTuple = ser::Impossible<Atom, RuntimeError>;
+    type SerializeTupleStruct = ser::Impossible<Atom, RuntimeError>;
+    type SerializeTupleVariant = ser::Impossible<Atom, RuntimeError>;
+    type SerializeMap = ser::Impossible<Atom, RuntimeError>;
+    type SerializeStruct = ser::Impossible<Atom, RuntimeError>;
+    type SerializeStructVariant = ser::Impossible<Atom, RuntimeError>;
+
+    fn serialize_bool(self, v: bool) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Atom, RuntimeError> {
+        number_key(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Atom, RuntimeError> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Atom, RuntimeError> {
+        Ok(Atom::from_js_string(&Value::Number(v).to_js_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Atom, RuntimeError> {
+        Ok(Atom::intern(v.encode_utf8(&mut [0; 4])))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Atom, RuntimeError> {
+        Ok(Atom::intern(v))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_none(self) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit(self) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Atom, RuntimeError> {
+        Ok(Atom::intern(variant))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Atom, RuntimeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Atom, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, RuntimeError> {
+        Err(key_error())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, RuntimeError> {
+        Err(key_error())
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Value, E> {
+        Ok(Value::Boolean(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Value, E> {
+        Ok(Value::Number(v as f64))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Value, E> {
+        Ok(Value::Number(v as f64))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Value, E> {
+        Ok(Value::Number(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Value, E> {
+        Ok(Value::String(v.into()))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Value, E> {
+        Ok(array(v.iter().map(|&b| Value::Number(b.into())).collect()))
+    }
+
+    fn visit_none<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Value, D::Error> {
+        Value::deserialize(deserializer)
+    }
+
+    fn visit_unit<E>(self) -> Result<Value, E> {
+        Ok(Value::Null)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(element) = seq.next_element()? {
+            elements.push(element);
+        }
+        Ok(array(elements))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0));
+        while let Some((key, value)) = map.next_entry::<String, Value>()? {
+            entries.push((Atom::intern(&key), value));
+        }
+        Ok(object(entries))
+    }
+}
+
+/// Objects enclosing the value being deserialized, innermost first.
+struct Ancestor {
+    ptr: *const (),
+    parent: Option<Rc<Ancestor>>,
+}
+
+fn contains(ancestors: &Option<Rc<Ancestor>>, ptr: *const ()) -> bool {
+    let mut current = ancestors.as_deref();
+    while let Some(ancestor) = current {
+        if ancestor.ptr == ptr {
+            return true;
+        }
+        current = ancestor.parent.as_deref();
+    }
+    false
+}
+
+/// Reads Rust types out of a script value.
+struct ValueDeserializer {
+    value: Value,
+    ancestors: Option<Rc<Ancestor>>,
+}
+
+impl ValueDeserializer {
+    /// Ancestors for the children of this value, or an error if the value
+    /// is one of its own ancestors.
+    fn enter(&self) -> Result<Option<Rc<Ancestor>>, RuntimeError> {
+        match object_ptr(&self.value) {
+            Some(ptr) if contains(&self.ancestors, ptr) => Err(circular()),
+            Some(ptr) => Ok(Some(Rc::new(Ancestor {
+                ptr,
+                parent: self.ancestors.clone(),
+            }))),
+            None => Ok(self.ancestors.clone()),
+        }
+    }
+
+    fn unexpected(&self) -> de::Unexpected<'_> {
+        match &self.value {
+            Value::Undefined | Value::Null => de::Unexpected::Unit,
+            Value::Boolean(b) => de::Unexpected::Bool(*b),
+            Value::Number(n) => de::Unexpected::Float(*n),
+            Value::String(_) => de::Unexpected::Other("string"),
+            Value::Symbol(_) => de::Unexpected::Other("symbol"),
+            Value::Array(_) => de::Unexpected::Seq,
+            Value::Object(_) => de::Unexpected::Map,
+            Value::Function(_) | Value::NativeFunction(_) => de::Unexpected::Other("function"),
+        }
+    }
+}
+
+impl<'de> Deserializer<'de> for ValueDeserializer {
+    type Error = RuntimeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuntimeError> {
+        let ancestors = self.enter()?;
+        match self.value {
+            Value::Undefined | Value::Null => visitor.visit_unit(),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            Value::Number(n) => {
+                // Hand integral numbers over as integers so that integer
+                // fields accept them
+                let integral = n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER;
+                if integral && !(n == 0.0 && n.is_sign_negative()) {
+                    visitor.visit_i64(n as i64)
+                } else {
+                    visitor.visit_f64(n)
+                }
+            }
+            Value::String(s) => visitor.visit_string(js_to_string(&s)?),
+            Value::Array(arr) => visitor.visit_seq(SeqDeserializer {
+                array: arr,
+                next: 0,
+                ancestors,
+            }),
+            Value::Object(obj) => {
+                let entries: Vec<_> = obj
+                    .borrow()
+                    .properties()
+                    .iter()
+                    .filter(|(k, v)| !k.is_symbol() && !v.is_undefined())
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+                visitor.visit_map(MapDeserializer {
+                    entries: entries.into_iter(),
+                    value: None,
+                    ancestors,
+                })
+            }
+            Value::Symbol(_) => Err(RuntimeError::TypeError(
+                "symbols cannot be deserialized".to_string(),
+            )),
+            Value::Function(_) | Value::NativeFunction(_) => Err(RuntimeError::TypeError(
+                "functions cannot be deserialized".to_string(),
+            )),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuntimeError> {
+        match self.value {
+            Value::Undefined | Value::Null => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RuntimeError> {
+        match self.value {
+            Value::Undefined | Value::Null => visitor.visit_unit(),
+            _ => Err(de::Error::invalid_type(self.unexpected(), &visitor)),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        let ancestors = self.enter()?;
+        let (variant, payload) = match &self.value {
+            Value::String(s) => (js_to_string(s)?, None),
+            Value::Object(obj) => {
+                let obj = obj.borrow();
+                let mut entries = obj.properties().iter().filter(|(key, _)| !key.is_symbol());
+                match (entries.next(), entries.next()) {
+                    (Some((key, value)), None) => {
+                        (js_to_string(key.as_js_string())?, Some(value.clone()))
+                    }
+                    _ => {
+                        return Err(RuntimeError::TypeError(
+                            "enum object must have exactly one property".to_string(),
+                        ))
+                    }
+                }
+            }
+            _ => return Err(de::Error::invalid_type(self.unexpected(), &"enum")),
+        };
+        visitor.visit_enum(EnumDeserializer {
+            variant,
+            payload: payload.map(|value| ValueDeserializer { value, ancestors }),
+        })
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit_struct seq tuple tuple_struct map struct identifier
+        ignored_any
+    }
+}
+
+struct SeqDeserializer {
+    array: Rc<RefCell<JsArray>>,
+    /// Index of the next element to read; holes read as undefined
+    next: u32,
+    ancestors: Option<Rc<Ancestor>>,
+}
+
+impl<'de> SeqAccess<'de> for SeqDeserializer {
+    type Error = RuntimeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, RuntimeError> {
+        let array = self.array.borrow();
+        if self.next >= array.len() {
+            return Ok(None);
+        }
+        let value = array.get(self.next).cloned().unwrap_or(Value::Undefined);
+        drop(array);
+        self.next += 1;
+        seed.deserialize(ValueDeserializer {
+            value,
+            ancestors: self.ancestors.clone(),
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.array.borrow().len() - self.next) as usize)
+    }
+}
+
+struct MapDeserializer {
+    entries: std::vec::IntoIter<(Atom, Value)>,
+    /// Value of the entry whose key was just read
+    value: Option<Value>,
+    ancestors: Option<Rc<Ancestor>>,
+}
+
+impl<'de> MapAccess<'de> for MapDeserializer {
+    type Error = RuntimeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, RuntimeError> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                let key = js_to_string(key.as_js_string())?;
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, RuntimeError> {
+        let value = self.value.take().ok_or_else(|| {
+            RuntimeError::Internal("map value deserialized before its key".to_string())
+        })?;
+        seed.deserialize(ValueDeserializer {
+            value,
+            ancestors: self.ancestors.clone(),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumDeserializer {
+    variant: String,
+    payload: Option<ValueDeserializer>,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer {
+    type Error = RuntimeError;
+    type Variant = VariantDeserializer;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, VariantDeserializer), RuntimeError> {
+        let variant = seed.deserialize(self.variant.into_deserializer())?;
+        Ok((variant, VariantDeserializer(self.payload)))
+    }
+}
+
+/// Payload of an enum variant, absent for unit variants.
+struct VariantDeserializer(Option<ValueDeserializer>);
+
+impl VariantDeserializer {
+    fn payload(self) -> Result<ValueDeserializer, RuntimeError> {
+        self.0.ok_or_else(|| {
+            RuntimeError::TypeError("enum variant is missing its payload".to_string())
+        })
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantDeserializer {
+    type Error = RuntimeError;
+
+    fn unit_variant(self) -> Result<(), RuntimeError> {
+        match self.0 {
+            Some(payload) => de::Deserialize::deserialize(payload),
+            None => Ok(()),
+        }
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, RuntimeError> {
+        seed.deserialize(self.payload()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        self.payload()?.deserialize_any(visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RuntimeError> {
+        self.payload()?.deserialize_any(visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Config {
+        name: String,
+        retries: u32,
+        ratio: f64,
+        tags: Vec<String>,
+        parent: Option<Box<Config>>,
+        mode: Mode,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum Mode {
+        Fast,
+        Limited(u8),
+        Custom { level: i32 },
+    }
+
+    fn get(value: &Value, key: &str) -> Value {
+        match value {
+            Value::Object(obj) => obj.borrow().get(key).cloned().unwrap(),
+            _ => panic!("not an object: {:?}", value),
+        }
+    }
+
+    #[test]
+    fn test_round_trip_struct() {
+        let config = Config {
+            name: "main".to_string(),
+            retries: 3,
+            ratio: 0.5,
+            tags: vec!["a".to_string(), "b".to_string()],
+            parent: None,
+            mode: Mode::Custom { level: -2 },
+        };
+
+        let value = to_value(&config).unwrap();
+        assert_eq!(get(&value, "name"), Value::from("main"));
+        assert_eq!(get(&value, "retries"), Value::Number(3.0));
+        assert_eq!(get(&value, "parent"), Value::Null);
+        assert_eq!(
+            get(&get(&get(&value, "mode"), "Custom"), "level"),
+            Value::Number(-2.0)
+        );
+        assert_eq!(from_value::<Config>(value), Ok(config));
+    }
+
+    #[test]
+    fn test_enums() {
+        assert_eq!(to_value(&Mode::Fast), Ok(Value::from("Fast")));
+        assert_eq!(from_value(Value::from("Fast")), Ok(Mode::Fast));
+        let limited = to_value(&Mode::Limited(7)).unwrap();
+        assert_eq!(from_value(limited), Ok(Mode::Limited(7)));
+    }
+
+    #[test]
+    fn test_undefined_and_null() {
+        let mut vm = crate::VM::default();
+        let value = vm
+            .eval("var o = { a: undefined, b: null, c: 1 }; o")
+            .unwrap();
+
+        let parsed: HashMap<String, Option<f64>> = from_value(value).unwrap();
+        assert_eq!(
+            parsed,
+            HashMap::from([("b".to_string(), None), ("c".to_string(), Some(1.0))])
+        );
+        assert_eq!(from_value::<Option<u8>>(Value::Undefined), Ok(None));
+        assert_eq!(from_value::<()>(Value::Null), Ok(()));
+        assert_eq!(to_value(&()), Ok(Value::Null));
+    }
+
+    #[test]
+    fn test_numbers_and_integers() {
+        assert_eq!(from_value::<i32>(Value::Number(-4.0)), Ok(-4));
+        assert_eq!(from_value::<f64>(Value::Number(4.0)), Ok(4.0));
+        assert!(from_value::<u8>(Value::Number(1.5)).is_err());
+        assert!(from_value::<u8>(Value::Number(300.0)).is_err());
+        assert!(from_value::<u32>(Value::Number(-1.0)).is_err());
+        assert_eq!(
+            to_value(&9007199254740991u64),
+            Ok(Value::Number(9007199254740991.0))
+        );
+        assert!(matches!(
+            to_value(&(1u64 << 53)),
+            Err(RuntimeError::RangeError(_))
+        ));
+        assert_eq!(to_value(&1.5f32), Ok(Value::Number(1.5)));
+    }
+
+    #[test]
+    fn test_functions_rejected() {
+        let mut vm = crate::VM::default();
+        let value = vm.eval("var o = { f: function () {} }; o").unwrap();
+
+        assert_eq!(
+            from_value::<HashMap<String, Value>>(value),
+            Err(RuntimeError::TypeError(
+                "functions cannot be deserialized".to_string()
+            ))
+        );
+        let value = vm.eval("o.f").unwrap();
+        assert!(to_value(&value).is_err());
+    }
+
+    #[test]
+    fn test_cycles_rejected() {
+        let mut vm = crate::VM::default();
+        let value = vm.eval("var o = { list: [] }; o.list[0] = o; o").unwrap();
+
+        assert_eq!(to_value(&value), Err(circular()));
+        assert_eq!(from_value::<Value>(value), Err(circular()));
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let map = HashMap::from([(1, "one"), (2, "two")]);
+        let value = to_value(&map).unwrap();
+
+        assert_eq!(get(&value, "1"), Value::from("one"));
+        assert_eq!(
+            from_value::<HashMap<String, String>>(value).unwrap().len(),
+            2
+        );
+        assert_eq!(to_value(&HashMap::from([((1, 2), 3)])), Err(key_error()));
+    }
+}
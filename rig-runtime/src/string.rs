@@ -0,0 +1,568 @@
+//! Immutable, reference-counted strings used by the runtime.
+//!
+//! Script strings are sequences of UTF-16 code units and may contain lone
+//! surrogates. Strings whose code units all fit in a byte are stored as
+//! Latin-1, everything else as UTF-16; a string is only ever stored as UTF-16
+//! when it has to be, so two equal strings always share a representation.
+//!
+//! Cloning a [`JsString`] only bumps a reference count. Concatenation builds a
+//! rope node instead of copying both halves, and the rope is flattened lazily
+//! the first time its contents are actually needed.
+
+use std::cell::{OnceCell, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::{Rc, Weak};
+use std::string::FromUtf16Error;
+
+/// Concatenations whose result is shorter than this are copied eagerly,
+/// since a rope node would cost more than the copy.
+const MIN_ROPE_LEN: usize = 32;
+
+#[derive(Clone)]
+pub struct JsString(Rc<Repr>);
+
+enum Repr {
+    Flat(Flat),
+    Rope(Rope),
+}
+
+enum Flat {
+    Latin1(Box<[u8]>),
+    Utf16(Box<[u16]>),
+}
+
+struct Rope {
+    /// Length in code units
+    len: usize,
+    /// True if both halves are Latin-1, so the flattened string is too
+    latin1: bool,
+    /// Both halves of the concatenation, released once the rope is flattened
+    children: RefCell<Option<(JsString, JsString)>>,
+    flat: OnceCell<Flat>,
+}
+
+/// A reference to a string that does not keep it alive.
+pub(crate) struct WeakJsString(Weak<Repr>);
+
+impl WeakJsString {
+    /// The string, unless every [`JsString`] sharing it has been dropped.
+    pub(crate) fn upgrade(&self) -> Option<JsString> {
+        self.0.upgrade().map(JsString)
+    }
+
+    pub(crate) fn is_dropped(&self) -> bool {
+        self.0.strong_count() == 0
+    }
+}
+
+/// A borrowed view of flat string contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JsStr<'a> {
+    Latin1(&'a [u8]),
+    Utf16(&'a [u16]),
+}
+
+/// Iterator over the code units of a [`JsStr`].
+#[derive(Clone)]
+pub enum CodeUnits<'a> {
+    Latin1(std::slice::Iter<'a, u8>),
+    Utf16(std::slice::Iter<'a, u16>),
+}
+
+impl JsString {
+    pub fn new(s: &str) -> Self {
+        if s.chars().all(|c| (c as u32) < 0x100) {
+            JsString::from_flat(Flat::Latin1(s.chars().map(|c| c as u8).collect()))
+        } else {
+            JsString::from_flat(Flat::Utf16(s.encode_utf16().collect()))
+        }
+    }
+
+    /// Creates a string from raw code units, which may include lone surrogates.
+    pub fn from_utf16(units: &[u16]) -> Self {
+        if units.iter().all(|&u| u < 0x100) {
+            JsString::from_flat(Flat::Latin1(units.iter().map(|&u| u as u8).collect()))
+        } else {
+            JsString::from_flat(Flat::Utf16(units.into()))
+        }
+    }
+
+    /// Creates a string from Latin-1 bytes, one code unit per byte.
+    pub fn from_latin1(bytes: &[u8]) -> Self {
+        JsString::from_flat(Flat::Latin1(bytes.into()))
+    }
+
+    /// Creates a one code unit string, as `String.fromCharCode` does.
+    pub fn from_code_unit(unit: u16) -> Self {
+        JsString::from_utf16(&[unit])
+    }
+
+    fn from_flat(flat: Flat) -> Self {
+        JsString(Rc::new(Repr::Flat(flat)))
+    }
+
+    /// Length of the string in UTF-16 code units
+    pub fn len(&self) -> usize {
+        match &*self.0 {
+            Repr::Flat(flat) => flat.view().len(),
+            Repr::Rope(rope) => rope.len,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns true if every code unit fits in a byte.
+    pub fn is_latin1(&self) -> bool {
+        match &*self.0 {
+            Repr::Flat(flat) => matches!(flat, Flat::Latin1(_)),
+            Repr::Rope(rope) => rope.latin1,
+        }
+    }
+
+    /// Returns the string contents, flattening the rope if necessary.
+    pub fn as_js_str(&self) -> JsStr<'_> {
+        match &*self.0 {
+            Repr::Flat(flat) => flat.view(),
+            Repr::Rope(rope) => rope
+                .flat
+                .get_or_init(|| {
+                    let flat = rope.flatten();
+                    rope.children.borrow_mut().take();
+                    flat
+                })
+                .view(),
+        }
+    }
+
+    /// Returns the code unit at `index`, as `charCodeAt` does.
+    pub fn code_unit_at(&self, index: usize) -> Option<u16> {
+        self.as_js_str().get(index)
+    }
+
+    pub fn code_units(&self) -> CodeUnits<'_> {
+        self.as_js_str().code_units()
+    }
+
+    /// Returns the code units in `start..end`, clamped to the string length.
+    pub fn substring(&self, start: usize, end: usize) -> JsString {
+        let len = self.len();
+        let end = end.min(len);
+        let start = start.min(end);
+        if start == 0 && end == len {
+            return self.clone();
+        }
+        match self.as_js_str() {
+            JsStr::Latin1(bytes) => JsString::from_latin1(&bytes[start..end]),
+            JsStr::Utf16(units) => JsString::from_utf16(&units[start..end]),
+        }
+    }
+
+    /// Converts to a Rust string, failing on lone surrogates.
+    pub fn to_std_string(&self) -> Result<String, FromUtf16Error> {
+        match self.as_js_str() {
+            JsStr::Latin1(bytes) => Ok(bytes.iter().map(|&b| b as char).collect()),
+            JsStr::Utf16(units) => String::from_utf16(units),
+        }
+    }
+
+    /// Converts to a Rust string, replacing lone surrogates with U+FFFD.
+    pub fn to_std_string_lossy(&self) -> String {
+        match self.as_js_str() {
+            JsStr::Latin1(bytes) => bytes.iter().map(|&b| b as char).collect(),
+            JsStr::Utf16(units) => String::from_utf16_lossy(units),
+        }
+    }
+
+    /// Concatenates two strings without copying either of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the combined length overflows `usize`. The VM refuses to
+    /// build strings longer than V8's limit long before that.
+    pub fn concat(&self, other: &JsString) -> JsString {
+        if other.is_empty() {
+            return self.clone();
+        }
+        if self.is_empty() {
+            return other.clone();
+        }
+        let len = self
+            .len()
+            .checked_add(other.len())
+            .expect("string length overflows usize");
+        let latin1 = self.is_latin1() && other.is_latin1();
+        if len < MIN_ROPE_LEN {
+            let mut units: Vec<u16> = Vec::with_capacity(len);
+            units.extend(self.code_units());
+            units.extend(other.code_units());
+            return JsString::from_utf16(&units);
+        }
+        JsString(Rc::new(Repr::Rope(Rope {
+            len,
+            latin1,
+            children: RefCell::new(Some((self.clone(), other.clone()))),
+            flat: OnceCell::new(),
+        })))
+    }
+
+    /// Returns true if both values share the same underlying allocation.
+    pub fn ptr_eq(a: &JsString, b: &JsString) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0) as *const ()
+    }
+
+    pub(crate) fn downgrade(&self) -> WeakJsString {
+        WeakJsString(Rc::downgrade(&self.0))
+    }
+
+    /// Returns true if this string is an unflattened rope.
+    pub fn is_rope(&self) -> bool {
+        matches!(&*self.0, Repr::Rope(rope) if rope.flat.get().is_none())
+    }
+}
+
+impl Flat {
+    fn view(&self) -> JsStr<'_> {
+        match self {
+            Flat::Latin1(bytes) => JsStr::Latin1(bytes),
+            Flat::Utf16(units) => JsStr::Utf16(units),
+        }
+    }
+}
+
+impl Rope {
+    /// Walks the rope iteratively so deeply nested ropes built by repeated
+    /// `+=` cannot overflow the native stack.
+    fn flatten(&self) -> Flat {
+        let mut latin1 = Vec::new();
+        let mut utf16 = Vec::new();
+        if self.latin1 {
+            latin1.reserve(self.len);
+        } else {
+            utf16.reserve(self.len);
+        }
+        let mut push = |s: JsStr<'_>| match s {
+            JsStr::Latin1(bytes) if self.latin1 => latin1.extend_from_slice(bytes),
+            s => utf16.extend(s.code_units()),
+        };
+
+        let mut stack = Vec::new();
+        if let Some((left, right)) = &*self.children.borrow() {
+            stack.push(right.clone());
+            stack.push(left.clone());
+        }
+        while let Some(s) = stack.pop() {
+            match &*s.0 {
+                Repr::Flat(flat) => push(flat.view()),
+                Repr::Rope(rope) => {
+                    if let Some(flat) = rope.flat.get() {
+                        push(flat.view());
+                    } else if let Some((left, right)) = &*rope.children.borrow() {
+                        stack.push(right.clone());
+                        stack.push(left.clone());
+                    }
+                }
+            }
+        }
+
+        if self.latin1 {
+            Flat::Latin1(latin1.into_boxed_slice())
+        } else {
+            Flat::Utf16(utf16.into_boxed_slice())
+        }
+    }
+}
+
+impl Drop for Rope {
+    /// Dismantles uniquely owned child ropes iteratively for the same reason
+    /// flattening does.
+    fn drop(&mut self) {
+        let mut stack = Vec::new();
+        if let Some((left, right)) = self.children.get_mut().take() {
+            stack.push(left);
+            stack.push(right);
+        }
+        while let Some(s) = stack.pop() {
+            if let Ok(Repr::Rope(mut rope)) = Rc::try_unwrap(s.0) {
+                if let Some((left, right)) = rope.children.get_mut().take() {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+    }
+}
+
+impl<'a> JsStr<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            JsStr::Latin1(bytes) => bytes.len(),
+            JsStr::Utf16(units) => units.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<u16> {
+        match self {
+            JsStr::Latin1(bytes) => bytes.get(index).map(|&b| b as u16),
+            JsStr::Utf16(units) => units.get(index).copied(),
+        }
+    }
+
+    pub fn code_units(&self) -> CodeUnits<'a> {
+        match *self {
+            JsStr::Latin1(bytes) => CodeUnits::Latin1(bytes.iter()),
+            JsStr::Utf16(units) => CodeUnits::Utf16(units.iter()),
+        }
+    }
+}
+
+impl Iterator for CodeUnits<'_> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        match self {
+            CodeUnits::Latin1(iter) => iter.next().map(|&b| b as u16),
+            CodeUnits::Utf16(iter) => iter.next().copied(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            CodeUnits::Latin1(iter) => iter.size_hint(),
+            CodeUnits::Utf16(iter) => iter.size_hint(),
+        }
+    }
+}
+
+impl DoubleEndedIterator for CodeUnits<'_> {
+    fn next_back(&mut self) -> Option<u16> {
+        match self {
+            CodeUnits::Latin1(iter) => iter.next_back().map(|&b| b as u16),
+            CodeUnits::Utf16(iter) => iter.next_back().copied(),
+        }
+    }
+}
+
+impl ExactSizeIterator for CodeUnits<'_> {}
+
+/// Whether a code unit is white space or a line terminator, as trimmed by
+/// `String.prototype.trim` and ignored around numeric strings.
+pub(crate) fn is_whitespace(unit: u16) -> bool {
+    matches!(
+        unit,
+        0x09..=0x0d
+            | 0x20
+            | 0xa0
+            | 0x1680
+            | 0x2000..=0x200a
+            | 0x2028
+            | 0x2029
+            | 0x202f
+            | 0x205f
+            | 0x3000
+            | 0xfeff
+    )
+}
+
+/// Feeds code units to `state` exactly like `JsString`'s `Hash` impl, so
+/// lookups keyed by other string types stay consistent with it.
+pub(crate) fn hash_code_units<H: Hasher>(units: impl Iterator<Item = u16>, state: &mut H) {
+    let mut len = 0usize;
+    for unit in units {
+        state.write_u16(unit);
+        len += 1;
+    }
+    state.write_usize(len);
+}
+
+impl From<&str> for JsString {
+    fn from(s: &str) -> Self {
+        JsString::new(s)
+    }
+}
+
+impl From<String> for JsString {
+    fn from(s: String) -> Self {
+        JsString::new(&s)
+    }
+}
+
+impl PartialEq for JsString {
+    fn eq(&self, other: &Self) -> bool {
+        JsString::ptr_eq(self, other) || self.as_js_str() == other.as_js_str()
+    }
+}
+
+impl Eq for JsString {}
+
+impl PartialEq<str> for JsString {
+    fn eq(&self, other: &str) -> bool {
+        self.code_units().eq(other.encode_utf16())
+    }
+}
+
+impl PartialEq<&str> for JsString {
+    fn eq(&self, other: &&str) -> bool {
+        *self == **other
+    }
+}
+
+impl PartialOrd for JsString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JsString {
+    /// Orders strings by code unit, as the relational operators do.
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.as_js_str(), other.as_js_str()) {
+            (JsStr::Latin1(a), JsStr::Latin1(b)) => a.cmp(b),
+            (a, b) => a.code_units().cmp(b.code_units()),
+        }
+    }
+}
+
+impl Hash for JsString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_code_units(self.code_units(), state);
+    }
+}
+
+impl fmt::Display for JsString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_std_string_lossy())
+    }
+}
+
+impl fmt::Debug for JsString {
+    /// Formats like a Rust string literal, escaping lone surrogates.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"")?;
+        for c in char::decode_utf16(self.code_units()) {
+            match c {
+                Ok(c) => write!(f, "{}", c.escape_debug())?,
+                Err(e) => write!(f, "\\u{{{:x}}}", e.unpaired_surrogate())?,
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concat_builds_rope() {
+        let a = JsString::from("a".repeat(20));
+        let b = JsString::from("b".repeat(20));
+        let s = a.concat(&b);
+
+        assert!(s.is_rope());
+        assert_eq!(s.len(), 40);
+        assert_eq!(
+            s.to_std_string().unwrap(),
+            format!("{}{}", "a".repeat(20), "b".repeat(20))
+        );
+        assert!(!s.is_rope());
+    }
+
+    #[test]
+    fn test_short_concat_is_flat() {
+        let s = JsString::from("foo").concat(&JsString::from("bar"));
+
+        assert!(!s.is_rope());
+        assert_eq!(s, JsString::from("foobar"));
+    }
+
+    #[test]
+    fn test_deep_rope() {
+        let piece = JsString::from("x".repeat(MIN_ROPE_LEN));
+        let mut s = JsString::from("");
+        for _ in 0..100_000 {
+            s = s.concat(&piece);
+        }
+
+        assert_eq!(s.len(), 100_000 * MIN_ROPE_LEN);
+        assert!(s.code_units().all(|u| u == 'x' as u16));
+    }
+
+    #[test]
+    fn test_deep_rope_drop() {
+        let piece = JsString::from("y".repeat(MIN_ROPE_LEN));
+        let mut s = JsString::from("");
+        for _ in 0..100_000 {
+            s = s.concat(&piece);
+        }
+        drop(s);
+    }
+
+    #[test]
+    fn test_latin1_fast_path() {
+        let s = JsString::from("caf\u{e9}");
+
+        assert!(s.is_latin1());
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.code_unit_at(3), Some(0xE9));
+        assert_eq!(s.to_std_string().unwrap(), "caf\u{e9}");
+    }
+
+    #[test]
+    fn test_utf16_code_units() {
+        let s = JsString::from("a\u{1F600}b");
+
+        assert!(!s.is_latin1());
+        assert_eq!(s.len(), 4);
+        assert_eq!(s.code_unit_at(1), Some(0xD83D));
+        assert_eq!(s.code_unit_at(2), Some(0xDE00));
+        assert_eq!(s.substring(1, 3).to_std_string().unwrap(), "\u{1F600}");
+    }
+
+    #[test]
+    fn test_lone_surrogate() {
+        let s = JsString::from_code_unit(0xD800);
+
+        assert_eq!(s.len(), 1);
+        assert!(s.to_std_string().is_err());
+        assert_eq!(s.to_std_string_lossy(), "\u{FFFD}");
+        assert_eq!(format!("{:?}", s), "\"\\u{d800}\"");
+
+        // Splitting a surrogate pair and joining it again restores the char
+        let pair = JsString::from("\u{1F600}");
+        let joined = pair.substring(0, 1).concat(&pair.substring(1, 2));
+        assert_eq!(joined, pair);
+    }
+
+    #[test]
+    fn test_mixed_rope_flattens_to_utf16() {
+        let a = JsString::from("a".repeat(20));
+        let b = JsString::from("\u{3b1}".repeat(20));
+        let s = a.concat(&b);
+
+        assert!(!s.is_latin1());
+        assert_eq!(s.len(), 40);
+        assert_eq!(s.code_unit_at(0), Some('a' as u16));
+        assert_eq!(s.code_unit_at(39), Some(0x3B1));
+    }
+
+    #[test]
+    fn test_code_unit_ordering() {
+        // U+FF61 sorts before U+1F600 by code point but after it by code unit
+        let a = JsString::from("\u{FF61}");
+        let b = JsString::from("\u{1F600}");
+
+        assert!(a > b);
+    }
+}
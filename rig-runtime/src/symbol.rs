@@ -0,0 +1,156 @@
+//! Symbols, the unique values that can key properties besides strings.
+//!
+//! A symbol is equal only to itself, whatever its description. The
+//! well-known symbols, such as `Symbol.iterator`, are shared by every VM on
+//! a thread, so that Rust code can name them without a VM at hand.
+
+use std::fmt;
+use std::rc::Rc;
+
+use crate::JsString;
+
+/// A symbol. Cloning shares the same symbol.
+#[derive(Clone)]
+pub struct JsSymbol(Rc<SymbolData>);
+
+struct SymbolData {
+    description: Option<JsString>,
+    /// `Symbol(description)`, as `String(symbol)` gives
+    descriptive: JsString,
+    /// Set on the private names of classes
+    private: bool,
+}
+
+impl JsSymbol {
+    /// A new symbol, distinct from every other.
+    pub fn new(description: Option<JsString>) -> Self {
+        let descriptive = JsString::from("Symbol(")
+            .concat(description.as_ref().unwrap_or(&JsString::from("")))
+            .concat(&JsString::from(")"));
+        JsSymbol(Rc::new(SymbolData {
+            description,
+            descriptive,
+            private: false,
+        }))
+    }
+
+    /// A private name such as `#count`, which keys private members rather
+    /// than properties.
+    pub(crate) fn private(name: JsString) -> Self {
+        JsSymbol(Rc::new(SymbolData {
+            description: Some(name.clone()),
+            descriptive: name,
+            private: true,
+        }))
+    }
+
+    pub(crate) fn is_private(&self) -> bool {
+        self.0.private
+    }
+
+    pub fn description(&self) -> Option<&JsString> {
+        self.0.description.as_ref()
+    }
+
+    /// The symbol as a string, such as `Symbol(foo)`.
+    pub fn descriptive_string(&self) -> &JsString {
+        &self.0.descriptive
+    }
+
+    pub fn ptr_eq(a: &JsSymbol, b: &JsSymbol) -> bool {
+        Rc::ptr_eq(&a.0, &b.0)
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const () {
+        Rc::as_ptr(&self.0) as *const ()
+    }
+
+    /// `Symbol.asyncIterator`, the method that makes an object async
+    /// iterable.
+    pub fn async_iterator() -> JsSymbol {
+        WELL_KNOWN.with(|symbols| symbols.async_iterator.clone())
+    }
+
+    /// `Symbol.hasInstance`, the method `instanceof` calls on its right
+    /// operand.
+    pub fn has_instance() -> JsSymbol {
+        WELL_KNOWN.with(|symbols| symbols.has_instance.clone())
+    }
+
+    /// `Symbol.iterator`, the method that makes an object iterable.
+    pub fn iterator() -> JsSymbol {
+        WELL_KNOWN.with(|symbols| symbols.iterator.clone())
+    }
+
+    /// `Symbol.toPrimitive`, the method that converts an object to a
+    /// primitive.
+    pub fn to_primitive() -> JsSymbol {
+        WELL_KNOWN.with(|symbols| symbols.to_primitive.clone())
+    }
+
+    /// `Symbol.toStringTag`, the name `Object.prototype.toString` reports.
+    pub fn to_string_tag() -> JsSymbol {
+        WELL_KNOWN.with(|symbols| symbols.to_string_tag.clone())
+    }
+}
+
+impl PartialEq for JsSymbol {
+    fn eq(&self, other: &Self) -> bool {
+        JsSymbol::ptr_eq(self, other)
+    }
+}
+
+impl Eq for JsSymbol {}
+
+impl fmt::Debug for JsSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0.descriptive, f)
+    }
+}
+
+struct WellKnown {
+    async_iterator: JsSymbol,
+    has_instance: JsSymbol,
+    iterator: JsSymbol,
+    to_primitive: JsSymbol,
+    to_string_tag: JsSymbol,
+}
+
+thread_local! {
+    static WELL_KNOWN: WellKnown = {
+        let symbol = |name: &str| JsSymbol::new(Some(JsString::from(format!("Symbol.{name}"))));
+        WellKnown {
+            async_iterator: symbol("asyncIterator"),
+            has_instance: symbol("hasInstance"),
+            iterator: symbol("iterator"),
+            to_primitive: symbol("toPrimitive"),
+            to_string_tag: symbol("toStringTag"),
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_symbols_are_unique() {
+        let a = JsSymbol::new(Some(JsString::from("a")));
+        let b = JsSymbol::new(Some(JsString::from("a")));
+        assert_ne!(a, b);
+        assert_eq!(a, a.clone());
+        assert_eq!(JsSymbol::iterator(), JsSymbol::iterator());
+        assert_eq!(
+            JsSymbol::iterator()
+                .descriptive_string()
+                .to_std_string_lossy(),
+            "Symbol(Symbol.iterator)"
+        );
+        assert_eq!(
+            JsSymbol::new(None)
+                .descriptive_string()
+                .to_std_string_lossy(),
+            "Symbol()"
+        );
+    }
+}